@@ -8,7 +8,7 @@ use troy::tags::*;
 use criterion::Criterion;
 
 fn criterion_benchmark(c: &mut Criterion) {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     let old = div().class("app")
         .child(div().class("header")
             .child(p().text("Todo List"))
@@ -34,7 +34,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         )
         .done();
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     let new = div().class("app")
         .child(div().class("header")
             .child(p().text("Todo List"))
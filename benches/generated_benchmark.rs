@@ -0,0 +1,26 @@
+#[macro_use]
+extern crate criterion;
+extern crate troy;
+
+use troy::bench_support::{churn, generate, TreeShape};
+use troy::diff::diff;
+
+use criterion::Criterion;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let old = generate(&TreeShape::default());
+    let new = churn(&old, 10.0, 1);
+    c.bench_function("diff generated keyed", move |b| b.iter(|| diff(&old, &new)));
+
+    let old = generate(&TreeShape {
+        keyed_ratio: 0.0,
+        ..TreeShape::default()
+    });
+    let new = churn(&old, 10.0, 1);
+    c.bench_function("diff generated unkeyed", move |b| {
+        b.iter(|| diff(&old, &new))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
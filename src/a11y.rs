@@ -0,0 +1,242 @@
+//! # Accessibility checks
+//!
+//! Walks a tree and reports common accessibility issues:
+//!
+//! - `img` without `alt`.
+//! - Form controls without a label.
+//! - Buttons without an accessible name.
+//! - Skipped heading levels (`h1` followed by `h3`).
+//! - `html` element without `lang`.
+//!
+//! The checks are static and can't see CSS or scripts, so they are meant as a
+//! regression guard for views rather than a full audit.
+//!
+use element::VElement;
+use node::VNode;
+use path::NodePath;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    ImgAlt,
+    ControlLabel,
+    ButtonName,
+    HeadingOrder,
+    HtmlLang,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: Rule,
+    pub path: NodePath,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at {}: {}", self.rule, self.path, self.message)
+    }
+}
+
+/// Check a tree and return all findings in document order.
+///
+pub fn check(root: &VNode) -> Vec<Finding> {
+    let mut checker = Checker {
+        label_targets: HashSet::new(),
+        findings: Vec::new(),
+        last_heading: None,
+    };
+    collect_label_targets(root, &mut checker.label_targets);
+    checker.walk(root, &mut NodePath::root(), false);
+    checker.findings
+}
+
+/// Collect ids referenced by `label` elements with `for` attribute.
+///
+fn collect_label_targets(node: &VNode, targets: &mut HashSet<String>) {
+    if let VNode::Element(element) = node.resolve() {
+        if element.get_tag() == "label" {
+            if let Some(target) = element.get_attributes().get("for") {
+                targets.insert(target.to_string());
+            }
+        }
+        for child in element.get_children() {
+            collect_label_targets(child, targets);
+        }
+    }
+}
+
+fn has_nonempty_attr(element: &VElement, name: &str) -> bool {
+    element
+        .get_attributes()
+        .get(name)
+        .is_some_and(|value| !value.trim().is_empty())
+}
+
+/// Element has a name provided through ARIA or title attributes.
+///
+fn has_aria_name(element: &VElement) -> bool {
+    has_nonempty_attr(element, "aria-label")
+        || has_nonempty_attr(element, "aria-labelledby")
+        || has_nonempty_attr(element, "title")
+}
+
+/// Text content or `alt` of images inside an element.
+///
+fn has_content_name(node: &VNode) -> bool {
+    match node {
+        VNode::Text(text) => !text.get_content().trim().is_empty(),
+        VNode::Element(element) => {
+            (element.get_tag() == "img" && has_nonempty_attr(element, "alt"))
+                || has_aria_name(element)
+                || element.get_children().iter().any(has_content_name)
+        }
+        VNode::Component(_) | VNode::Shared(_) => has_content_name(node.resolve()),
+    }
+}
+
+struct Checker {
+    label_targets: HashSet<String>,
+    findings: Vec<Finding>,
+    last_heading: Option<usize>,
+}
+
+impl Checker {
+    fn report(&mut self, rule: Rule, path: &NodePath, message: String) {
+        self.findings.push(Finding {
+            rule,
+            path: path.clone(),
+            message,
+        });
+    }
+
+    fn walk(&mut self, node: &VNode, path: &mut NodePath, in_label: bool) {
+        let element = match node.resolve() {
+            VNode::Element(element) => element,
+            _ => return,
+        };
+        let tag = element.get_tag();
+        let attributes = element.get_attributes();
+
+        match tag {
+            "html" if !has_nonempty_attr(element, "lang") => self.report(
+                Rule::HtmlLang,
+                path,
+                "html element without lang".to_string(),
+            ),
+            "img" if !attributes.contains_key("alt") => {
+                self.report(Rule::ImgAlt, path, "img without alt".to_string())
+            }
+            "button" if !has_content_name(node) => self.report(
+                Rule::ButtonName,
+                path,
+                "button without accessible name".to_string(),
+            ),
+            "input" | "select" | "textarea" => {
+                let input_type = attributes.get("type").map(|t| t.as_ref()).unwrap_or("text");
+                let exempt = tag == "input"
+                    && ["hidden", "submit", "reset", "button", "image"].contains(&input_type);
+                let labelled = in_label
+                    || has_aria_name(element)
+                    || attributes
+                        .get("id")
+                        .is_some_and(|id| self.label_targets.contains(id.as_ref()));
+                if !exempt && !labelled {
+                    self.report(Rule::ControlLabel, path, format!("{} without label", tag));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(level) = heading_level(tag) {
+            if let Some(last) = self.last_heading {
+                if level > last + 1 {
+                    self.report(
+                        Rule::HeadingOrder,
+                        path,
+                        format!("h{} follows h{}", level, last),
+                    );
+                }
+            }
+            self.last_heading = Some(level);
+        }
+
+        let in_label = in_label || tag == "label";
+        for (index, child) in element.get_children().iter().enumerate() {
+            path.push(index);
+            self.walk(child, path, in_label);
+            path.pop();
+        }
+    }
+}
+
+fn heading_level(tag: &str) -> Option<usize> {
+    match tag {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    fn rules(node: &VNode) -> Vec<(Rule, String)> {
+        check(node)
+            .into_iter()
+            .map(|finding| (finding.rule, finding.path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn accessible_tree() {
+        #[rustfmt::skip]
+        let node = html().attr("lang", "en")
+            .child(body()
+                .child(h1().text("Title"))
+                .child(h2().text("Section"))
+                .child(img().attr("alt", ""))
+                .child(label().attr("for", "name").text("Name"))
+                .child(input().attr("id", "name"))
+                .child(label().text("Email").child(input().attr("type", "email")))
+                .child(input().attr("type", "hidden"))
+                .child(button().child(img().attr("alt", "Close")))
+                .child(button().attr("aria-label", "Menu")))
+            .done();
+
+        assert_eq!(rules(&node), vec![]);
+    }
+
+    #[test]
+    fn inaccessible_tree() {
+        #[rustfmt::skip]
+        let node = html()
+            .child(body()
+                .child(h1().text("Title"))
+                .child(h3().text("Section"))
+                .child(img())
+                .child(input().attr("id", "name"))
+                .child(select())
+                .child(button().child(img().attr("alt", ""))))
+            .done();
+
+        assert_eq!(
+            rules(&node),
+            vec![
+                (Rule::HtmlLang, "/".to_string()),
+                (Rule::HeadingOrder, "/0/1".to_string()),
+                (Rule::ImgAlt, "/0/2".to_string()),
+                (Rule::ControlLabel, "/0/3".to_string()),
+                (Rule::ControlLabel, "/0/4".to_string()),
+                (Rule::ButtonName, "/0/5".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! # Async diff
+//!
+//! `diff` runs to completion, which on big trees can block an async
+//! executor for a noticeable time. `diff_async` returns a future which does
+//! the same work in steps and yields to the executor after every few nodes.
+//!
+//! The diff is split by node pairs: the future first finds the pairs of old
+//! and new elements the diff will compare, then diffs them children first,
+//! so each step only compares one element with the diffs of its children
+//! ready. Components and shared nodes are diffed in a single step.
+//!
+//! ```
+//! use std::future::Future;
+//! use std::pin::pin;
+//! use std::task::{Context, Poll, Waker};
+//! use troy::async_diff::diff_async;
+//! use troy::diff::diff;
+//! use troy::tags::*;
+//!
+//! let old = ul().child(li().key("a").text("a")).child(li().key("b").text("b")).done();
+//! let new = ul().child(li().key("b").text("b")).child(li().key("a").text("A")).done();
+//!
+//! // Executor which polls the future until it is ready.
+//! let mut future = pin!(diff_async(&old, &new).yield_every(1));
+//! let mut context = Context::from_waker(Waker::noop());
+//! let op = loop {
+//!     if let Poll::Ready(op) = future.as_mut().poll(&mut context) {
+//!         break op;
+//!     }
+//! };
+//!
+//! assert_eq!(op, diff(&old, &new));
+//! ```
+//!
+use diff::{child_pairs, diff_node, node_pair, Lifecycle, NodeOp};
+use node::VNode;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const DEFAULT_YIELD_EVERY: usize = 1000;
+
+/// Future of the diff of two trees, see `diff_async`.
+///
+#[must_use = "futures do nothing unless polled"]
+pub struct DiffFuture<'old, 'new> {
+    /// Node pairs to diff, parents before children.
+    pairs: Vec<(&'old VNode, &'new VNode)>,
+    /// Pairs before this index had their children pairs added.
+    expanded: usize,
+    /// Pairs from this index on are diffed.
+    diffed: usize,
+    lifecycle: Lifecycle<'old, 'new>,
+    yield_every: usize,
+}
+
+/// Diff the trees, yielding to the executor after every 1000 nodes. The
+/// result is the same as the result of `diff`.
+///
+pub fn diff_async<'old, 'new>(old: &'old VNode, new: &'new VNode) -> DiffFuture<'old, 'new> {
+    DiffFuture {
+        pairs: vec![(old, new)],
+        expanded: 0,
+        diffed: 1,
+        lifecycle: Lifecycle::default(),
+        yield_every: DEFAULT_YIELD_EVERY,
+    }
+}
+
+impl<'old, 'new> DiffFuture<'old, 'new> {
+    /// Yield after diffing this many nodes.
+    ///
+    pub fn yield_every(mut self, nodes: usize) -> Self {
+        self.yield_every = nodes.max(1);
+        self
+    }
+
+    /// Do one unit of work. Returns the diff when it is complete.
+    ///
+    fn step(&mut self) -> Option<NodeOp<'new>> {
+        if self.expanded < self.pairs.len() {
+            let (old, new) = self.pairs[self.expanded];
+            self.expanded += 1;
+            if let (VNode::Element(old_element), VNode::Element(new_element)) = (old, new) {
+                if old_element.get_tag() == new_element.get_tag()
+                    && old_element.get_key() == new_element.get_key()
+                    && !old_element.is_void()
+                    && !new_element.is_void()
+                {
+                    let pairs = child_pairs(old_element, new_element);
+                    self.pairs
+                        .extend(pairs.into_iter().map(|(_, old, new)| (old, new)));
+                }
+            }
+            self.diffed = self.pairs.len();
+            return None;
+        }
+
+        self.diffed -= 1;
+        let (old, new) = self.pairs[self.diffed];
+        let op = diff_node(old, new, &mut self.lifecycle);
+        if self.diffed == 0 {
+            Some(op)
+        } else {
+            self.lifecycle.diffed.insert(node_pair(old, new), op);
+            None
+        }
+    }
+}
+
+impl<'old, 'new> Future for DiffFuture<'old, 'new> {
+    type Output = NodeOp<'new>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<NodeOp<'new>> {
+        let future = self.get_mut();
+        for _ in 0..future.yield_every {
+            if let Some(op) = future.step() {
+                return Poll::Ready(op);
+            }
+        }
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use std::pin::pin;
+    use std::task::Waker;
+    use tags::*;
+
+    fn block_on<'new>(future: DiffFuture<'_, 'new>) -> (NodeOp<'new>, usize) {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        let mut polls = 1;
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(op) => {
+                    // Every diff computed ahead was used.
+                    assert!(future.lifecycle.diffed.is_empty());
+                    return (op, polls);
+                }
+                Poll::Pending => polls += 1,
+            }
+        }
+    }
+
+    fn rows(rows: &[(&'static str, &'static str)]) -> VNode {
+        let mut body = tbody();
+        for (key, value) in rows {
+            body = body.child(
+                tr().key(*key)
+                    .child(td().text(*key))
+                    .child(td().class(*value).text(*value)),
+            );
+        }
+        table().child(body).done()
+    }
+
+    #[test]
+    fn same_result_as_diff() {
+        let old = rows(&[("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")]);
+        let new = rows(&[("d", "4"), ("b", "5"), ("a", "1"), ("e", "6")]);
+
+        let (op, polls) = block_on(diff_async(&old, &new).yield_every(3));
+        assert_eq!(op, diff(&old, &new));
+        assert!(polls > 1);
+
+        let (op, polls) = block_on(diff_async(&old, &new));
+        assert_eq!(op, diff(&old, &new));
+        assert_eq!(polls, 1);
+    }
+
+    #[test]
+    fn root_replace() {
+        let old = div().text("a").done();
+        let new = p().text("a").done();
+
+        assert_eq!(
+            block_on(diff_async(&old, &new).yield_every(1)).0,
+            diff(&old, &new)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! # Attribute values
+//!
+//! Closed sets of values of common attributes, used by the typed helpers
+//! of `VElement` such as `type_` and `target`, and the boolean attributes
+//! of the HTML spec.
+//!
+//! ```
+//! use troy::attrs::{InputType, Target};
+//! use troy::tags::*;
+//!
+//! let field = input().id("email").name("email").type_(InputType::Email);
+//! let link = a().attr("href", "/help").target(Target::Blank);
+//!
+//! assert_eq!(field, input().attr("id", "email").attr("name", "email").attr("type", "email"));
+//! assert_eq!(link.get_attributes().get("target").unwrap(), "_blank");
+//! ```
+//!
+use element::AttrValue;
+
+/// Value of the `type` attribute of `input`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputType {
+    Button,
+    Checkbox,
+    Color,
+    Date,
+    DatetimeLocal,
+    Email,
+    File,
+    Hidden,
+    Image,
+    Month,
+    Number,
+    Password,
+    Radio,
+    Range,
+    Reset,
+    Search,
+    Submit,
+    Tel,
+    Text,
+    Time,
+    Url,
+    Week,
+}
+
+impl InputType {
+    pub fn as_str(self) -> &'static str {
+        use self::InputType::*;
+        match self {
+            Button => "button",
+            Checkbox => "checkbox",
+            Color => "color",
+            Date => "date",
+            DatetimeLocal => "datetime-local",
+            Email => "email",
+            File => "file",
+            Hidden => "hidden",
+            Image => "image",
+            Month => "month",
+            Number => "number",
+            Password => "password",
+            Radio => "radio",
+            Range => "range",
+            Reset => "reset",
+            Search => "search",
+            Submit => "submit",
+            Tel => "tel",
+            Text => "text",
+            Time => "time",
+            Url => "url",
+            Week => "week",
+        }
+    }
+}
+
+impl From<InputType> for AttrValue {
+    fn from(value: InputType) -> Self {
+        value.as_str().into()
+    }
+}
+
+/// Value of the `target` attribute of links and forms.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Blank,
+    Self_,
+    Parent,
+    Top,
+}
+
+impl Target {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Target::Blank => "_blank",
+            Target::Self_ => "_self",
+            Target::Parent => "_parent",
+            Target::Top => "_top",
+        }
+    }
+}
+
+impl From<Target> for AttrValue {
+    fn from(value: Target) -> Self {
+        value.as_str().into()
+    }
+}
+
+/// Boolean attributes of the HTML spec, which are on whenever present.
+///
+pub const BOOLEAN_ATTRIBUTES: &[&str] = &[
+    "allowfullscreen",
+    "async",
+    "autofocus",
+    "autoplay",
+    "checked",
+    "controls",
+    "default",
+    "defer",
+    "disabled",
+    "formnovalidate",
+    "hidden",
+    "inert",
+    "ismap",
+    "itemscope",
+    "loop",
+    "multiple",
+    "muted",
+    "nomodule",
+    "novalidate",
+    "open",
+    "playsinline",
+    "readonly",
+    "required",
+    "reversed",
+    "selected",
+];
+
+pub fn is_boolean_attribute(name: &str) -> bool {
+    BOOLEAN_ATTRIBUTES.contains(&name)
+}
+
+/// Check if the value is one of the spec's forms of a boolean attribute
+/// being on: empty, as in `checked` or `checked=""`, or the attribute's
+/// own name in any case, as in `checked="checked"`. Other values turn the
+/// attribute on too, but are kept as they are.
+///
+pub fn is_canonical_boolean(name: &str, value: &str) -> bool {
+    is_boolean_attribute(name) && (value.is_empty() || value.eq_ignore_ascii_case(name))
+}
+
+/// Check if two values of an attribute mean the same.
+///
+pub fn same_value(name: &str, old: &str, new: &str) -> bool {
+    old == new || (is_canonical_boolean(name, old) && is_canonical_boolean(name, new))
+}
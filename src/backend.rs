@@ -0,0 +1,340 @@
+//! # Backends
+//!
+//! A backend is a target the diff is applied to, such as a browser DOM, a
+//! terminal or a widget toolkit. It implements `DomBackend`, a few primitive
+//! operations on its own node handles, and `Mounted` drives it: it mounts a
+//! tree once, then turns each diff into backend operations.
+//!
+//! ```
+//! use troy::backend::{DomBackend, Mounted};
+//! use troy::diff::{diff, TextOp};
+//! use troy::tags::*;
+//!
+//! /// Backend which logs operations, with nodes numbered in creation order.
+//! #[derive(Default)]
+//! struct Log(usize, Vec<String>);
+//!
+//! impl DomBackend for Log {
+//!     type Node = usize;
+//!
+//!     fn create_element(&mut self, tag: &str) -> usize {
+//!         self.0 += 1;
+//!         self.1.push(format!("create {} <{}>", self.0, tag));
+//!         self.0
+//!     }
+//!
+//!     fn create_text(&mut self, content: &str) -> usize {
+//!         self.0 += 1;
+//!         self.1.push(format!("create {} {:?}", self.0, content));
+//!         self.0
+//!     }
+//!
+//!     fn set_attribute(&mut self, _: &usize, _: &str, _: &str) {}
+//!     fn remove_attribute(&mut self, _: &usize, _: &str) {}
+//!     fn add_class(&mut self, _: &usize, _: &str) {}
+//!     fn remove_class(&mut self, _: &usize, _: &str) {}
+//!     fn add_token(&mut self, _: &usize, _: &str, _: &str) {}
+//!     fn remove_token(&mut self, _: &usize, _: &str, _: &str) {}
+//!     fn update_text(&mut self, _: &usize, _: &TextOp) {}
+//!
+//!     fn set_children(&mut self, parent: &usize, children: &[usize]) {
+//!         self.1.push(format!("children of {}: {:?}", parent, children));
+//!     }
+//!
+//!     fn remove_node(&mut self, node: &usize) {
+//!         self.1.push(format!("remove {}", node));
+//!     }
+//! }
+//!
+//! let old = ul().child(li().key("a")).child(li().key("b")).done();
+//! let new = ul().child(li().key("b")).done();
+//!
+//! let mut backend = Log::default();
+//! let mut mounted = Mounted::mount(&mut backend, &old);
+//! mounted.patch(&mut backend, &diff(&old, &new)).unwrap();
+//!
+//! assert_eq!(&backend.1[4..], ["children of 1: [3]", "remove 2"]);
+//! ```
+//!
+use diff::{AttrDiff, AttrOp, ChildDiff, ChildInserts, NodeOp, TextOp};
+use error::Error;
+use node::VNode;
+use patch::Stamp;
+
+pub trait DomBackend {
+    /// Handle of a node owned by the backend.
+    ///
+    type Node: Clone;
+
+    fn create_element(&mut self, tag: &str) -> Self::Node;
+    fn create_text(&mut self, content: &str) -> Self::Node;
+    fn set_attribute(&mut self, node: &Self::Node, name: &str, value: &str);
+    fn remove_attribute(&mut self, node: &Self::Node, name: &str);
+    fn add_class(&mut self, node: &Self::Node, name: &str);
+    fn remove_class(&mut self, node: &Self::Node, name: &str);
+
+    /// Add a token to a token-list attribute, like `DOMTokenList::add`.
+    ///
+    fn add_token(&mut self, node: &Self::Node, name: &str, token: &str);
+
+    /// Remove a token from a token-list attribute, like
+    /// `DOMTokenList::remove`.
+    ///
+    fn remove_token(&mut self, node: &Self::Node, name: &str, token: &str);
+
+    /// Change the content of a text node, see `TextOp::apply`.
+    ///
+    fn update_text(&mut self, node: &Self::Node, op: &TextOp);
+
+    /// Make the nodes children of the element, in this order. Nodes in the
+    /// list may be new, or current children of the element at other
+    /// positions.
+    ///
+    fn set_children(&mut self, parent: &Self::Node, children: &[Self::Node]);
+
+    /// Node and its subtree are no longer used. Called after the node was
+    /// removed from its parent's children, or for a replaced root.
+    ///
+    fn remove_node(&mut self, node: &Self::Node);
+}
+
+/// Tree of backend nodes mirroring the last applied tree.
+///
+#[derive(Debug)]
+pub struct Mounted<N> {
+    node: N,
+    children: Vec<Mounted<N>>,
+    generation: u64,
+}
+
+impl<N> Mounted<N>
+where
+    N: Clone,
+{
+    /// Create backend nodes for the tree.
+    ///
+    pub fn mount<B>(backend: &mut B, node: &VNode) -> Self
+    where
+        B: DomBackend<Node = N>,
+    {
+        match node.resolve() {
+            VNode::Element(element) => {
+                let handle = backend.create_element(element.get_tag());
+                let mut classes: Vec<&str> =
+                    element.get_classes().iter().map(|c| c.as_ref()).collect();
+                classes.sort();
+                for class in classes {
+                    backend.add_class(&handle, class);
+                }
+                let mut attributes: Vec<_> = element.get_attributes().iter().collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    backend.set_attribute(&handle, name, value);
+                }
+
+                let children: Vec<Mounted<N>> = element
+                    .get_children()
+                    .iter()
+                    .map(|child| Mounted::mount(backend, child))
+                    .collect();
+                if !children.is_empty() {
+                    let handles: Vec<N> = children.iter().map(|c| c.node.clone()).collect();
+                    backend.set_children(&handle, &handles);
+                }
+                Mounted {
+                    node: handle,
+                    children,
+                    generation: 0,
+                }
+            }
+            VNode::Text(text) => Mounted {
+                node: backend.create_text(text.get_content()),
+                children: Vec::new(),
+                generation: 0,
+            },
+            _ => unreachable!("resolved node is an element or text"),
+        }
+    }
+
+    /// Backend node of the root, which changes when the root is replaced.
+    ///
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+
+    pub fn children(&self) -> &[Mounted<N>] {
+        &self.children
+    }
+
+    /// Generation of the last tree applied with `patch_stamped`, 0 before
+    /// the first one.
+    ///
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Apply the diff if it was diffed against the last applied tree, see
+    /// `patch::Stamp`. Stale patches are rejected without touching the
+    /// backend.
+    ///
+    pub fn patch_stamped<B>(
+        &mut self,
+        backend: &mut B,
+        stamp: Stamp,
+        op: &NodeOp,
+    ) -> Result<(), Error>
+    where
+        B: DomBackend<Node = N>,
+    {
+        stamp.check(self.generation)?;
+        self.patch(backend, op)?;
+        self.generation = stamp.generation;
+        Ok(())
+    }
+
+    /// Apply the diff of the last applied tree to the backend.
+    ///
+    /// Returns an error if the diff doesn't fit the mounted tree, in which
+    /// case the backend may be partially updated.
+    ///
+    pub fn patch<B>(&mut self, backend: &mut B, op: &NodeOp) -> Result<(), Error>
+    where
+        B: DomBackend<Node = N>,
+    {
+        match op {
+            NodeOp::Skip(1) => Ok(()),
+            NodeOp::Replace(node) => {
+                let generation = self.generation;
+                let old = ::std::mem::replace(self, Mounted::mount(backend, node));
+                self.generation = generation;
+                backend.remove_node(&old.node);
+                Ok(())
+            }
+            NodeOp::Update(attrs, children, inserts) => {
+                self.update(backend, attrs, children, inserts)
+            }
+            NodeOp::Text(op) => {
+                backend.update_text(&self.node, op);
+                Ok(())
+            }
+            op => Err(Error::InvalidPatch(format!(
+                "unexpected root operation {:?}",
+                op
+            ))),
+        }
+    }
+
+    fn update<B>(
+        &mut self,
+        backend: &mut B,
+        attrs: &AttrDiff,
+        children: &ChildDiff,
+        inserts: &ChildInserts,
+    ) -> Result<(), Error>
+    where
+        B: DomBackend<Node = N>,
+    {
+        for attr in attrs.iter().flatten() {
+            match attr {
+                AttrOp::InsertClass(name) => backend.add_class(&self.node, name),
+                AttrOp::RemoveClass(name) => backend.remove_class(&self.node, name),
+                AttrOp::Insert(name, value) | AttrOp::Update(name, value) => {
+                    backend.set_attribute(&self.node, name, value)
+                }
+                AttrOp::Remove(name) => backend.remove_attribute(&self.node, name),
+                AttrOp::InsertToken(name, token) => backend.add_token(&self.node, name, token),
+                AttrOp::RemoveToken(name, token) => backend.remove_token(&self.node, name, token),
+            }
+        }
+
+        if children.is_none() && inserts.is_none() {
+            return Ok(());
+        }
+
+        // Children which stay in place, in order, and children placed at
+        // explicit positions by Move or Insert.
+        let mut staying = Vec::new();
+        let mut placed = Vec::new();
+        let mut removed = Vec::new();
+
+        let mut old_children = ::std::mem::take(&mut self.children).into_iter();
+        let mut next_child = |index: usize| {
+            old_children
+                .next()
+                .ok_or_else(|| Error::InvalidPatch(format!("operation on missing child {}", index)))
+        };
+        let mut index = 0;
+
+        for op in children.iter().flatten() {
+            match op {
+                NodeOp::Skip(count) => {
+                    for _ in 0..*count {
+                        staying.push(next_child(index)?);
+                        index += 1;
+                    }
+                }
+                NodeOp::Remove(count) => {
+                    for _ in 0..*count {
+                        removed.push(next_child(index)?);
+                        index += 1;
+                    }
+                }
+                NodeOp::Move(position, attrs, children, inserts) => {
+                    let mut child = next_child(index)?;
+                    child.update(backend, attrs, children, inserts)?;
+                    placed.push((*position, child));
+                    index += 1;
+                }
+                NodeOp::Replace(node) => {
+                    removed.push(next_child(index)?);
+                    staying.push(Mounted::mount(backend, node));
+                    index += 1;
+                }
+                NodeOp::Update(attrs, children, inserts) => {
+                    let mut child = next_child(index)?;
+                    child.update(backend, attrs, children, inserts)?;
+                    staying.push(child);
+                    index += 1;
+                }
+                NodeOp::Text(op) => {
+                    let child = next_child(index)?;
+                    backend.update_text(&child.node, op);
+                    staying.push(child);
+                    index += 1;
+                }
+            }
+        }
+        staying.extend(old_children);
+        for (position, node) in inserts.iter().flatten() {
+            placed.push((*position, Mounted::mount(backend, node)));
+        }
+
+        let new_len = staying.len() + placed.len();
+        let mut slots: Vec<Option<Mounted<N>>> = (0..new_len).map(|_| None).collect();
+        for (position, child) in placed {
+            match slots.get_mut(position) {
+                Some(slot @ None) => *slot = Some(child),
+                Some(Some(_)) => {
+                    let message = format!("two children placed at {}", position);
+                    return Err(Error::InvalidPatch(message));
+                }
+                None => {
+                    let message = format!("position {} out of {} children", position, new_len);
+                    return Err(Error::InvalidPatch(message));
+                }
+            }
+        }
+        let mut staying = staying.into_iter();
+        self.children = slots
+            .into_iter()
+            .map(|slot| slot.or_else(|| staying.next()).unwrap())
+            .collect();
+
+        let handles: Vec<N> = self.children.iter().map(|c| c.node.clone()).collect();
+        backend.set_children(&self.node, &handles);
+        for child in &removed {
+            backend.remove_node(&child.node);
+        }
+        Ok(())
+    }
+}
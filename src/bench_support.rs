@@ -0,0 +1,218 @@
+//! # Benchmark workloads
+//!
+//! Generated trees of a given shape, and changed copies of them, for
+//! benchmarking the differ and patchers on realistic workloads instead of a
+//! single hand-written tree. Generation is deterministic: the same shape
+//! and seed produce the same tree.
+//!
+//! ```
+//! use troy::bench_support::{churn, generate, TreeShape};
+//! use troy::diff::diff;
+//!
+//! let shape = TreeShape {
+//!     width: 10,
+//!     depth: 2,
+//!     ..TreeShape::default()
+//! };
+//! let old = generate(&shape);
+//! let new = churn(&old, 10.0, 1);
+//!
+//! let op = diff(&old, &new);
+//! ```
+//!
+use element::VElement;
+use node::VNode;
+use rng::XorShift;
+use text::VText;
+
+/// Keys of inserted children start here, above keys of generated trees.
+///
+const CHURN_KEYS: u64 = 1 << 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeShape {
+    /// Children of each element above the leaves.
+    pub width: usize,
+    /// Levels of elements below the root.
+    pub depth: usize,
+    /// Share of children lists which are keyed, from 0 to 1. Children of
+    /// other lists have no keys.
+    pub keyed_ratio: f64,
+    /// Seed for the pseudo-random generator.
+    pub seed: u64,
+}
+
+impl Default for TreeShape {
+    fn default() -> Self {
+        TreeShape {
+            width: 10,
+            depth: 3,
+            keyed_ratio: 1.0,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// Generate a tree of the shape: `div` elements down to `depth`, with
+/// `span` leaves holding a text.
+///
+pub fn generate(shape: &TreeShape) -> VNode {
+    let mut generator = Generator {
+        rng: XorShift::new(shape.seed),
+        next_key: 0,
+    };
+    generator.element(shape, 0, None)
+}
+
+/// Copy of a tree with about `percent` percent of children changed: their
+/// content changed, removed, moved or preceded by a new child. Lists
+/// change the same way whether they are keyed or not.
+///
+pub fn churn(tree: &VNode, percent: f64, seed: u64) -> VNode {
+    let mut generator = Generator {
+        rng: XorShift::new(seed),
+        next_key: CHURN_KEYS,
+    };
+    let mut tree = tree.resolve().clone();
+    generator.churn(&mut tree, percent / 100.0);
+    tree
+}
+
+/// True with the given probability.
+///
+fn chance(rng: &mut XorShift, probability: f64) -> bool {
+    (rng.next() >> 11) as f64 / ((1u64 << 53) as f64) < probability
+}
+
+struct Generator {
+    rng: XorShift,
+    next_key: u64,
+}
+
+impl Generator {
+    fn key(&mut self) -> u64 {
+        self.next_key += 1;
+        self.next_key
+    }
+
+    fn element(&mut self, shape: &TreeShape, level: usize, key: Option<u64>) -> VNode {
+        if level == shape.depth {
+            return self.leaf(key);
+        }
+
+        let mut element = VElement::new("div").class(format!("level-{}", level));
+        if let Some(key) = key {
+            element = element.key(key);
+        }
+        let keyed = chance(&mut self.rng, shape.keyed_ratio);
+        for _ in 0..shape.width {
+            let key = if keyed { Some(self.key()) } else { None };
+            element = element.child_node(self.element(shape, level + 1, key));
+        }
+        element.done()
+    }
+
+    fn leaf(&mut self, key: Option<u64>) -> VNode {
+        let mut leaf = VElement::new("span").class("leaf");
+        if let Some(key) = key {
+            leaf = leaf.key(key);
+        }
+        leaf.text(format!("item {}", self.rng.below(1000))).done()
+    }
+
+    fn churn(&mut self, node: &mut VNode, probability: f64) {
+        let element = match node.as_element_mut() {
+            Some(element) => element,
+            None => return,
+        };
+        let keyed = element
+            .get_children()
+            .iter()
+            .any(|child| child.key().is_some());
+        let mut children = Vec::with_capacity(element.get_children().len());
+
+        for mut child in element.get_children_mut().drain(..) {
+            if chance(&mut self.rng, probability) {
+                let key = if keyed { Some(self.key()) } else { None };
+                children.push(self.leaf(key));
+            }
+            if chance(&mut self.rng, probability) {
+                continue;
+            }
+            if chance(&mut self.rng, probability) {
+                self.change(&mut child);
+            }
+            self.churn(&mut child, probability);
+            children.push(child);
+        }
+        for index in 0..children.len() {
+            if chance(&mut self.rng, probability) {
+                let other = self.rng.below(children.len());
+                children.swap(index, other);
+            }
+        }
+
+        *element.get_children_mut() = children;
+    }
+
+    /// Change the text of a leaf, or a class of other elements.
+    ///
+    fn change(&mut self, node: &mut VNode) {
+        if let Some(element) = node.as_element_mut() {
+            if element.get_tag() == "span" {
+                let text = format!("changed {}", self.rng.below(1000));
+                *element.get_children_mut() = vec![VNode::Text(VText::new(text))];
+            } else {
+                element.add_class("changed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(node: &VNode) -> usize {
+        match node {
+            VNode::Element(element) => 1 + element.get_children().iter().map(count).sum::<usize>(),
+            _ => 1,
+        }
+    }
+
+    #[test]
+    fn generated_shape() {
+        let shape = TreeShape {
+            width: 3,
+            depth: 2,
+            keyed_ratio: 0.0,
+            ..TreeShape::default()
+        };
+        let tree = generate(&shape);
+
+        // Root, 3 divs, 9 spans and 9 texts.
+        assert_eq!(count(&tree), 22);
+        assert_eq!(generate(&shape), tree);
+        assert_eq!(tree.resolve().key(), None);
+
+        let keyed = generate(&TreeShape {
+            keyed_ratio: 1.0,
+            ..shape
+        });
+        if let VNode::Element(element) = &keyed {
+            assert!(element
+                .get_children()
+                .iter()
+                .all(|child| child.key().is_some()));
+        }
+    }
+
+    #[test]
+    fn churned_copies() {
+        let tree = generate(&TreeShape::default());
+
+        assert_eq!(churn(&tree, 0.0, 1), tree);
+        assert_ne!(churn(&tree, 10.0, 1), tree);
+        assert_eq!(churn(&tree, 10.0, 1), churn(&tree, 10.0, 1));
+    }
+}
@@ -0,0 +1,937 @@
+//! # Binary encoding
+//!
+//! Compact encoding of trees and patches for sending them between threads,
+//! workers or processes. Components and shared nodes are encoded as the
+//! nodes they stand for, so decoded trees contain only elements and text.
+//!
+//! ```
+//! use troy::binary::{decode_node, decode_op, encode_node, encode_op};
+//! use troy::diff::diff;
+//! use troy::tags::*;
+//!
+//! let old = div().child(p().text("a")).done();
+//! let new = div().child(p().class("b").text("b")).done();
+//!
+//! assert_eq!(decode_node(&encode_node(&new)), Ok(div().child(p().class("b").text("b")).done()));
+//!
+//! let bytes = encode_op(&diff(&old, &new));
+//! let patch = decode_op(&bytes).unwrap();
+//! assert_eq!(patch.as_op(), diff(&old, &new));
+//! ```
+//!
+//! ## Format
+//!
+//! Every message starts with the format version byte. Numbers are LEB128
+//! varints, strings are a length followed by UTF-8 bytes, optional values
+//! and variants are prefixed with a tag byte. Classes and attributes are
+//! sorted, so equal trees have equal encodings.
+//!
+//! ## Sessions
+//!
+//! `SessionEncoder` and `SessionDecoder` encode the messages of a
+//! connection with a dictionary of tag, class and attribute names shared by
+//! both ends. Session messages start with `SESSION_FORMAT_VERSION`, and a
+//! name is zero followed by the string the first time it's sent, or its
+//! index in the dictionary plus one afterwards. Messages are not compressed
+//! further. There is no deflate framing in the format yet, so until then a
+//! transport which compresses, like the websocket permessage-deflate
+//! extension or HTTP content encoding, is the way to deflate them.
+//!
+use diff::{AttrDiff, AttrOp, ChildDiff, ChildInserts, NodeOp, TextOp};
+use element::VElement;
+use key::{scoped_key, Key};
+use node::VNode;
+use patch::{OwnedChildDiff, OwnedChildInserts, OwnedOp};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::str;
+use text::text;
+
+/// Version of the format, first byte of every message.
+///
+pub const FORMAT_VERSION: u8 = 1;
+
+/// First byte of session messages, see `SessionEncoder`.
+///
+pub const SESSION_FORMAT_VERSION: u8 = FORMAT_VERSION | 0x80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// Message ended in the middle of a value.
+    UnexpectedEnd,
+    /// Message has bytes after the encoded value.
+    TrailingBytes,
+    /// Message was encoded with another version of the format.
+    UnsupportedVersion(u8),
+    /// Unknown variant tag.
+    InvalidTag(u8),
+    /// Number doesn't fit into usize.
+    InvalidNumber,
+    InvalidUtf8,
+    /// Session message refers to a name which wasn't sent in the session.
+    UnknownName(usize),
+    /// Tree is nested deeper than `DecodeOptions::max_depth`.
+    TooDeep(usize),
+    /// Message has more nodes than `DecodeOptions::max_nodes`.
+    TooManyNodes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::DecodeError::*;
+
+        match self {
+            UnexpectedEnd => write!(f, "unexpected end of message"),
+            TrailingBytes => write!(f, "trailing bytes after message"),
+            UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            InvalidTag(tag) => write!(f, "invalid tag {}", tag),
+            InvalidNumber => write!(f, "number out of range"),
+            InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            UnknownName(index) => write!(f, "unknown name {} in session message", index),
+            TooDeep(limit) => write!(f, "tree is nested deeper than {}", limit),
+            TooManyNodes(limit) => write!(f, "message has more than {} nodes", limit),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// Limits on decoded messages, for messages from untrusted sources. Without
+/// them a deeply nested tree overflows the stack while it's decoded.
+///
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// Fail on trees or patches nested deeper than this, counting the root
+    /// as 1. Zero, the default, doesn't limit.
+    pub max_depth: usize,
+    /// Fail on messages with more nodes than this. Zero, the default,
+    /// doesn't limit.
+    pub max_nodes: usize,
+}
+
+pub fn encode_node(node: &VNode) -> Vec<u8> {
+    let mut writer = Writer(vec![FORMAT_VERSION], None);
+    writer.node(node);
+    writer.0
+}
+
+/// Decode a tree without limits, see `decode_node_with` for trees from
+/// untrusted sources.
+///
+pub fn decode_node(bytes: &[u8]) -> Result<VNode, DecodeError> {
+    decode_node_with(bytes, &DecodeOptions::default())
+}
+
+pub fn decode_node_with(bytes: &[u8], options: &DecodeOptions) -> Result<VNode, DecodeError> {
+    decode(bytes, options, |reader| reader.node())
+}
+
+pub fn encode_op(op: &NodeOp) -> Vec<u8> {
+    let mut writer = Writer(vec![FORMAT_VERSION], None);
+    writer.op(op);
+    writer.0
+}
+
+pub fn decode_op(bytes: &[u8]) -> Result<OwnedOp, DecodeError> {
+    decode_op_with(bytes, &DecodeOptions::default())
+}
+
+pub fn decode_op_with(bytes: &[u8], options: &DecodeOptions) -> Result<OwnedOp, DecodeError> {
+    decode(bytes, options, |reader| reader.op())
+}
+
+/// Encode a generation counter and the tree of that generation, if any,
+/// see `VDom::snapshot`.
+///
+pub fn encode_snapshot(generation: u64, tree: Option<&VNode>) -> Vec<u8> {
+    let mut writer = Writer(vec![FORMAT_VERSION], None);
+    writer.number64(generation);
+    match tree {
+        Some(tree) => {
+            writer.0.push(1);
+            writer.node(tree);
+        }
+        None => writer.0.push(0),
+    }
+    writer.0
+}
+
+pub fn decode_snapshot(bytes: &[u8]) -> Result<(u64, Option<VNode>), DecodeError> {
+    decode_snapshot_with(bytes, &DecodeOptions::default())
+}
+
+pub fn decode_snapshot_with(
+    bytes: &[u8],
+    options: &DecodeOptions,
+) -> Result<(u64, Option<VNode>), DecodeError> {
+    decode(bytes, options, |reader| {
+        let generation = reader.number64()?;
+        let tree = match reader.byte()? {
+            0 => None,
+            1 => Some(reader.node()?),
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+        Ok((generation, tree))
+    })
+}
+
+/// Encode a named patch, see `patch::Patch`.
+///
+pub fn encode_patch(name: &str, op: &NodeOp) -> Vec<u8> {
+    let mut writer = Writer(vec![FORMAT_VERSION], None);
+    writer.string(name);
+    writer.op(op);
+    writer.0
+}
+
+pub fn decode_patch(bytes: &[u8]) -> Result<(String, OwnedOp), DecodeError> {
+    decode(bytes, &DecodeOptions::default(), |reader| {
+        Ok((reader.string()?, reader.op()?))
+    })
+}
+
+/// Encoder of the patches of one session, which sends every tag, class and
+/// attribute name once. Later messages refer to the names by index, so a
+/// long lived connection with frequent updates sends little more than the
+/// values that changed.
+///
+/// Messages must be decoded in order by a `SessionDecoder` which saw every
+/// earlier message of the session. A client which lost a message, or failed
+/// to decode one, starts over with a new pair.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SessionEncoder {
+    names: HashMap<String, usize>,
+}
+
+impl SessionEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn encode_node(&mut self, node: &VNode) -> Vec<u8> {
+        let mut writer = Writer(vec![SESSION_FORMAT_VERSION], Some(&mut self.names));
+        writer.node(node);
+        writer.0
+    }
+
+    pub fn encode_op(&mut self, op: &NodeOp) -> Vec<u8> {
+        let mut writer = Writer(vec![SESSION_FORMAT_VERSION], Some(&mut self.names));
+        writer.op(op);
+        writer.0
+    }
+
+    /// Number of names sent in the session.
+    ///
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Decoder of the messages of a `SessionEncoder`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SessionDecoder {
+    names: Vec<String>,
+    options: DecodeOptions,
+}
+
+impl SessionDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decoder which checks every message against the limits.
+    ///
+    pub fn with_options(options: DecodeOptions) -> Self {
+        SessionDecoder {
+            names: Vec::new(),
+            options,
+        }
+    }
+
+    pub fn decode_node(&mut self, bytes: &[u8]) -> Result<VNode, DecodeError> {
+        decode_with(
+            bytes,
+            SESSION_FORMAT_VERSION,
+            Some(&mut self.names),
+            &self.options,
+            |reader| reader.node(),
+        )
+    }
+
+    pub fn decode_op(&mut self, bytes: &[u8]) -> Result<OwnedOp, DecodeError> {
+        decode_with(
+            bytes,
+            SESSION_FORMAT_VERSION,
+            Some(&mut self.names),
+            &self.options,
+            |reader| reader.op(),
+        )
+    }
+}
+
+fn decode<T, F>(bytes: &[u8], options: &DecodeOptions, read: F) -> Result<T, DecodeError>
+where
+    F: FnOnce(&mut Reader) -> Result<T, DecodeError>,
+{
+    decode_with(bytes, FORMAT_VERSION, None, options, read)
+}
+
+fn decode_with<T, F>(
+    bytes: &[u8],
+    format: u8,
+    names: Option<&mut Vec<String>>,
+    options: &DecodeOptions,
+    read: F,
+) -> Result<T, DecodeError>
+where
+    F: FnOnce(&mut Reader) -> Result<T, DecodeError>,
+{
+    let mut reader = Reader {
+        bytes,
+        names,
+        options: options.clone(),
+        depth: 0,
+        nodes: 0,
+    };
+    match reader.byte()? {
+        version if version == format => {}
+        version => return Err(DecodeError::UnsupportedVersion(version)),
+    }
+    let value = read(&mut reader)?;
+    if !reader.bytes.is_empty() {
+        return Err(DecodeError::TrailingBytes);
+    }
+    Ok(value)
+}
+
+//
+// # Writer
+//
+
+/// Writes messages, with names looked up in the dictionary of a session
+/// if there is one.
+///
+struct Writer<'a>(Vec<u8>, Option<&'a mut HashMap<String, usize>>);
+
+impl<'a> Writer<'a> {
+    fn number(&mut self, value: usize) {
+        self.number64(value as u64);
+    }
+
+    fn number64(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, value: &str) {
+        self.number(value.len());
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    /// Tag, class or attribute name. In a session, a name is written as its
+    /// index in the dictionary plus one, or zero and the name the first
+    /// time.
+    ///
+    fn name(&mut self, value: &str) {
+        let names = match &mut self.1 {
+            Some(names) => names,
+            None => return self.string(value),
+        };
+        match names.get(value) {
+            Some(&index) => self.number(index + 1),
+            None => {
+                let index = names.len();
+                names.insert(value.to_string(), index);
+                self.0.push(0);
+                self.string(value);
+            }
+        }
+    }
+
+    fn key(&mut self, key: &Key) {
+        match key {
+            Key::Str(key) => {
+                self.0.push(1);
+                self.string(key);
+            }
+            Key::U64(key) => {
+                self.0.push(2);
+                self.number64(*key);
+            }
+            Key::I64(key) => {
+                // Zigzag encoding keeps small negative keys short.
+                self.0.push(3);
+                self.number64(((key << 1) ^ (key >> 63)) as u64);
+            }
+            Key::Scoped(scope, key) => {
+                self.0.push(4);
+                self.string(scope);
+                self.key(key);
+            }
+        }
+    }
+
+    fn node(&mut self, node: &VNode) {
+        match node.resolve() {
+            VNode::Element(element) => {
+                self.0.push(0);
+                self.name(element.get_tag());
+                self.0.push(element.is_void() as u8);
+                match element.get_key() {
+                    None => self.0.push(0),
+                    Some(key) => self.key(key),
+                }
+
+                let mut classes: Vec<&str> =
+                    element.get_classes().iter().map(|c| c.as_ref()).collect();
+                classes.sort();
+                self.number(classes.len());
+                for class in classes {
+                    self.name(class);
+                }
+
+                let mut attributes: Vec<(&str, &str)> = element
+                    .get_attributes()
+                    .iter()
+                    .map(|(name, value)| (name.as_ref(), value.as_ref()))
+                    .collect();
+                attributes.sort();
+                self.number(attributes.len());
+                for (name, value) in attributes {
+                    self.name(name);
+                    self.string(value);
+                }
+
+                self.number(element.get_children().len());
+                for child in element.get_children() {
+                    self.node(child);
+                }
+            }
+            VNode::Text(content) => {
+                self.0.push(1);
+                self.string(content.get_content());
+            }
+            _ => unreachable!("resolved node is an element or text"),
+        }
+    }
+
+    fn attrs(&mut self, attrs: &AttrDiff) {
+        let attrs = match attrs {
+            Some(attrs) => attrs,
+            None => return self.0.push(0),
+        };
+        self.0.push(1);
+        self.number(attrs.len());
+        for attr in attrs {
+            match attr {
+                AttrOp::InsertClass(name) => {
+                    self.0.push(0);
+                    self.name(name);
+                }
+                AttrOp::RemoveClass(name) => {
+                    self.0.push(1);
+                    self.name(name);
+                }
+                AttrOp::Insert(name, value) => {
+                    self.0.push(2);
+                    self.name(name);
+                    self.string(value);
+                }
+                AttrOp::Update(name, value) => {
+                    self.0.push(3);
+                    self.name(name);
+                    self.string(value);
+                }
+                AttrOp::Remove(name) => {
+                    self.0.push(4);
+                    self.name(name);
+                }
+                AttrOp::InsertToken(name, token) => {
+                    self.0.push(5);
+                    self.name(name);
+                    self.string(token);
+                }
+                AttrOp::RemoveToken(name, token) => {
+                    self.0.push(6);
+                    self.name(name);
+                    self.string(token);
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, attrs: &AttrDiff, children: &ChildDiff, inserts: &ChildInserts) {
+        self.attrs(attrs);
+        match children {
+            Some(children) => {
+                self.0.push(1);
+                self.number(children.len());
+                for child in children {
+                    self.op(child);
+                }
+            }
+            None => self.0.push(0),
+        }
+        match inserts {
+            Some(inserts) => {
+                self.0.push(1);
+                self.number(inserts.len());
+                for (position, node) in inserts {
+                    self.number(*position);
+                    self.node(node);
+                }
+            }
+            None => self.0.push(0),
+        }
+    }
+
+    fn op(&mut self, op: &NodeOp) {
+        match op {
+            NodeOp::Skip(count) => {
+                self.0.push(0);
+                self.number(*count);
+            }
+            NodeOp::Remove(count) => {
+                self.0.push(1);
+                self.number(*count);
+            }
+            NodeOp::Move(position, attrs, children, inserts) => {
+                self.0.push(2);
+                self.number(*position);
+                self.update(attrs, children, inserts);
+            }
+            NodeOp::Update(attrs, children, inserts) => {
+                self.0.push(3);
+                self.update(attrs, children, inserts);
+            }
+            NodeOp::Replace(node) => {
+                self.0.push(4);
+                self.node(node);
+            }
+            NodeOp::Text(op) => {
+                self.0.push(5);
+                self.text_op(op);
+            }
+        }
+    }
+
+    fn text_op(&mut self, op: &TextOp) {
+        match op {
+            TextOp::Set(content) => {
+                self.0.push(1);
+                self.string(content);
+            }
+            TextOp::Splice { at, remove, insert } => {
+                self.0.push(0);
+                self.number(*at);
+                self.number(*remove);
+                self.string(insert);
+            }
+        }
+    }
+}
+
+//
+// # Reader
+//
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    names: Option<&'a mut Vec<String>>,
+    options: DecodeOptions,
+    depth: usize,
+    nodes: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn flag(&mut self) -> Result<bool, DecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, DecodeError> {
+        let value = self.number64()?;
+        if value > usize::MAX as u64 {
+            return Err(DecodeError::InvalidNumber);
+        }
+        Ok(value as usize)
+    }
+
+    fn number64(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= u64::BITS {
+                return Err(DecodeError::InvalidNumber);
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.number()?;
+        if len > self.bytes.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn name(&mut self) -> Result<String, DecodeError> {
+        if self.names.is_none() {
+            return self.string();
+        }
+        match self.number()? {
+            0 => {
+                let name = self.string()?;
+                if let Some(names) = &mut self.names {
+                    names.push(name.clone());
+                }
+                Ok(name)
+            }
+            index => self
+                .names
+                .as_ref()
+                .and_then(|names| names.get(index - 1))
+                .cloned()
+                .ok_or(DecodeError::UnknownName(index)),
+        }
+    }
+
+    /// Key after its tag byte.
+    ///
+    fn key(&mut self, tag: u8) -> Result<Key, DecodeError> {
+        Ok(match tag {
+            1 => Key::from(self.string()?),
+            2 => Key::U64(self.number64()?),
+            3 => {
+                let zigzag = self.number64()?;
+                Key::I64((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+            }
+            4 => {
+                let scope = self.string()?;
+                let tag = self.byte()?;
+                scoped_key(scope, self.key(tag)?)
+            }
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        })
+    }
+
+    /// Count a node, or an op nested in another, at one level deeper than
+    /// the current one.
+    ///
+    fn enter(&mut self, node: bool) -> Result<(), DecodeError> {
+        self.depth += 1;
+        if self.options.max_depth > 0 && self.depth > self.options.max_depth {
+            return Err(DecodeError::TooDeep(self.options.max_depth));
+        }
+        if node {
+            self.nodes += 1;
+            if self.options.max_nodes > 0 && self.nodes > self.options.max_nodes {
+                return Err(DecodeError::TooManyNodes(self.options.max_nodes));
+            }
+        }
+        Ok(())
+    }
+
+    fn node(&mut self) -> Result<VNode, DecodeError> {
+        self.enter(true)?;
+        let node = self.node_inner();
+        self.depth -= 1;
+        node
+    }
+
+    fn node_inner(&mut self) -> Result<VNode, DecodeError> {
+        match self.byte()? {
+            0 => {
+                let tag = self.name()?;
+                let mut element = if self.flag()? {
+                    VElement::new_void(tag)
+                } else {
+                    VElement::new(tag)
+                };
+                match self.byte()? {
+                    0 => {}
+                    tag => element = element.key(self.key(tag)?),
+                }
+                for _ in 0..self.number()? {
+                    element = element.class(self.name()?);
+                }
+                for _ in 0..self.number()? {
+                    element = element.attr(self.name()?, self.string()?);
+                }
+                for _ in 0..self.number()? {
+                    element = element.child_node(self.node()?);
+                }
+                Ok(element.done())
+            }
+            1 => Ok(text(self.string()?).done()),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+
+    fn attrs(&mut self) -> Result<AttrDiff, DecodeError> {
+        if !self.flag()? {
+            return Ok(None);
+        }
+        let len = self.number()?;
+        let mut attrs = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            attrs.push(match self.byte()? {
+                0 => AttrOp::InsertClass(self.name()?),
+                1 => AttrOp::RemoveClass(self.name()?),
+                2 => AttrOp::Insert(self.name()?, self.string()?),
+                3 => AttrOp::Update(self.name()?, self.string()?),
+                4 => AttrOp::Remove(self.name()?),
+                5 => AttrOp::InsertToken(self.name()?, self.string()?),
+                6 => AttrOp::RemoveToken(self.name()?, self.string()?),
+                tag => return Err(DecodeError::InvalidTag(tag)),
+            });
+        }
+        Ok(Some(attrs))
+    }
+
+    fn update(&mut self) -> Result<(AttrDiff, OwnedChildDiff, OwnedChildInserts), DecodeError> {
+        let attrs = self.attrs()?;
+        let children = if self.flag()? {
+            let len = self.number()?;
+            let mut children = Vec::with_capacity(len.min(self.bytes.len()));
+            for _ in 0..len {
+                children.push(self.op()?);
+            }
+            Some(children)
+        } else {
+            None
+        };
+        let inserts = if self.flag()? {
+            let len = self.number()?;
+            let mut inserts = Vec::with_capacity(len.min(self.bytes.len()));
+            for _ in 0..len {
+                inserts.push((self.number()?, self.node()?));
+            }
+            Some(inserts)
+        } else {
+            None
+        };
+        Ok((attrs, children, inserts))
+    }
+
+    fn op(&mut self) -> Result<OwnedOp, DecodeError> {
+        self.enter(false)?;
+        let op = self.op_inner();
+        self.depth -= 1;
+        op
+    }
+
+    fn op_inner(&mut self) -> Result<OwnedOp, DecodeError> {
+        match self.byte()? {
+            0 => Ok(OwnedOp::Skip(self.number()?)),
+            1 => Ok(OwnedOp::Remove(self.number()?)),
+            2 => {
+                let position = self.number()?;
+                let (attrs, children, inserts) = self.update()?;
+                Ok(OwnedOp::Move(position, attrs, children, inserts))
+            }
+            3 => {
+                let (attrs, children, inserts) = self.update()?;
+                Ok(OwnedOp::Update(attrs, children, inserts))
+            }
+            4 => Ok(OwnedOp::Replace(self.node()?)),
+            5 => Ok(OwnedOp::Text(self.text_op()?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+
+    fn text_op(&mut self) -> Result<TextOp, DecodeError> {
+        match self.byte()? {
+            0 => Ok(TextOp::Splice {
+                at: self.number()?,
+                remove: self.number()?,
+                insert: self.string()?,
+            }),
+            1 => Ok(TextOp::Set(self.string()?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    fn round_trip() {
+        #[rustfmt::skip]
+        let old = ul().class("list")
+            .child(li().key("1").attr("title", "one").text("Ash"))
+            .child(li().key("2").text("Bob"))
+            .done();
+        #[rustfmt::skip]
+        let new = ul().class("list").class("users")
+            .child(li().key("2").text("Bob"))
+            .child(li().key("3").child(br()).text("Cid \u{1f600}"))
+            .child(li().key("1").attr("title", "first").text("Ash"))
+            .done();
+
+        assert_eq!(decode_node(&encode_node(&new)).as_ref(), Ok(&new));
+
+        let op = diff(&old, &new);
+        assert_eq!(decode_op(&encode_op(&op)).unwrap().as_op(), op);
+
+        let op = NodeOp::Text(TextOp::splice("Ash", "Ashley"));
+        assert_eq!(decode_op(&encode_op(&op)).unwrap().as_op(), op);
+
+        let op = NodeOp::Text(TextOp::Set("Bob".to_string()));
+        assert_eq!(decode_op(&encode_op(&op)).unwrap().as_op(), op);
+    }
+
+    #[test]
+    fn numeric_keys() {
+        #[rustfmt::skip]
+        let tree = ul()
+            .child(li().key(u64::MAX))
+            .child(li().key(-1i64))
+            .child(li().key(i64::MIN))
+            .child(li().key("1"))
+            .child(li().key_in("users", 1u64))
+            .child(li().key_in("a", scoped_key("b", -1)))
+            .done();
+
+        assert_eq!(decode_node(&encode_node(&tree)), Ok(tree));
+    }
+
+    #[test]
+    fn session_messages() {
+        let mut encoder = SessionEncoder::new();
+        let mut decoder = SessionDecoder::new();
+        let old = ul().class("list").child(li().attr("title", "a")).done();
+        let new = ul().class("list").child(li().attr("title", "b")).done();
+
+        let first = encoder.encode_node(&old);
+        assert_eq!(decoder.decode_node(&first).as_ref(), Ok(&old));
+        assert_eq!(encoder.len(), 4);
+
+        let op = diff(&old, &new);
+        let bytes = encoder.encode_op(&op);
+        assert!(bytes.len() < encode_op(&op).len());
+        assert_eq!(decoder.decode_op(&bytes).unwrap().as_op(), op);
+
+        assert_eq!(
+            decode_op(&bytes),
+            Err(DecodeError::UnsupportedVersion(SESSION_FORMAT_VERSION))
+        );
+        assert_eq!(
+            SessionDecoder::new().decode_op(&bytes),
+            Err(DecodeError::UnknownName(4))
+        );
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let bytes = encode_node(&p().text("a").done());
+
+        assert_eq!(decode_node(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode_node(&[2]), Err(DecodeError::UnsupportedVersion(2)));
+        assert_eq!(
+            decode_node(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            decode_node(&[bytes.as_slice(), &[0]].concat()),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            decode_node(&[FORMAT_VERSION, 7]),
+            Err(DecodeError::InvalidTag(7))
+        );
+        assert_eq!(
+            decode_node(&[FORMAT_VERSION, 1, 1, 0xff]),
+            Err(DecodeError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let node = div().child(p().text("a")).child(p()).done();
+        let bytes = encode_node(&node);
+        let options = |max_depth, max_nodes| DecodeOptions {
+            max_depth,
+            max_nodes,
+        };
+
+        assert_eq!(decode_node_with(&bytes, &options(3, 4)), Ok(node.clone()));
+        assert_eq!(
+            decode_node_with(&bytes, &options(2, 0)),
+            Err(DecodeError::TooDeep(2))
+        );
+        assert_eq!(
+            decode_node_with(&bytes, &options(0, 3)),
+            Err(DecodeError::TooManyNodes(3))
+        );
+
+        let op = encode_op(&diff(&node, &div().child(p().text("b")).done()));
+        assert!(decode_op_with(&op, &options(3, 0)).is_ok());
+        assert_eq!(
+            decode_op_with(&op, &options(2, 0)),
+            Err(DecodeError::TooDeep(2))
+        );
+
+        let session = SessionEncoder::new().encode_node(&node);
+        assert_eq!(
+            SessionDecoder::with_options(options(0, 3)).decode_node(&session),
+            Err(DecodeError::TooManyNodes(3))
+        );
+    }
+
+    #[test]
+    fn deep_message_fails_without_overflow() {
+        // 400k nested elements, which overflow the stack if decoded.
+        let mut bytes = vec![FORMAT_VERSION];
+        for _ in 0..400_000 {
+            bytes.extend_from_slice(&[0, 1, b'a', 0, 0, 0, 0, 1]);
+        }
+        bytes.extend_from_slice(&[1, 0]);
+        let options = DecodeOptions {
+            max_depth: 100,
+            ..DecodeOptions::default()
+        };
+
+        assert_eq!(
+            decode_node_with(&bytes, &options),
+            Err(DecodeError::TooDeep(100))
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! # Error boundaries
+//!
+//! Containment for views which can fail, such as user-provided components
+//! rendered on the server. `error_boundary` builds its subtree and renders
+//! the fallback instead if building returns an error or panics.
+//!
+//! ```
+//! use troy::boundary::{error_boundary, take_errors};
+//! use troy::tags::*;
+//!
+//! let view = error_boundary(
+//!     || "x".parse::<u32>().map(|n| span().text(n.to_string()).done()),
+//!     |error| p().class("error").text(error.to_string()).done(),
+//! );
+//!
+//! assert_eq!(view, p().class("error").text("invalid digit found in string").done());
+//! assert_eq!(take_errors().len(), 1);
+//! ```
+//!
+//! Components are rendered lazily, so the boundary renders all components of
+//! the subtree while building it, and panics in them are caught too. The
+//! subtree gives up lazy rendering in exchange.
+//!
+//! Panics are still reported by the panic hook, which prints them to stderr
+//! by default.
+//!
+use node::VNode;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoundaryError {
+    /// View returned an error.
+    Error(String),
+    /// View panicked, with the panic message.
+    Panic(String),
+}
+
+impl fmt::Display for BoundaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoundaryError::Error(message) => write!(f, "{}", message),
+            BoundaryError::Panic(message) => write!(f, "panic: {}", message),
+        }
+    }
+}
+
+thread_local! {
+    static ERRORS: RefCell<Vec<BoundaryError>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Errors caught by boundaries on this thread since the last call, oldest
+/// first.
+///
+pub fn take_errors() -> Vec<BoundaryError> {
+    ERRORS.with(|errors| errors.replace(Vec::new()))
+}
+
+/// Build subtree with `view`, or with `fallback` if `view` returns an error
+/// or panics. Caught errors are recorded, see `take_errors`.
+///
+pub fn error_boundary<F, G, E>(view: F, fallback: G) -> VNode
+where
+    F: FnOnce() -> Result<VNode, E>,
+    G: FnOnce(&BoundaryError) -> VNode,
+    E: fmt::Display,
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(|| view().inspect(render_components)));
+
+    let error = match result {
+        Ok(Ok(node)) => return node,
+        Ok(Err(error)) => BoundaryError::Error(error.to_string()),
+        Err(payload) => BoundaryError::Panic(panic_message(&*payload)),
+    };
+
+    let node = fallback(&error);
+    ERRORS.with(|errors| errors.borrow_mut().push(error));
+    node
+}
+
+fn render_components(node: &VNode) {
+    if let VNode::Element(element) = node.resolve() {
+        for child in element.get_children() {
+            render_components(child);
+        }
+    }
+}
+
+fn panic_message(payload: &dyn Any) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use component::{component, Component};
+    use tags::*;
+
+    struct Broken;
+
+    impl Component for Broken {
+        type Props = ();
+
+        fn props(&self) -> &() {
+            &()
+        }
+
+        fn render(&self) -> VNode {
+            panic!("broken component")
+        }
+
+        fn should_update(&self, _: &()) -> bool {
+            true
+        }
+    }
+
+    fn fallback(error: &BoundaryError) -> VNode {
+        p().text(error.to_string()).done()
+    }
+
+    #[test]
+    fn catches_panic_in_component() {
+        take_errors();
+        let view = div()
+            .child_node(error_boundary(
+                || Ok::<_, String>(section().child_node(component(Broken).done()).done()),
+                fallback,
+            ))
+            .done();
+
+        assert_eq!(
+            view,
+            div().child(p().text("panic: broken component")).done()
+        );
+        assert_eq!(
+            take_errors(),
+            [BoundaryError::Panic("broken component".to_string())]
+        );
+    }
+
+    #[test]
+    fn passes_through_successful_view() {
+        take_errors();
+        let view = error_boundary(|| Ok::<_, String>(span().done()), fallback);
+
+        assert_eq!(view, span().done());
+        assert!(take_errors().is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+//! # Checked element builder
+//!
+//! `VElement` is its own builder: every method returns a usable element,
+//! so nothing checks the element once it is complete. `ElementBuilder`
+//! only collects inputs, and `build` checks the finished element before
+//! handing it out: tag and attribute names, that void elements have no
+//! children and that keys are unique among siblings.
+//!
+//! ```
+//! use troy::builder::ElementBuilder;
+//! use troy::error::Error;
+//! use troy::tags::*;
+//!
+//! let list = ElementBuilder::new("ul")
+//!     .child(li().key("a"))
+//!     .child(li().key("b"))
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(list, ul().child(li().key("a")).child(li().key("b")));
+//!
+//! let duplicate = ElementBuilder::new("ul")
+//!     .child(li().key("a"))
+//!     .child(li().key("a"))
+//!     .build();
+//! assert_eq!(duplicate, Err(Error::DuplicateKey("a".to_string())));
+//! ```
+//!
+use element::{AttrValue, VElement};
+use error::Error;
+use key::Key;
+use node::VNode;
+use types::CowString;
+
+/// Inputs of an element which is checked when built.
+///
+#[derive(Debug, Clone)]
+pub struct ElementBuilder {
+    element: VElement,
+}
+
+impl ElementBuilder {
+    /// Start building an element with specified tag.
+    ///
+    #[cfg_attr(feature = "debug-locations", track_caller)]
+    pub fn new<S>(tag: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        ElementBuilder {
+            element: VElement::new(tag),
+        }
+    }
+
+    /// Start building a void element, which can't have children.
+    ///
+    #[cfg_attr(feature = "debug-locations", track_caller)]
+    pub fn new_void<S>(tag: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        ElementBuilder {
+            element: VElement::new_void(tag),
+        }
+    }
+
+    pub fn key<K>(self, key: K) -> Self
+    where
+        K: Into<Key>,
+    {
+        ElementBuilder {
+            element: self.element.key(key),
+        }
+    }
+
+    pub fn attr<N, V>(self, name: N, value: V) -> Self
+    where
+        N: Into<CowString>,
+        V: Into<AttrValue>,
+    {
+        ElementBuilder {
+            element: self.element.attr(name, value),
+        }
+    }
+
+    pub fn class<S>(self, name: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        ElementBuilder {
+            element: self.element.class(name),
+        }
+    }
+
+    pub fn class_list<S>(self, classes: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        ElementBuilder {
+            element: self.element.class_list(classes),
+        }
+    }
+
+    pub fn child(self, element: VElement) -> Self {
+        ElementBuilder {
+            element: self.element.child(element),
+        }
+    }
+
+    pub fn child_node(self, node: VNode) -> Self {
+        ElementBuilder {
+            element: self.element.child_node(node),
+        }
+    }
+
+    pub fn children<I>(self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = VNode>,
+    {
+        ElementBuilder {
+            element: self.element.children(nodes),
+        }
+    }
+
+    pub fn text<S>(self, text: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        ElementBuilder {
+            element: self.element.text(text),
+        }
+    }
+
+    /// Check the element and its descendants and return it.
+    ///
+    pub fn build(self) -> Result<VElement, Error> {
+        self.element.validate()?;
+        Ok(self.element)
+    }
+
+    /// Check the element and wrap it into VNode.
+    ///
+    pub fn done(self) -> Result<VNode, Error> {
+        self.build().map(VElement::done)
+    }
+}
+
+impl From<VElement> for ElementBuilder {
+    fn from(element: VElement) -> Self {
+        ElementBuilder { element }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_at_build() {
+        let builder = ElementBuilder::new_void("br").text("x");
+        assert_eq!(builder.build(), Err(Error::VoidChildren("br".to_string())));
+
+        let builder = ElementBuilder::new("div").attr("on click", "");
+        assert_eq!(
+            builder.done(),
+            Err(Error::InvalidAttributeName("on click".to_string()))
+        );
+
+        let builder = ElementBuilder::new("div").child(VElement::new("a b"));
+        assert_eq!(
+            builder.build(),
+            Err(Error::InvalidTagName("a b".to_string()))
+        );
+    }
+
+    #[test]
+    fn builds_element() {
+        let element = ElementBuilder::new("div")
+            .key("k")
+            .class_list("a b")
+            .attr("id", "x")
+            .text("1")
+            .build();
+
+        let expected = VElement::new("div")
+            .key("k")
+            .class_list("a b")
+            .attr("id", "x")
+            .text("1");
+        assert_eq!(element, Ok(expected));
+    }
+}
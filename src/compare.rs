@@ -0,0 +1,282 @@
+//! # Tree comparison
+//!
+//! Structural comparison of two trees which ignores differences that don't
+//! matter for the rendered HTML, such as attribute order or whitespace
+//! between elements. Useful for comparing built trees against parsed
+//! fixture markup.
+//!
+use debug::tree_diff_string;
+use element::VElement;
+use node::VNode;
+use path::NodePath;
+use std::fmt;
+
+/// Options which tune strictness of the comparison.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompareOptions {
+    /// Collapse runs of whitespace in text content into a single space and
+    /// trim it.
+    pub collapse_whitespace: bool,
+    /// Drop text nodes which contain only whitespace.
+    pub ignore_whitespace_text: bool,
+    /// Don't compare element keys.
+    pub ignore_keys: bool,
+    /// Attribute names which are not compared.
+    pub ignored_attributes: Vec<String>,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        CompareOptions {
+            collapse_whitespace: true,
+            ignore_whitespace_text: true,
+            ignore_keys: false,
+            ignored_attributes: Vec::new(),
+        }
+    }
+}
+
+impl CompareOptions {
+    /// Options which only ignore attribute and class order.
+    ///
+    pub fn strict() -> Self {
+        CompareOptions {
+            collapse_whitespace: false,
+            ignore_whitespace_text: false,
+            ignore_keys: false,
+            ignored_attributes: Vec::new(),
+        }
+    }
+}
+
+/// First difference found between two trees.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    /// Path to the differing node.
+    pub path: NodePath,
+    pub message: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at {}: {}", self.path, self.message)
+    }
+}
+
+/// Compare two trees, returning the first difference.
+///
+pub fn html_equivalent(a: &VNode, b: &VNode, options: &CompareOptions) -> Result<(), Difference> {
+    compare(a, b, options, &mut NodePath::root())
+}
+
+/// Assert that two trees are equivalent with default options.
+///
+pub fn assert_html_equivalent(a: &VNode, b: &VNode) {
+    assert_html_equivalent_with(a, b, &CompareOptions::default());
+}
+
+/// Assert that two trees are equivalent.
+///
+/// Panics with the first difference and a line diff of both trees.
+///
+pub fn assert_html_equivalent_with(a: &VNode, b: &VNode, options: &CompareOptions) {
+    if let Err(difference) = html_equivalent(a, b, options) {
+        panic!(
+            "trees are not equivalent {}\n\n{}",
+            difference,
+            tree_diff_string(a, b, false)
+        );
+    }
+}
+
+fn normalize_text<'a>(text: &'a str, options: &CompareOptions) -> std::borrow::Cow<'a, str> {
+    if options.collapse_whitespace {
+        text.split_whitespace().collect::<Vec<_>>().join(" ").into()
+    } else {
+        text.into()
+    }
+}
+
+fn significant_children<'a>(node: &'a VNode, options: &CompareOptions) -> Vec<&'a VNode> {
+    match node.resolve() {
+        VNode::Element(element) => element
+            .get_children()
+            .iter()
+            .map(VNode::resolve)
+            .filter(|child| match child {
+                VNode::Text(text) => {
+                    !(options.ignore_whitespace_text && text.get_content().trim().is_empty())
+                }
+                _ => true,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Where both elements were built, with the `debug-locations` feature.
+///
+fn built_at(a: &VElement, b: &VElement) -> String {
+    match (a.get_location(), b.get_location()) {
+        (Some(a), Some(b)) => format!(" (built at {} and {})", a, b),
+        _ => String::new(),
+    }
+}
+
+fn compare(
+    a: &VNode,
+    b: &VNode,
+    options: &CompareOptions,
+    path: &mut NodePath,
+) -> Result<(), Difference> {
+    let difference = |path: &NodePath, message: String| {
+        Err(Difference {
+            path: path.clone(),
+            message,
+        })
+    };
+
+    match (a.resolve(), b.resolve()) {
+        (VNode::Text(a), VNode::Text(b)) => {
+            let (a, b) = (
+                normalize_text(a.get_content(), options),
+                normalize_text(b.get_content(), options),
+            );
+            if a != b {
+                return difference(path, format!("text {:?} != {:?}", a, b));
+            }
+            Ok(())
+        }
+        (VNode::Element(a_element), VNode::Element(b_element)) => {
+            let difference = |path: &NodePath, message: String| {
+                difference(path, message + &built_at(a_element, b_element))
+            };
+
+            if a_element.get_tag() != b_element.get_tag() {
+                return difference(
+                    path,
+                    format!("tag {} != {}", a_element.get_tag(), b_element.get_tag()),
+                );
+            }
+
+            if !options.ignore_keys && a_element.get_key() != b_element.get_key() {
+                return difference(
+                    path,
+                    format!("key {:?} != {:?}", a_element.get_key(), b_element.get_key()),
+                );
+            }
+
+            if a_element.get_classes() != b_element.get_classes() {
+                let mut a_classes: Vec<_> = a_element.get_classes().iter().collect();
+                let mut b_classes: Vec<_> = b_element.get_classes().iter().collect();
+                a_classes.sort();
+                b_classes.sort();
+                return difference(path, format!("classes {:?} != {:?}", a_classes, b_classes));
+            }
+
+            let ignored = |name: &str| options.ignored_attributes.iter().any(|n| n == name);
+            let mut names: Vec<_> = a_element
+                .get_attributes()
+                .keys()
+                .chain(b_element.get_attributes().keys())
+                .filter(|name| !ignored(name))
+                .collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let a_value = a_element.get_attributes().get(name);
+                let b_value = b_element.get_attributes().get(name);
+                if a_value != b_value {
+                    return difference(
+                        path,
+                        format!("attribute {} {:?} != {:?}", name, a_value, b_value),
+                    );
+                }
+            }
+
+            let a_children = significant_children(a, options);
+            let b_children = significant_children(b, options);
+            if a_children.len() != b_children.len() {
+                return difference(
+                    path,
+                    format!(
+                        "children count {} != {}",
+                        a_children.len(),
+                        b_children.len()
+                    ),
+                );
+            }
+
+            for (index, (a_child, b_child)) in a_children.iter().zip(b_children).enumerate() {
+                path.push(index);
+                compare(a_child, b_child, options, path)?;
+                path.pop();
+            }
+
+            Ok(())
+        }
+        _ => difference(path, "node types differ".to_string()),
+    }
+}
+
+#[cfg(all(test, feature = "tags", feature = "parser"))]
+mod tests {
+    use super::*;
+    use parser::parse;
+    use tags::*;
+
+    #[test]
+    fn equivalent_to_parsed_markup() {
+        let built = ul()
+            .class_list("b a")
+            .attr("id", "list")
+            .attr("role", "menu")
+            .child(li().text("one  two"))
+            .done();
+        let parsed = parse(
+            r#"
+            <ul role="menu" class="a b" id="list">
+                <li>
+                    one
+                    two
+                </li>
+            </ul>
+        "#,
+        )
+        .unwrap();
+
+        assert_html_equivalent(&built, &parsed);
+        assert!(html_equivalent(&built, &parsed, &CompareOptions::strict()).is_err());
+    }
+
+    #[test]
+    fn differences() {
+        let a = div().child(p()).child(p().attr("id", "a")).done();
+        let b = div().child(p()).child(p().attr("id", "b")).done();
+
+        let result = html_equivalent(&a, &b, &CompareOptions::default());
+        #[cfg(not(feature = "debug-locations"))]
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "at /1: attribute id Some(\"a\") != Some(\"b\")"
+        );
+        #[cfg(feature = "debug-locations")]
+        assert!(result.unwrap_err().to_string().starts_with(
+            "at /1: attribute id Some(\"a\") != Some(\"b\") (built at src/compare.rs:"
+        ));
+
+        let options = CompareOptions {
+            ignored_attributes: vec!["id".to_string()],
+            ..CompareOptions::default()
+        };
+        assert_eq!(html_equivalent(&a, &b, &options), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "trees are not equivalent at /: tag div != p")]
+    fn assert_panics() {
+        assert_html_equivalent(&div().done(), &p().done());
+    }
+}
@@ -0,0 +1,506 @@
+//! # Components
+//!
+//! A component is a piece of view with its own props. It is rendered lazily:
+//! when the differ finds the same component type in the old and new trees and
+//! `should_update` returns false, the diff is `Skip(1)` and the new component
+//! is never rendered. It takes over the node of the old component instead,
+//! if that one was rendered.
+//!
+//! Everywhere else (rendering, matching, patch inserts) a component stands for
+//! the node it renders to, see `VNode::resolve`.
+//!
+//! ```
+//! use troy::component::{component, Component};
+//! use troy::node::VNode;
+//! use troy::tags::*;
+//!
+//! struct Counter {
+//!     count: u32,
+//! }
+//!
+//! impl Component for Counter {
+//!     type Props = u32;
+//!
+//!     fn props(&self) -> &u32 {
+//!         &self.count
+//!     }
+//!
+//!     fn render(&self) -> VNode {
+//!         span().class("counter").text(self.count.to_string()).done()
+//!     }
+//!
+//!     fn should_update(&self, old_props: &u32) -> bool {
+//!         self.count != *old_props
+//!     }
+//! }
+//!
+//! let view = div().child_node(component(Counter { count: 1 }).done()).done();
+//! ```
+//!
+use context::Context;
+use key::{scoped_key, Key};
+use node::VNode;
+use state::StateId;
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use sync::{MaybeSync, Once, Shared};
+use types::CowString;
+
+pub trait Component {
+    type Props;
+
+    /// Props the component was created with. They are passed to
+    /// `should_update` of the component which replaces this one in the next
+    /// frame.
+    ///
+    fn props(&self) -> &Self::Props;
+
+    fn render(&self) -> VNode;
+
+    /// Check if the component needs to be rendered and diffed again, given
+    /// the props of the component it replaces.
+    ///
+    fn should_update(&self, old_props: &Self::Props) -> bool;
+
+    /// Called after the component became part of the tree, see
+    /// `diff::Lifecycle`.
+    ///
+    fn mounted(&self) {}
+
+    /// Called after the component stopped being part of the tree.
+    ///
+    fn unmounted(&self) {}
+}
+
+/// Object safe part of Component used by VComponent.
+///
+trait AnyComponent: MaybeSync {
+    fn as_any(&self) -> &dyn Any;
+    fn render(&self) -> VNode;
+    fn should_update(&self, old: &dyn AnyComponent) -> bool;
+    fn mounted(&self);
+    fn unmounted(&self);
+}
+
+impl<C> AnyComponent for C
+where
+    C: Component + MaybeSync + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn render(&self) -> VNode {
+        Component::render(self)
+    }
+
+    fn should_update(&self, old: &dyn AnyComponent) -> bool {
+        match old.as_any().downcast_ref::<C>() {
+            Some(old) => Component::should_update(self, old.props()),
+            None => true,
+        }
+    }
+
+    fn mounted(&self) {
+        Component::mounted(self)
+    }
+
+    fn unmounted(&self) {
+        Component::unmounted(self)
+    }
+}
+
+#[derive(Clone)]
+pub struct VComponent {
+    type_id: TypeId,
+    type_name: &'static str,
+    key: Option<Key>,
+    component: Shared<dyn AnyComponent>,
+    context: Context,
+    rendered: Once<Shared<VNode>>,
+    /// Id of the state of a stateful component, see `state::stateful`.
+    state_id: Option<StateId>,
+}
+
+impl VComponent {
+    /// Create a new VComponent. The component is not rendered until its
+    /// node is needed, but it renders with the context it was created in.
+    ///
+    pub fn new<C>(component: C) -> Self
+    where
+        C: Component + MaybeSync + 'static,
+    {
+        VComponent {
+            type_id: TypeId::of::<C>(),
+            type_name: ::std::any::type_name::<C>(),
+            key: None,
+            component: Shared::new(component),
+            context: Context::current(),
+            rendered: Once::new(),
+            state_id: None,
+        }
+    }
+
+    /// Set a key for VComponent, used when diffing keyed children lists.
+    ///
+    pub fn key<K>(mut self, key: K) -> Self
+    where
+        K: Into<Key>,
+    {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Set a key within a scope, see `key::scoped_key`.
+    ///
+    pub fn key_in<S, K>(self, scope: S, key: K) -> Self
+    where
+        S: Into<CowString>,
+        K: Into<Key>,
+    {
+        self.key(scoped_key(scope, key))
+    }
+
+    /// Wrap component into VNode.
+    ///
+    pub fn done(self) -> VNode {
+        VNode::Component(self)
+    }
+
+    pub fn get_key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+
+    pub fn get_type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Node the component renders to. The component is rendered on the first
+    /// call only.
+    ///
+    pub fn rendered(&self) -> &VNode {
+        self.rendered.get_or_init(|| {
+            let node = self.context.enter(|| self.component.render());
+            Shared::new(node)
+        })
+    }
+
+    /// Check if the component was rendered already.
+    ///
+    pub fn is_rendered(&self) -> bool {
+        self.rendered.get().is_some()
+    }
+
+    /// Node the component rendered to, without rendering it.
+    ///
+    pub(crate) fn get_rendered(&self) -> Option<&VNode> {
+        self.rendered.get().map(|node| &**node)
+    }
+
+    /// Take over the node of the old component, which this one replaces
+    /// without being rendered, so the tree keeps the node which is shown.
+    ///
+    pub(crate) fn adopt(&self, old: &VComponent) {
+        if let Some(node) = old.rendered.get() {
+            let _ = self.rendered.set(node.clone());
+        }
+    }
+
+    pub(crate) fn with_state_id(mut self, id: StateId) -> Self {
+        self.state_id = Some(id);
+        self
+    }
+
+    pub(crate) fn get_state_id(&self) -> Option<&StateId> {
+        self.state_id.as_ref()
+    }
+
+    /// Check if the component needs to be rendered and diffed again when it
+    /// replaces the old one. Always true for components of different types.
+    ///
+    pub fn should_update(&self, old: &VComponent) -> bool {
+        self.type_id != old.type_id || self.component.should_update(&*old.component)
+    }
+
+    /// Check if the component stands for the same component instance as the
+    /// old one, which is true for components of the same type.
+    ///
+    pub fn is_same(&self, old: &VComponent) -> bool {
+        self.type_id == old.type_id
+    }
+
+    /// Lifecycle callbacks run with the context the component was created in.
+    ///
+    pub(crate) fn mounted(&self) {
+        self.context.enter(|| self.component.mounted())
+    }
+
+    pub(crate) fn unmounted(&self) {
+        self.context.enter(|| self.component.unmounted())
+    }
+}
+
+impl fmt::Debug for VComponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VComponent")
+            .field("type_name", &self.type_name)
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+/// Components are equal if they are of the same type and render to equal
+/// nodes.
+///
+impl PartialEq for VComponent {
+    fn eq(&self, other: &VComponent) -> bool {
+        self.type_id == other.type_id
+            && self.key == other.key
+            && self.rendered() == other.rendered()
+    }
+}
+
+impl Eq for VComponent {}
+
+impl Hash for VComponent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_id.hash(state);
+        self.key.hash(state);
+        self.rendered().hash(state);
+    }
+}
+
+pub fn component<C>(component: C) -> VComponent
+where
+    C: Component + MaybeSync + 'static,
+{
+    VComponent::new(component)
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::NodeOp::*;
+    use diff::{apply, diff, diff_with_lifecycle, TextOp};
+    use sync::{Lock, Shared};
+    use tags::*;
+    use text::text;
+
+    struct Label {
+        text: String,
+    }
+
+    impl Component for Label {
+        type Props = String;
+
+        fn props(&self) -> &String {
+            &self.text
+        }
+
+        fn render(&self) -> VNode {
+            p().text(self.text.clone()).done()
+        }
+
+        fn should_update(&self, old_props: &String) -> bool {
+            self.text != *old_props
+        }
+    }
+
+    fn label(text: &str) -> VNode {
+        component(Label {
+            text: text.to_string(),
+        })
+        .done()
+    }
+
+    #[test]
+    fn lazy_render() {
+        let node = component(Label {
+            text: "a".to_string(),
+        });
+
+        assert!(!node.is_rendered());
+        assert_eq!(node.rendered(), &p().text("a").done());
+        assert!(node.is_rendered());
+    }
+
+    #[test]
+    fn unchanged_component_is_skipped() {
+        let old = div().child_node(label("a")).done();
+        let new = div().child_node(label("a")).done();
+
+        assert_eq!(diff(&old, &new), Skip(1));
+        match &new {
+            VNode::Element(element) => match &element.get_children()[0] {
+                VNode::Component(component) => assert!(!component.is_rendered()),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn changed_component_is_diffed() {
+        let old = div().child_node(label("a")).done();
+        let new = div().child_node(label("b")).done();
+        let set = Text(TextOp::Set("b".into()));
+
+        assert_eq!(
+            diff(&old, &new),
+            Update(None, Some(vec![Update(None, Some(vec![set]), None)]), None)
+        );
+    }
+
+    type Log = Shared<Lock<Vec<String>>>;
+
+    struct Logged {
+        name: &'static str,
+        children: Vec<Shared<VNode>>,
+        log: Log,
+    }
+
+    impl Component for Logged {
+        type Props = &'static str;
+
+        fn props(&self) -> &&'static str {
+            &self.name
+        }
+
+        fn render(&self) -> VNode {
+            section()
+                .children(self.children.iter().cloned().map(VNode::Shared))
+                .done()
+        }
+
+        fn should_update(&self, _: &&'static str) -> bool {
+            true
+        }
+
+        fn mounted(&self) {
+            self.log.borrow_mut().push(format!("mounted {}", self.name));
+        }
+
+        fn unmounted(&self) {
+            self.log
+                .borrow_mut()
+                .push(format!("unmounted {}", self.name));
+        }
+    }
+
+    fn logged(log: &Log, name: &'static str, children: Vec<VNode>) -> VNode {
+        component(Logged {
+            name,
+            children: children.into_iter().map(Shared::new).collect(),
+            log: log.clone(),
+        })
+        .key(name)
+        .done()
+    }
+
+    fn lifecycle_log(old: &VNode, new: &VNode) -> Vec<String> {
+        let (_, lifecycle) = diff_with_lifecycle(old, new);
+        lifecycle.notify();
+        lifecycle
+            .unmounted
+            .iter()
+            .chain(&lifecycle.mounted)
+            .map(|component| component.get_key().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn replaced_subtree_is_unmounted_and_mounted() {
+        let log = Log::default();
+        let old = div()
+            .child_node(logged(&log, "a", vec![logged(&log, "b", vec![])]))
+            .done();
+        let new = p()
+            .child_node(logged(&log, "c", vec![logged(&log, "d", vec![])]))
+            .done();
+
+        assert_eq!(lifecycle_log(&old, &new), ["a", "b", "d", "c"]);
+        assert_eq!(
+            *log.borrow(),
+            ["unmounted a", "unmounted b", "mounted d", "mounted c"]
+        );
+    }
+
+    #[test]
+    fn removed_and_inserted_children() {
+        let log = Log::default();
+        let old = ul()
+            .child_node(logged(&log, "a", vec![]))
+            .child_node(logged(&log, "b", vec![]))
+            .child_node(logged(&log, "c", vec![]))
+            .done();
+        let new = ul()
+            .child_node(logged(&log, "c", vec![]))
+            .child_node(logged(&log, "a", vec![logged(&log, "e", vec![])]))
+            .child_node(logged(&log, "d", vec![]))
+            .done();
+
+        // Moved and updated components stay mounted.
+        assert_eq!(lifecycle_log(&old, &new), ["b", "e", "d"]);
+    }
+
+    struct Word(String);
+
+    impl Component for Word {
+        type Props = String;
+
+        fn props(&self) -> &String {
+            &self.0
+        }
+
+        fn render(&self) -> VNode {
+            text(self.0.clone()).done()
+        }
+
+        fn should_update(&self, old_props: &String) -> bool {
+            self.0 != *old_props
+        }
+    }
+
+    #[test]
+    fn moved_component_with_changed_text() {
+        let word =
+            |key: &'static str, content: &str| component(Word(content.to_string())).key(key).done();
+        let old = div()
+            .child_node(word("a", "a"))
+            .child_node(word("b", "b"))
+            .done();
+        let new = div()
+            .child_node(word("b", "B"))
+            .child_node(word("a", "a"))
+            .done();
+
+        let op = diff(&old, &new);
+        assert_eq!(op.validate_against(&old), Ok(()));
+        assert_eq!(apply(&old, &op), Ok(new.clone()));
+
+        let dropped = Update(None, Some(vec![Skip(1), Move(0, None, None, None)]), None);
+        assert_ne!(op, dropped);
+    }
+
+    #[test]
+    fn component_type_change() {
+        let log = Log::default();
+        let old = div().child_node(logged(&log, "a", vec![])).done();
+        let new = div()
+            .child_node(
+                component(Label {
+                    text: "a".to_string(),
+                })
+                .key("a")
+                .done(),
+            )
+            .done();
+
+        let (_, lifecycle) = diff_with_lifecycle(&old, &new);
+        assert_eq!(lifecycle.unmounted.len(), 1);
+        assert_eq!(lifecycle.mounted.len(), 1);
+        assert_eq!(lifecycle.unmounted[0].get_key(), Some(&"a".into()));
+        assert!(lifecycle.mounted[0].get_type_name().ends_with("Label"));
+    }
+}
@@ -0,0 +1,243 @@
+//! # Content model validation
+//!
+//! Checks HTML content model rules which browsers "fix" silently by
+//! re-parenting nodes while parsing markup. A tree violating them renders to
+//! a DOM with a different shape than the VNode tree, which breaks patching.
+//!
+//! - Block elements inside `p`.
+//! - Nested interactive elements (`a`, `button`) and nested `form`.
+//! - `li` outside of `ul`, `ol` or `menu`.
+//! - Table structure: `tr` in table sections, cells in `tr`, sections and
+//!   `caption`/`colgroup` in `table`, and no text directly in table parts.
+//!
+//! Available with the `strict` feature.
+//!
+use node::VNode;
+use path::NodePath;
+use std::fmt;
+
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+const INTERACTIVE_TAGS: &[&str] = &["a", "button"];
+
+const TABLE_CHILD_TAGS: &[&str] = &["caption", "colgroup", "thead", "tbody", "tfoot", "tr"];
+
+const TABLE_SECTION_TAGS: &[&str] = &["thead", "tbody", "tfoot"];
+
+const TABLE_STRUCTURE_TAGS: &[&str] = &["table", "thead", "tbody", "tfoot", "tr"];
+
+/// Elements allowed anywhere, such as in `table` or `ul`.
+///
+const SCRIPT_SUPPORTING_TAGS: &[&str] = &["script", "template"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub path: NodePath,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Validate the tree and return all violations in document order.
+///
+pub fn validate(root: &VNode) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    walk(
+        root,
+        &mut Vec::new(),
+        &mut NodePath::root(),
+        &mut violations,
+    );
+    violations
+}
+
+fn walk<'a>(
+    node: &'a VNode,
+    ancestors: &mut Vec<&'a str>,
+    path: &mut NodePath,
+    violations: &mut Vec<Violation>,
+) {
+    if let VNode::Component(_) | VNode::Shared(_) = node {
+        return walk(node.resolve(), ancestors, path, violations);
+    }
+
+    let mut report = |message: String| {
+        violations.push(Violation {
+            path: path.clone(),
+            message,
+        })
+    };
+    let parent = ancestors.last().cloned();
+
+    let element = match node {
+        VNode::Element(element) => element,
+        VNode::Text(text) => {
+            if let Some(parent) = parent {
+                if TABLE_STRUCTURE_TAGS.contains(&parent) && !text.get_content().trim().is_empty() {
+                    report(format!("text directly inside `{}`", parent));
+                }
+            }
+            return;
+        }
+        VNode::Component(_) | VNode::Shared(_) => return,
+    };
+    let tag = element.get_tag();
+
+    if BLOCK_TAGS.contains(&tag) && ancestors.contains(&"p") {
+        report(format!("block element `{}` inside `p`", tag));
+    }
+
+    if INTERACTIVE_TAGS.contains(&tag) {
+        if let Some(outer) = ancestors.iter().find(|a| INTERACTIVE_TAGS.contains(a)) {
+            report(format!("interactive element `{}` inside `{}`", tag, outer));
+        }
+    }
+
+    if tag == "form" && ancestors.contains(&"form") {
+        report("nested `form`".to_string());
+    }
+
+    let expected_parents: Option<&[&str]> = match tag {
+        "li" => Some(&["ul", "ol", "menu"]),
+        "tr" => Some(&["table", "thead", "tbody", "tfoot"]),
+        "td" | "th" => Some(&["tr"]),
+        "thead" | "tbody" | "tfoot" | "caption" | "colgroup" => Some(&["table"]),
+        _ => None,
+    };
+    if let Some(expected) = expected_parents {
+        if !parent.is_some_and(|parent| expected.contains(&parent)) {
+            report(format!(
+                "`{}` must be inside {}",
+                tag,
+                expected
+                    .iter()
+                    .map(|t| format!("`{}`", t))
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ));
+        }
+    }
+
+    let allowed_children: Option<&[&str]> = match tag {
+        "table" => Some(TABLE_CHILD_TAGS),
+        "thead" | "tbody" | "tfoot" => Some(&["tr"]),
+        "tr" => Some(&["td", "th"]),
+        _ => None,
+    };
+
+    ancestors.push(tag);
+    for (index, child) in element.get_children().iter().enumerate() {
+        path.push(index);
+        if let (Some(allowed), VNode::Element(child_element)) = (allowed_children, child.resolve())
+        {
+            let child_tag = child_element.get_tag();
+            // Wrong placement of table parts is reported for the child itself.
+            let reported = TABLE_CHILD_TAGS.contains(&child_tag)
+                || TABLE_SECTION_TAGS.contains(&child_tag)
+                || child_tag == "td"
+                || child_tag == "th";
+            if !allowed.contains(&child_tag)
+                && !SCRIPT_SUPPORTING_TAGS.contains(&child_tag)
+                && !reported
+            {
+                violations.push(Violation {
+                    path: path.clone(),
+                    message: format!("`{}` is not allowed inside `{}`", child_tag, tag),
+                });
+            }
+        }
+        walk(child, ancestors, path, violations);
+        path.pop();
+    }
+    ancestors.pop();
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    fn messages(node: &VNode) -> Vec<String> {
+        validate(node).iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn valid_tree() {
+        #[rustfmt::skip]
+        let node = div()
+            .child(p().child(a().text("link")).child(span()))
+            .child(ul().child(li().child(div())))
+            .child(form().child(button().text("Send")))
+            .child(table()
+                .child(caption().text("Caption"))
+                .child(thead().child(tr().child(th().text("A"))))
+                .child(tbody().child(tr().child(td().text("1"))))
+                .child(tr().child(td())))
+            .done();
+
+        assert_eq!(messages(&node), Vec::<String>::new());
+    }
+
+    #[test]
+    fn invalid_tree() {
+        #[rustfmt::skip]
+        let node = div()
+            .child(p().child(span().child(div())))
+            .child(a().child(button()))
+            .child(form().child(form()))
+            .child(li())
+            .child(table()
+                .text("text")
+                .child(div())
+                .child(td()))
+            .done();
+
+        assert_eq!(
+            messages(&node),
+            vec![
+                "/0/0/0: block element `div` inside `p`",
+                "/1/0: interactive element `button` inside `a`",
+                "/2/0: nested `form`",
+                "/3: `li` must be inside `ul` or `ol` or `menu`",
+                "/4/0: text directly inside `table`",
+                "/4/1: `div` is not allowed inside `table`",
+                "/4/2: `td` must be inside `tr`",
+            ]
+        );
+    }
+}
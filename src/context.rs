@@ -0,0 +1,167 @@
+//! # Context
+//!
+//! Values scoped to a subtree, such as theme or locale, which views read with
+//! `use_context` instead of taking them as arguments.
+//!
+//! `with_context` makes a value available while its subtree is built.
+//! Components capture the context they were created in and see it when they
+//! are rendered, even though rendering happens later, during diffing.
+//!
+//! ```
+//! use troy::context::{use_context, with_context};
+//! use troy::node::VNode;
+//! use troy::tags::*;
+//!
+//! fn button_view(label: &str) -> VNode {
+//!     let theme = use_context::<String>("theme");
+//!     let theme = theme.as_ref().map_or("light", |t| t.as_str());
+//!     button().class(format!("{}-button", theme)).text(label.to_string()).done()
+//! }
+//!
+//! let view = with_context("theme", "dark".to_string(), || {
+//!     div().child_node(button_view("Save")).done()
+//! });
+//! ```
+//!
+//! Context values are not passed to `Component::should_update`, so a
+//! component which depends on them should have them in its props too.
+//!
+use node::VNode;
+use std::cell::RefCell;
+use sync::{AnyValue, MaybeSync, Shared};
+
+thread_local! {
+    static CURRENT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+/// Snapshot of context values, innermost value first.
+///
+#[derive(Clone, Default)]
+pub(crate) struct Context(Option<Shared<Entry>>);
+
+struct Entry {
+    key: &'static str,
+    value: Shared<AnyValue>,
+    parent: Context,
+}
+
+impl Context {
+    /// Context the current subtree is built in.
+    ///
+    pub(crate) fn current() -> Context {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Run function with this context as current.
+    ///
+    pub(crate) fn enter<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Restores previous context even if `f` panics.
+        struct Restore(Option<Context>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                if let Some(context) = self.0.take() {
+                    CURRENT.with(|current| *current.borrow_mut() = context);
+                }
+            }
+        }
+
+        let previous = CURRENT.with(|current| current.replace(self.clone()));
+        let _restore = Restore(Some(previous));
+        f()
+    }
+
+    fn get(&self, key: &str) -> Option<Shared<AnyValue>> {
+        let mut context = self;
+        while let Some(entry) = &context.0 {
+            if entry.key == key {
+                return Some(entry.value.clone());
+            }
+            context = &entry.parent;
+        }
+        None
+    }
+}
+
+/// Build subtree with a context value available to it.
+///
+pub fn with_context<T, F>(key: &'static str, value: T, subtree: F) -> VNode
+where
+    T: MaybeSync + 'static,
+    F: FnOnce() -> VNode,
+{
+    let context = Context(Some(Shared::new(Entry {
+        key,
+        value: Shared::new(value),
+        parent: Context::current(),
+    })));
+    context.enter(subtree)
+}
+
+/// Closest context value with the key, if it has the requested type.
+///
+pub fn use_context<T>(key: &str) -> Option<Shared<T>>
+where
+    T: MaybeSync + 'static,
+{
+    Context::current()
+        .get(key)
+        .and_then(|value| value.downcast::<T>().ok())
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use component::{component, Component};
+    use tags::*;
+
+    struct Greeting;
+
+    impl Component for Greeting {
+        type Props = ();
+
+        fn props(&self) -> &() {
+            &()
+        }
+
+        fn render(&self) -> VNode {
+            let name = use_context::<&str>("name").map_or("nobody", |name| *name);
+            p().text(format!("Hello, {}!", name)).done()
+        }
+
+        fn should_update(&self, _: &()) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn nested_context() {
+        let view = with_context("name", "outer", || {
+            div()
+                .child_node(with_context("name", "inner", || component(Greeting).done()))
+                .child_node(component(Greeting).done())
+                .done()
+        });
+
+        assert!(use_context::<&str>("name").is_none());
+        assert_eq!(
+            view,
+            div()
+                .child(p().text("Hello, inner!"))
+                .child(p().text("Hello, outer!"))
+                .done()
+        );
+    }
+
+    #[test]
+    fn context_type_mismatch() {
+        with_context("count", 1u32, || {
+            assert_eq!(use_context::<u32>("count").as_deref(), Some(&1));
+            assert!(use_context::<u64>("count").is_none());
+            div().done()
+        });
+    }
+}
@@ -0,0 +1,148 @@
+//! # Conversion traits
+//!
+//! Interop with trees produced by other builders. A type implementing
+//! `IntoVNode` can be fed into the differ, so an application can adopt the
+//! reconciler without rewriting its views. `FromVNode` goes the other way,
+//! for handing diffed trees to code which expects another representation.
+//!
+//! ```
+//! use troy::convert::IntoVNode;
+//! use troy::diff::diff;
+//! use troy::element::VElement;
+//! use troy::node::VNode;
+//! use troy::text::text;
+//!
+//! // Tree type of some other library.
+//! enum Markup {
+//!     Tag(&'static str, Vec<(&'static str, String)>, Vec<Markup>),
+//!     Text(String),
+//! }
+//!
+//! impl IntoVNode for Markup {
+//!     fn into_vnode(self) -> VNode {
+//!         match self {
+//!             Markup::Tag(tag, attributes, children) => {
+//!                 let mut element = VElement::new(tag);
+//!                 for (name, value) in attributes {
+//!                     element = element.attr(name, value);
+//!                 }
+//!                 element.children(children.into_iter().map(IntoVNode::into_vnode)).done()
+//!             }
+//!             Markup::Text(content) => text(content).done(),
+//!         }
+//!     }
+//! }
+//!
+//! let old = Markup::Tag("p", vec![], vec![Markup::Text("a".to_string())]).into_vnode();
+//! let new = Markup::Tag("p", vec![], vec![Markup::Text("b".to_string())]).into_vnode();
+//! let op = diff(&old, &new);
+//! ```
+//!
+use component::VComponent;
+use element::VElement;
+use node::VNode;
+use render::render_to_string;
+use text::{text, VText};
+
+pub trait IntoVNode {
+    fn into_vnode(self) -> VNode;
+}
+
+pub trait FromVNode {
+    /// Convert the tree. Components and shared nodes are converted as the
+    /// nodes they stand for.
+    ///
+    fn from_vnode(node: &VNode) -> Self;
+}
+
+impl IntoVNode for VNode {
+    fn into_vnode(self) -> VNode {
+        self
+    }
+}
+
+impl IntoVNode for VElement {
+    fn into_vnode(self) -> VNode {
+        self.done()
+    }
+}
+
+impl IntoVNode for VText {
+    fn into_vnode(self) -> VNode {
+        self.done()
+    }
+}
+
+impl IntoVNode for VComponent {
+    fn into_vnode(self) -> VNode {
+        self.done()
+    }
+}
+
+/// Strings become text nodes.
+///
+impl IntoVNode for String {
+    fn into_vnode(self) -> VNode {
+        text(self).done()
+    }
+}
+
+impl IntoVNode for &str {
+    fn into_vnode(self) -> VNode {
+        text(self.to_string()).done()
+    }
+}
+
+/// Trees become HTML, see `render::render_to_string`.
+///
+impl FromVNode for String {
+    fn from_vnode(node: &VNode) -> Self {
+        render_to_string(node)
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    /// Tree of tag names only.
+    #[derive(Debug, PartialEq)]
+    struct Outline(String, Vec<Outline>);
+
+    impl FromVNode for Outline {
+        fn from_vnode(node: &VNode) -> Self {
+            match node.resolve() {
+                VNode::Element(element) => Outline(
+                    element.get_tag().to_string(),
+                    element
+                        .get_children()
+                        .iter()
+                        .map(Outline::from_vnode)
+                        .collect(),
+                ),
+                _ => Outline("#text".to_string(), vec![]),
+            }
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let node = div()
+            .child(p().text("a"))
+            .child_node("b".into_vnode())
+            .done();
+
+        assert_eq!(
+            Outline::from_vnode(&node),
+            Outline(
+                "div".to_string(),
+                vec![
+                    Outline("p".to_string(), vec![Outline("#text".to_string(), vec![])]),
+                    Outline("#text".to_string(), vec![]),
+                ]
+            )
+        );
+        assert_eq!(String::from_vnode(&node), "<div><p>a</p>b</div>");
+    }
+}
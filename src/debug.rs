@@ -0,0 +1,109 @@
+//! # Debugging helpers
+//!
+//! Line based diff of two rendered trees, for quick interactive debugging of
+//! view functions. This is independent from the `diff` module: it compares
+//! `Display` output of both trees, not their structure.
+//!
+use node::VNode;
+use std::fmt::Write;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Print line diff of two trees to stderr with ANSI colors.
+///
+pub fn print_tree_diff(old: &VNode, new: &VNode) {
+    eprint!("{}", tree_diff_string(old, new, true));
+}
+
+/// Render line diff of two trees.
+///
+/// Removed lines are prefixed with `-`, added lines with `+`, and unchanged
+/// lines with a space. With `color` set removed lines are red and added lines
+/// are green.
+///
+pub fn tree_diff_string(old: &VNode, new: &VNode, color: bool) -> String {
+    let old_string = old.to_string();
+    let new_string = new.to_string();
+    let old_lines: Vec<&str> = old_string.lines().collect();
+    let new_lines: Vec<&str> = new_string.lines().collect();
+
+    // Longest common subsequence table, lcs[i][j] is the LCS length of
+    // old_lines[i..] and new_lines[j..].
+    let mut lcs = vec![vec![0; new_lines.len() + 1]; old_lines.len() + 1];
+    for i in (0..old_lines.len()).rev() {
+        for j in (0..new_lines.len()).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let mut line = |prefix: char, text: &str, ansi: &str| {
+        if color && !ansi.is_empty() {
+            writeln!(out, "{}{}{}{}", ansi, prefix, text, RESET).unwrap();
+        } else {
+            writeln!(out, "{}{}", prefix, text).unwrap();
+        }
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_lines.len() || j < new_lines.len() {
+        if i < old_lines.len() && j < new_lines.len() && old_lines[i] == new_lines[j] {
+            line(' ', old_lines[i], "");
+            i += 1;
+            j += 1;
+        } else if j < new_lines.len() && (i == old_lines.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            line('+', new_lines[j], GREEN);
+            j += 1;
+        } else {
+            line('-', old_lines[i], RED);
+            i += 1;
+        }
+    }
+
+    out
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn tree_diff_lines() {
+        let old = div().child(p().text("one")).child(p().text("two")).done();
+        let new = div().child(p().text("one")).child(p().text("three")).done();
+
+        let result = format!("\n{}", tree_diff_string(&old, &new, false));
+
+        let expected = r#"
+ <div>
+     <p>
+         one
+     </p>
+     <p>
++        three
+-        two
+     </p>
+ </div>
+"#;
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn tree_diff_colors() {
+        let old = p().text("one").done();
+        let new = p().text("two").done();
+
+        let result = tree_diff_string(&old, &new, true);
+
+        assert!(result.contains("\x1b[32m+    two\x1b[0m"));
+        assert!(result.contains("\x1b[31m-    one\x1b[0m"));
+    }
+}
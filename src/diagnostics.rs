@@ -0,0 +1,186 @@
+//! # Diagnostics
+//!
+//! Some mistakes in views don't fail: a void element with children renders
+//! without them, and children with duplicate or missing keys are diffed by
+//! position, which loses their state and costs more. `Diagnostics::collect`
+//! runs a function, such as building and diffing a frame, and collects
+//! warnings about such mistakes for the application to log.
+//!
+//! Outside of a collection nothing is checked, so diagnostics cost nothing
+//! unless they are collected.
+//!
+//! ```
+//! use troy::diagnostics::{Diagnostics, Kind};
+//! use troy::tags::*;
+//!
+//! let (_, diagnostics) = Diagnostics::collect(|| {
+//!     ul().child(li().key("a")).child(li().key("a")).done()
+//! });
+//!
+//! assert_eq!(diagnostics.get_diagnostics()[0].kind, Kind::DuplicateKey);
+//! ```
+//!
+use std::cell::RefCell;
+use std::fmt;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// Several siblings share the same key.
+    DuplicateKey,
+    /// Some siblings have keys and others don't.
+    MixedKeys,
+    /// Void element has children, which are not rendered.
+    VoidChildren,
+    /// Rendered text contains character references, so it was likely
+    /// escaped before it got into the tree and renders escaped twice.
+    DoubleEscaped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: Kind,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+/// Warnings collected while running a function, in the order they were
+/// reported.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// Run the function and collect warnings reported while it runs on this
+    /// thread. Collections nest, each one collects its own warnings.
+    ///
+    pub fn collect<R, F>(f: F) -> (R, Diagnostics)
+    where
+        F: FnOnce() -> R,
+    {
+        // Restores the outer collection even if `f` panics.
+        struct Restore(Option<Vec<Diagnostic>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let outer = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = outer);
+            }
+        }
+
+        let outer = CURRENT.with(|current| current.replace(Some(Vec::new())));
+        let restore = Restore(outer);
+        let result = f();
+        let diagnostics = CURRENT.with(|current| current.replace(None));
+        drop(restore);
+        (result, Diagnostics(diagnostics.unwrap_or_default()))
+    }
+
+    pub fn get_diagnostics(&self) -> &[Diagnostic] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = ::std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Whether warnings are collected, so checks can be skipped otherwise.
+///
+pub(crate) fn is_collecting() -> bool {
+    CURRENT.with(|current| current.borrow().is_some())
+}
+
+/// Report a warning to the current collection, if there is one.
+///
+pub(crate) fn report(kind: Kind, message: String) {
+    CURRENT.with(|current| {
+        if let Some(diagnostics) = current.borrow_mut().as_mut() {
+            diagnostics.push(Diagnostic { kind, message });
+        }
+    });
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    #[cfg(not(feature = "debug-locations"))]
+    fn builder_warnings() {
+        let (_, diagnostics) = Diagnostics::collect(|| {
+            div()
+                .child(br().text("x"))
+                .child(p().key("a"))
+                .child(p().key("a"))
+                .done()
+        });
+
+        assert_eq!(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "VoidChildren: void element `br` has children, which are not rendered",
+                "DuplicateKey: key \"a\" is used by several children of `div`",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debug-locations")]
+    fn warnings_with_locations() {
+        let line = line!() + 2;
+        let (_, diagnostics) = Diagnostics::collect(|| {
+            let old = ul().child(li().key("a")).child(li()).done();
+            let new = ul().child(br().text("x")).child(li()).done();
+            let _ = diff(&old, &new);
+        });
+        let messages: Vec<String> = diagnostics.into_iter().map(|d| d.message).collect();
+
+        let built_at = format!("built at {}:{}:", file!(), line);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains(&format!(
+            "`br` has children, which are not rendered (built at {}:{}:",
+            file!(),
+            line + 1
+        )));
+        assert!(messages[1].contains(&format!(
+            "diffed by position (first keyed child {}",
+            built_at
+        )));
+    }
+
+    #[test]
+    fn diff_warnings() {
+        let old = ul().child(li().key("a")).child(li()).done();
+        let new = ul().child(li()).child(li().key("b")).done();
+
+        let (_, diagnostics) = Diagnostics::collect(|| diff(&old, &new));
+        let kinds: Vec<Kind> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec![Kind::MixedKeys]);
+
+        let (_, diagnostics) = Diagnostics::collect(|| diff(&old, &old));
+        assert!(diagnostics.is_empty());
+    }
+}
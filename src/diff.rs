@@ -9,11 +9,13 @@
 //! for each old child, and possibly new inserts.
 //!
 //! Each child must have an operation associated with it by the same index if
-//! a node with children is to be updated.
+//! a node with children is to be updated. Children after the last operation
+//! are skipped.
 //!
 //! Some operations are reducible if they occur in sequence. For example if
 //! there are several Skip(1) in a row, we can reduce them to single Skip(3).
-//! It still signifies skipping three children, but takes less space.
+//! It still signifies skipping three children, but takes less space. See
+//! `PatchBuilder` for all reductions.
 //!
 //! Position parameters (such as in Move or Insert operations) refer to
 //! positon in the new children list.
@@ -48,7 +50,7 @@
 //!     None,
 //!     Some(vec![
 //!         Update(Some(vec![InsertClass("message")]), None, None),
-//!         Move(3, None, Some(vec![Replace(VText("Bobby"))]), None),
+//!         Move(3, None, Some(vec![Text(Set("Bobby"))]), None),
 //!         Update(Some(vec![RemoveClass("offline"), InsertClass("online")]), None, None)
 //!         Update(Some(vec![RemoveClass("offline"), InsertClass("online")]), None, None)
 //!         Remove(1),
@@ -58,10 +60,26 @@
 //!     ])
 //! )
 //!
+use attrs;
+use component::VComponent;
+use diagnostics;
+use diff_cache;
 use element::VElement;
+use error::Error;
+use key::Key;
 use node::VNode;
-use op_queue::OpQueue;
+use patch_builder::PatchBuilder;
+use path::NodePath;
+use profile::{DiffProfile, Recorder};
+use state::StateStore;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use sync;
+use text::text;
+#[cfg(feature = "tracing")]
+use trace::{self, Phase};
 use types::CowString;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -71,6 +89,90 @@ pub enum AttrOp {
     Insert(String, String),
     Update(String, String),
     Remove(String),
+    /// Add a token to a token-list attribute, see `DiffOptions::token_lists`.
+    InsertToken(String, String),
+    /// Remove a token from a token-list attribute.
+    RemoveToken(String, String),
+}
+
+/// Attributes holding whitespace-separated tokens, which are worth diffing
+/// token by token with `DiffOptions::token_lists`.
+///
+pub const TOKEN_LIST_ATTRIBUTES: &[&str] = &[
+    "rel",
+    "rev",
+    "sandbox",
+    "headers",
+    "accesskey",
+    "aria-labelledby",
+    "aria-describedby",
+    "aria-controls",
+    "aria-owns",
+    "aria-flowto",
+];
+
+/// Change of the content of a text node which stays in place.
+///
+/// Offsets and lengths count UTF-16 code units, like `replaceData` of DOM
+/// text nodes.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TextOp {
+    /// Replace the whole content, like setting `nodeValue` of DOM text
+    /// nodes.
+    Set(String),
+    /// Remove `remove` units at `at`, then insert `insert` there.
+    Splice {
+        at: usize,
+        remove: usize,
+        insert: String,
+    },
+}
+
+impl TextOp {
+    /// Splice which turns the old content into the new one, covering only
+    /// the part between their common prefix and suffix.
+    ///
+    pub fn splice(old: &str, new: &str) -> TextOp {
+        let prefix: usize = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(old, new)| old == new)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+        let suffix: usize = old_rest
+            .chars()
+            .rev()
+            .zip(new_rest.chars().rev())
+            .take_while(|(old, new)| old == new)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let removed = &old_rest[..old_rest.len() - suffix];
+        TextOp::Splice {
+            at: old[..prefix].encode_utf16().count(),
+            remove: removed.encode_utf16().count(),
+            insert: new_rest[..new_rest.len() - suffix].to_string(),
+        }
+    }
+
+    /// Apply the change to the old content. Offsets past the end of the
+    /// content or inside a surrogate pair are clamped.
+    ///
+    pub fn apply(&self, content: &str) -> String {
+        match self {
+            TextOp::Set(content) => content.clone(),
+            TextOp::Splice { at, remove, insert } => {
+                let units: Vec<u16> = content.encode_utf16().collect();
+                let start = (*at).min(units.len());
+                let end = (start + remove).min(units.len());
+                let mut result = String::from_utf16_lossy(&units[..start]);
+                result.push_str(insert);
+                result.push_str(&String::from_utf16_lossy(&units[end..]));
+                result
+            }
+        }
+    }
 }
 
 pub type AttrDiff = Option<Vec<AttrOp>>;
@@ -85,25 +187,876 @@ pub enum NodeOp<'new> {
     Move(usize, AttrDiff, ChildDiff<'new>, ChildInserts<'new>),
     Update(AttrDiff, ChildDiff<'new>, ChildInserts<'new>),
     Replace(&'new VNode),
+    /// Change of the content of a text node, which keeps the node.
+    Text(TextOp),
+}
+
+impl fmt::Display for TextOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextOp::Set(content) => write!(f, "set {:?}", content),
+            TextOp::Splice { at, remove, insert } => {
+                write!(f, "splice at {} -{} +{:?}", at, remove, insert)
+            }
+        }
+    }
+}
+
+impl fmt::Display for AttrOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::AttrOp::*;
+
+        match self {
+            InsertClass(name) => write!(f, "+class {:?}", name),
+            RemoveClass(name) => write!(f, "-class {:?}", name),
+            Insert(name, value) => write!(f, "+{}={:?}", name, value),
+            Update(name, value) => write!(f, "~{}={:?}", name, value),
+            Remove(name) => write!(f, "-{}", name),
+            InsertToken(name, token) => write!(f, "+{} {:?}", name, token),
+            RemoveToken(name, token) => write!(f, "-{} {:?}", name, token),
+        }
+    }
+}
+
+/// Human-readable form of the diff, meant for debugging.
+///
+/// Each line is one operation, indented by depth. Operations on children are
+/// prefixed with the index of the old child they apply to, inserts with the
+/// position in the new children list. Attribute operations are sorted.
+///
+/// ```text
+/// Update
+///     child[0] Update ▸ +class "message"
+///     child[1] Move→3 ▸ +class "offline" ▸ -class "online"
+///     child[2..3] Skip
+///     child[4] Remove
+///     insert[4]
+///         <p class="offline">
+///             Fiz
+///         </p>
+/// ```
+///
+#[cfg(feature = "display")]
+impl<'new> fmt::Display for NodeOp<'new> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_range(start: usize, count: usize, f: &mut fmt::Formatter) -> fmt::Result {
+            if count == 1 {
+                write!(f, "child[{}] ", start)
+            } else {
+                write!(f, "child[{}..{}] ", start, start + count - 1)
+            }
+        }
+
+        fn fmt_node(indent_level: usize, node: &VNode, f: &mut fmt::Formatter) -> fmt::Result {
+            for line in node.to_string().lines() {
+                writeln!(f, "{}{}", "    ".repeat(indent_level), line)?;
+            }
+            Ok(())
+        }
+
+        fn fmt_update(
+            indent_level: usize,
+            attrs: &AttrDiff,
+            children: &ChildDiff,
+            inserts: &ChildInserts,
+            f: &mut fmt::Formatter,
+        ) -> fmt::Result {
+            let indent_string = "    ".repeat(indent_level);
+
+            if let Some(attrs) = attrs {
+                let mut attrs: Vec<&AttrOp> = attrs.iter().collect();
+                attrs.sort();
+                for attr in attrs {
+                    write!(f, " ▸ {}", attr)?;
+                }
+            }
+            writeln!(f)?;
+
+            if let Some(children) = children {
+                let mut index = 0;
+                for child in children {
+                    write!(f, "{}    ", indent_string)?;
+                    match child {
+                        NodeOp::Skip(count) | NodeOp::Remove(count) => {
+                            fmt_range(index, *count, f)?;
+                            index += count;
+                        }
+                        _ => {
+                            fmt_range(index, 1, f)?;
+                            index += 1;
+                        }
+                    }
+                    fmt_indent(indent_level + 1, child, f)?;
+                }
+            }
+
+            if let Some(inserts) = inserts {
+                for (position, node) in inserts {
+                    writeln!(f, "{}    insert[{}]", indent_string, position)?;
+                    fmt_node(indent_level + 2, node, f)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        fn fmt_indent(indent_level: usize, op: &NodeOp, f: &mut fmt::Formatter) -> fmt::Result {
+            match op {
+                NodeOp::Skip(_) => writeln!(f, "Skip"),
+                NodeOp::Remove(_) => writeln!(f, "Remove"),
+                NodeOp::Replace(node) => {
+                    writeln!(f, "Replace")?;
+                    fmt_node(indent_level + 1, node, f)
+                }
+                NodeOp::Move(position, attrs, children, inserts) => {
+                    write!(f, "Move→{}", position)?;
+                    fmt_update(indent_level, attrs, children, inserts, f)
+                }
+                NodeOp::Update(attrs, children, inserts) => {
+                    write!(f, "Update")?;
+                    fmt_update(indent_level, attrs, children, inserts, f)
+                }
+                NodeOp::Text(op) => writeln!(f, "Text ▸ {}", op),
+            }
+        }
+
+        fmt_indent(0, self, f)
+    }
 }
 
+/// Components which start or stop being part of the tree, collected by
+/// `diff_with_lifecycle`.
+///
+/// Components of removed and replaced subtrees are unmounted parents first,
+/// components of inserted and replacing subtrees are mounted children first.
+/// A component diffed against an old component of the same type stays
+/// mounted, even if it renders a different node.
+///
+#[derive(Debug, Default)]
+pub struct Lifecycle<'old, 'new> {
+    pub unmounted: Vec<&'old VComponent>,
+    pub mounted: Vec<&'new VComponent>,
+    enabled: bool,
+    /// Diffs of node pairs computed ahead, by node addresses, see
+    /// `async_diff`.
+    pub(crate) diffed: HashMap<(usize, usize), NodeOp<'new>>,
+    pub(crate) options: DiffOptions,
+    /// Sub-patches by subtree hashes, see `diff_cache`.
+    pub(crate) cache: Option<diff_cache::Active>,
+    /// Profile of the diff in progress, see `diff_with_profile`.
+    profile: Option<Recorder>,
+}
+
+impl<'old, 'new> Lifecycle<'old, 'new> {
+    /// Lifecycle of a tree which is inserted as a whole, when there is no old
+    /// tree to diff against.
+    ///
+    pub fn insert(new: &'new VNode) -> Self {
+        let mut lifecycle = Lifecycle {
+            enabled: true,
+            ..Lifecycle::default()
+        };
+        lifecycle.mount(new);
+        lifecycle
+    }
+
+    /// Call `unmounted` of unmounted components, then `mounted` of mounted
+    /// ones.
+    ///
+    pub fn notify(&self) {
+        for component in &self.unmounted {
+            component.unmounted();
+        }
+        for component in &self.mounted {
+            component.mounted();
+        }
+    }
+
+    fn unmount_component(&mut self, component: &'old VComponent) {
+        if self.enabled {
+            self.unmounted.push(component);
+        }
+    }
+
+    fn mount_component(&mut self, component: &'new VComponent) {
+        if self.enabled {
+            self.mounted.push(component);
+        }
+    }
+
+    /// Unmount components of a subtree which is removed or replaced.
+    ///
+    fn unmount(&mut self, node: &'old VNode) {
+        if !self.enabled {
+            return;
+        }
+        match node {
+            VNode::Component(component) => {
+                self.unmounted.push(component);
+                self.unmount(component.rendered());
+            }
+            VNode::Shared(shared) => self.unmount(shared),
+            VNode::Element(element) => {
+                for child in element.get_children() {
+                    self.unmount(child);
+                }
+            }
+            VNode::Text(_) => {}
+        }
+    }
+
+    /// Mount components of a subtree which is inserted or replacing.
+    ///
+    fn mount(&mut self, node: &'new VNode) {
+        if !self.enabled {
+            return;
+        }
+        match node {
+            VNode::Component(component) => {
+                self.mount(component.rendered());
+                self.mounted.push(component);
+            }
+            VNode::Shared(shared) => self.mount(shared),
+            VNode::Element(element) => {
+                for child in element.get_children() {
+                    self.mount(child);
+                }
+            }
+            VNode::Text(_) => {}
+        }
+    }
+}
+
+/// Diff the trees. Any pair of trees can be diffed: children lists with
+/// missing or duplicate keys are diffed by position.
+///
 pub fn diff<'new>(old: &VNode, new: &'new VNode) -> NodeOp<'new> {
+    diff_node(old, new, &mut Lifecycle::default())
+}
+
+/// Cost model of the diff.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffOptions {
+    /// Keyed children with fewer nodes than this, counting the child
+    /// itself, are removed and inserted again instead of being moved.
+    /// Moving a node in a real DOM can cost more than creating a small
+    /// one. Zero, the default, always moves.
+    pub replace_moves_below: usize,
+    /// Match children of lists without any keys by a hash of their tag and
+    /// attributes, or of their content for texts, instead of by position.
+    /// Reordered unkeyed lists are then moved instead of rewritten. Two
+    /// different children with equal tags and attributes are matched with
+    /// each other, so this is off by default.
+    pub key_by_content: bool,
+    /// Texts longer than this many bytes, before or after the change, are
+    /// changed with `TextOp::Splice` instead of `TextOp::Set`, so a small
+    /// edit of a long text sends only the edit. Zero, the default, always
+    /// sets the whole content.
+    pub splice_text_above: usize,
+    /// Attributes changed with `InsertToken` and `RemoveToken` of their
+    /// whitespace-separated tokens, like classes, instead of with `Update`,
+    /// for example `TOKEN_LIST_ATTRIBUTES`. Changes which reorder the kept
+    /// tokens still update the whole value. Empty by default.
+    pub token_lists: Vec<CowString>,
+    /// Record the time and operations of the diff of each node, see
+    /// `diff_with_profile`. Off by default.
+    pub profile: bool,
+    /// Fail on trees with nodes nested deeper than this, see
+    /// `try_diff_with_options`. Zero, the default, doesn't limit.
+    pub max_depth: usize,
+    /// Fail on trees with more nodes than this. Zero, the default, doesn't
+    /// limit.
+    pub max_nodes: usize,
+}
+
+/// Diff the trees with a tuned cost model.
+///
+pub fn diff_with_options<'new>(
+    old: &VNode,
+    new: &'new VNode,
+    options: &DiffOptions,
+) -> NodeOp<'new> {
+    let mut lifecycle = Lifecycle {
+        options: options.clone(),
+        ..Lifecycle::default()
+    };
+    diff_node(old, new, &mut lifecycle)
+}
+
+/// Diff the trees with a tuned cost model, after checking both against
+/// `max_depth` and `max_nodes`, for trees from untrusted sources.
+/// `diff_with_options` doesn't check the limits.
+///
+pub fn try_diff_with_options<'new>(
+    old: &VNode,
+    new: &'new VNode,
+    options: &DiffOptions,
+) -> Result<NodeOp<'new>, Error> {
+    old.check_limits(options.max_depth, options.max_nodes)?;
+    new.check_limits(options.max_depth, options.max_nodes)?;
+    Ok(diff_with_options(old, new, options))
+}
+
+/// Diff the trees with a tuned cost model, and return the `DiffProfile` of
+/// the diff if `options.profile` is set.
+///
+pub fn diff_with_profile<'new>(
+    old: &VNode,
+    new: &'new VNode,
+    options: &DiffOptions,
+) -> (NodeOp<'new>, Option<DiffProfile>) {
+    let mut lifecycle = Lifecycle {
+        options: options.clone(),
+        profile: if options.profile {
+            Some(Recorder::default())
+        } else {
+            None
+        },
+        ..Lifecycle::default()
+    };
+    let op = diff_node(old, new, &mut lifecycle);
+    let profile = lifecycle.profile.take().and_then(Recorder::finish);
+    (op, profile)
+}
+
+/// Diff the trees and collect components mounted and unmounted by the diff.
+///
+pub fn diff_with_lifecycle<'old, 'new>(
+    old: &'old VNode,
+    new: &'new VNode,
+) -> (NodeOp<'new>, Lifecycle<'old, 'new>) {
+    let mut lifecycle = Lifecycle {
+        enabled: true,
+        ..Lifecycle::default()
+    };
+    let op = diff_node(old, new, &mut lifecycle);
+    (op, lifecycle)
+}
+
+/// Diff lists of top-level nodes, such as several roots mounted into one
+/// container or children of a fragment, like children of an element.
+///
+/// Returns operations on the old nodes and inserts of new nodes, the
+/// children and inserts of an `Update` of their parent. Both are empty when
+/// the lists are the same.
+///
+pub fn diff_nodes<'new>(
+    old: &[VNode],
+    new: &'new [VNode],
+) -> (Vec<NodeOp<'new>>, Vec<ChildInsert<'new>>) {
+    let (children, inserts) = diff_children(old, new, &mut Lifecycle::default());
+    (children.unwrap_or_default(), inserts.unwrap_or_default())
+}
+
+/// Prune parts of a patch which do no work: updates which only skip
+/// children become `Skip(1)`, empty lists become `None`, and adjacent skips
+/// and removes are merged.
+///
+/// `diff` output is already minimal, but patches built or combined by other
+/// means, such as custom diff strategies, can carry such wrappers.
+///
+pub fn minimize<'new>(op: NodeOp<'new>) -> NodeOp<'new> {
+    use self::NodeOp::*;
+
+    match op {
+        Update(attrs, children, inserts) => match minimize_update(attrs, children, inserts) {
+            (None, None, None) => Skip(1),
+            (attrs, children, inserts) => Update(attrs, children, inserts),
+        },
+        Move(position, attrs, children, inserts) => {
+            let (attrs, children, inserts) = minimize_update(attrs, children, inserts);
+            Move(position, attrs, children, inserts)
+        }
+        op => op,
+    }
+}
+
+fn minimize_update<'new>(
+    attrs: AttrDiff,
+    children: ChildDiff<'new>,
+    inserts: ChildInserts<'new>,
+) -> (AttrDiff, ChildDiff<'new>, ChildInserts<'new>) {
+    let attrs = attrs.filter(|attrs| !attrs.is_empty());
+    let children = children.and_then(|children| {
+        let mut builder = PatchBuilder::new();
+        for op in children {
+            builder.push(minimize(op));
+        }
+        let ops = builder.done();
+        if ops.is_empty() {
+            None
+        } else {
+            Some(ops)
+        }
+    });
+    let inserts = inserts.filter(|inserts| !inserts.is_empty());
+    (attrs, children, inserts)
+}
+
+/// Copy of an element without its children.
+///
+fn copy_element_shell(element: &VElement) -> VElement {
+    let tag = element.get_tag().to_string();
+    let mut copy = if element.is_void() {
+        VElement::new_void(tag)
+    } else {
+        VElement::new(tag)
+    };
+    if let Some(key) = element.get_key() {
+        copy = copy.key(key.clone());
+    }
+    for (name, value) in element.get_attributes() {
+        copy = copy.attr(name.clone(), value.clone());
+    }
+    for class in element.get_classes() {
+        copy = copy.class(class.clone());
+    }
+    copy
+}
+
+/// Build the new tree from the old tree and its patch, as a patcher does
+/// with a real DOM. Components and shared nodes of the old tree which the
+/// patch updates come out as the nodes they stand for.
+///
+/// Meant for checking diffs in tests, and for consumers which need the new
+/// tree without an interpreter of their own.
+///
+pub fn apply(old: &VNode, op: &NodeOp) -> Result<VNode, Error> {
+    match (op, old.resolve()) {
+        (NodeOp::Skip(1), _) => Ok(old.clone()),
+        (NodeOp::Replace(new), _) => Ok((*new).clone()),
+        (NodeOp::Update(attrs, children, inserts), VNode::Element(element)) => {
+            apply_update(element, attrs, children, inserts)
+        }
+        (NodeOp::Text(op), VNode::Text(old)) => Ok(text(op.apply(old.get_content())).done()),
+        (NodeOp::Update(..), _) => Err(Error::InvalidPatch("Update on a text node".to_string())),
+        (NodeOp::Text(_), _) => Err(Error::InvalidPatch("Text on an element".to_string())),
+        (op, _) => {
+            let message = format!("unexpected root operation {:?}", op);
+            Err(Error::InvalidPatch(message))
+        }
+    }
+}
+
+fn apply_update(
+    old: &VElement,
+    attrs: &AttrDiff,
+    children: &ChildDiff,
+    inserts: &ChildInserts,
+) -> Result<VNode, Error> {
+    // Attributes are applied on a shell with old attributes and classes.
+    let mut shell = copy_element_shell(old);
+    let mut removed_attrs = Vec::new();
+    let mut removed_classes = Vec::new();
+    for attr in attrs.iter().flatten() {
+        match attr {
+            AttrOp::InsertClass(name) => shell = shell.class(name.clone()),
+            AttrOp::RemoveClass(name) => removed_classes.push(name.as_str()),
+            AttrOp::Insert(name, value) | AttrOp::Update(name, value) => {
+                shell = shell.attr(name.clone(), value.clone())
+            }
+            AttrOp::Remove(name) => removed_attrs.push(name.as_str()),
+            AttrOp::InsertToken(name, token) | AttrOp::RemoveToken(name, token) => {
+                let value = shell.get_attributes().get(name.as_str()).ok_or_else(|| {
+                    Error::InvalidPatch(format!("token of missing attribute {}", name))
+                })?;
+                let mut tokens: Vec<&str> =
+                    value.split_whitespace().filter(|t| t != token).collect();
+                if let AttrOp::InsertToken(..) = attr {
+                    tokens.push(token);
+                }
+                let value = tokens.join(" ");
+                shell = shell.attr(name.clone(), value)
+            }
+        }
+    }
+    let mut element = VElement::new(shell.get_tag().to_string());
+    if shell.is_void() {
+        element = VElement::new_void(shell.get_tag().to_string());
+    }
+    if let Some(key) = shell.get_key() {
+        element = element.key(key.clone());
+    }
+    for (name, value) in shell.get_attributes() {
+        if !removed_attrs.contains(&name.as_ref()) {
+            element = element.attr(name.clone(), value.clone());
+        }
+    }
+    for class in shell.get_classes() {
+        if !removed_classes.contains(&class.as_ref()) {
+            element = element.class(class.clone());
+        }
+    }
+
+    // Children which stay in place, in order.
+    let mut staying = Vec::new();
+    // Children placed at explicit positions, by Move or Insert.
+    let mut placed = Vec::new();
+
+    let old_children = old.get_children();
+    let mut index = 0;
+    let old_child = |index: usize| {
+        old_children
+            .get(index)
+            .ok_or_else(|| Error::InvalidPatch(format!("operation on missing child {}", index)))
+    };
+
+    for op in children.iter().flatten() {
+        match op {
+            NodeOp::Skip(0) | NodeOp::Remove(0) => {
+                return Err(Error::InvalidPatch(format!("empty {:?}", op)));
+            }
+            NodeOp::Skip(count) => {
+                for _ in 0..*count {
+                    staying.push(old_child(index)?.clone());
+                    index += 1;
+                }
+            }
+            NodeOp::Remove(count) => {
+                old_child(index + count - 1)?;
+                index += count;
+            }
+            NodeOp::Move(position, attrs, children, inserts) => {
+                let moved = match old_child(index)?.resolve() {
+                    VNode::Element(element) => apply_update(element, attrs, children, inserts)?,
+                    text if attrs.is_none() && children.is_none() && inserts.is_none() => {
+                        text.clone()
+                    }
+                    _ => return Err(Error::InvalidPatch("update of a text".to_string())),
+                };
+                placed.push((*position, moved));
+                index += 1;
+            }
+            op => {
+                staying.push(apply(old_child(index)?, op)?);
+                index += 1;
+            }
+        }
+    }
+    for child in &old_children[index.min(old_children.len())..] {
+        staying.push(child.clone());
+    }
+    for (position, node) in inserts.iter().flatten() {
+        placed.push((*position, (*node).clone()));
+    }
+
+    let new_len = staying.len() + placed.len();
+    let mut slots: Vec<Option<VNode>> = (0..new_len).map(|_| None).collect();
+    for (position, node) in placed {
+        match slots.get_mut(position) {
+            Some(slot @ None) => *slot = Some(node),
+            Some(Some(_)) => {
+                let message = format!("two children placed at {}", position);
+                return Err(Error::InvalidPatch(message));
+            }
+            None => {
+                let message = format!("position {} out of {} children", position, new_len);
+                return Err(Error::InvalidPatch(message));
+            }
+        }
+    }
+    let mut staying = staying.into_iter();
+    for slot in slots {
+        element = element.child_node(match slot {
+            Some(node) => node,
+            None => staying.next().unwrap(),
+        });
+    }
+
+    Ok(element.done())
+}
+
+impl<'new> NodeOp<'new> {
+    /// Check that the patch fits the old tree, by simulating it: every
+    /// operation refers to an existing child, attribute and class operations
+    /// find the old element with or without the attribute or class as
+    /// expected, and moved and inserted children fill the new children list
+    /// without gaps or overlaps.
+    ///
+    /// Meant for catching bugs in code which builds or transforms patches.
+    /// `VDom::update` checks its diffs with debug assertions enabled.
+    ///
+    pub fn validate_against(&self, old: &VNode) -> Result<(), Error> {
+        let path = &mut NodePath::root();
+        match self {
+            NodeOp::Skip(1) | NodeOp::Replace(_) => Ok(()),
+            NodeOp::Text(_) => validate_text(old, path),
+            NodeOp::Update(attrs, children, inserts) => {
+                validate_update(old, attrs, children, inserts, path)
+            }
+            op => Err(invalid_op(
+                path,
+                format!("unexpected root operation {:?}", op),
+            )),
+        }
+    }
+}
+
+fn invalid_op(path: &NodePath, message: String) -> Error {
+    Error::InvalidPatch(format!("at {}: {}", path, message))
+}
+
+fn validate_text(old: &VNode, path: &NodePath) -> Result<(), Error> {
+    match old.resolve() {
+        VNode::Text(_) => Ok(()),
+        _ => Err(invalid_op(path, "text change of an element".to_string())),
+    }
+}
+
+fn validate_update(
+    old: &VNode,
+    attrs: &AttrDiff,
+    children: &ChildDiff,
+    inserts: &ChildInserts,
+    path: &mut NodePath,
+) -> Result<(), Error> {
+    let element = match old.resolve() {
+        VNode::Element(element) => element,
+        VNode::Text(_) => return Err(invalid_op(path, "update of a text".to_string())),
+        _ => unreachable!("resolved node is an element or text"),
+    };
+
+    for attr in attrs.iter().flatten() {
+        let fits = match attr {
+            AttrOp::InsertClass(name) => !element.get_classes().contains(name.as_str()),
+            AttrOp::RemoveClass(name) => element.get_classes().contains(name.as_str()),
+            AttrOp::Insert(name, _) => !element.get_attributes().contains_key(name.as_str()),
+            AttrOp::Update(name, _) | AttrOp::Remove(name) => {
+                element.get_attributes().contains_key(name.as_str())
+            }
+            AttrOp::InsertToken(name, token) | AttrOp::RemoveToken(name, token) => {
+                match element.get_attributes().get(name.as_str()) {
+                    Some(value) => {
+                        let present = value.split_whitespace().any(|t| t == token);
+                        present == matches!(attr, AttrOp::RemoveToken(..))
+                    }
+                    None => false,
+                }
+            }
+        };
+        if !fits {
+            return Err(invalid_op(
+                path,
+                format!("{} doesn't fit `{}`", attr, element.get_tag()),
+            ));
+        }
+    }
+
+    if children.is_none() && inserts.is_none() {
+        return Ok(());
+    }
+    if element.is_void() {
+        let message = format!("children of void element `{}`", element.get_tag());
+        return Err(invalid_op(path, message));
+    }
+
+    let old_children = element.get_children();
+    let mut index = 0;
+    let mut staying = 0;
+    let mut placed = Vec::new();
+    for op in children.iter().flatten() {
+        let count = match op {
+            NodeOp::Skip(0) | NodeOp::Remove(0) => {
+                return Err(invalid_op(path, format!("empty {:?}", op)));
+            }
+            NodeOp::Skip(count) | NodeOp::Remove(count) => *count,
+            _ => 1,
+        };
+        if index + count > old_children.len() {
+            let message = format!(
+                "operation on missing child {} of {}",
+                index + count - 1,
+                old_children.len()
+            );
+            return Err(invalid_op(path, message));
+        }
+        match op {
+            NodeOp::Skip(_) | NodeOp::Replace(_) => staying += count,
+            NodeOp::Text(_) => {
+                path.push(index);
+                validate_text(&old_children[index], path)?;
+                path.pop();
+                staying += 1;
+            }
+            NodeOp::Remove(_) => {}
+            NodeOp::Update(attrs, children, inserts) => {
+                path.push(index);
+                validate_update(&old_children[index], attrs, children, inserts, path)?;
+                path.pop();
+                staying += 1;
+            }
+            NodeOp::Move(position, attrs, children, inserts) => {
+                // Moves of text children, as with `key_by_content`, carry no
+                // update. Text which changes is removed and inserted instead.
+                let text = matches!(old_children[index].resolve(), VNode::Text(_));
+                if !text || attrs.is_some() || children.is_some() || inserts.is_some() {
+                    path.push(index);
+                    validate_update(&old_children[index], attrs, children, inserts, path)?;
+                    path.pop();
+                }
+                placed.push(*position);
+            }
+        }
+        index += count;
+    }
+    staying += old_children.len() - index;
+    placed.extend(inserts.iter().flatten().map(|(position, _)| *position));
+
+    let new_len = staying + placed.len();
+    let mut filled = vec![false; new_len];
+    for position in placed {
+        match filled.get_mut(position) {
+            Some(slot @ false) => *slot = true,
+            Some(true) => {
+                let message = format!("two children placed at {}", position);
+                return Err(invalid_op(path, message));
+            }
+            None => {
+                let message = format!("position {} out of {} children", position, new_len);
+                return Err(invalid_op(path, message));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Key of a node pair in `Lifecycle::diffed`.
+///
+pub(crate) fn node_pair(old: &VNode, new: &VNode) -> (usize, usize) {
+    (old as *const VNode as usize, new as *const VNode as usize)
+}
+
+pub(crate) fn diff_node<'old, 'new>(
+    old: &'old VNode,
+    new: &'new VNode,
+    lifecycle: &mut Lifecycle<'old, 'new>,
+) -> NodeOp<'new> {
+    if let Some(recorder) = lifecycle.profile.as_mut() {
+        recorder.enter(new);
+        let op = diff_node_unprofiled(old, new, lifecycle);
+        if let Some(recorder) = lifecycle.profile.as_mut() {
+            recorder.exit(&op);
+        }
+        return op;
+    }
+    diff_node_unprofiled(old, new, lifecycle)
+}
+
+fn diff_node_unprofiled<'old, 'new>(
+    old: &'old VNode,
+    new: &'new VNode,
+    lifecycle: &mut Lifecycle<'old, 'new>,
+) -> NodeOp<'new> {
+    if !lifecycle.diffed.is_empty() {
+        if let Some(op) = lifecycle.diffed.remove(&node_pair(old, new)) {
+            return op;
+        }
+    }
+
+    let cached = lifecycle.cache.as_mut().and_then(|cache| {
+        let key = cache.key(old, new)?;
+        Some((key, cache.get(key, new)))
+    });
+    match cached {
+        Some((_, Some(op))) => op,
+        Some((key, None)) => {
+            let op = diff_pair(old, new, lifecycle);
+            if let Some(cache) = lifecycle.cache.as_mut() {
+                cache.insert(key, &op, new);
+            }
+            op
+        }
+        None => diff_pair(old, new, lifecycle),
+    }
+}
+
+fn diff_pair<'old, 'new>(
+    old: &'old VNode,
+    new: &'new VNode,
+    lifecycle: &mut Lifecycle<'old, 'new>,
+) -> NodeOp<'new> {
     use self::NodeOp::*;
     use node::VNode::*;
 
     match (old, new) {
+        // Static subtree which was mounted before produces Skip without
+        // being compared.
+        (Element(old_element), Element(new_element))
+            if old_element.is_static_subtree() && new_element.is_static_subtree() =>
+        {
+            StateStore::keep(old);
+            Skip(1)
+        }
+        // Component which doesn't need update, and has no stateful
+        // component with changed state under it, produces Skip without being
+        // rendered.
+        (Component(old_component), Component(new_component))
+            if !new_component.should_update(old_component) && !StateStore::is_dirty(old) =>
+        {
+            new_component.adopt(old_component);
+            StateStore::keep(old);
+            Skip(1)
+        }
+        // Same shared node from the previous frame produces Skip.
+        (Shared(old_shared), Shared(new_shared))
+            if sync::Shared::ptr_eq(old_shared, new_shared) =>
+        {
+            StateStore::keep(old);
+            Skip(1)
+        }
+        // Otherwise components and shared nodes are diffed as the nodes
+        // they stand for, one level at a time so nested components are
+        // seen. Components of different types are unmounted and mounted
+        // around the diff of their nodes.
+        (Shared(old_shared), _) => diff_node(old_shared, new, lifecycle),
+        (_, Shared(new_shared)) => diff_node(old, new_shared, lifecycle),
+        (Component(old_component), Component(new_component))
+            if new_component.is_same(old_component) =>
+        {
+            diff_node(
+                old_component.rendered(),
+                new_component.rendered(),
+                lifecycle,
+            )
+        }
+        (Component(old_component), _) => {
+            lifecycle.unmount_component(old_component);
+            diff_node(old_component.rendered(), new, lifecycle)
+        }
+        (_, Component(new_component)) => {
+            let op = diff_node(old, new_component.rendered(), lifecycle);
+            lifecycle.mount_component(new_component);
+            op
+        }
         (Element(old_element), Element(new_element)) => {
-            // Elements with different tags produce Replace.
-            if old_element.get_tag() != new_element.get_tag() {
-                Replace(&new)
-            // Elements with different keys produce Replace.
-            } else if old_element.get_key() != new_element.get_key() {
-                Replace(&new)
+            // Elements with different tags, keys or voidness produce Replace.
+            if old_element.get_tag() != new_element.get_tag()
+                || old_element.get_key() != new_element.get_key()
+                || old_element.is_void() != new_element.is_void()
+            {
+                lifecycle.unmount(old);
+                lifecycle.mount(new);
+                Replace(new)
             // Diff attributes and children lists.
             } else {
-                let attr_diff = diff_attributes(old_element, new_element);
-                // TODO: Diff children.
-                let (children_diff, children_inserts) = diff_children(old_element, new_element);
+                #[cfg(feature = "tracing")]
+                let span = trace::enter(Phase::Attributes);
+                let attr_diff =
+                    diff_attributes(old_element, new_element, &lifecycle.options.token_lists);
+                #[cfg(feature = "tracing")]
+                span.exit(attr_diff.as_ref().map_or(0, Vec::len));
+                // Void elements can't have children, so patchers never get
+                // child operations for them.
+                let (children_diff, children_inserts) = if new_element.is_void() {
+                    (None, None)
+                } else {
+                    diff_children(
+                        old_element.get_children(),
+                        new_element.get_children(),
+                        lifecycle,
+                    )
+                };
                 // Check if either of attr and children diffs returned Some.
                 match (attr_diff, children_diff, children_inserts) {
                     // No diffs produce Skip.
@@ -113,12 +1066,34 @@ pub fn diff<'new>(old: &VNode, new: &'new VNode) -> NodeOp<'new> {
                 }
             }
         }
+        // Equal text produces Skip.
+        (VNode::Text(old_text), VNode::Text(new_text)) if old_text == new_text => Skip(1),
+        // Long text is spliced, when enabled.
+        (VNode::Text(old_text), VNode::Text(new_text))
+            if lifecycle.options.splice_text_above > 0
+                && old_text
+                    .get_content()
+                    .len()
+                    .max(new_text.get_content().len())
+                    > lifecycle.options.splice_text_above =>
+        {
+            let (old_content, new_content) = (old_text.get_content(), new_text.get_content());
+            NodeOp::Text(TextOp::splice(old_content, new_content))
+        }
+        // Other text changes set the content, so the node is kept.
+        (VNode::Text(_), VNode::Text(new_text)) => {
+            NodeOp::Text(TextOp::Set(new_text.get_content().to_string()))
+        }
         // VNodes of different type produce Replace
-        _ => Replace(&new),
+        _ => {
+            lifecycle.unmount(old);
+            lifecycle.mount(new);
+            Replace(new)
+        }
     }
 }
 
-fn diff_attributes(old: &VElement, new: &VElement) -> AttrDiff {
+fn diff_attributes(old: &VElement, new: &VElement, token_lists: &[CowString]) -> AttrDiff {
     use self::AttrOp::*;
 
     // Find removed and inserted classes
@@ -127,11 +1102,11 @@ fn diff_attributes(old: &VElement, new: &VElement) -> AttrDiff {
     let new_classes = new.get_classes();
 
     let remove_classes: Vec<AttrOp> = old_classes
-        .difference(&new_classes)
+        .difference(new_classes)
         .map(|c| RemoveClass(c.clone().into_owned()))
         .collect();
     let insert_classes: Vec<AttrOp> = new_classes
-        .difference(&old_classes)
+        .difference(old_classes)
         .map(|c| InsertClass(c.clone().into_owned()))
         .collect();
 
@@ -156,7 +1131,14 @@ fn diff_attributes(old: &VElement, new: &VElement) -> AttrDiff {
                 value.clone().into_owned(),
             )),
             (Some(old_value), Some(new_value)) => {
-                if old_value != new_value {
+                if attrs::same_value(key, old_value, new_value) {
+                } else if let Some(ops) = token_lists
+                    .iter()
+                    .find(|name| *name == key)
+                    .and_then(|_| diff_tokens(key, old_value, new_value))
+                {
+                    attr_diff.extend(ops);
+                } else {
                     attr_diff.push(AttrOp::Update(
                         key.clone().into_owned(),
                         new_value.clone().into_owned(),
@@ -169,37 +1151,119 @@ fn diff_attributes(old: &VElement, new: &VElement) -> AttrDiff {
 
     // Return result
 
-    if attr_diff.len() > 0 {
+    if !attr_diff.is_empty() {
         Some(attr_diff)
     } else {
         None
     }
 }
 
-fn diff_children<'new>(
-    old: &VElement,
-    new: &'new VElement,
+/// Token operations turning the old value into the new one, if applying
+/// them keeps the order of the new tokens.
+fn diff_tokens(name: &str, old: &str, new: &str) -> Option<Vec<AttrOp>> {
+    let old_tokens: Vec<&str> = old.split_whitespace().collect();
+    let new_tokens: Vec<&str> = new.split_whitespace().collect();
+
+    let mut removed: Vec<&str> = vec![];
+    for token in &old_tokens {
+        if !new_tokens.contains(token) && !removed.contains(token) {
+            removed.push(token);
+        }
+    }
+    let mut inserted: Vec<&str> = vec![];
+    for token in &new_tokens {
+        if !old_tokens.contains(token) && !inserted.contains(token) {
+            inserted.push(token);
+        }
+    }
+
+    // Removing drops tokens in place and inserting appends them, like
+    // `DOMTokenList` does.
+    let mut result: Vec<&str> = old_tokens
+        .iter()
+        .filter(|t| !removed.contains(t))
+        .cloned()
+        .collect();
+    result.extend(inserted.iter().cloned());
+    if result != new_tokens {
+        return None;
+    }
+
+    let remove = removed
+        .into_iter()
+        .map(|t| AttrOp::RemoveToken(name.to_string(), t.to_string()));
+    let insert = inserted
+        .into_iter()
+        .map(|t| AttrOp::InsertToken(name.to_string(), t.to_string()));
+    Some(remove.chain(insert).collect())
+}
+
+fn diff_children<'old, 'new>(
+    old_children: &'old [VNode],
+    new_children: &'new [VNode],
+    lifecycle: &mut Lifecycle<'old, 'new>,
 ) -> (ChildDiff<'new>, ChildInserts<'new>) {
     use self::NodeOp::*;
 
-    let old_children = old.get_children();
-    let new_children = new.get_children();
-
     match (old_children.len(), new_children.len()) {
         // Both children lists are empty, no diff and no inserts.
         (0, 0) => (None, None),
         // Old children list is not empty, add Remove for each old child.
-        (old_len, 0) => (Some(vec![Remove(old_len)]), None),
+        (old_len, 0) => {
+            for child in old_children {
+                lifecycle.unmount(child);
+            }
+            (Some(vec![Remove(old_len)]), None)
+        }
         // New children list is not empty, add Insert for each new child.
-        (0, _) => (None, Some(new_children.iter().enumerate().collect())),
+        (0, _) => {
+            for child in new_children {
+                lifecycle.mount(child);
+            }
+            (None, Some(new_children.iter().enumerate().collect()))
+        }
+        // Neither list has keys, match children by their content.
+        (_, _)
+            if lifecycle.options.key_by_content
+                && old_children
+                    .iter()
+                    .chain(new_children)
+                    .all(|child| child.key().is_none()) =>
+        {
+            let mut builder = PatchBuilder::new();
+            let mut inserts: Vec<ChildInsert> = Vec::new();
+            diff_middles(
+                &mut builder,
+                &mut inserts,
+                0,
+                content_keys(old_children),
+                content_keys(new_children),
+                lifecycle,
+            );
+
+            #[cfg(feature = "tracing")]
+            let span = trace::enter(Phase::Emit);
+            let (ops, inserts) = builder.done_with_inserts(inserts);
+            #[cfg(feature = "tracing")]
+            span.exit(ops.len() + inserts.len());
+            match (ops.len(), inserts.len()) {
+                (0, 0) => (None, None),
+                (0, _) => (None, Some(inserts)),
+                (_, 0) => (Some(ops), None),
+                (_, _) => (Some(ops), Some(inserts)),
+            }
+        }
         // Both children lists are not empty
         (old_len, new_len) => {
-            let mut op_queue = OpQueue::new();
+            let mut builder = PatchBuilder::new();
             let mut inserts: Vec<ChildInsert> = Vec::new();
 
             let old_children_len = old_children.len();
             let new_children_len = new_children.len();
 
+            #[cfg(feature = "tracing")]
+            let span = trace::enter(Phase::Scan);
+
             // Find common prefix length
             let max_prefix_len = old_len.min(new_len);
             let mut prefix_len = 0;
@@ -225,13 +1289,16 @@ fn diff_children<'new>(
                 }
             }
 
+            #[cfg(feature = "tracing")]
+            span.exit(prefix_len + suffix_len);
+
             // Calculate middle length for both lists
             let old_middle_len = old_len - (prefix_len + suffix_len);
             let new_middle_len = new_len - (prefix_len + suffix_len);
 
             // Push operations for common prefix
             for i in 0..prefix_len {
-                op_queue.push(diff(&old_children[i], &new_children[i]));
+                builder.push(diff_node(&old_children[i], &new_children[i], lifecycle));
             }
 
             // Push operations for middle
@@ -239,12 +1306,20 @@ fn diff_children<'new>(
                 // Both middles are empty, do nothing
                 (0, 0) => {}
                 // New middle is empty, add Remove for each old middle child
-                (old_middle_len, 0) => op_queue.push(Remove(old_middle_len)),
+                (old_middle_len, 0) => {
+                    let middle = &old_children[prefix_len..(prefix_len + old_middle_len)];
+                    for child in middle {
+                        lifecycle.unmount(child);
+                    }
+                    builder.push(Remove(old_middle_len));
+                }
                 // Old middle is empty, add Insert for each new middle child
                 (0, new_middle_len) => {
-                    for i in prefix_len..(prefix_len + new_middle_len) {
-                        inserts.push((i, &new_children[i]));
+                    let middle = prefix_len..(prefix_len + new_middle_len);
+                    for child in &new_children[middle.clone()] {
+                        lifecycle.mount(child);
                     }
+                    inserts.extend(middle.clone().zip(&new_children[middle]));
                 }
                 // TODO: Collecting references to VNodes can be suboptimal if
                 // the middle is big.
@@ -262,13 +1337,31 @@ fn diff_children<'new>(
                         .iter()
                         .collect();
 
-                    diff_middles(
-                        &mut op_queue,
-                        &mut inserts,
-                        prefix_len,
-                        old_middle_children,
-                        new_middle_children,
-                    );
+                    // Children can only be matched by keys if keys are
+                    // unique.
+                    if uniquely_keyed(&old_middle_children) && uniquely_keyed(&new_middle_children)
+                    {
+                        diff_middles(
+                            &mut builder,
+                            &mut inserts,
+                            prefix_len,
+                            with_keys(old_middle_children),
+                            with_keys(new_middle_children),
+                            lifecycle,
+                        );
+                    } else {
+                        if diagnostics::is_collecting() {
+                            report_positional(&old_middle_children, &new_middle_children);
+                        }
+                        diff_middles_by_position(
+                            &mut builder,
+                            &mut inserts,
+                            prefix_len,
+                            old_middle_children,
+                            new_middle_children,
+                            lifecycle,
+                        );
+                    }
                 }
             };
 
@@ -277,15 +1370,20 @@ fn diff_children<'new>(
             let new_suffix_start = new_len - suffix_len;
 
             for i in 0..suffix_len {
-                op_queue.push(diff(
+                builder.push(diff_node(
                     &old_children[old_suffix_start + i],
                     &new_children[new_suffix_start + i],
+                    lifecycle,
                 ));
             }
 
             // Extract operations and generate final results
 
-            let mut ops = op_queue.remove_single_skip().done();
+            #[cfg(feature = "tracing")]
+            let span = trace::enter(Phase::Emit);
+            let (ops, inserts) = builder.done_with_inserts(inserts);
+            #[cfg(feature = "tracing")]
+            span.exit(ops.len() + inserts.len());
 
             match (ops.len(), inserts.len()) {
                 (0, 0) => (None, None),
@@ -297,41 +1395,222 @@ fn diff_children<'new>(
     }
 }
 
-// TODO: Implement middle children reconciliation
-fn diff_middles<'new>(
-    op_queue: &mut OpQueue<'new>,
+/// Pairs of old and new children which `diff_children` diffs against each
+/// other, with indices of the old children.
+///
+pub(crate) fn child_pairs<'old, 'new>(
+    old: &'old VElement,
+    new: &'new VElement,
+) -> Vec<(usize, &'old VNode, &'new VNode)> {
+    if old.is_static_subtree() && new.is_static_subtree() {
+        return Vec::new();
+    }
+
+    let old_children = old.get_children();
+    let new_children = new.get_children();
+    let (old_len, new_len) = (old_children.len(), new_children.len());
+
+    let max_prefix_len = old_len.min(new_len);
+    let prefix_len = (0..max_prefix_len)
+        .take_while(|i| old_children[*i].key() == new_children[*i].key())
+        .count();
+    let suffix_len = (0..max_prefix_len - prefix_len)
+        .take_while(|i| old_children[old_len - i - 1].key() == new_children[new_len - i - 1].key())
+        .count();
+
+    let mut pairs: Vec<(usize, &VNode, &VNode)> = (0..prefix_len)
+        .map(|i| (i, &old_children[i], &new_children[i]))
+        .collect();
+
+    let old_middle: Vec<&VNode> = old_children[prefix_len..old_len - suffix_len]
+        .iter()
+        .collect();
+    let new_middle: Vec<&VNode> = new_children[prefix_len..new_len - suffix_len]
+        .iter()
+        .collect();
+    if old_middle.is_empty() || new_middle.is_empty() {
+        // Middle children are only removed or inserted.
+    } else if uniquely_keyed(&old_middle) && uniquely_keyed(&new_middle) {
+        let new_by_key: HashMap<&Key, &VNode> = new_middle
+            .iter()
+            .map(|child| (child.key().expect("children are keyed"), *child))
+            .collect();
+        for (offset, old_child) in old_middle.into_iter().enumerate() {
+            let key = old_child.key().expect("children are keyed");
+            if let Some(new_child) = new_by_key.get(key) {
+                pairs.push((prefix_len + offset, old_child, new_child));
+            }
+        }
+    } else {
+        let middle = old_middle.into_iter().zip(new_middle).enumerate();
+        pairs.extend(middle.map(|(offset, (old, new))| (prefix_len + offset, old, new)));
+    }
+
+    pairs.extend((0..suffix_len).map(|i| {
+        let (old_index, new_index) = (old_len - suffix_len + i, new_len - suffix_len + i);
+        (
+            old_index,
+            &old_children[old_index],
+            &new_children[new_index],
+        )
+    }));
+    pairs
+}
+
+/// Check if all children have keys, and no key is used twice.
+///
+fn uniquely_keyed(children: &[&VNode]) -> bool {
+    let mut keys = HashSet::with_capacity(children.len());
+    children
+        .iter()
+        .all(|child| child.key().is_some_and(|key| keys.insert(key)))
+}
+
+fn with_keys(children: Vec<&VNode>) -> Vec<(&Key, &VNode)> {
+    children
+        .into_iter()
+        .map(|child| (child.key().expect("children are keyed"), child))
+        .collect()
+}
+
+/// Implicit keys of unkeyed children: a hash of the tag and attributes of
+/// elements, or of the content of texts, and how many earlier children had
+/// the same hash.
+///
+fn content_keys(children: &[VNode]) -> Vec<((u64, usize), &VNode)> {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    children
+        .iter()
+        .map(|child| {
+            let mut hasher = DefaultHasher::new();
+            match child.resolve() {
+                VNode::Element(element) => {
+                    element.get_tag().hash(&mut hasher);
+                    element.is_void().hash(&mut hasher);
+                    let mut classes: Vec<&CowString> = element.get_classes().iter().collect();
+                    classes.sort();
+                    classes.hash(&mut hasher);
+                    let mut attributes: Vec<(&CowString, &CowString)> =
+                        element.get_attributes().iter().collect();
+                    attributes.sort();
+                    attributes.hash(&mut hasher);
+                }
+                VNode::Text(text) => text.get_content().hash(&mut hasher),
+                // Components are matched by their order among components.
+                _ => {}
+            }
+            let hash = hasher.finish();
+            let count = seen.entry(hash).or_insert(0);
+            *count += 1;
+            ((hash, *count - 1), child)
+        })
+        .collect()
+}
+
+/// Report children which have keys but are diffed by position, because
+/// other children don't have keys or keys are duplicated.
+///
+fn report_positional(old_children: &[&VNode], new_children: &[&VNode]) {
+    let count = old_children.len() + new_children.len();
+    let keyed = old_children
+        .iter()
+        .chain(new_children)
+        .filter(|child| child.key().is_some())
+        .count();
+    if keyed == 0 {
+        return;
+    }
+    // Where the first keyed child was built, with `debug-locations`.
+    let built_at = new_children
+        .iter()
+        .chain(old_children)
+        .filter(|child| child.key().is_some())
+        .find_map(|child| match child.resolve() {
+            VNode::Element(element) => element.get_location(),
+            _ => None,
+        })
+        .map_or(String::new(), |location| {
+            format!(" (first keyed child built at {})", location)
+        });
+    if keyed < count {
+        let message = format!(
+            "{} of {} children have keys, children are diffed by position{}",
+            keyed, count, built_at
+        );
+        diagnostics::report(diagnostics::Kind::MixedKeys, message);
+    } else {
+        let message = format!(
+            "children with duplicate keys are diffed by position{}",
+            built_at
+        );
+        diagnostics::report(diagnostics::Kind::DuplicateKey, message);
+    }
+}
+
+/// Diff children at the same positions, then remove or insert the rest.
+///
+fn diff_middles_by_position<'old, 'new>(
+    builder: &mut PatchBuilder<'new>,
     inserts: &mut Vec<ChildInsert<'new>>,
     offset: usize,
-    old_children: Vec<&VNode>,
+    old_children: Vec<&'old VNode>,
     new_children: Vec<&'new VNode>,
+    lifecycle: &mut Lifecycle<'old, 'new>,
+) {
+    for (old_child, new_child) in old_children.iter().zip(&new_children) {
+        builder.push(diff_node(old_child, new_child, lifecycle));
+    }
+    if old_children.len() > new_children.len() {
+        for child in &old_children[new_children.len()..] {
+            lifecycle.unmount(child);
+        }
+        builder.push(NodeOp::Remove(old_children.len() - new_children.len()));
+    }
+    for (index, child) in new_children.iter().enumerate().skip(old_children.len()) {
+        lifecycle.mount(child);
+        inserts.push((offset + index, child));
+    }
+}
+
+/// Diff children by unique keys, each child paired with its key.
+///
+fn diff_middles<'old, 'new, K: Eq + Hash>(
+    builder: &mut PatchBuilder<'new>,
+    inserts: &mut Vec<ChildInsert<'new>>,
+    offset: usize,
+    old_children: Vec<(K, &'old VNode)>,
+    new_children: Vec<(K, &'new VNode)>,
+    lifecycle: &mut Lifecycle<'old, 'new>,
 ) {
     use self::NodeOp::*;
 
     let mut planned_ops: Vec<Option<NodeOp<'new>>> = vec![None; old_children.len()];
 
     // Build a map between keys and their position in new children list.
-    let mut new_children_key_index: HashMap<&CowString, usize> =
-        HashMap::with_capacity(new_children.len());
-    for (index, child) in new_children.iter().enumerate() {
-        // Children without keys should have been handled before
-        new_children_key_index.insert(child.key().unwrap(), index);
+    #[cfg(feature = "tracing")]
+    let span = trace::enter(Phase::KeyIndex);
+    let mut new_children_key_index: HashMap<&K, usize> = HashMap::with_capacity(new_children.len());
+    for (index, (key, _)) in new_children.iter().enumerate() {
+        new_children_key_index.insert(key, index);
     }
+    #[cfg(feature = "tracing")]
+    span.exit(new_children_key_index.len());
 
     // Find positions of keys from new children list in old children list.
     let mut old_positions: Vec<Option<usize>> = vec![None; new_children.len()];
     let mut last_position = 0;
     let mut moved = false;
     let mut removed = 0;
-    for (index, child) in old_children.iter().enumerate() {
-        // Children without keys should have been handled before
-        match new_children_key_index.get(child.key().unwrap()) {
+    for (index, (key, child)) in old_children.iter().enumerate() {
+        match new_children_key_index.get(key) {
             Some(new_position) => {
                 // Having last seen position bigger than current position means that
                 // some children have been moved.
                 if last_position > *new_position {
                     moved = true;
                 } else {
-                    planned_ops[index] = Some(diff(child, new_children[*new_position]));
+                    planned_ops[index] =
+                        Some(diff_node(child, new_children[*new_position].1, lifecycle));
                 }
                 last_position = *new_position;
                 old_positions[*new_position] = Some(index);
@@ -339,6 +1618,7 @@ fn diff_middles<'new>(
             // If old key is not found in new children, old child should be removed.
             None => {
                 removed += 1;
+                lifecycle.unmount(child);
                 planned_ops[index] = Some(Remove(1))
             }
         }
@@ -346,9 +1626,10 @@ fn diff_middles<'new>(
 
     // Check if we need to Insert new children.
     if (old_children.len() - removed) != new_children.len() {
-        for (index, child) in new_children.iter().enumerate() {
+        for (index, (_, child)) in new_children.iter().enumerate() {
             // If position is not found, we Insert.
-            if let None = old_positions[index] {
+            if old_positions[index].is_none() {
+                lifecycle.mount(child);
                 inserts.push((offset + index, child));
             }
         }
@@ -357,27 +1638,50 @@ fn diff_middles<'new>(
     // If some chidren have moved we find largest increasing subsequence in
     // old_positions and move children outside of it.
     if moved {
+        #[cfg(feature = "tracing")]
+        let span = trace::enter(Phase::Lis);
         let lis = positions_lis(&old_positions);
+        #[cfg(feature = "tracing")]
+        span.exit(old_positions.len());
         let mut lis_index = 0;
 
-        for (old_index, old_child) in old_children.iter().enumerate() {
+        for (old_index, (key, old_child)) in old_children.iter().enumerate() {
             // Find new position for current old child
-            if let Some(new_position) = new_children_key_index.get(old_child.key().unwrap()) {
-                let new_child = new_children[*new_position];
-                let need_diff = match planned_ops[old_index] {
-                    Some(_) => false,
-                    None => true,
+            if let Some(new_position) = new_children_key_index.get(key) {
+                let new_child = new_children[*new_position].1;
+                // Children are diffed once, so lifecycle events aren't
+                // collected twice.
+                let op = match planned_ops[old_index].take() {
+                    Some(op) => op,
+                    None => diff_node(old_child, new_child, lifecycle),
                 };
                 // If current old child is in LIS, don't move it
                 if lis_index < lis.len() && old_index == lis[lis_index] {
-                    if need_diff {
-                        planned_ops[old_index] = Some(diff(old_child, new_child));
-                    }
+                    planned_ops[old_index] = Some(op);
                     lis_index += 1;
                 // If not, move it
+                } else if is_below(new_child, lifecycle.options.replace_moves_below)
+                    && is_below(old_child, lifecycle.options.replace_moves_below)
+                {
+                    // Tiny subtrees are cheaper to recreate than to move.
+                    inserts.push((offset + *new_position, new_child));
+                    planned_ops[old_index] = Some(Remove(1));
                 } else {
-                    planned_ops[old_index] = match diff(old_child, new_child) {
+                    planned_ops[old_index] = match op {
                         Update(a, u, i) => Some(Move(offset + *new_position, a, u, i)),
+                        // Replaced child is removed, and the new child is
+                        // inserted at its position.
+                        Replace(node) => {
+                            inserts.push((offset + *new_position, node));
+                            Some(Remove(1))
+                        }
+                        // A move can't carry a text change, so changed text
+                        // is removed and inserted again like a replaced
+                        // child.
+                        Text(_) => {
+                            inserts.push((offset + *new_position, new_child));
+                            Some(Remove(1))
+                        }
                         _ => Some(Move(offset + *new_position, None, None, None)),
                     }
                 }
@@ -385,20 +1689,45 @@ fn diff_middles<'new>(
         }
     }
 
+    inserts.sort_by_key(|(position, _)| *position);
+
     // Build the queue
     for op in planned_ops.into_iter() {
         match op {
-            Some(op) => op_queue.push(op),
+            Some(op) => builder.push(op),
             None => {
-                op_queue.push(Skip(1));
+                builder.push(Skip(1));
             }
         }
     }
 }
 
-fn positions_lis(positions: &Vec<Option<usize>>) -> Vec<usize> {
+/// Whether the subtree has fewer than `limit` nodes, all of them elements
+/// and texts. Subtrees with components are never counted as small: their
+/// components would have to be unmounted and mounted again.
+///
+fn is_below(node: &VNode, limit: usize) -> bool {
+    fn count(node: &VNode, remaining: &mut usize) -> bool {
+        if *remaining == 0 {
+            return false;
+        }
+        *remaining -= 1;
+        match node {
+            VNode::Element(element) => element
+                .get_children()
+                .iter()
+                .all(|child| count(child, remaining)),
+            VNode::Text(_) => true,
+            VNode::Component(_) | VNode::Shared(_) => false,
+        }
+    }
+
+    count(node, &mut limit.saturating_sub(1))
+}
+
+fn positions_lis(positions: &[Option<usize>]) -> Vec<usize> {
     let n = positions.len();
-    let mut m = vec![0; n];
+    let mut m = vec![0; n + 1];
     let mut p = vec![0; n];
     let mut l = 0;
 
@@ -408,7 +1737,7 @@ fn positions_lis(positions: &Vec<Option<usize>>) -> Vec<usize> {
 
         if let Some(p_i) = positions[i] {
             while lo <= hi {
-                let mut mid = (lo + hi) / 2;
+                let mid = (lo + hi) / 2;
 
                 match positions[m[mid]] {
                     Some(p_mid) => {
@@ -424,7 +1753,7 @@ fn positions_lis(positions: &Vec<Option<usize>>) -> Vec<usize> {
                 }
             }
 
-            let mut new_l = lo;
+            let new_l = lo;
             p[i] = m[new_l - 1];
             m[new_l] = i;
 
@@ -443,12 +1772,53 @@ fn positions_lis(positions: &Vec<Option<usize>>) -> Vec<usize> {
     o
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "tags"))]
 mod tests {
     use super::NodeOp::*;
     use super::*;
     use tags::*;
-    use text::text;
+
+    //
+    // # Display
+    //
+
+    #[test]
+    #[cfg(feature = "display")]
+    fn display_node_op() {
+        #[rustfmt::skip]
+        let old = div()
+            .child(p().key("1"))
+            .child(p().key("2").class("online"))
+            .child(p().key("3"))
+            .child(p().key("4"))
+            .child(p().key("5"))
+            .done();
+
+        #[rustfmt::skip]
+        let new = div().attr("id", "users")
+            .child(p().key("1").class("message"))
+            .child(p().key("3"))
+            .child(p().key("4"))
+            .child(p().key("2").class("offline"))
+            .child(p().key("6").text("Fiz"))
+            .done();
+
+        let result = format!("\n{}", diff(&old, &new));
+
+        let expected = r#"
+Update ▸ +id="users"
+    child[0] Update ▸ +class "message"
+    child[1] Move→3 ▸ +class "offline" ▸ -class "online"
+    child[2..3] Skip
+    child[4] Remove
+    insert[4]
+        <p>
+            Fiz
+        </p>
+"#;
+
+        assert_eq!(result, expected);
+    }
 
     //
     // # Comparing types and tags
@@ -484,6 +1854,33 @@ mod tests {
         assert_eq!(result, Skip(1));
     }
 
+    #[test]
+    fn void_and_non_void_elements() {
+        let old = VElement::new("br").done();
+        let new = br().done();
+
+        let result = diff(&old, &new);
+
+        assert_eq!(result, Replace(&new));
+    }
+
+    #[test]
+    fn void_element_children_are_not_diffed() {
+        let old = br().text("a").done();
+        let new = br().attr("id", "b").text("b").done();
+
+        let result = diff(&old, &new);
+
+        assert_eq!(
+            result,
+            Update(
+                Some(vec![AttrOp::Insert("id".into(), "b".into())]),
+                None,
+                None
+            )
+        );
+    }
+
     //
     // # Comparing attributes
     //
@@ -530,13 +1927,13 @@ mod tests {
 
     #[test]
     fn same_tags_with_same_attributes() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .attr("attr_a", "aaa")
             .attr("attr_b", "bbb")
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .attr("attr_a", "aaa")
             .attr("attr_b", "bbb")
@@ -549,14 +1946,14 @@ mod tests {
 
     #[test]
     fn same_tags_with_different_attributes() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .attr("attr_a", "aaa")
             .attr("attr_b", "bbb")
             .attr("attr_c", "ccc")
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .attr("attr_b", "bbb")
             .attr("attr_c", "***")
@@ -581,14 +1978,14 @@ mod tests {
 
     #[test]
     fn same_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(p())
             .child(p())
             .child(p())
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(p())
             .child(p())
@@ -602,11 +1999,11 @@ mod tests {
 
     #[test]
     fn inserted_all_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(p())
             .child(p())
@@ -627,14 +2024,14 @@ mod tests {
 
     #[test]
     fn removed_all_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(p())
             .child(p())
             .child(p())
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .done();
 
@@ -645,12 +2042,12 @@ mod tests {
 
     #[test]
     fn prepended_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(p())
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(div())
             .child(div())
@@ -671,13 +2068,13 @@ mod tests {
 
     #[test]
     fn inserted_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(p())
             .child(p())
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(p())
             .child(div())
@@ -698,12 +2095,12 @@ mod tests {
 
     #[test]
     fn appended_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(p())
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(p())
             .child(div())
@@ -724,7 +2121,7 @@ mod tests {
 
     #[test]
     fn inserted_and_modified_unkeyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(div()
                 .child(p())
@@ -735,7 +2132,7 @@ mod tests {
             )
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(div()
                 .child(p())
@@ -767,14 +2164,14 @@ mod tests {
 
     #[test]
     fn same_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
             .child(p().key("c3"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -788,11 +2185,11 @@ mod tests {
 
     #[test]
     fn inserted_all_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -817,14 +2214,14 @@ mod tests {
 
     #[test]
     fn removed_all_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
             .child(p().key("c3"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .done();
 
@@ -835,13 +2232,13 @@ mod tests {
 
     #[test]
     fn prepended_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c2"))
             .child(p().key("c3"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -858,13 +2255,13 @@ mod tests {
 
     #[test]
     fn inserted_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c3"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -881,13 +2278,13 @@ mod tests {
 
     #[test]
     fn appended_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -904,7 +2301,7 @@ mod tests {
 
     #[test]
     fn removed_middle_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -914,7 +2311,7 @@ mod tests {
             .child(p().key("c6"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c4"))
@@ -935,7 +2332,7 @@ mod tests {
 
     #[test]
     fn replaced_middle_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -943,7 +2340,7 @@ mod tests {
             .child(p().key("c4"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(div().key("d1"))
@@ -957,18 +2354,19 @@ mod tests {
             result,
             Update(
                 None,
-                Some(vec![Skip(1), Remove(2), Skip(1)]),
                 Some(vec![
-                    (1, &div().key("d1").done()),
-                    (2, &div().key("d2").done()),
-                ])
+                    Skip(1),
+                    Replace(&div().key("d1").done()),
+                    Replace(&div().key("d2").done()),
+                ]),
+                None
             )
         );
     }
 
     #[test]
     fn replaced_beginning_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -976,7 +2374,7 @@ mod tests {
             .child(p().key("c4"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("d1"))
             .child(div().key("d2"))
@@ -990,18 +2388,18 @@ mod tests {
             result,
             Update(
                 None,
-                Some(vec![Remove(2), Skip(2)]),
                 Some(vec![
-                    (0, &p().key("d1").done()),
-                    (1, &div().key("d2").done()),
-                ])
+                    Replace(&p().key("d1").done()),
+                    Replace(&div().key("d2").done()),
+                ]),
+                None
             )
         );
     }
 
     #[test]
     fn replaced_end_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -1009,7 +2407,7 @@ mod tests {
             .child(p().key("c4"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -1023,18 +2421,19 @@ mod tests {
             result,
             Update(
                 None,
-                Some(vec![Skip(2), Remove(2)]),
                 Some(vec![
-                    (2, &div().key("d1").done()),
-                    (3, &p().key("d2").done()),
-                ])
+                    Skip(2),
+                    Replace(&div().key("d1").done()),
+                    Replace(&p().key("d2").done()),
+                ]),
+                None
             )
         );
     }
 
     #[test]
     fn moved_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -1043,7 +2442,7 @@ mod tests {
             .child(p().key("c5"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c2"))
             .child(p().key("c1"))
@@ -1071,7 +2470,7 @@ mod tests {
 
     #[test]
     fn moved_and_updated_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div().key("p")
             .child(p().key("c1"))
             .child(p().key("c2"))
@@ -1080,7 +2479,7 @@ mod tests {
             .child(p().key("c5"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div().key("p")
             .child(p().key("c2").child(p()))
             .child(p().key("c1").class("aaa"))
@@ -1112,12 +2511,12 @@ mod tests {
 
     #[test]
     fn added_around_keyed_children() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let old = div()
             .child(p().key("1").text("initial"))
             .done();
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let new = div()
             .child(div().key("2").text("prefix"))
             .child(p().key("1").text("updated"))
@@ -1125,14 +2524,13 @@ mod tests {
             .done();
 
         let result = diff(&old, &new);
+        let text = vec![Text(TextOp::Set("updated".into()))];
 
         assert_eq!(
             result,
             Update(
                 None,
-                Some(vec![
-                    Update(None, Some(vec![Replace(&text("updated").done())]), None),
-                ]),
+                Some(vec![Update(None, Some(text), None)]),
                 Some(vec![
                     (0, &div().key("2").text("prefix").done()),
                     (2, &div().key("3").text("postfix").done()),
@@ -1140,4 +2538,524 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn moved_and_replaced_keyed_children() {
+        #[rustfmt::skip]
+        let old = div()
+            .child(p().key("1"))
+            .child(p().key("2"))
+            .child(p().key("3"))
+            .done();
+
+        #[rustfmt::skip]
+        let new = div()
+            .child(p().key("2"))
+            .child(p().key("3"))
+            .child(span().key("1"))
+            .done();
+
+        let result = diff(&old, &new);
+
+        assert_eq!(
+            result,
+            Update(
+                None,
+                Some(vec![Remove(1)]),
+                Some(vec![(2, &span().key("1").done())])
+            )
+        );
+    }
+
+    #[test]
+    fn tiny_moved_children_are_replaced() {
+        let list = |keys: &[&'static str]| {
+            let items = keys.iter().map(|key| li().key(*key).text(*key).done());
+            ul().children(items).done()
+        };
+        let old = list(&["a", "b", "c"]);
+        let new = list(&["c", "a", "b"]);
+        let moved = Update(None, Some(vec![Skip(2), Move(0, None, None, None)]), None);
+
+        assert_eq!(diff(&old, &new), moved);
+        let options = DiffOptions {
+            replace_moves_below: 2,
+            ..DiffOptions::default()
+        };
+        assert_eq!(diff_with_options(&old, &new, &options), moved);
+
+        let options = DiffOptions {
+            replace_moves_below: 3,
+            ..DiffOptions::default()
+        };
+        let c = li().key("c").text("c").done();
+        assert_eq!(
+            diff_with_options(&old, &new, &options),
+            Update(None, Some(vec![Skip(2), Remove(1)]), Some(vec![(0, &c)]))
+        );
+    }
+
+    #[test]
+    fn unkeyed_children_are_matched_by_content() {
+        let list = |ids: &[&'static str]| {
+            let items = ids.iter().map(|id| li().attr("id", *id).text(*id).done());
+            ul().children(items).done()
+        };
+        let old = list(&["a", "b", "c"]);
+        let new = list(&["c", "a", "b"]);
+        let options = DiffOptions {
+            key_by_content: true,
+            ..DiffOptions::default()
+        };
+
+        assert_eq!(
+            diff_with_options(&old, &new, &options),
+            Update(None, Some(vec![Skip(2), Move(0, None, None, None)]), None)
+        );
+        assert_ne!(diff(&old, &new), diff_with_options(&old, &new, &options));
+    }
+
+    #[test]
+    fn equal_unkeyed_children_are_matched_in_order() {
+        let added = li().class("new").done();
+        let old = ul().child(li().text("a")).child(li().text("b")).done();
+        let new = ul()
+            .child_node(added.clone())
+            .child(li().text("a"))
+            .child(li().text("b"))
+            .done();
+        let options = DiffOptions {
+            key_by_content: true,
+            ..DiffOptions::default()
+        };
+
+        assert_eq!(
+            diff_with_options(&old, &new, &options),
+            Update(None, None, Some(vec![(0, &added)]))
+        );
+    }
+
+    #[test]
+    fn static_subtrees_are_skipped() {
+        let old = div().child(header().text("Old").static_subtree()).done();
+        let new = div().child(header().text("New").static_subtree()).done();
+        let mounted = div().child(header().text("Old")).done();
+
+        assert_eq!(diff(&old, &new), Skip(1));
+        assert_ne!(diff(&mounted, &new), Skip(1));
+    }
+
+    #[test]
+    fn top_level_nodes() {
+        let old = vec![p().key("a").done(), p().key("b").done()];
+        let new = vec![p().key("b").done(), p().key("c").done()];
+        let (ops, inserts) = diff_nodes(&old, &new);
+
+        assert_eq!(ops, vec![Remove(1)]);
+        assert_eq!(inserts, vec![(1, &new[1])]);
+        assert_eq!(diff_nodes(&old, &old), (vec![], vec![]));
+
+        // Same as children of a wrapping element.
+        let old_root = div().children(old.clone()).done();
+        let new_root = div().children(new.clone()).done();
+        assert_eq!(
+            diff(&old_root, &new_root),
+            Update(None, Some(ops), Some(inserts))
+        );
+    }
+
+    //
+    // # Comparing mixed and duplicate keys
+    //
+
+    #[test]
+    fn mixed_keyed_and_unkeyed_children() {
+        #[rustfmt::skip]
+        let old = div()
+            .child(p().key("a"))
+            .child(p().text("x"))
+            .done();
+
+        #[rustfmt::skip]
+        let new = div()
+            .child(p().text("y"))
+            .child(p().key("b"))
+            .done();
+
+        let result = diff(&old, &new);
+
+        assert_eq!(
+            result,
+            Update(
+                None,
+                Some(vec![
+                    Replace(&p().text("y").done()),
+                    Replace(&p().key("b").done()),
+                ]),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        #[rustfmt::skip]
+        let old = div()
+            .child(p().key("c"))
+            .child(p().key("a").text("1"))
+            .child(p().key("a").text("2"))
+            .child(p().key("d"))
+            .done();
+
+        #[rustfmt::skip]
+        let new = div()
+            .child(p().key("d"))
+            .child(p().key("a").text("2"))
+            .child(p().key("a").text("1"))
+            .done();
+
+        let result = diff(&old, &new);
+
+        assert_eq!(
+            result,
+            Update(
+                None,
+                Some(vec![
+                    Replace(&p().key("d").done()),
+                    Update(None, Some(vec![Text(TextOp::Set("2".into()))]), None),
+                    Update(None, Some(vec![Text(TextOp::Set("1".into()))]), None),
+                    Remove(1),
+                ]),
+                None
+            )
+        );
+    }
+
+    //
+    // # Minimizing
+    //
+
+    #[test]
+    fn minimize_prunes_skip_only_updates() {
+        let node = p().done();
+        let op = Update(
+            Some(vec![]),
+            Some(vec![
+                Update(None, Some(vec![Skip(2)]), None),
+                Skip(1),
+                Update(None, Some(vec![Update(None, None, Some(vec![]))]), None),
+                Move(0, None, Some(vec![Skip(1)]), None),
+                Remove(1),
+                Update(None, Some(vec![Skip(1), Remove(1), Remove(2)]), None),
+            ]),
+            Some(vec![(3, &node)]),
+        );
+
+        assert_eq!(
+            minimize(op),
+            Update(
+                None,
+                Some(vec![
+                    Skip(3),
+                    Move(0, None, None, None),
+                    Remove(1),
+                    Update(None, Some(vec![Skip(1), Remove(3)]), None),
+                ]),
+                Some(vec![(3, &node)]),
+            )
+        );
+        assert_eq!(minimize(Update(None, Some(vec![Skip(4)]), None)), Skip(1));
+    }
+
+    #[test]
+    fn minimize_keeps_diff_output() {
+        #[rustfmt::skip]
+        let old = div().child(p().text("a")).child(p().text("b")).child(p()).done();
+        #[rustfmt::skip]
+        let new = div().child(p().text("a")).child(p().text("c")).child(p()).done();
+
+        assert_eq!(minimize(diff(&old, &new)), diff(&old, &new));
+    }
+
+    #[test]
+    fn patches_are_validated() {
+        #[rustfmt::skip]
+        let old = ul().class("list").attr("id", "a")
+            .child(li().key("a").text("a"))
+            .child(li().key("b").text("b"))
+            .child(li().key("c").text("c"))
+            .done();
+        #[rustfmt::skip]
+        let new = ul().attr("title", "t")
+            .child(li().key("c").text("c"))
+            .child(li().key("d").text("d"))
+            .child(li().key("a").class("x").text("a"))
+            .done();
+        assert_eq!(diff(&new, &old).validate_against(&new), Ok(()));
+        assert_eq!(diff(&old, &new).validate_against(&old), Ok(()));
+        assert_eq!(diff(&old, &br().done()).validate_against(&old), Ok(()));
+
+        let invalid = |op: NodeOp| op.validate_against(&old).unwrap_err().to_string();
+        assert_eq!(
+            invalid(Update(None, Some(vec![Skip(2), Remove(2)]), None)),
+            "invalid patch: at /: operation on missing child 3 of 3"
+        );
+        assert_eq!(
+            invalid(Update(
+                None,
+                Some(vec![Move(1, None, None, None), Move(1, None, None, None)]),
+                None
+            )),
+            "invalid patch: at /: two children placed at 1"
+        );
+        assert_eq!(
+            invalid(Update(None, Some(vec![Move(3, None, None, None)]), None)),
+            "invalid patch: at /: position 3 out of 3 children"
+        );
+        for empty in &[Remove(0), Skip(0)] {
+            let op = Update(None, Some(vec![empty.clone()]), None);
+            let message = format!("invalid patch: at /: empty {:?}", empty);
+            assert_eq!(invalid(op.clone()), message);
+            assert_eq!(
+                apply(&old, &op),
+                Err(Error::InvalidPatch(format!("empty {:?}", empty)))
+            );
+        }
+        assert_eq!(
+            invalid(Update(
+                None,
+                Some(vec![
+                    Skip(1),
+                    Update(Some(vec![AttrOp::Remove("id".into())]), None, None)
+                ]),
+                None
+            )),
+            "invalid patch: at /1: -id doesn't fit `li`"
+        );
+        assert_eq!(
+            invalid(Remove(1)),
+            "invalid patch: at /: unexpected root operation Remove(1)"
+        );
+
+        let old = p().text("a").child(br()).text("b").done();
+        let new = p().text("b").child(br()).text("a").done();
+        let options = DiffOptions {
+            key_by_content: true,
+            ..DiffOptions::default()
+        };
+        let op = diff_with_options(&old, &new, &options);
+        let moves = vec![
+            Skip(1),
+            Move(1, None, None, None),
+            Move(0, None, None, None),
+        ];
+        assert_eq!(op, Update(None, Some(moves), None));
+        assert_eq!(op.validate_against(&old), Ok(()));
+
+        // A moved text can't be updated.
+        let moves = vec![Move(2, None, Some(vec![]), None), Skip(2)];
+        let op = Update(None, Some(moves), None);
+        assert_eq!(
+            op.validate_against(&old).unwrap_err().to_string(),
+            "invalid patch: at /0: update of a text"
+        );
+        assert!(apply(&old, &op).is_err());
+    }
+
+    #[test]
+    fn long_texts_are_spliced() {
+        let splice = |at, remove, insert: &str| {
+            NodeOp::Text(TextOp::Splice {
+                at,
+                remove,
+                insert: insert.to_string(),
+            })
+        };
+        let options = DiffOptions {
+            splice_text_above: 8,
+            ..DiffOptions::default()
+        };
+        let old = p().text("Hello, \u{1f600} world").done();
+        let new = p().text("Hello, \u{1f600} brave world").done();
+
+        assert_eq!(
+            diff_with_options(&old, &new, &options),
+            Update(None, Some(vec![splice(10, 0, "brave ")]), None)
+        );
+        let short = p().text("Hi").done();
+        assert_eq!(
+            diff_with_options(&p().text("Ho").done(), &short, &options),
+            Update(None, Some(vec![Text(TextOp::Set("Hi".into()))]), None)
+        );
+        assert_eq!(
+            diff_with_options(&old, &new, &DiffOptions::default()),
+            diff(&old, &new)
+        );
+
+        let (old, new) = ("abcabc \u{e9}", "abc \u{e8}");
+        let op = TextOp::splice(old, new);
+        assert_eq!(op.apply(old), new);
+        assert_eq!(
+            op,
+            TextOp::Splice {
+                at: 3,
+                remove: 5,
+                insert: " \u{e8}".to_string()
+            }
+        );
+        assert_eq!(op.to_string(), "splice at 3 -5 +\" \u{e8}\"");
+    }
+
+    #[test]
+    fn token_lists_are_diffed_by_token() {
+        let options = DiffOptions {
+            token_lists: TOKEN_LIST_ATTRIBUTES
+                .iter()
+                .map(|&name| name.into())
+                .collect(),
+            ..DiffOptions::default()
+        };
+
+        let old = a().attr("rel", "nofollow noopener").done();
+        let new = a().attr("rel", "noopener  noreferrer").done();
+        let op = diff_with_options(&old, &new, &options);
+        assert_eq!(
+            op,
+            Update(
+                Some(vec![
+                    AttrOp::RemoveToken("rel".into(), "nofollow".into()),
+                    AttrOp::InsertToken("rel".into(), "noreferrer".into()),
+                ]),
+                None,
+                None
+            )
+        );
+        assert!(op.validate_against(&old).is_ok());
+        #[cfg(feature = "display")]
+        assert_eq!(
+            op.to_string(),
+            "Update ▸ +rel \"noreferrer\" ▸ -rel \"nofollow\"\n"
+        );
+
+        // Only whitespace changes.
+        let spaced = a().attr("rel", " nofollow   noopener").done();
+        assert_eq!(diff_with_options(&old, &spaced, &options), Skip(1));
+
+        // Reordered tokens can't be reached with token operations.
+        let reordered = a().attr("rel", "noopener nofollow").done();
+        assert_eq!(
+            diff_with_options(&old, &reordered, &options),
+            Update(
+                Some(vec![AttrOp::Update(
+                    "rel".into(),
+                    "noopener nofollow".into()
+                )]),
+                None,
+                None
+            )
+        );
+
+        // Unlisted attributes are updated.
+        assert_eq!(
+            diff(&old, &new),
+            Update(
+                Some(vec![AttrOp::Update(
+                    "rel".into(),
+                    "noopener  noreferrer".into()
+                )]),
+                None,
+                None
+            )
+        );
+
+        let invalid = Update(
+            Some(vec![AttrOp::InsertToken("rel".into(), "noopener".into())]),
+            None,
+            None,
+        );
+        assert!(invalid.validate_against(&old).is_err());
+    }
+
+    #[test]
+    fn boolean_attributes_are_normalized() {
+        let parsed = input().attr("checked", "checked").attr("required", "");
+        let built = input().attr("checked", "").attr("required", "Required");
+
+        assert_eq!(diff(&parsed.clone().done(), &built.done()), Skip(1));
+        // Other values are kept, even though they turn the attribute on.
+        let other = input().attr("checked", "false").attr("required", "");
+        assert_eq!(
+            diff(&parsed.done(), &other.done()),
+            Update(
+                Some(vec![AttrOp::Update("checked".into(), "false".into())]),
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn limits_are_checked_before_diffing() {
+        let old = div().child(p().text("a")).done();
+        let new = div().child(p().child(b().text("b"))).child(p()).done();
+        let options = DiffOptions {
+            max_depth: 3,
+            ..DiffOptions::default()
+        };
+
+        assert_eq!(
+            try_diff_with_options(&old, &new, &options),
+            Err(Error::TooDeep(3))
+        );
+        let options = DiffOptions {
+            max_depth: 4,
+            max_nodes: 4,
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            try_diff_with_options(&old, &new, &options),
+            Err(Error::TooManyNodes(4))
+        );
+        let options = DiffOptions {
+            max_nodes: 5,
+            ..options
+        };
+        assert_eq!(
+            try_diff_with_options(&old, &new, &options),
+            Ok(diff(&old, &new))
+        );
+    }
+
+    #[test]
+    fn apply_builds_new_tree() {
+        #[rustfmt::skip]
+        let old = ul().class("list")
+            .child(li().key("a").text("Ash"))
+            .child(li().key("b").attr("rel", "x").text("Bob"))
+            .child(li().key("c").text("Cid"))
+            .done();
+        #[rustfmt::skip]
+        let new = ul().class("users")
+            .child(li().key("c").text("Cid"))
+            .child(li().key("d").text("Dan"))
+            .child(li().key("b").attr("rel", "x y").text("Bobby"))
+            .done();
+
+        assert_eq!(apply(&old, &diff(&old, &new)), Ok(new.clone()));
+        let options = DiffOptions {
+            splice_text_above: 1,
+            token_lists: vec!["rel".into()],
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            apply(&old, &diff_with_options(&old, &new, &options)),
+            Ok(new.clone())
+        );
+
+        assert_eq!(
+            apply(&text("a").done(), &Update(None, None, None)),
+            Err(Error::InvalidPatch("Update on a text node".to_string()))
+        );
+        assert!(apply(&p().done(), &Update(None, Some(vec![Skip(1)]), None)).is_err());
+    }
 }
@@ -0,0 +1,383 @@
+//! # Diff caching
+//!
+//! Views which rotate among a few known states, such as dashboards cycling
+//! through panels or tabs switching back and forth, diff the same pairs of
+//! subtrees over and over. `DiffCache` remembers the patch of each pair of
+//! subtrees it diffed, keyed by the hashes of the old and new subtree, and
+//! returns the remembered patch when the same pair comes up again instead
+//! of diffing it.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::diff_cache::DiffCache;
+//! use troy::node::VNode;
+//! use troy::tags::*;
+//!
+//! fn panel(name: &'static str) -> VNode {
+//!     div().class(name).child(h2().text(name)).done()
+//! }
+//!
+//! let mut cache = DiffCache::new(64);
+//! for _ in 0..3 {
+//!     let (old, new) = (panel("cpu"), panel("memory"));
+//!     assert_eq!(cache.diff(&old, &new), diff(&old, &new));
+//! }
+//! assert!(cache.hits() > 0);
+//! ```
+//!
+//! Hashing both trees costs a walk over each of them per diff, so the cache
+//! pays off when the cached subtrees are large. Subtrees with components
+//! aren't cached, since hashing them would render components which don't
+//! need an update. Subtrees are compared by a 64 bit hash only, so a hash
+//! collision returns a wrong patch.
+//!
+use diff::{diff_node, AttrDiff, DiffOptions, Lifecycle, NodeOp, TextOp};
+use node::VNode;
+use path::NodePath;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::mem;
+use types::CowString;
+
+type CacheKey = (u64, u64);
+type CachedChildren = Option<Vec<CachedOp>>;
+type CachedInserts = Option<Vec<(usize, NodePath)>>;
+
+/// Patch with nodes of the new subtree replaced by their paths in it.
+///
+#[derive(Debug, Clone)]
+enum CachedOp {
+    Skip(usize),
+    Remove(usize),
+    Move(usize, AttrDiff, CachedChildren, CachedInserts),
+    Update(AttrDiff, CachedChildren, CachedInserts),
+    Replace(NodePath),
+    Text(TextOp),
+}
+
+#[derive(Debug, Default)]
+pub struct DiffCache {
+    options: DiffOptions,
+    entries: Entries,
+    hits: u64,
+}
+
+impl DiffCache {
+    /// Cache of at most `capacity` sub-patches. When it is full, the oldest
+    /// ones are dropped first.
+    ///
+    pub fn new(capacity: usize) -> Self {
+        DiffCache {
+            entries: Entries {
+                capacity,
+                ..Entries::default()
+            },
+            ..DiffCache::default()
+        }
+    }
+
+    /// Diff with a tuned cost model, see `diff::diff_with_options`. All
+    /// patches in the cache are diffed with the same options.
+    ///
+    pub fn with_options(mut self, options: DiffOptions) -> Self {
+        self.options = options;
+        self.clear();
+        self
+    }
+
+    /// Diff the trees like `diff::diff`, reusing and remembering patches of
+    /// their subtrees.
+    ///
+    pub fn diff<'new>(&mut self, old: &VNode, new: &'new VNode) -> NodeOp<'new> {
+        let mut active = Active::default();
+        hash_tree(old, &mut active.hashes);
+        hash_tree(new, &mut active.hashes);
+        collect_paths(new, &mut NodePath::root(), &mut active.paths);
+        active.entries = mem::take(&mut self.entries);
+
+        let mut lifecycle = Lifecycle::default();
+        lifecycle.options = self.options.clone();
+        lifecycle.cache = Some(active);
+        let op = diff_node(old, new, &mut lifecycle);
+
+        let active = lifecycle.cache.take().unwrap_or_default();
+        self.entries = active.entries;
+        self.hits += active.hits;
+        op
+    }
+
+    /// Number of cached sub-patches.
+    ///
+    pub fn len(&self) -> usize {
+        self.entries.patches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.patches.is_empty()
+    }
+
+    /// Number of subtree pairs whose patch was found in the cache.
+    ///
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.patches.clear();
+        self.entries.order.clear();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    capacity: usize,
+    patches: HashMap<CacheKey, CachedOp>,
+    order: VecDeque<CacheKey>,
+}
+
+/// State of the cache during one diff.
+///
+#[derive(Debug, Default)]
+pub(crate) struct Active {
+    /// Subtree hashes by node address, for both trees.
+    hashes: HashMap<usize, u64>,
+    /// Paths of the nodes of the new tree by node address.
+    paths: HashMap<usize, NodePath>,
+    entries: Entries,
+    hits: u64,
+}
+
+impl Active {
+    /// Cache key of a pair of subtrees, or None if either isn't cached.
+    ///
+    pub(crate) fn key(&self, old: &VNode, new: &VNode) -> Option<CacheKey> {
+        Some((
+            *self.hashes.get(&address(old))?,
+            *self.hashes.get(&address(new))?,
+        ))
+    }
+
+    /// Cached patch of the pair, with paths resolved in the `new` subtree.
+    ///
+    pub(crate) fn get<'new>(&mut self, key: CacheKey, new: &'new VNode) -> Option<NodeOp<'new>> {
+        let op = from_cached(self.entries.patches.get(&key)?, new)?;
+        self.hits += 1;
+        Some(op)
+    }
+
+    /// Remember the patch of a pair of subtrees.
+    ///
+    pub(crate) fn insert(&mut self, key: CacheKey, op: &NodeOp, new: &VNode) {
+        let entries = &mut self.entries;
+        if entries.capacity == 0 || entries.patches.contains_key(&key) {
+            return;
+        }
+        let base = match self.paths.get(&address(new)) {
+            Some(base) => base,
+            None => return,
+        };
+        let op = match to_cached(op, base, &self.paths) {
+            Some(op) => op,
+            None => return,
+        };
+        while entries.patches.len() >= entries.capacity {
+            match entries.order.pop_front() {
+                Some(oldest) => entries.patches.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.patches.insert(key, op);
+        entries.order.push_back(key);
+    }
+}
+
+fn address(node: &VNode) -> usize {
+    node as *const VNode as usize
+}
+
+/// Hash every subtree without components, bottom up, by node address.
+/// Shared nodes are hashed as the nodes they stand for.
+///
+fn hash_tree(node: &VNode, hashes: &mut HashMap<usize, u64>) -> Option<u64> {
+    let hash = match node {
+        VNode::Component(_) => return None,
+        VNode::Shared(shared) => hash_tree(shared, hashes)?,
+        VNode::Text(text) => {
+            let mut hasher = DefaultHasher::new();
+            1u8.hash(&mut hasher);
+            text.get_content().hash(&mut hasher);
+            hasher.finish()
+        }
+        VNode::Element(element) => {
+            let children: Vec<Option<u64>> = element
+                .get_children()
+                .iter()
+                .map(|child| hash_tree(child, hashes))
+                .collect();
+
+            let mut hasher = DefaultHasher::new();
+            0u8.hash(&mut hasher);
+            element.get_tag().hash(&mut hasher);
+            element.get_key().hash(&mut hasher);
+            element.is_void().hash(&mut hasher);
+            element.is_static_subtree().hash(&mut hasher);
+            let mut classes: Vec<&CowString> = element.get_classes().iter().collect();
+            classes.sort();
+            classes.hash(&mut hasher);
+            let mut attributes: Vec<(&CowString, &CowString)> =
+                element.get_attributes().iter().collect();
+            attributes.sort();
+            attributes.hash(&mut hasher);
+            for child in children {
+                child?.hash(&mut hasher);
+            }
+            hasher.finish()
+        }
+    };
+    hashes.insert(address(node), hash);
+    Some(hash)
+}
+
+/// Paths of the nodes of a tree by node address. Shared nodes and the nodes
+/// they stand for have the same path.
+///
+fn collect_paths(node: &VNode, path: &mut NodePath, paths: &mut HashMap<usize, NodePath>) {
+    paths.insert(address(node), path.clone());
+    match node {
+        VNode::Shared(shared) => collect_paths(shared, path, paths),
+        VNode::Element(element) => {
+            for (index, child) in element.get_children().iter().enumerate() {
+                path.push(index);
+                collect_paths(child, path, paths);
+                path.pop();
+            }
+        }
+        VNode::Component(_) | VNode::Text(_) => {}
+    }
+}
+
+fn to_cached(op: &NodeOp, base: &NodePath, paths: &HashMap<usize, NodePath>) -> Option<CachedOp> {
+    let relative = |node: &VNode| {
+        let path = paths.get(&address(node))?;
+        if path.starts_with(base) {
+            Some(NodePath::from(path.indices()[base.depth()..].to_vec()))
+        } else {
+            None
+        }
+    };
+    let children = |children: &Option<Vec<NodeOp>>| match children {
+        Some(children) => children
+            .iter()
+            .map(|op| to_cached(op, base, paths))
+            .collect::<Option<Vec<_>>>()
+            .map(Some),
+        None => Some(None),
+    };
+    let inserts = |inserts: &Option<Vec<(usize, &VNode)>>| match inserts {
+        Some(inserts) => inserts
+            .iter()
+            .map(|(position, node)| Some((*position, relative(node)?)))
+            .collect::<Option<Vec<_>>>()
+            .map(Some),
+        None => Some(None),
+    };
+
+    Some(match op {
+        NodeOp::Skip(count) => CachedOp::Skip(*count),
+        NodeOp::Remove(count) => CachedOp::Remove(*count),
+        NodeOp::Move(position, attrs, c, i) => {
+            CachedOp::Move(*position, attrs.clone(), children(c)?, inserts(i)?)
+        }
+        NodeOp::Update(attrs, c, i) => CachedOp::Update(attrs.clone(), children(c)?, inserts(i)?),
+        NodeOp::Replace(node) => CachedOp::Replace(relative(node)?),
+        NodeOp::Text(op) => CachedOp::Text(op.clone()),
+    })
+}
+
+/// Resolve the paths of a cached patch in the new subtree, or None if they
+/// don't fit it.
+///
+fn from_cached<'new>(op: &CachedOp, new: &'new VNode) -> Option<NodeOp<'new>> {
+    let children = |children: &CachedChildren| match children {
+        Some(children) => children
+            .iter()
+            .map(|op| from_cached(op, new))
+            .collect::<Option<Vec<_>>>()
+            .map(Some),
+        None => Some(None),
+    };
+    let inserts = |inserts: &CachedInserts| match inserts {
+        Some(inserts) => inserts
+            .iter()
+            .map(|(position, path)| Some((*position, path.resolve(new)?)))
+            .collect::<Option<Vec<_>>>()
+            .map(Some),
+        None => Some(None),
+    };
+
+    Some(match op {
+        CachedOp::Skip(count) => NodeOp::Skip(*count),
+        CachedOp::Remove(count) => NodeOp::Remove(*count),
+        CachedOp::Move(position, attrs, c, i) => {
+            NodeOp::Move(*position, attrs.clone(), children(c)?, inserts(i)?)
+        }
+        CachedOp::Update(attrs, c, i) => NodeOp::Update(attrs.clone(), children(c)?, inserts(i)?),
+        CachedOp::Replace(path) => NodeOp::Replace(path.resolve(new)?),
+        CachedOp::Text(op) => NodeOp::Text(op.clone()),
+    })
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    fn state(n: usize) -> VNode {
+        match n {
+            0 => ul()
+                .child(li().key("a").text("a"))
+                .child(li().key("b").text("b"))
+                .done(),
+            1 => ul()
+                .child(li().key("b").class("first").text("b"))
+                .child(li().key("c").child(p().text("c")))
+                .child(li().key("a").text("a"))
+                .done(),
+            _ => ol().child(li().text("replaced")).done(),
+        }
+    }
+
+    #[test]
+    fn cached_patches_match_diff() {
+        let mut cache = DiffCache::new(64);
+        for round in 0..3 {
+            let hits = cache.hits();
+            for (from, to) in &[(0, 1), (1, 2), (2, 0), (1, 0)] {
+                let (old, new) = (state(*from), state(*to));
+                assert_eq!(
+                    cache.diff(&old, &new),
+                    diff(&old, &new),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
+            assert!(round == 0 || cache.hits() >= hits + 4);
+        }
+    }
+
+    #[test]
+    fn oldest_patches_are_dropped() {
+        let mut cache = DiffCache::new(2);
+        cache.diff(&state(0), &state(1));
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        let mut cache = DiffCache::new(0);
+        cache.diff(&state(0), &state(1));
+        assert!(cache.is_empty());
+    }
+}
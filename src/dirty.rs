@@ -0,0 +1,238 @@
+//! # Dirty regions
+//!
+//! `DirtySet` collects the parts of a tree which changed since the previous
+//! frame, by path or by key. `diff_dirty` diffs only those parts and their
+//! ancestors, and produces `Skip(1)` for everything else without comparing
+//! it, so a frame which changes one row of a large page costs about as much
+//! as the row.
+//!
+//! Paths are positions in the new tree. Outside of the dirty parts the trees
+//! are expected to be the same: changes which weren't marked are not
+//! rendered. When an ancestor of a dirty part has a different number of
+//! children than before, the ancestor is diffed as a whole.
+//!
+//! ```
+//! use troy::diff::{diff, NodeOp};
+//! use troy::dirty::{diff_dirty, DirtySet};
+//! use troy::path::NodePath;
+//! use troy::tags::*;
+//!
+//! let old = ul().child(li().text("a")).child(li().text("b")).done();
+//! let new = ul().child(li().text("a")).child(li().text("c")).done();
+//!
+//! let mut dirty = DirtySet::new();
+//! dirty.mark(NodePath::root().child(1));
+//!
+//! assert_eq!(diff_dirty(&old, &new, &dirty), diff(&old, &new));
+//! assert_eq!(diff_dirty(&old, &new, &DirtySet::new()), NodeOp::Skip(1));
+//! ```
+//!
+use diff::{diff_node, Lifecycle, NodeOp};
+use key::Key;
+use node::VNode;
+use patch_builder::PatchBuilder;
+use path::NodePath;
+use std::collections::{BTreeSet, HashSet};
+
+/// Paths and keys of nodes which changed since the previous frame.
+///
+#[derive(Debug, Clone, Default)]
+pub struct DirtySet {
+    paths: BTreeSet<NodePath>,
+    keys: HashSet<Key>,
+}
+
+impl DirtySet {
+    pub fn new() -> Self {
+        DirtySet::default()
+    }
+
+    /// Mark the node at the path and its children as changed.
+    ///
+    pub fn mark(&mut self, path: NodePath) {
+        self.paths.insert(path);
+    }
+
+    /// Mark nodes with the key and their children as changed, wherever they
+    /// are in the tree.
+    ///
+    pub fn mark_key<K: Into<Key>>(&mut self, key: K) {
+        self.keys.insert(key.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.keys.is_empty()
+    }
+
+    /// Forget all marks, such as after the frame is diffed.
+    ///
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.keys.clear();
+    }
+
+    /// Whether the node at the path or one of its ancestors is marked.
+    ///
+    fn covers(&self, path: &NodePath) -> bool {
+        let indices = path.indices();
+        (0..=indices.len()).any(|depth| {
+            self.paths
+                .contains(&NodePath::from(indices[..depth].to_vec()))
+        })
+    }
+
+    /// Whether a descendant of the node at the path is marked.
+    ///
+    fn has_below(&self, path: &NodePath) -> bool {
+        // Descendants are ordered right after the path itself.
+        self.paths
+            .range(path..)
+            .find(|other| *other != path)
+            .is_some_and(|other| other.starts_with(path))
+    }
+
+    /// Marked paths, with keyed nodes of the tree resolved to their paths.
+    ///
+    fn with_keys_resolved(&self, root: &VNode) -> DirtySet {
+        fn collect(
+            node: &VNode,
+            path: &mut NodePath,
+            keys: &HashSet<Key>,
+            out: &mut BTreeSet<NodePath>,
+        ) {
+            if node.key().is_some_and(|key| keys.contains(key)) {
+                out.insert(path.clone());
+                return;
+            }
+            if let VNode::Element(element) = node.resolve() {
+                for (index, child) in element.get_children().iter().enumerate() {
+                    path.push(index);
+                    collect(child, path, keys, out);
+                    path.pop();
+                }
+            }
+        }
+
+        let mut paths = self.paths.clone();
+        collect(root, &mut NodePath::root(), &self.keys, &mut paths);
+        DirtySet {
+            paths,
+            keys: HashSet::new(),
+        }
+    }
+}
+
+/// Diff the dirty parts of the trees, skipping the rest.
+///
+pub fn diff_dirty<'new>(old: &VNode, new: &'new VNode, dirty: &DirtySet) -> NodeOp<'new> {
+    let resolved;
+    let dirty = if dirty.keys.is_empty() {
+        dirty
+    } else {
+        resolved = dirty.with_keys_resolved(new);
+        &resolved
+    };
+    diff_region(
+        old,
+        new,
+        &mut NodePath::root(),
+        dirty,
+        &mut Lifecycle::default(),
+    )
+}
+
+fn diff_region<'old, 'new>(
+    old: &'old VNode,
+    new: &'new VNode,
+    path: &mut NodePath,
+    dirty: &DirtySet,
+    lifecycle: &mut Lifecycle<'old, 'new>,
+) -> NodeOp<'new> {
+    if dirty.covers(path) {
+        return diff_node(old, new, lifecycle);
+    }
+    if !dirty.has_below(path) {
+        return NodeOp::Skip(1);
+    }
+
+    match (old.resolve(), new.resolve()) {
+        (VNode::Element(old_element), VNode::Element(new_element))
+            if old_element.get_tag() == new_element.get_tag()
+                && old_element.get_key() == new_element.get_key()
+                && old_element.get_children().len() == new_element.get_children().len() =>
+        {
+            let mut builder = PatchBuilder::new();
+            let children = old_element
+                .get_children()
+                .iter()
+                .zip(new_element.get_children());
+            for (index, (old_child, new_child)) in children.enumerate() {
+                path.push(index);
+                builder.push(diff_region(old_child, new_child, path, dirty, lifecycle));
+                path.pop();
+            }
+            match builder.done() {
+                ref ops if ops.is_empty() => NodeOp::Skip(1),
+                ops => NodeOp::Update(None, Some(ops), None),
+            }
+        }
+        // The structure around the dirty part changed, diff all of it.
+        _ => diff_node(old, new, lifecycle),
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    fn clean_changes_are_skipped() {
+        let old = div().child(p().text("a")).child(p().text("b")).done();
+        let new = div().child(p().text("x")).child(p().text("y")).done();
+
+        let second = NodePath::root().child(1);
+        let mut dirty = DirtySet::new();
+        dirty.mark(second.clone());
+
+        assert_eq!(
+            diff_dirty(&old, &new, &dirty),
+            NodeOp::Update(
+                None,
+                Some(vec![
+                    NodeOp::Skip(1),
+                    diff(second.resolve(&old).unwrap(), second.resolve(&new).unwrap()),
+                ]),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn keys_and_changed_structure() {
+        let old = ul()
+            .child(li().key(1).text("a"))
+            .child(li().key(2).text("b"))
+            .done();
+        let new = ul()
+            .child(li().key(1).text("a"))
+            .child(li().key(2).text("c"))
+            .done();
+        let longer = ul()
+            .child(li().key(1))
+            .child(li().key(2))
+            .child(li().key(3))
+            .done();
+
+        let mut dirty = DirtySet::new();
+        dirty.mark_key(2);
+
+        assert_eq!(diff_dirty(&old, &new, &dirty), diff(&old, &new));
+        assert_eq!(diff_dirty(&old, &longer, &dirty), diff(&old, &longer));
+
+        dirty.clear();
+        assert!(dirty.is_empty());
+        assert_eq!(diff_dirty(&old, &new, &dirty), NodeOp::Skip(1));
+    }
+}
@@ -1,25 +1,57 @@
+use attrs::{self, InputType, Target};
+use diagnostics;
+use error::{is_valid_attribute_name, is_valid_tag_name, Error};
+use key::{scoped_key, Key};
+#[cfg(feature = "display")]
+use node::fmt_element;
 use node::VNode;
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "display")]
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::panic::Location;
+use style::Style;
 use text::VText;
 use types::CowString;
+use whitespace::{self, Whitespace};
 
 type Classes = HashSet<CowString>;
 type Attributes = HashMap<CowString, CowString>;
-type Key = Option<CowString>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VElement {
     tag: CowString,
     void: bool,
-    key: Key,
+    key: Option<Key>,
     attributes: Attributes,
     classes: Classes,
     children: Vec<VNode>,
+    static_subtree: bool,
+    #[cfg(feature = "debug-locations")]
+    location: BuiltAt,
 }
 
+/// Source location where an element was built. Equal to any other
+/// location, so it doesn't make otherwise equal elements different.
+///
+#[cfg(feature = "debug-locations")]
+#[derive(Debug, Clone, Copy)]
+struct BuiltAt(&'static Location<'static>);
+
+#[cfg(feature = "debug-locations")]
+impl PartialEq for BuiltAt {
+    fn eq(&self, _: &BuiltAt) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "debug-locations")]
+impl Eq for BuiltAt {}
+
 impl VElement {
     /// Create a new VElement with specified tag.
     ///
+    #[cfg_attr(feature = "debug-locations", track_caller)]
     pub fn new<S>(tag: S) -> Self
     where
         S: Into<CowString>,
@@ -31,12 +63,16 @@ impl VElement {
             attributes: Attributes::new(),
             classes: Classes::new(),
             children: Vec::new(),
+            static_subtree: false,
+            #[cfg(feature = "debug-locations")]
+            location: BuiltAt(Location::caller()),
         }
     }
 
     /// Create a new void VElement with specified tag.
     /// Void elements don't have a closing tag and can't have children.
     ///
+    #[cfg_attr(feature = "debug-locations", track_caller)]
     pub fn new_void<S>(tag: S) -> Self
     where
         S: Into<CowString>,
@@ -48,6 +84,9 @@ impl VElement {
             attributes: Attributes::new(),
             classes: Classes::new(),
             children: Vec::new(),
+            static_subtree: false,
+            #[cfg(feature = "debug-locations")]
+            location: BuiltAt(Location::caller()),
         }
     }
 
@@ -63,13 +102,34 @@ impl VElement {
         self.void
     }
 
-    pub fn get_key(&self) -> Option<&CowString> {
-        match self.key {
-            Some(ref key) => Some(&key),
-            None => None,
+    pub fn is_static_subtree(&self) -> bool {
+        self.static_subtree
+    }
+
+    /// Source location of the call which created the element, such as a
+    /// tag function. Recorded only with the `debug-locations` feature.
+    ///
+    pub fn get_location(&self) -> Option<&'static Location<'static>> {
+        #[cfg(feature = "debug-locations")]
+        return Some(self.location.0);
+        #[cfg(not(feature = "debug-locations"))]
+        return None;
+    }
+
+    /// Where the element was built for messages, as ` (built at
+    /// src/view.rs:10:5)`, or empty without `debug-locations`.
+    ///
+    pub(crate) fn built_at(&self) -> String {
+        match self.get_location() {
+            Some(location) => format!(" (built at {})", location),
+            None => String::new(),
         }
     }
 
+    pub fn get_key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+
     pub fn get_attributes(&self) -> &Attributes {
         &self.attributes
     }
@@ -82,27 +142,68 @@ impl VElement {
         &self.children
     }
 
+    /// Consume the VElement and return its children.
+    ///
+    pub fn into_children(self) -> Vec<VNode> {
+        self.children
+    }
+
     //
     // # Builder
     //
 
-    /// Set a key for VElement.
+    /// Set a key for VElement: a string or a number, see `key`.
     ///
-    pub fn key<S>(mut self, key: S) -> Self
+    pub fn key<K>(mut self, key: K) -> Self
     where
-        S: Into<CowString>,
+        K: Into<Key>,
     {
         self.key = Some(key.into());
         self
     }
 
-    /// Add attribute to VElement.
+    /// Set a key within a scope, see `key::scoped_key`.
     ///
-    pub fn attr<S>(mut self, name: S, value: S) -> Self
+    pub fn key_in<S, K>(self, scope: S, key: K) -> Self
     where
         S: Into<CowString>,
+        K: Into<Key>,
     {
-        self.attributes.insert(name.into(), value.into());
+        self.key(scoped_key(scope, key))
+    }
+
+    /// Mark the element and its children as never changing, such as a
+    /// header or an icon sprite. Diffing it against a static element of the
+    /// previous frame produces Skip without comparing them, so changes to
+    /// either of them are not rendered.
+    ///
+    pub fn static_subtree(mut self) -> Self {
+        self.static_subtree = true;
+        self
+    }
+
+    /// Reserve capacity for at least the specified number of attributes and
+    /// children.
+    ///
+    pub fn reserve(mut self, attributes: usize, children: usize) -> Self {
+        self.attributes.reserve(attributes);
+        self.children.reserve(children);
+        self
+    }
+
+    /// Add attribute to VElement.
+    ///
+    /// The `class` attribute is not stored as an attribute: its value is
+    /// parsed as a class list and added to the class set, same as with
+    /// `class_list`. Otherwise rendering and diffing would see two competing
+    /// sources of classes.
+    ///
+    pub fn attr<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<CowString>,
+        V: Into<AttrValue>,
+    {
+        self.set_attr(name, value);
         self
     }
 
@@ -118,6 +219,18 @@ impl VElement {
         self
     }
 
+    /// Add each class of a collection to VElement. Unlike `class_list`,
+    /// items are not split on whitespace.
+    ///
+    pub fn classes<I, S>(mut self, classes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<CowString>,
+    {
+        self.classes.extend(classes.into_iter().map(Into::into));
+        self
+    }
+
     /// Add class to VElement.
     ///
     pub fn class<S>(mut self, name: S) -> Self
@@ -128,6 +241,60 @@ impl VElement {
         self
     }
 
+    /// Set the `id` attribute.
+    ///
+    pub fn id<S>(self, id: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        self.attr("id", id.into())
+    }
+
+    /// Set the `name` attribute of a form control.
+    ///
+    pub fn name<S>(self, name: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        self.attr("name", name.into())
+    }
+
+    /// Set the `placeholder` attribute of a text field.
+    ///
+    pub fn placeholder<S>(self, placeholder: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        self.attr("placeholder", placeholder.into())
+    }
+
+    /// Set the `type` attribute of an input.
+    ///
+    pub fn type_(self, input_type: InputType) -> Self {
+        self.attr("type", input_type)
+    }
+
+    /// Set the `for` attribute of a label, the id of its control.
+    ///
+    pub fn for_<S>(self, id: S) -> Self
+    where
+        S: Into<CowString>,
+    {
+        self.attr("for", id.into())
+    }
+
+    /// Set the `target` attribute of a link or form.
+    ///
+    pub fn target(self, target: Target) -> Self {
+        self.attr("target", target)
+    }
+
+    /// Set the `style` attribute from typed properties.
+    ///
+    pub fn style(self, style: Style) -> Self {
+        self.attr("style", style)
+    }
+
     /// Add VElement as a child.
     ///
     pub fn child(mut self, element: VElement) -> Self {
@@ -135,6 +302,23 @@ impl VElement {
         self
     }
 
+    /// Add already finished VNode as a child.
+    ///
+    pub fn child_node(mut self, node: VNode) -> Self {
+        self.children.push(node);
+        self
+    }
+
+    /// Add several finished VNodes as children.
+    ///
+    pub fn children<I>(mut self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = VNode>,
+    {
+        self.children.extend(nodes);
+        self
+    }
+
     /// Add Text node as a child.
     ///
     pub fn text<S>(mut self, text: S) -> Self
@@ -145,9 +329,483 @@ impl VElement {
         self
     }
 
+    /// Add Text node as a child, decoding HTML character references such as
+    /// `&amp;` in the text.
+    ///
+    pub fn text_decoded(mut self, text: &str) -> Self {
+        self.children.push(VNode::Text(VText::new_decoded(text)));
+        self
+    }
+
+    /// Apply whitespace policy to text of the children added so far and
+    /// their descendants. Text inside `pre`, `textarea` and `code` is left
+    /// as it is.
+    ///
+    pub fn whitespace(mut self, policy: Whitespace) -> Self {
+        self.normalize_whitespace(policy);
+        self
+    }
+
+    fn normalize_whitespace(&mut self, policy: Whitespace) {
+        if policy == Whitespace::Preserve || whitespace::is_preserved(&self.tag) {
+            return;
+        }
+
+        let children = std::mem::take(&mut self.children);
+        for child in children {
+            match child {
+                VNode::Text(text) => {
+                    let content = whitespace::apply(text.get_content(), policy);
+                    if content.is_empty() && policy == Whitespace::Trim {
+                        continue;
+                    }
+                    self.children
+                        .push(VNode::Text(VText::new(content.into_owned())));
+                }
+                VNode::Element(mut element) => {
+                    element.normalize_whitespace(policy);
+                    self.children.push(VNode::Element(element));
+                }
+                component => self.children.push(component),
+            }
+        }
+    }
+
     /// Finish building the VElement and wrap it into VNode.
     ///
+    /// Reports void elements with children and duplicate keys of children
+    /// to `diagnostics`, while diagnostics are collected.
+    ///
     pub fn done(self) -> VNode {
+        if diagnostics::is_collecting() {
+            self.report_diagnostics();
+        }
         VNode::Element(self)
     }
+
+    fn report_diagnostics(&self) {
+        if self.void && !self.children.is_empty() {
+            let message = format!(
+                "void element `{}` has children, which are not rendered{}",
+                self.tag,
+                self.built_at()
+            );
+            diagnostics::report(diagnostics::Kind::VoidChildren, message);
+        }
+
+        let mut keys = HashSet::new();
+        for key in self.children.iter().filter_map(VNode::key) {
+            if !keys.insert(key) {
+                let message = format!(
+                    "key {:?} is used by several children of `{}`{}",
+                    key,
+                    self.tag,
+                    self.built_at()
+                );
+                diagnostics::report(diagnostics::Kind::DuplicateKey, message);
+            }
+        }
+    }
+
+    //
+    // # Checked builder
+    //
+
+    /// Add attribute to VElement, checking the attribute name.
+    ///
+    pub fn try_attr<N, V>(self, name: N, value: V) -> Result<Self, Error>
+    where
+        N: Into<CowString>,
+        V: Into<AttrValue>,
+    {
+        let name = name.into();
+        if !is_valid_attribute_name(&name) {
+            return Err(Error::InvalidAttributeName(name.into_owned()));
+        }
+        Ok(self.attr(name, value))
+    }
+
+    /// Validate the VElement and its descendants.
+    ///
+    /// Checks tag and attribute names, that void elements have no children,
+    /// and that keys are unique among siblings.
+    ///
+    pub fn validate(&self) -> Result<(), Error> {
+        if !is_valid_tag_name(&self.tag) {
+            return Err(Error::InvalidTagName(self.tag.to_string()));
+        }
+
+        if let Some(name) = self
+            .attributes
+            .keys()
+            .find(|name| !is_valid_attribute_name(name))
+        {
+            return Err(Error::InvalidAttributeName(name.to_string()));
+        }
+
+        if self.void && !self.children.is_empty() {
+            return Err(Error::VoidChildren(self.tag.to_string()));
+        }
+
+        let mut keys = HashSet::new();
+        for child in &self.children {
+            if let Some(key) = child.key() {
+                if !keys.insert(key) {
+                    return Err(Error::DuplicateKey(key.to_string()));
+                }
+            }
+            if let VNode::Element(element) = child.resolve() {
+                element.validate()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the VElement and wrap it into VNode.
+    ///
+    pub fn done_checked(self) -> Result<VNode, Error> {
+        self.validate()?;
+        Ok(self.done())
+    }
+
+    //
+    // # Mutation
+    //
+
+    /// Set attribute of a built VElement. Like with `attr`, the `class`
+    /// attribute adds classes instead, and a boolean attribute set to
+    /// `false` is removed.
+    ///
+    pub fn set_attr<N, V>(&mut self, name: N, value: V)
+    where
+        N: Into<CowString>,
+        V: Into<AttrValue>,
+    {
+        let name = name.into();
+        let AttrValue(value, flag) = value.into();
+        if name == "class" {
+            for class in value.split_whitespace() {
+                self.classes.insert(class.to_string().into());
+            }
+        } else if let (Some(on), true) = (flag, attrs::is_boolean_attribute(&name)) {
+            if on {
+                self.attributes.insert(name, "".into());
+            } else {
+                self.attributes.remove(&name);
+            }
+        } else {
+            self.attributes.insert(name, value);
+        }
+    }
+
+    /// Remove attribute, returning its value.
+    ///
+    pub fn remove_attr(&mut self, name: &str) -> Option<CowString> {
+        self.attributes.remove(name)
+    }
+
+    /// Add class to a built VElement.
+    ///
+    pub fn add_class<S>(&mut self, name: S)
+    where
+        S: Into<CowString>,
+    {
+        self.classes.insert(name.into());
+    }
+
+    /// Remove class, returning whether it was present.
+    ///
+    pub fn remove_class(&mut self, name: &str) -> bool {
+        self.classes.remove(name)
+    }
+
+    /// Append a child node to a built VElement.
+    ///
+    pub fn push_child(&mut self, node: VNode) {
+        self.children.push(node);
+    }
+
+    /// Children for in-place changes, such as inserting, removing or
+    /// reordering them.
+    ///
+    pub fn get_children_mut(&mut self) -> &mut Vec<VNode> {
+        &mut self.children
+    }
+}
+
+/// Value accepted by `attr`: a string, a number or a boolean.
+///
+/// Numbers are written the way `Display` writes them, booleans as `true`
+/// and `false`, which is what enumerated attributes such as `draggable`,
+/// `contenteditable` and `aria-*` expect. Boolean attributes such as
+/// `hidden` or `disabled` are on whenever they are present, whatever
+/// their value, so for them `true` sets an empty value and `false` leaves
+/// the attribute out.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrValue(CowString, Option<bool>);
+
+impl AttrValue {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<AttrValue> for CowString {
+    fn from(value: AttrValue) -> Self {
+        value.0
+    }
+}
+
+impl From<&'static str> for AttrValue {
+    fn from(value: &'static str) -> Self {
+        AttrValue(value.into(), None)
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(value: String) -> Self {
+        AttrValue(value.into(), None)
+    }
+}
+
+impl From<CowString> for AttrValue {
+    fn from(value: CowString) -> Self {
+        AttrValue(value, None)
+    }
+}
+
+impl From<bool> for AttrValue {
+    fn from(value: bool) -> Self {
+        AttrValue(if value { "true" } else { "false" }.into(), Some(value))
+    }
+}
+
+macro_rules! attr_value_from_display {
+    ($($number:ty),*) => {
+        $(
+            impl From<$number> for AttrValue {
+                fn from(value: $number) -> Self {
+                    AttrValue(value.to_string().into(), None)
+                }
+            }
+        )*
+    };
+}
+
+attr_value_from_display!(i32, i64, u32, u64, usize, f64);
+
+/// Hash is canonical: classes and attributes are hashed in sorted order,
+/// so elements which are equal hash the same regardless of the order they
+/// were built in.
+///
+impl Hash for VElement {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.tag.hash(state);
+        self.void.hash(state);
+        self.key.hash(state);
+
+        let mut classes: Vec<&CowString> = self.classes.iter().collect();
+        classes.sort();
+        classes.hash(state);
+
+        let mut attributes: Vec<(&CowString, &CowString)> = self.attributes.iter().collect();
+        attributes.sort();
+        attributes.hash(state);
+
+        self.children.hash(state);
+    }
+}
+
+/// Same output as the `Display` of the element wrapped into `VNode`.
+///
+#[cfg(feature = "display")]
+impl fmt::Display for VElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_element(0, self, f)
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+    use text::text;
+
+    #[test]
+    fn class_attribute() {
+        let element = div().class("aaa").attr("class", "bbb  ccc");
+
+        assert!(element.get_attributes().is_empty());
+        assert_eq!(
+            element.get_classes(),
+            &["aaa", "bbb", "ccc"].iter().map(|c| (*c).into()).collect()
+        );
+    }
+
+    #[test]
+    fn attr_name_and_value_types() {
+        let count = 3;
+        let element = div().attr("data-count", count.to_string());
+
+        assert_eq!(element.get_attributes().get("data-count").unwrap(), "3");
+    }
+
+    #[test]
+    fn attr_from_primitives() {
+        let element = textarea()
+            .attr("rows", 5)
+            .attr("cols", 80u32)
+            .attr("draggable", true);
+        let element = element.attr("data-ratio", 0.5).attr("data-offset", -3i64);
+        let attributes = element.get_attributes();
+
+        assert_eq!(attributes.get("rows").unwrap(), "5");
+        assert_eq!(attributes.get("cols").unwrap(), "80");
+        assert_eq!(attributes.get("draggable").unwrap(), "true");
+        assert_eq!(attributes.get("data-ratio").unwrap(), "0.5");
+        assert_eq!(attributes.get("data-offset").unwrap(), "-3");
+
+        let mut element = input().attr("disabled", true).attr("hidden", false);
+        assert_eq!(element.get_attributes().get("disabled").unwrap(), "");
+        assert_eq!(element.get_attributes().get("hidden"), None);
+        element.set_attr("disabled", false);
+        assert!(element.get_attributes().is_empty());
+    }
+
+    #[test]
+    fn classes_from_iterator() {
+        let active = vec!["item".to_string(), "active".to_string()];
+        let element = li()
+            .classes(active)
+            .classes(["a", "b"])
+            .classes(None::<&str>);
+
+        assert_eq!(element, li().class_list("item active a b"));
+    }
+
+    #[test]
+    fn in_place_mutation() {
+        let mut node = div().class("a").attr("id", "x").child(p()).done();
+        {
+            let element = node.as_element_mut().unwrap();
+            element.set_attr("title", "t");
+            element.set_attr("class", "b c");
+            assert_eq!(element.remove_attr("id"), Some("x".into()));
+            assert!(element.remove_class("a"));
+            element.add_class("d");
+            element.push_child(text("1").done());
+            element.get_children_mut().remove(0);
+        }
+
+        assert_eq!(
+            node,
+            div()
+                .class_list("b c d")
+                .attr("title", "t")
+                .text("1")
+                .done()
+        );
+        assert!(text("1").done().as_element_mut().is_none());
+    }
+
+    #[test]
+    fn decoded_text() {
+        assert_eq!(
+            p().text_decoded("Tom &amp; Jerry&#x27;s").done(),
+            p().text("Tom & Jerry's").done()
+        );
+    }
+
+    #[test]
+    fn whitespace_policy() {
+        #[rustfmt::skip]
+        let element = div()
+            .text("\n    ")
+            .child(p().text("  Hello,\n    world!  "))
+            .child(pre().text("  keep\n  this  "))
+            .whitespace(Whitespace::Trim);
+
+        #[rustfmt::skip]
+        let expected = div()
+            .child(p().text("Hello, world!"))
+            .child(pre().text("  keep\n  this  "));
+
+        assert_eq!(element, expected);
+    }
+
+    #[test]
+    fn checked_attributes() {
+        assert!(div().try_attr("data-id", "1").is_ok());
+        assert_eq!(
+            div().try_attr("on click", "x").unwrap_err(),
+            Error::InvalidAttributeName("on click".to_string())
+        );
+    }
+
+    #[test]
+    fn checked_done() {
+        #[rustfmt::skip]
+        let valid = ul()
+            .child(li().key("a"))
+            .child(li().key("b").child(br()))
+            .done_checked();
+        assert!(valid.is_ok());
+
+        let void_children = div().child(br().text("x")).done_checked();
+        assert_eq!(void_children, Err(Error::VoidChildren("br".to_string())));
+
+        let duplicate = ul()
+            .child(li().key("a"))
+            .child(li().key("a"))
+            .done_checked();
+        assert_eq!(duplicate, Err(Error::DuplicateKey("a".to_string())));
+
+        let attribute = div().attr("a\"b", "").done_checked();
+        assert_eq!(
+            attribute,
+            Err(Error::InvalidAttributeName("a\"b".to_string()))
+        );
+
+        let tag = VElement::new("my tag").done_checked();
+        assert_eq!(tag, Err(Error::InvalidTagName("my tag".to_string())));
+    }
+
+    #[test]
+    fn hash_is_order_independent() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash(element: VElement) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            element.done().hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a = div()
+            .class("x")
+            .class("y")
+            .attr("id", "1")
+            .attr("title", "t");
+        let b = div()
+            .class("y")
+            .class("x")
+            .attr("title", "t")
+            .attr("id", "1");
+        let c = div().class("x").attr("id", "1");
+
+        assert_eq!(hash(a), hash(b));
+        assert_ne!(hash(div().class("x").attr("id", "1")), hash(c.class("y")));
+    }
+
+    #[test]
+    #[cfg(feature = "debug-locations")]
+    fn locations_are_recorded() {
+        let line = line!() + 1;
+        let element = div().child(p());
+
+        let location = element.get_location().unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+        assert_eq!(element, div().child(p()));
+    }
 }
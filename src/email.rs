@@ -0,0 +1,261 @@
+//! # Email rendering
+//!
+//! Email clients ignore `<style>` elements and most of modern CSS, so email
+//! markup carries its styles inline and lays out content with tables.
+//! `render_email` renders the same views as the web, with styles of classes
+//! inlined into `style` attributes, and `check` reports elements and styles
+//! which common clients don't support.
+//!
+//! ```
+//! use troy::email::{check, inline, render_email, EmailOptions};
+//! use troy::style::{Display, Px, Style};
+//! use troy::tags::*;
+//!
+//! let mut options = EmailOptions::default();
+//! options.class_style("title", Style::new().font_size(Px(20)));
+//! options.class_style("row", Style::new().display(Display::Flex));
+//!
+//! let view = div().child(h1().class("title").attr("style", "color: red").text("Hi")).done();
+//! assert_eq!(
+//!     render_email(&view, &options),
+//!     r#"<div><h1 class="title" style="font-size: 20px; color: red">Hi</h1></div>"#
+//! );
+//!
+//! let row = div().class("row").done();
+//! assert_eq!(check(&inline(&row, &options)).len(), 1);
+//! ```
+//!
+use node::VNode;
+use path::NodePath;
+use render::render_to_string;
+use std::fmt;
+use style::Style;
+use types::CowString;
+
+/// Elements most email clients drop or don't render.
+///
+const UNSUPPORTED_ELEMENTS: &[&str] = &[
+    "audio", "canvas", "embed", "form", "iframe", "object", "script", "svg", "video",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmailOptions {
+    /// Styles inlined into elements with the class, in the order they were
+    /// added. The element's own `style` attribute wins over them.
+    pub class_styles: Vec<(CowString, Style)>,
+    /// Remove classes once their styles are inlined.
+    pub strip_classes: bool,
+    /// Mark tables as layout tables: `role="presentation"` and zero
+    /// `border`, `cellpadding` and `cellspacing`, unless they are set.
+    pub presentation_tables: bool,
+}
+
+impl EmailOptions {
+    /// Add styles of a class.
+    ///
+    pub fn class_style<S: Into<CowString>>(&mut self, class: S, style: Style) {
+        self.class_styles.push((class.into(), style));
+    }
+}
+
+/// Tree with class styles inlined and tables marked, see `EmailOptions`.
+/// Components and shared nodes are replaced by the nodes they stand for.
+///
+pub fn inline(node: &VNode, options: &EmailOptions) -> VNode {
+    let mut node = node.resolve().clone();
+    inline_node(&mut node, options);
+    node
+}
+
+/// Render tree into HTML for email.
+///
+pub fn render_email(node: &VNode, options: &EmailOptions) -> String {
+    render_to_string(&inline(node, options))
+}
+
+fn inline_node(node: &mut VNode, options: &EmailOptions) {
+    if let VNode::Component(_) | VNode::Shared(_) = node {
+        *node = node.resolve().clone();
+    }
+    let element = match node.as_element_mut() {
+        Some(element) => element,
+        None => return,
+    };
+
+    let mut style = Style::new();
+    let mut inlined = Vec::new();
+    for (class, class_style) in &options.class_styles {
+        if element.get_classes().contains(class) {
+            for (name, value) in class_style.get_properties() {
+                style = style.property(name.clone(), value.clone());
+            }
+            inlined.push(class.clone());
+        }
+    }
+    if !inlined.is_empty() {
+        if let Some(own) = element.get_attributes().get("style") {
+            for (name, value) in parse_declarations(own) {
+                style = style.property(name.to_string(), value.to_string());
+            }
+        }
+        element.set_attr("style", style);
+        if options.strip_classes {
+            for class in &inlined {
+                element.remove_class(class);
+            }
+        }
+    }
+
+    if options.presentation_tables && element.get_tag() == "table" {
+        for (name, value) in &[
+            ("role", "presentation"),
+            ("border", "0"),
+            ("cellpadding", "0"),
+            ("cellspacing", "0"),
+        ] {
+            if !element.get_attributes().contains_key(*name) {
+                element.set_attr(*name, *value);
+            }
+        }
+    }
+
+    for child in element.get_children_mut() {
+        inline_node(child, options);
+    }
+}
+
+/// Declarations of a `style` attribute as trimmed names and values.
+///
+fn parse_declarations(style: &str) -> impl Iterator<Item = (&str, &str)> {
+    style.split(';').filter_map(|declaration| {
+        let (name, value) = declaration.split_once(':')?;
+        Some((name.trim(), value.trim()))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnsupportedElement,
+    UnsupportedStyle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: Rule,
+    pub path: NodePath,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at {}: {}", self.rule, self.path, self.message)
+    }
+}
+
+/// Check a tree for elements and inline styles email clients don't
+/// support, and return all findings in document order. Check the tree
+/// returned by `inline` to include class styles.
+///
+pub fn check(root: &VNode) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    check_node(root, &mut NodePath::root(), &mut findings);
+    findings
+}
+
+fn check_node(node: &VNode, path: &mut NodePath, findings: &mut Vec<Finding>) {
+    let element = match node.resolve() {
+        VNode::Element(element) => element,
+        _ => return,
+    };
+
+    let tag = element.get_tag();
+    if UNSUPPORTED_ELEMENTS.contains(&tag) {
+        findings.push(Finding {
+            rule: Rule::UnsupportedElement,
+            path: path.clone(),
+            message: format!("{} is not supported by email clients", tag),
+        });
+    }
+    if let Some(style) = element.get_attributes().get("style") {
+        for (name, value) in parse_declarations(style) {
+            let unsupported = match name {
+                "display" => ["flex", "inline-flex", "grid", "inline-grid"].contains(&value),
+                "position" => value != "static",
+                "float" => true,
+                _ => false,
+            };
+            if unsupported {
+                findings.push(Finding {
+                    rule: Rule::UnsupportedStyle,
+                    path: path.clone(),
+                    message: format!("{}: {} is not supported by email clients", name, value),
+                });
+            }
+        }
+    }
+
+    for (index, child) in element.get_children().iter().enumerate() {
+        path.push(index);
+        check_node(child, path, findings);
+        path.pop();
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use style::{Display, Position, Px};
+    use tags::*;
+
+    #[test]
+    fn inlined_styles_and_tables() {
+        let mut options = EmailOptions {
+            strip_classes: true,
+            presentation_tables: true,
+            ..EmailOptions::default()
+        };
+        options.class_style("box", Style::new().padding(Px(8)).color("black"));
+        options.class_style("muted", Style::new().color("gray"));
+
+        let view = table()
+            .attr("border", "1")
+            .child(tr().child(td().class("box").class("muted").class("other")))
+            .done();
+
+        assert_eq!(
+            render_email(&view, &options),
+            concat!(
+                r#"<table border="1" cellpadding="0" cellspacing="0" role="presentation">"#,
+                r#"<tr><td class="other" style="padding: 8px; color: gray"></td></tr></table>"#
+            )
+        );
+    }
+
+    #[test]
+    fn unsupported_elements_and_styles() {
+        let view = div()
+            .child(video())
+            .child(
+                div().style(
+                    Style::new()
+                        .display(Display::Grid)
+                        .position(Position::Absolute),
+                ),
+            )
+            .child(div().attr("style", "display: block; position: static"))
+            .done();
+
+        let findings: Vec<(Rule, String)> = check(&view)
+            .into_iter()
+            .map(|finding| (finding.rule, finding.path.to_string()))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                (Rule::UnsupportedElement, "/0".to_string()),
+                (Rule::UnsupportedStyle, "/1".to_string()),
+                (Rule::UnsupportedStyle, "/1".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use binary::DecodeError;
+#[cfg(feature = "parser")]
+use parser::ParseError;
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Tag name is empty or contains characters not allowed in tag names.
+    InvalidTagName(String),
+    /// Attribute name is empty or contains characters not allowed in
+    /// attribute names.
+    InvalidAttributeName(String),
+    /// Void element (such as `br` or `input`) has children.
+    VoidChildren(String),
+    /// Several siblings share the same key.
+    DuplicateKey(String),
+    /// URL attribute uses a scheme not allowed by the URL policy.
+    DisallowedUrl(String),
+    /// Patch doesn't fit the tree it is applied to.
+    InvalidPatch(String),
+    /// Patch was diffed against the tree of the first generation, but the
+    /// tree of the second one is mounted.
+    StalePatch(u64, u64),
+    /// There is no node to update at the path.
+    InvalidPath(String),
+    /// There is no live session with the id, see `live`.
+    UnknownSession(String),
+    /// Tree is nested deeper than the limit, see `VNode::check_limits`.
+    TooDeep(usize),
+    /// Tree has more nodes than the limit.
+    TooManyNodes(usize),
+    /// Binary tree or patch can't be decoded.
+    Decode(DecodeError),
+    /// Markup can't be parsed.
+    #[cfg(feature = "parser")]
+    Parse(ParseError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Error::*;
+
+        match self {
+            InvalidTagName(name) => write!(f, "invalid tag name {:?}", name),
+            InvalidAttributeName(name) => write!(f, "invalid attribute name {:?}", name),
+            VoidChildren(tag) => write!(f, "void element `{}` can't have children", tag),
+            DuplicateKey(key) => write!(f, "duplicate key {:?} among siblings", key),
+            DisallowedUrl(url) => write!(f, "disallowed URL {:?}", url),
+            InvalidPatch(message) => write!(f, "invalid patch: {}", message),
+            StalePatch(base, mounted) => write!(
+                f,
+                "patch against generation {} applied to generation {}",
+                base, mounted
+            ),
+            InvalidPath(message) => write!(f, "invalid path: {}", message),
+            UnknownSession(id) => write!(f, "unknown session {:?}", id),
+            TooDeep(limit) => write!(f, "tree is nested deeper than {}", limit),
+            TooManyNodes(limit) => write!(f, "tree has more than {} nodes", limit),
+            Decode(error) => write!(f, "decode error: {}", error),
+            #[cfg(feature = "parser")]
+            Parse(error) => write!(f, "parse error: {}", error),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::TooDeep(limit) => Error::TooDeep(limit),
+            DecodeError::TooManyNodes(limit) => Error::TooManyNodes(limit),
+            error => Error::Decode(error),
+        }
+    }
+}
+
+#[cfg(feature = "parser")]
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+/// Check tag name: ASCII alphanumeric characters and dashes, starting with
+/// a letter.
+///
+pub fn is_valid_tag_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Check attribute name as defined by the HTML spec: no whitespace, quotes,
+/// `>`, `/`, `=` or control characters.
+/// https://html.spec.whatwg.org/multipage/syntax.html#attributes-2
+///
+pub fn is_valid_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|c| {
+            c.is_whitespace()
+                || c.is_control()
+                || c == '"'
+                || c == '\''
+                || c == '>'
+                || c == '/'
+                || c == '='
+        })
+}
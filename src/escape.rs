@@ -0,0 +1,262 @@
+//! HTML escaping helpers.
+//!
+use std::borrow::Cow;
+use std::char;
+
+/// Named character references understood by `decode_entities`.
+///
+/// This is the commonly used subset of the HTML named references, not the
+/// full table of over two thousand names.
+///
+pub const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("iexcl", '¡'),
+    ("cent", '¢'),
+    ("pound", '£'),
+    ("yen", '¥'),
+    ("euro", '€'),
+    ("sect", '§'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("deg", '°'),
+    ("plusmn", '±'),
+    ("times", '×'),
+    ("divide", '÷'),
+    ("micro", 'µ'),
+    ("para", '¶'),
+    ("middot", '·'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("iquest", '¿'),
+    ("shy", '\u{ad}'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("sbquo", '‚'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("bdquo", '„'),
+    ("bull", '•'),
+    ("hellip", '…'),
+    ("prime", '′'),
+    ("larr", '←'),
+    ("uarr", '↑'),
+    ("rarr", '→'),
+    ("darr", '↓'),
+    ("harr", '↔'),
+    ("hearts", '♥'),
+    ("check", '✓'),
+    ("ensp", '\u{2002}'),
+    ("emsp", '\u{2003}'),
+    ("thinsp", '\u{2009}'),
+    ("zwnj", '\u{200c}'),
+    ("zwj", '\u{200d}'),
+];
+
+/// Quote around attribute values.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quote {
+    #[default]
+    Double,
+    Single,
+}
+
+impl Quote {
+    pub fn as_char(self) -> char {
+        match self {
+            Quote::Double => '"',
+            Quote::Single => '\'',
+        }
+    }
+}
+
+/// Characters escaped in text and attribute values.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Escaping {
+    /// Only what would change the meaning: `&` and `<` in text, `&` and the
+    /// quote in attribute values. The shortest output.
+    Minimal,
+    /// `&`, `<` and `>`, and the quote in attribute values.
+    #[default]
+    Standard,
+    /// `&`, `<`, `>`, both quotes and all non-ASCII characters, so the
+    /// output is ASCII and survives consumers unsure of the encoding.
+    Aggressive,
+}
+
+/// Escape text content: `&`, `<` and `>`.
+///
+pub fn escape_text(text: &str) -> Cow<'_, str> {
+    escape_text_with(text, Escaping::Standard)
+}
+
+/// Escape attribute value: `&`, `<`, `>` and `"`.
+///
+pub fn escape_attr(value: &str) -> Cow<'_, str> {
+    escape_attr_with(value, Quote::Double, Escaping::Standard)
+}
+
+/// Escape text content with the escaping policy.
+///
+pub fn escape_text_with(text: &str, escaping: Escaping) -> Cow<'_, str> {
+    match escaping {
+        Escaping::Minimal => escape(text, |c| c == '&' || c == '<'),
+        Escaping::Standard => escape(text, |c| c == '&' || c == '<' || c == '>'),
+        Escaping::Aggressive => escape(text, is_aggressive),
+    }
+}
+
+/// Escape attribute value written between the quotes with the escaping
+/// policy.
+///
+pub fn escape_attr_with(value: &str, quote: Quote, escaping: Escaping) -> Cow<'_, str> {
+    let quote = quote.as_char();
+    match escaping {
+        Escaping::Minimal => escape(value, |c| c == '&' || c == quote),
+        Escaping::Standard => escape(value, |c| c == '&' || c == '<' || c == '>' || c == quote),
+        Escaping::Aggressive => escape(value, is_aggressive),
+    }
+}
+
+/// Escape content of a raw text element (`script`, `style`), which can't
+/// contain character references. `</` is written as `<\/`, so the content
+/// can't close the element early, as `</script>` in a string would.
+///
+pub fn escape_raw(content: &str) -> Cow<'_, str> {
+    if content.contains("</") {
+        Cow::Owned(content.replace("</", "<\\/"))
+    } else {
+        Cow::Borrowed(content)
+    }
+}
+
+fn is_aggressive(c: char) -> bool {
+    c == '&' || c == '<' || c == '>' || c == '"' || c == '\'' || !c.is_ascii()
+}
+
+fn escape<F: Fn(char) -> bool>(input: &str, needs_escape: F) -> Cow<'_, str> {
+    if !input.contains(&needs_escape) {
+        return Cow::Borrowed(input);
+    }
+
+    let mut output = String::with_capacity(input.len() + 8);
+    for c in input.chars() {
+        if !needs_escape(c) {
+            output.push(c);
+            continue;
+        }
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push_str(&format!("&#x{:x};", c as u32)),
+        }
+    }
+    Cow::Owned(output)
+}
+
+/// Check if the text contains character references, which is what text
+/// escaped twice looks like once it's escaped again.
+///
+pub fn looks_escaped(input: &str) -> bool {
+    input.contains('&') && decode_entities(input) != input
+}
+
+/// Decode character references: named (`&amp;`), decimal (`&#39;`) and
+/// hexadecimal (`&#x27;`).
+///
+/// Unknown or malformed references are left as they are. Numeric references
+/// to invalid code points decode to U+FFFD.
+///
+pub fn decode_entities(input: &str) -> Cow<'_, str> {
+    if !input.contains('&') {
+        return Cow::Borrowed(input);
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest
+            .find(';')
+            .filter(|end| *end > 1 && *end <= 32)
+            .and_then(|end| decode_reference(&rest[1..end]).map(|c| (c, end)));
+
+        match decoded {
+            Some((c, end)) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+
+    Cow::Owned(output)
+}
+
+/// Decode reference without leading `&` and trailing `;`.
+///
+fn decode_reference(reference: &str) -> Option<char> {
+    if let Some(number) = reference.strip_prefix('#') {
+        let code = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return Some(
+            char::from_u32(code)
+                .filter(|c| *c != '\0')
+                .unwrap_or('\u{fffd}'),
+        );
+    }
+
+    NAMED_ENTITIES
+        .iter()
+        .find(|(name, _)| *name == reference)
+        .map(|(_, c)| *c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_text("a < b & \"c\""), "a &lt; b &amp; \"c\"");
+        assert_eq!(escape_attr("a < b & \"c\""), "a &lt; b &amp; &quot;c&quot;");
+        assert!(match escape_text("plain") {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        });
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(decode_entities("it&#x27;s &#39;ok&#39;"), "it's 'ok'");
+        assert_eq!(decode_entities("&lt;p&gt;&hellip;"), "<p>…");
+        assert_eq!(
+            decode_entities("a & b &unknown; &#xZZ; &"),
+            "a & b &unknown; &#xZZ; &"
+        );
+        assert_eq!(decode_entities("&#0;"), "\u{fffd}");
+        assert_eq!(decode_entities(&escape_attr("<\"&\">")), "<\"&\">");
+    }
+}
@@ -0,0 +1,261 @@
+//! # C ABI
+//!
+//! Functions for diffing from C and other languages with a C FFI, with the
+//! `ffi` feature. Trees and patches use the formats of `json` and `binary`.
+//! Build a library to link against with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or
+//! `staticlib`); the types are plain enough for cbindgen to write the
+//! header.
+//!
+//! ```c
+//! char *patch = troy_diff("{\"tag\": \"p\"}", "{\"tag\": \"b\"}");
+//! if (patch == NULL) {
+//!     fprintf(stderr, "%s\n", troy_last_error());
+//! } else {
+//!     apply(patch);
+//!     troy_string_free(patch);
+//! }
+//! ```
+//!
+//! Results are owned by the caller and freed with `troy_string_free` and
+//! `troy_buffer_free`. On failure, functions return null and
+//! `troy_last_error` describes the failure until the next call on the
+//! thread. Trees nested deeper than `MAX_DEPTH` or with more than
+//! `MAX_NODES` nodes fail, and so do panics, which never unwind into the
+//! caller.
+//!
+use diff::DiffOptions;
+use json::{diff_binary_with, diff_json_with};
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// Deepest tree the functions diff.
+///
+pub const MAX_DEPTH: usize = 256;
+
+/// Largest tree the functions diff, in nodes.
+///
+pub const MAX_NODES: usize = 1_000_000;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Bytes owned by the caller, freed with `troy_buffer_free`. `data` is
+/// null on failure.
+///
+#[repr(C)]
+#[derive(Debug)]
+pub struct TroyBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// Diff two JSON trees and return the JSON patch, or null on failure.
+///
+/// # Safety
+///
+/// Both arguments must be null or point to NUL-terminated strings.
+///
+#[no_mangle]
+pub unsafe extern "C" fn troy_diff(old: *const c_char, new: *const c_char) -> *mut c_char {
+    call(ptr::null_mut(), || {
+        let (old, new) = (str_arg(old, "old")?, str_arg(new, "new")?);
+        let patch = diff_json_with(old, new, &limits()).map_err(|error| error.to_string())?;
+        let patch = CString::new(patch).map_err(|error| error.to_string())?;
+        Ok(patch.into_raw())
+    })
+}
+
+/// Diff two binary trees and return the binary patch, see `binary`.
+///
+/// # Safety
+///
+/// Each pointer must be null or point to the given number of bytes.
+///
+#[no_mangle]
+pub unsafe extern "C" fn troy_diff_binary(
+    old: *const u8,
+    old_len: usize,
+    new: *const u8,
+    new_len: usize,
+) -> TroyBuffer {
+    let failed = TroyBuffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+    call(failed, || {
+        if old.is_null() || new.is_null() {
+            return Err("null tree".to_string());
+        }
+        let old = slice::from_raw_parts(old, old_len);
+        let new = slice::from_raw_parts(new, new_len);
+        let patch = diff_binary_with(old, new, &limits()).map_err(|error| error.to_string())?;
+        let len = patch.len();
+        let data = Box::into_raw(patch.into_boxed_slice()) as *mut u8;
+        Ok(TroyBuffer { data, len })
+    })
+}
+
+/// Message of the last failure on this thread, or null. Valid until the
+/// next call on the thread.
+///
+#[no_mangle]
+pub extern "C" fn troy_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| match &*error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Free a string returned by troy.
+///
+/// # Safety
+///
+/// The string must be null or returned by troy, and not freed before.
+///
+#[no_mangle]
+pub unsafe extern "C" fn troy_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Free a buffer returned by troy.
+///
+/// # Safety
+///
+/// The buffer must be returned by troy, and not freed before.
+///
+#[no_mangle]
+pub unsafe extern "C" fn troy_buffer_free(buffer: TroyBuffer) {
+    if !buffer.data.is_null() {
+        let data = ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+        drop(Box::from_raw(data));
+    }
+}
+
+unsafe fn str_arg<'a>(arg: *const c_char, name: &str) -> Result<&'a str, String> {
+    if arg.is_null() {
+        return Err(format!("null {} tree", name));
+    }
+    CStr::from_ptr(arg)
+        .to_str()
+        .map_err(|_| format!("{} tree is not UTF-8", name))
+}
+
+fn limits() -> DiffOptions {
+    DiffOptions {
+        max_depth: MAX_DEPTH,
+        max_nodes: MAX_NODES,
+        ..DiffOptions::default()
+    }
+}
+
+/// Result of an entry point, with its error or panic recorded for
+/// `troy_last_error` and turned into the failed result.
+///
+fn call<T, F>(failed: T, body: F) -> T
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(result)) => succeed(result),
+        Ok(Err(message)) => fail(message, failed),
+        Err(payload) => fail(format!("panic: {}", panic_message(&*payload)), failed),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown panic", String::as_str),
+    }
+}
+
+fn succeed<T>(result: T) -> T {
+    LAST_ERROR.with(|error| *error.borrow_mut() = None);
+    result
+}
+
+fn fail<T>(message: String, result: T) -> T {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+    result
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use binary::{decode_op, encode_node};
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    fn diff_through_c_abi() {
+        let old = CString::new(r#"{"tag": "p"}"#).unwrap();
+        let new = CString::new(r#"{"tag": "b"}"#).unwrap();
+        unsafe {
+            let patch = troy_diff(old.as_ptr(), new.as_ptr());
+            assert_eq!(
+                CStr::from_ptr(patch).to_str(),
+                Ok(r#"{"node":{"tag":"b"},"op":"replace"}"#)
+            );
+            assert!(troy_last_error().is_null());
+            troy_string_free(patch);
+
+            let invalid = CString::new("{").unwrap();
+            assert!(troy_diff(old.as_ptr(), invalid.as_ptr()).is_null());
+            assert!(CStr::from_ptr(troy_last_error())
+                .to_str()
+                .unwrap()
+                .starts_with("invalid JSON"));
+            assert!(troy_diff(ptr::null(), new.as_ptr()).is_null());
+        }
+
+        let (old, new) = (p().text("a").done(), p().text("b").done());
+        let (old_bytes, new_bytes) = (encode_node(&old), encode_node(&new));
+        unsafe {
+            let patch = troy_diff_binary(
+                old_bytes.as_ptr(),
+                old_bytes.len(),
+                new_bytes.as_ptr(),
+                new_bytes.len(),
+            );
+            let bytes = slice::from_raw_parts(patch.data, patch.len);
+            assert_eq!(decode_op(bytes).unwrap().as_op(), diff(&old, &new));
+            troy_buffer_free(patch);
+        }
+    }
+
+    #[test]
+    fn failures_through_c_abi() {
+        let mut deep = p().done();
+        for _ in 0..MAX_DEPTH {
+            deep = div().child_node(deep).done();
+        }
+        let deep = encode_node(&deep);
+        let new = encode_node(&p().done());
+        unsafe {
+            let patch = troy_diff_binary(deep.as_ptr(), deep.len(), new.as_ptr(), new.len());
+            assert!(patch.data.is_null());
+            assert_eq!(
+                CStr::from_ptr(troy_last_error()).to_str(),
+                Ok("invalid binary message: tree is nested deeper than 256")
+            );
+        }
+
+        assert!(call(ptr::null_mut::<c_char>(), || panic!("broken")).is_null());
+        assert_eq!(
+            unsafe { CStr::from_ptr(troy_last_error()) }.to_str(),
+            Ok("panic: broken")
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! # Reconciler fuzzing
+//!
+//! Randomized check of the differ on keyed lists.
+//!
+//! Each iteration generates a base list of keyed children and a mutation of
+//! it (permutation, insertions, removals and content changes), diffs the two
+//! parent elements, applies the diff to the old tree, and checks that the
+//! result equals the new tree. A failing case is shrunk to a minimal one
+//! before it's reported.
+//!
+//! Children are created by a user supplied function, so the same check can
+//! run against custom node shapes:
+//!
+//! ```
+//! use troy::fuzz::{fuzz, FuzzOptions};
+//! use troy::tags::*;
+//!
+//! let result = fuzz(&FuzzOptions::default(), |key, version| {
+//!     li().key(key.to_string())
+//!         .child(span().class("label").text(format!("item {}", key)))
+//!         .class(format!("version-{}", version))
+//!         .done()
+//! });
+//!
+//! assert!(result.is_ok(), "{}", result.unwrap_err());
+//! ```
+//!
+use diff::{apply, diff};
+use element::VElement;
+use node::VNode;
+use rng::XorShift;
+use std::fmt;
+use std::panic;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzOptions {
+    /// Seed for the pseudo-random generator. Same seed produces same cases.
+    pub seed: u64,
+    /// Number of generated cases.
+    pub iterations: usize,
+    /// Maximum length of generated lists.
+    pub max_len: usize,
+}
+
+impl Default for FuzzOptions {
+    fn default() -> Self {
+        FuzzOptions {
+            seed: 0x5eed,
+            iterations: 1000,
+            max_len: 12,
+        }
+    }
+}
+
+/// Pair of children lists, each child being a `(key, version)` pair.
+///
+/// Children with the same key and different versions have different content.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzCase {
+    pub old: Vec<(usize, u32)>,
+    pub new: Vec<(usize, u32)>,
+}
+
+impl fmt::Display for FuzzCase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_list(list: &[(usize, u32)], f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "[")?;
+            for (index, (key, version)) in list.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}v{}", key, version)?;
+            }
+            write!(f, "]")
+        }
+
+        fmt_list(&self.old, f)?;
+        write!(f, " -> ")?;
+        fmt_list(&self.new, f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzFailure {
+    pub seed: u64,
+    pub iteration: usize,
+    /// Case as it was generated.
+    pub original: FuzzCase,
+    /// Minimal case that still fails.
+    pub shrunk: FuzzCase,
+    /// Failure description of the shrunk case.
+    pub message: String,
+}
+
+impl fmt::Display for FuzzFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "fuzz case failed (seed {}, iteration {})",
+            self.seed, self.iteration
+        )?;
+        writeln!(f, "original: {}", self.original)?;
+        writeln!(f, "shrunk:   {}", self.shrunk)?;
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Default child shape: keyed paragraph with versioned text.
+///
+pub fn default_child(key: &str, version: u32) -> VNode {
+    VElement::new("p")
+        .key(key.to_string())
+        .text(format!("{} v{}", key, version))
+        .done()
+}
+
+/// Run randomized diff and apply checks.
+///
+pub fn fuzz<F>(options: &FuzzOptions, make_child: F) -> Result<(), Box<FuzzFailure>>
+where
+    F: Fn(&str, u32) -> VNode,
+{
+    let mut rng = XorShift::new(options.seed);
+
+    for iteration in 0..options.iterations {
+        let case = generate(&mut rng, options.max_len);
+        if check(&case, &make_child).is_err() {
+            let shrunk = shrink(case.clone(), &make_child);
+            let message = check(&shrunk, &make_child).unwrap_err();
+            return Err(Box::new(FuzzFailure {
+                seed: options.seed,
+                iteration,
+                original: case,
+                shrunk,
+                message,
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+/// Run diff and apply for a single case.
+///
+pub fn check<F>(case: &FuzzCase, make_child: &F) -> Result<(), String>
+where
+    F: Fn(&str, u32) -> VNode,
+{
+    let build = |list: &[(usize, u32)]| {
+        list.iter()
+            .fold(VElement::new("div"), |parent, (key, version)| {
+                parent.child_node(make_child(&key.to_string(), *version))
+            })
+            .done()
+    };
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let old = build(&case.old);
+        let new = build(&case.new);
+        let op = diff(&old, &new);
+        match apply(&old, &op).map_err(|error| error.to_string()) {
+            Ok(ref applied) if *applied == new => Ok(()),
+            Ok(applied) => Err(format!(
+                "applied diff doesn't match new tree\ndiff:\n{}applied:\n{}expected:\n{}",
+                op, applied, new
+            )),
+            Err(message) => Err(format!("diff can't be applied: {}", message)),
+        }
+    }));
+
+    result.unwrap_or_else(|_| Err("panicked".to_string()))
+}
+
+fn generate(rng: &mut XorShift, max_len: usize) -> FuzzCase {
+    let old_len = rng.below(max_len + 1);
+    let old: Vec<(usize, u32)> = (0..old_len).map(|key| (key, 0)).collect();
+
+    let mut new = old.clone();
+
+    // Remove some children
+    let removes = rng.below(new.len() + 1) / 2;
+    for _ in 0..removes {
+        let index = rng.below(new.len());
+        new.remove(index);
+    }
+
+    // Shuffle some children
+    let swaps = rng.below(new.len() + 1);
+    for _ in 0..swaps {
+        let (a, b) = (rng.below(new.len()), rng.below(new.len()));
+        new.swap(a, b);
+    }
+
+    // Insert new children
+    let inserts = rng.below(max_len.saturating_sub(new.len()) + 1);
+    for offset in 0..inserts {
+        let index = rng.below(new.len() + 1);
+        new.insert(index, (old_len + offset, 0));
+    }
+
+    // Change content of some children
+    for child in new.iter_mut() {
+        if rng.below(4) == 0 {
+            child.1 += 1;
+        }
+    }
+
+    FuzzCase { old, new }
+}
+
+/// Greedily remove children and reset versions while the case still fails.
+///
+fn shrink<F>(mut case: FuzzCase, make_child: &F) -> FuzzCase
+where
+    F: Fn(&str, u32) -> VNode,
+{
+    loop {
+        let mut candidates = Vec::new();
+
+        for index in 0..case.old.len() {
+            // Removing an old child also removes it from the new list.
+            let key = case.old[index].0;
+            let mut candidate = case.clone();
+            candidate.old.remove(index);
+            candidate.new.retain(|(k, _)| *k != key);
+            candidates.push(candidate);
+        }
+        for index in 0..case.new.len() {
+            let mut candidate = case.clone();
+            candidate.new.remove(index);
+            candidates.push(candidate);
+        }
+        for index in 0..case.new.len() {
+            if case.new[index].1 != 0 {
+                let mut candidate = case.clone();
+                candidate.new[index].1 = 0;
+                candidates.push(candidate);
+            }
+        }
+
+        match candidates
+            .into_iter()
+            .find(|candidate| check(candidate, make_child).is_err())
+        {
+            Some(smaller) => case = smaller,
+            None => return case,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzz_keyed_lists() {
+        let result = fuzz(&FuzzOptions::default(), default_child);
+
+        if let Err(failure) = result {
+            panic!("{}", failure);
+        }
+    }
+
+    #[test]
+    fn shrink_failing_case() {
+        // Checker which fails whenever key 3 is present in the new list.
+        let make_child = |key: &str, version: u32| {
+            if key == "3" {
+                panic!("bad child");
+            }
+            default_child(key, version)
+        };
+        let case = FuzzCase {
+            old: vec![(0, 0), (1, 0), (2, 0)],
+            new: vec![(2, 1), (3, 0), (0, 1)],
+        };
+
+        let shrunk = shrink(case, &make_child);
+
+        assert_eq!(
+            shrunk,
+            FuzzCase {
+                old: vec![],
+                new: vec![(3, 0)],
+            }
+        );
+    }
+}
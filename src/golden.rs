@@ -0,0 +1,110 @@
+//! # Golden fixture tests
+//!
+//! Harness for regression tests of the differ.
+//!
+//! Each fixture is a directory with three files:
+//!
+//! - `old.html` - markup of the old tree.
+//! - `new.html` - markup of the new tree.
+//! - `patch.txt` - expected serialized diff between them.
+//!
+//! Both trees are parsed with the `parser` module, diffed, and the serialized
+//! diff is compared against `patch.txt`. When the `TROY_BLESS` environment
+//! variable is set, `patch.txt` is written instead of compared, which is the
+//! way to create new fixtures or accept intended changes in the diff output.
+//!
+//! ```text
+//! TROY_BLESS=1 cargo test golden
+//! ```
+//!
+use diff::{diff, NodeOp};
+use parser::parse;
+use std::fs;
+use std::path::Path;
+
+/// Environment variable which switches the harness into bless mode.
+///
+pub const BLESS_VAR: &str = "TROY_BLESS";
+
+/// Run a single fixture directory.
+///
+/// Returns a description of the failure if the fixture can't be read or
+/// the diff doesn't match the expected patch.
+///
+pub fn check_fixture(dir: &Path) -> Result<(), String> {
+    let read = |name: &str| {
+        fs::read_to_string(dir.join(name))
+            .map_err(|e| format!("{}: can't read {}: {}", dir.display(), name, e))
+    };
+
+    let old =
+        parse(&read("old.html")?).map_err(|e| format!("{}: old.html: {}", dir.display(), e))?;
+    let new =
+        parse(&read("new.html")?).map_err(|e| format!("{}: new.html: {}", dir.display(), e))?;
+
+    let actual = serialize_patch(&diff(&old, &new));
+    let patch_path = dir.join("patch.txt");
+
+    if std::env::var_os(BLESS_VAR).is_some() {
+        return fs::write(&patch_path, &actual)
+            .map_err(|e| format!("{}: can't write patch.txt: {}", dir.display(), e));
+    }
+
+    let expected = read("patch.txt")?;
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: patch mismatch\n--- expected\n{}--- actual\n{}",
+            dir.display(),
+            expected,
+            actual
+        ))
+    }
+}
+
+/// Run every fixture directory found in `root`.
+///
+/// Panics with a report of all failed fixtures.
+///
+pub fn check_fixtures(root: &Path) {
+    let mut dirs: Vec<_> = fs::read_dir(root)
+        .unwrap_or_else(|e| panic!("{}: can't read fixtures: {}", root.display(), e))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+
+    let failures: Vec<String> = dirs
+        .iter()
+        .filter_map(|dir| check_fixture(dir).err())
+        .collect();
+
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} fixtures failed (set {} to update):\n\n{}",
+            failures.len(),
+            dirs.len(),
+            BLESS_VAR,
+            failures.join("\n")
+        );
+    }
+}
+
+/// Serialize diff into the deterministic text form stored in `patch.txt`.
+///
+/// This is the `Display` form of `NodeOp`, which sorts attribute operations.
+///
+pub fn serialize_patch(op: &NodeOp) -> String {
+    op.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_fixtures() {
+        check_fixtures(&Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures"));
+    }
+}
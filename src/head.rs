@@ -0,0 +1,380 @@
+//! # Document head
+//!
+//! Views deep in the tree often know what belongs in the `<head>`: a page
+//! sets the title, an article adds its description, a widget links its
+//! stylesheet. `use_head` lets them declare it while the tree is built, and
+//! `collect_head` gathers the declarations into a `Head` which is rendered
+//! and diffed separately from the body.
+//!
+//! Declarations are deduplicated, later ones winning: the title, `meta`
+//! elements by `name`, `property`, `http-equiv` or `charset`, the canonical
+//! link, and other links by `rel` and `href`.
+//!
+//! Common metadata has helpers which build the elements, for `Head::element`:
+//!
+//! ```
+//! use troy::head::{canonical, meta_description, og, twitter_card, Head, TwitterCard};
+//!
+//! let mut head = Head::new();
+//! head.element(meta_description("About heads"));
+//! head.element(og("title", "Heads"));
+//! head.element(twitter_card(TwitterCard::SummaryLargeImage));
+//! head.element(canonical("https://example.com/heads"));
+//!
+//! assert_eq!(head.get_elements().len(), 4);
+//! ```
+//!
+//! ```
+//! use troy::head::{collect_head, use_head};
+//! use troy::node::VNode;
+//! use troy::render::render_to_string;
+//! use troy::tags::*;
+//!
+//! fn article() -> VNode {
+//!     use_head(|head| {
+//!         head.title("Article");
+//!         head.meta("description", "About heads");
+//!     });
+//!     p().text("Text").done()
+//! }
+//!
+//! let (body, head) = collect_head(|| {
+//!     use_head(|head| head.title("Site"));
+//!     div().child_node(article()).done()
+//! });
+//!
+//! assert_eq!(
+//!     render_to_string(&head.to_node()),
+//!     r#"<head><title>Article</title><meta content="About heads" name="description"></head>"#
+//! );
+//! ```
+//!
+use element::VElement;
+use node::VNode;
+use std::cell::RefCell;
+use types::CowString;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Head>> = const { RefCell::new(None) };
+}
+
+/// Title, `meta` and `link` elements of a document head.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Head {
+    title: Option<CowString>,
+    elements: Vec<VElement>,
+}
+
+impl Head {
+    pub fn new() -> Self {
+        Head::default()
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_ref().map(|title| title.as_ref())
+    }
+
+    /// `meta` and `link` elements, in the order they were first declared.
+    ///
+    pub fn get_elements(&self) -> &[VElement] {
+        &self.elements
+    }
+
+    pub fn title<S: Into<CowString>>(&mut self, title: S) {
+        self.title = Some(title.into());
+    }
+
+    /// Add `<meta name="..." content="...">`.
+    ///
+    pub fn meta<N, C>(&mut self, name: N, content: C)
+    where
+        N: Into<CowString>,
+        C: Into<CowString>,
+    {
+        let name: CowString = name.into();
+        let content: CowString = content.into();
+        self.element(
+            VElement::new_void("meta")
+                .attr("name", name)
+                .attr("content", content),
+        );
+    }
+
+    /// Add `<link rel="..." href="...">`.
+    ///
+    pub fn link<R, H>(&mut self, rel: R, href: H)
+    where
+        R: Into<CowString>,
+        H: Into<CowString>,
+    {
+        let rel: CowString = rel.into();
+        let href: CowString = href.into();
+        self.element(
+            VElement::new_void("link")
+                .attr("rel", rel)
+                .attr("href", href),
+        );
+    }
+
+    /// Add a `meta` or `link` element, replacing an earlier element it
+    /// duplicates.
+    ///
+    pub fn element(&mut self, element: VElement) {
+        let earlier = identity(&element).and_then(|key| {
+            self.elements
+                .iter_mut()
+                .find(|other| identity(other).as_ref() == Some(&key))
+        });
+        match earlier {
+            Some(earlier) => *earlier = element,
+            None => self.elements.push(element),
+        }
+    }
+
+    /// Add declarations of another head after the declarations of this one.
+    ///
+    pub fn merge(&mut self, other: Head) {
+        if other.title.is_some() {
+            self.title = other.title;
+        }
+        for element in other.elements {
+            self.element(element);
+        }
+    }
+
+    /// The `head` element, title first.
+    ///
+    pub fn to_node(&self) -> VNode {
+        let mut head = VElement::new("head");
+        if let Some(title) = &self.title {
+            head = head.child(VElement::new("title").text(title.clone()));
+        }
+        for element in &self.elements {
+            head = head.child(element.clone());
+        }
+        head.done()
+    }
+}
+
+/// Kind of card shown when a page is shared on Twitter.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TwitterCard {
+    Summary,
+    SummaryLargeImage,
+    App,
+    Player,
+}
+
+impl TwitterCard {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TwitterCard::Summary => "summary",
+            TwitterCard::SummaryLargeImage => "summary_large_image",
+            TwitterCard::App => "app",
+            TwitterCard::Player => "player",
+        }
+    }
+}
+
+/// `<meta name="description" content="...">`.
+///
+pub fn meta_description<S: Into<CowString>>(content: S) -> VElement {
+    let content: CowString = content.into();
+    VElement::new_void("meta")
+        .attr("name", "description")
+        .attr("content", content)
+}
+
+/// Open Graph property, `og("title", ..)` for `<meta property="og:title"
+/// content="...">`.
+///
+pub fn og<S: Into<CowString>>(property: &str, content: S) -> VElement {
+    let content: CowString = content.into();
+    VElement::new_void("meta")
+        .attr("property", format!("og:{}", property))
+        .attr("content", content)
+}
+
+/// `<meta name="twitter:card" content="...">`.
+///
+pub fn twitter_card(card: TwitterCard) -> VElement {
+    VElement::new_void("meta")
+        .attr("name", "twitter:card")
+        .attr("content", card.as_str())
+}
+
+/// `<link rel="canonical" href="...">`.
+///
+pub fn canonical<S: Into<CowString>>(url: S) -> VElement {
+    let url: CowString = url.into();
+    VElement::new_void("link")
+        .attr("rel", "canonical")
+        .attr("href", url)
+}
+
+/// Attribute which identifies duplicate elements, with its value.
+///
+fn identity(element: &VElement) -> Option<(&'static str, String)> {
+    let attributes = element.get_attributes();
+    let get = |name: &str| attributes.get(name).map(|value| value.to_string());
+    match element.get_tag() {
+        "meta" => ["name", "property", "http-equiv"]
+            .iter()
+            .find_map(|name| get(name).map(|value| (*name, value)))
+            .or_else(|| get("charset").map(|_| ("charset", String::new()))),
+        "link" => match (get("rel"), get("href")) {
+            (Some(ref rel), _) if rel == "canonical" => Some(("canonical", String::new())),
+            (Some(rel), Some(href)) => Some(("link", format!("{} {}", rel, href))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Declare head elements from a view built inside `collect_head`. Does
+/// nothing outside of it.
+///
+pub fn use_head<F>(declare: F)
+where
+    F: FnOnce(&mut Head),
+{
+    CURRENT.with(|current| {
+        if let Some(head) = current.borrow_mut().as_mut() {
+            declare(head);
+        }
+    });
+}
+
+/// Build a tree and collect head elements its views declare with
+/// `use_head`. Components are rendered while collecting, so their
+/// declarations are included.
+///
+pub fn collect_head<F>(view: F) -> (VNode, Head)
+where
+    F: FnOnce() -> VNode,
+{
+    // Restores the head of an outer collection even if `view` panics.
+    struct Restore(Option<Head>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let outer = self.0.take();
+            CURRENT.with(|current| *current.borrow_mut() = outer);
+        }
+    }
+
+    fn render(node: &VNode) {
+        if let VNode::Element(element) = node.resolve() {
+            for child in element.get_children() {
+                render(child);
+            }
+        }
+    }
+
+    let outer = CURRENT.with(|current| current.replace(Some(Head::new())));
+    let restore = Restore(outer);
+    let node = view();
+    render(&node);
+    let head = CURRENT
+        .with(|current| current.replace(None))
+        .unwrap_or_default();
+    drop(restore);
+    (node, head)
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use component::{component, Component};
+    use render::render_to_string;
+    use tags::*;
+
+    #[test]
+    fn later_declarations_win() {
+        let mut head = Head::new();
+        head.title("First");
+        head.meta("description", "First");
+        head.link("stylesheet", "/a.css");
+        head.link("canonical", "/first");
+        head.element(meta().attr("charset", "utf-8"));
+
+        let mut later = Head::new();
+        later.title("Second");
+        later.meta("description", "Second");
+        later.link("stylesheet", "/b.css");
+        later.link("canonical", "/second");
+        head.merge(later);
+
+        assert_eq!(head.get_title(), Some("Second"));
+        assert_eq!(
+            render_to_string(&head.to_node()),
+            concat!(
+                r#"<head><title>Second</title>"#,
+                r#"<meta content="Second" name="description">"#,
+                r#"<link href="/a.css" rel="stylesheet">"#,
+                r#"<link href="/second" rel="canonical">"#,
+                r#"<meta charset="utf-8">"#,
+                r#"<link href="/b.css" rel="stylesheet"></head>"#
+            )
+        );
+    }
+
+    #[test]
+    fn metadata_helpers() {
+        let mut head = Head::new();
+        head.element(meta_description("First"));
+        head.element(og("title", "Title"));
+        head.element(og("image", "/a.png"));
+        head.element(twitter_card(TwitterCard::Summary));
+        head.element(canonical("/a"));
+        head.element(meta_description("Second"));
+        head.element(canonical("/b"));
+
+        assert_eq!(
+            render_to_string(&head.to_node()),
+            concat!(
+                r#"<head><meta content="Second" name="description">"#,
+                r#"<meta content="Title" property="og:title">"#,
+                r#"<meta content="/a.png" property="og:image">"#,
+                r#"<meta content="summary" name="twitter:card">"#,
+                r#"<link href="/b" rel="canonical"></head>"#
+            )
+        );
+    }
+
+    struct Page;
+
+    impl Component for Page {
+        type Props = ();
+
+        fn props(&self) -> &() {
+            &()
+        }
+
+        fn render(&self) -> VNode {
+            use_head(|head| head.title("Page"));
+            p().done()
+        }
+
+        fn should_update(&self, _: &()) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn components_and_nested_collections() {
+        let (_, head) = collect_head(|| {
+            let (_, inner) = collect_head(|| {
+                use_head(|head| head.title("Inner"));
+                p().done()
+            });
+            assert_eq!(inner.get_title(), Some("Inner"));
+            div().child_node(component(Page).done()).done()
+        });
+
+        assert_eq!(head.get_title(), Some("Page"));
+        use_head(|_| panic!("no head is collected"));
+    }
+}
@@ -0,0 +1,271 @@
+//! # Translated texts
+//!
+//! `trans` builds a text from a message id and named arguments instead of a
+//! finished string. The message template comes from the `Resolver` of the
+//! subtree, installed with `with_resolver`, and its `{name}` placeholders
+//! are filled with the arguments when the text is rendered.
+//!
+//! Translated texts are components whose props are the locale, the message
+//! id and the arguments. The differ compares those and produces `Skip(1)`
+//! for a text whose arguments didn't change, without resolving or
+//! formatting it again. Only texts with changed arguments are rendered and
+//! replaced.
+//!
+//! ```
+//! use troy::i18n::{trans, with_resolver, Messages};
+//! use troy::tags::*;
+//!
+//! let messages = Messages::new("en").message("greeting", "Hello, {name}!");
+//! let view = with_resolver(messages, || {
+//!     p().child_node(trans("greeting").arg("name", "Ash").done()).done()
+//! });
+//!
+//! assert_eq!(view.text_content(), "Hello, Ash!");
+//! ```
+//!
+//! Without a resolver, and for ids the resolver doesn't know, the id is used
+//! as the template.
+//!
+use component::{Component, VComponent};
+use context::{use_context, with_context};
+use node::VNode;
+use std::collections::HashMap;
+use sync::MaybeSync;
+use text::text;
+use types::CowString;
+
+const RESOLVER: &str = "troy::i18n::resolver";
+
+/// Source of message templates, such as a translation catalog.
+///
+pub trait Resolver: MaybeSync {
+    /// Locale of the templates. Texts built with another locale are
+    /// rendered again, even if their arguments didn't change.
+    ///
+    fn locale(&self) -> &str;
+
+    /// Template of the message, with `{name}` placeholders for arguments.
+    ///
+    fn template(&self, id: &str) -> Option<&str>;
+}
+
+/// Templates of one locale, by message id.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Messages {
+    locale: String,
+    templates: HashMap<String, String>,
+}
+
+impl Messages {
+    pub fn new<S: Into<String>>(locale: S) -> Self {
+        Messages {
+            locale: locale.into(),
+            templates: HashMap::new(),
+        }
+    }
+
+    /// Add the template of a message.
+    ///
+    pub fn message<I, T>(mut self, id: I, template: T) -> Self
+    where
+        I: Into<String>,
+        T: Into<String>,
+    {
+        self.templates.insert(id.into(), template.into());
+        self
+    }
+}
+
+impl Resolver for Messages {
+    fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    fn template(&self, id: &str) -> Option<&str> {
+        self.templates.get(id).map(String::as_str)
+    }
+}
+
+/// Build subtree with translated texts resolved by `resolver`.
+///
+pub fn with_resolver<R, F>(resolver: R, subtree: F) -> VNode
+where
+    R: Resolver + 'static,
+    F: FnOnce() -> VNode,
+{
+    let resolver: Box<dyn Resolver> = Box::new(resolver);
+    with_context(RESOLVER, resolver, subtree)
+}
+
+/// Fill `{name}` placeholders of the template with the arguments.
+/// Placeholders without an argument are left as they are.
+///
+pub fn format_message(template: &str, args: &[(CowString, String)]) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let end = match placeholder.find('}') {
+            Some(end) => end,
+            None => {
+                rest = placeholder;
+                break;
+            }
+        };
+        let name = &placeholder[1..end];
+        match args.iter().find(|(arg, _)| arg == name) {
+            Some((_, value)) => message.push_str(value),
+            None => message.push_str(&placeholder[..=end]),
+        }
+        rest = &placeholder[end + 1..];
+    }
+    message.push_str(rest);
+    message
+}
+
+/// Props of a translated text: the locale it was built in, the message id
+/// and the arguments.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransProps {
+    locale: Option<String>,
+    id: CowString,
+    args: Vec<(CowString, String)>,
+}
+
+/// Translated text, see `trans`.
+///
+#[derive(Debug, Clone)]
+pub struct Trans {
+    props: TransProps,
+}
+
+impl Trans {
+    /// Set the value of the `{name}` placeholder.
+    ///
+    pub fn arg<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<CowString>,
+        V: ToString,
+    {
+        let name = name.into();
+        let value = value.to_string();
+        match self.props.args.iter_mut().find(|(arg, _)| *arg == name) {
+            Some(arg) => arg.1 = value,
+            None => self.props.args.push((name, value)),
+        }
+        self
+    }
+
+    /// Wrap the text into VComponent, such as to give it a key.
+    ///
+    pub fn component(self) -> VComponent {
+        VComponent::new(self)
+    }
+
+    /// Wrap the text into VNode.
+    ///
+    pub fn done(self) -> VNode {
+        self.component().done()
+    }
+}
+
+impl Component for Trans {
+    type Props = TransProps;
+
+    fn props(&self) -> &TransProps {
+        &self.props
+    }
+
+    fn render(&self) -> VNode {
+        let resolver = use_context::<Box<dyn Resolver>>(RESOLVER);
+        let template = resolver
+            .as_ref()
+            .and_then(|resolver| resolver.template(&self.props.id))
+            .unwrap_or(&self.props.id);
+        text(format_message(template, &self.props.args)).done()
+    }
+
+    fn should_update(&self, old_props: &TransProps) -> bool {
+        self.props != *old_props
+    }
+}
+
+/// Translated text of the message with the id, in the locale of the
+/// resolver of the subtree it is built in.
+///
+pub fn trans<I>(id: I) -> Trans
+where
+    I: Into<CowString>,
+{
+    let locale = use_context::<Box<dyn Resolver>>(RESOLVER).map(|r| r.locale().to_string());
+    Trans {
+        props: TransProps {
+            locale,
+            id: id.into(),
+            args: Vec::new(),
+        },
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::NodeOp::*;
+    use diff::{diff, TextOp};
+    use tags::*;
+
+    fn view(locale: &str, name: &str) -> VNode {
+        let messages = match locale {
+            "en" => Messages::new("en").message("greeting", "Hello, {name}!"),
+            _ => Messages::new("de").message("greeting", "Hallo, {name}!"),
+        };
+        with_resolver(messages, || {
+            p().child_node(trans("greeting").arg("name", name).done())
+                .done()
+        })
+    }
+
+    #[test]
+    fn placeholders() {
+        let args = vec![("a".into(), "1".to_string()), ("b".into(), "2".to_string())];
+        assert_eq!(format_message("{a} and {b}", &args), "1 and 2");
+        assert_eq!(format_message("{c} {a", &args), "{c} {a");
+        assert_eq!(
+            trans("plain {a}").arg("a", 1).done().text_content(),
+            "plain 1"
+        );
+    }
+
+    #[test]
+    fn diffed_by_arguments() {
+        let old = view("en", "Ash");
+        assert_eq!(old.text_content(), "Hello, Ash!");
+
+        // Skipped text takes over the node of the old one without rendering.
+        let same = view("en", "Ash");
+        assert_eq!(diff(&old, &same), Skip(1));
+        let rendered = |node: &VNode| match node {
+            VNode::Element(element) => match &element.get_children()[0] {
+                VNode::Component(text) => text.rendered() as *const VNode,
+                node => panic!("unexpected node {:?}", node),
+            },
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert_eq!(rendered(&same), rendered(&old));
+
+        let renamed = view("en", "Bob");
+        assert_eq!(
+            diff(&old, &renamed),
+            Update(
+                None,
+                Some(vec![Text(TextOp::Set("Hello, Bob!".into()))]),
+                None
+            )
+        );
+        assert_eq!(view("de", "Ash").text_content(), "Hallo, Ash!");
+        assert_ne!(diff(&old, &view("de", "Ash")), Skip(1));
+    }
+}
@@ -0,0 +1,107 @@
+//! # Text interning
+//!
+//! Views which format the same few strings every frame, such as enum
+//! labels, status words or table headers, allocate a new copy of each of
+//! them per render. `Interner` keeps one copy of every distinct string and
+//! hands out a `Shared<str>` of it, so texts built from interned strings
+//! share one allocation across frames. The differ compares such texts by
+//! pointer before comparing their contents.
+//!
+//! ```
+//! use troy::diff::{diff, NodeOp};
+//! use troy::intern::Interner;
+//! use troy::node::VNode;
+//! use troy::tags::*;
+//!
+//! fn view(interner: &mut Interner, status: u8) -> VNode {
+//!     let label = interner.text(&format!("status {}", status));
+//!     p().child_node(label.done()).done()
+//! }
+//!
+//! let mut interner = Interner::new();
+//! let old = view(&mut interner, 2);
+//! let new = view(&mut interner, 2);
+//!
+//! assert_eq!(diff(&old, &new), NodeOp::Skip(1));
+//! assert_eq!(interner.len(), 1);
+//! ```
+//!
+//! Interned strings are kept until the interner and all texts using them
+//! are dropped, or until `remove_unused` once no text uses them, so a
+//! long-lived interner should only get strings from a bounded set and not
+//! user content.
+//!
+use std::collections::HashSet;
+use sync::Shared;
+use text::VText;
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Shared<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// Return the interned copy of `string`, allocating it on first use.
+    ///
+    pub fn intern(&mut self, string: &str) -> Shared<str> {
+        match self.strings.get(string) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Shared<str> = Shared::from(string);
+                self.strings.insert(interned.clone());
+                interned
+            }
+        }
+    }
+
+    /// Create a VText with interned content.
+    ///
+    pub fn text(&mut self, content: &str) -> VText {
+        VText::new_shared(self.intern(content))
+    }
+
+    /// Free strings which no text or other holder uses anymore.
+    ///
+    pub fn remove_unused(&mut self) {
+        self.strings
+            .retain(|interned| Shared::strong_count(interned) > 1);
+    }
+
+    /// Number of distinct strings interned.
+    ///
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interned_strings_share_allocation() {
+        let mut interner = Interner::new();
+        let first = interner.intern(&String::from("open"));
+        let second = interner.intern(&String::from("open"));
+        let other = interner.intern("closed");
+
+        assert!(Shared::ptr_eq(&first, &second));
+        assert!(!Shared::ptr_eq(&first, &other));
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.text("open").get_content().as_ptr(), first.as_ptr());
+
+        drop((first, second, other));
+        let text = interner.text("open");
+        interner.remove_unused();
+        assert_eq!(interner.len(), 1);
+        assert_eq!(text.get_content(), "open");
+    }
+}
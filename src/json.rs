@@ -0,0 +1,731 @@
+//! # JSON trees and patches
+//!
+//! JSON form of trees and patches for applications which build trees and
+//! apply patches outside of Rust, such as through `ffi`. `diff_json` takes
+//! two JSON trees and returns the JSON patch, `diff_binary` does the same
+//! with the binary encoding. `diff_json_with` and `diff_binary_with` also
+//! check the trees against the limits of `DiffOptions`, for trees from
+//! untrusted sources.
+//!
+//! ```
+//! use troy::json::diff_json;
+//!
+//! let old = r#"{"tag": "p", "children": ["a"]}"#;
+//! let new = r#"{"tag": "p", "classes": ["b"], "children": ["a"]}"#;
+//!
+//! assert_eq!(
+//!     diff_json(old, new).unwrap(),
+//!     r#"{"attrs":[{"name":"b","op":"insert_class"}],"op":"update"}"#
+//! );
+//! ```
+//!
+//! ## Trees
+//!
+//! Text nodes are strings. Elements are objects with `tag` and optional
+//! `key`, `void`, `classes`, `attributes` and `children`:
+//!
+//! ```json
+//! {"tag": "li", "key": "1", "attributes": {"title": "one"}, "children": ["Ash"]}
+//! ```
+//!
+//! ## Patches
+//!
+//! Operations are objects with `op` being `skip` or `remove` (with `count`),
+//! `update` or `move` (with `position`, and optional `attrs`, `children` and
+//! `inserts`), `replace` (with `node`), `set_text` (with `text`) or `splice`
+//! (with `at`, `remove` and `insert`, see `diff::TextOp`). Inserts are
+//! objects with `position` and `node`. Attribute operations have `op` being
+//! `insert_class`, `remove_class`, `insert`, `update` or `remove`, with
+//! `name` and `value` where needed, or `insert_token` or `remove_token`,
+//! with `name` and `token`.
+//!
+//! ## Schemas
+//!
+//! `tree_schema` and `patch_schema` describe both formats as JSON Schemas
+//! (draft 7), for validating messages or generating types in other
+//! languages.
+//!
+use binary::{decode_node_with, encode_op, DecodeError, DecodeOptions};
+use diff::{
+    diff, diff_with_options, try_diff_with_options, AttrDiff, AttrOp, ChildDiff, ChildInserts,
+    DiffOptions, NodeOp, TextOp,
+};
+use element::VElement;
+use key::{scoped_key, Key};
+use node::VNode;
+use serde_json::{self, json, Map, Value};
+use std::error;
+use std::fmt;
+use text::text;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonError {
+    /// Input is not valid JSON.
+    Syntax(String),
+    /// Input is valid JSON, but not a tree.
+    InvalidTree(String),
+    /// Input is not a valid binary message.
+    Binary(DecodeError),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Syntax(message) => write!(f, "invalid JSON: {}", message),
+            JsonError::InvalidTree(message) => write!(f, "invalid tree: {}", message),
+            JsonError::Binary(error) => write!(f, "invalid binary message: {}", error),
+        }
+    }
+}
+
+impl error::Error for JsonError {}
+
+impl From<DecodeError> for JsonError {
+    fn from(error: DecodeError) -> Self {
+        JsonError::Binary(error)
+    }
+}
+
+/// Diff two JSON trees and return the JSON patch.
+///
+pub fn diff_json(old: &str, new: &str) -> Result<String, JsonError> {
+    let old = tree_from_json(old)?;
+    let new = tree_from_json(new)?;
+    Ok(op_to_json(&diff(&old, &new)).to_string())
+}
+
+/// Diff two JSON trees with the options, failing on trees which exceed
+/// `max_depth` or `max_nodes`.
+///
+pub fn diff_json_with(old: &str, new: &str, options: &DiffOptions) -> Result<String, JsonError> {
+    let old = tree_from_json(old)?;
+    let new = tree_from_json(new)?;
+    let op = try_diff_with_options(&old, &new, options)
+        .map_err(|error| JsonError::InvalidTree(error.to_string()))?;
+    Ok(op_to_json(&op).to_string())
+}
+
+/// Diff two binary trees and return the binary patch, see `binary`.
+///
+pub fn diff_binary(old: &[u8], new: &[u8]) -> Result<Vec<u8>, JsonError> {
+    diff_binary_with(old, new, &DiffOptions::default())
+}
+
+/// Diff two binary trees with the options, failing on trees which exceed
+/// `max_depth` or `max_nodes` while they are decoded.
+///
+pub fn diff_binary_with(
+    old: &[u8],
+    new: &[u8],
+    options: &DiffOptions,
+) -> Result<Vec<u8>, JsonError> {
+    let limits = DecodeOptions {
+        max_depth: options.max_depth,
+        max_nodes: options.max_nodes,
+    };
+    let old = decode_node_with(old, &limits)?;
+    let new = decode_node_with(new, &limits)?;
+    Ok(encode_op(&diff_with_options(&old, &new, options)))
+}
+
+pub fn tree_from_json(json: &str) -> Result<VNode, JsonError> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|error| JsonError::Syntax(error.to_string()))?;
+    node_from_value(&value)
+}
+
+/// Strings and numbers as themselves, scoped keys as an object with the
+/// scope and the key.
+///
+fn key_to_json(key: &Key) -> Value {
+    match key {
+        Key::Str(key) => key.as_ref().into(),
+        Key::U64(key) => (*key).into(),
+        Key::I64(key) => (*key).into(),
+        Key::Scoped(scope, key) => {
+            let mut object = Map::new();
+            object.insert("scope".into(), scope.as_ref().into());
+            object.insert("key".into(), key_to_json(key));
+            object.into()
+        }
+    }
+}
+
+fn key_from_json(value: &Value) -> Result<Key, JsonError> {
+    match value {
+        Value::String(key) => Ok(Key::from(key.clone())),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(key), _) => Ok(Key::U64(key)),
+            (None, Some(key)) => Ok(Key::I64(key)),
+            _ => Err(JsonError::InvalidTree("key must be an integer".to_string())),
+        },
+        Value::Object(object) => match (object.get("scope"), object.get("key")) {
+            (Some(Value::String(scope)), Some(key)) => {
+                Ok(scoped_key(scope.clone(), key_from_json(key)?))
+            }
+            _ => Err(JsonError::InvalidTree(
+                "scoped key must have a scope and a key".to_string(),
+            )),
+        },
+        _ => Err(JsonError::InvalidTree(
+            "key must be a string, an integer or a scoped key".to_string(),
+        )),
+    }
+}
+
+pub fn tree_to_json(node: &VNode) -> Value {
+    match node.resolve() {
+        VNode::Element(element) => {
+            let mut object = Map::new();
+            object.insert("tag".into(), element.get_tag().into());
+            if let Some(key) = element.get_key() {
+                object.insert("key".into(), key_to_json(key));
+            }
+            if element.is_void() {
+                object.insert("void".into(), true.into());
+            }
+            if !element.get_classes().is_empty() {
+                let mut classes: Vec<&str> =
+                    element.get_classes().iter().map(|c| c.as_ref()).collect();
+                classes.sort();
+                object.insert("classes".into(), classes.into());
+            }
+            if !element.get_attributes().is_empty() {
+                let attributes: Map<String, Value> = element
+                    .get_attributes()
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.as_ref().into()))
+                    .collect();
+                object.insert("attributes".into(), attributes.into());
+            }
+            if !element.get_children().is_empty() {
+                let children: Vec<Value> =
+                    element.get_children().iter().map(tree_to_json).collect();
+                object.insert("children".into(), children.into());
+            }
+            object.into()
+        }
+        VNode::Text(content) => content.get_content().into(),
+        _ => unreachable!("resolved node is an element or text"),
+    }
+}
+
+pub fn op_to_json(op: &NodeOp) -> Value {
+    fn update(
+        object: &mut Map<String, Value>,
+        attrs: &AttrDiff,
+        children: &ChildDiff,
+        inserts: &ChildInserts,
+    ) {
+        if let Some(attrs) = attrs {
+            let attrs: Vec<Value> = attrs.iter().map(attr_op_to_json).collect();
+            object.insert("attrs".into(), attrs.into());
+        }
+        if let Some(children) = children {
+            let children: Vec<Value> = children.iter().map(op_to_json).collect();
+            object.insert("children".into(), children.into());
+        }
+        if let Some(inserts) = inserts {
+            let inserts: Vec<Value> = inserts
+                .iter()
+                .map(|(position, node)| {
+                    let mut insert = Map::new();
+                    insert.insert("position".into(), (*position).into());
+                    insert.insert("node".into(), tree_to_json(node));
+                    insert.into()
+                })
+                .collect();
+            object.insert("inserts".into(), inserts.into());
+        }
+    }
+
+    let mut object = Map::new();
+    match op {
+        NodeOp::Skip(count) => {
+            object.insert("op".into(), "skip".into());
+            object.insert("count".into(), (*count).into());
+        }
+        NodeOp::Remove(count) => {
+            object.insert("op".into(), "remove".into());
+            object.insert("count".into(), (*count).into());
+        }
+        NodeOp::Move(position, attrs, children, inserts) => {
+            object.insert("op".into(), "move".into());
+            object.insert("position".into(), (*position).into());
+            update(&mut object, attrs, children, inserts);
+        }
+        NodeOp::Update(attrs, children, inserts) => {
+            object.insert("op".into(), "update".into());
+            update(&mut object, attrs, children, inserts);
+        }
+        NodeOp::Replace(node) => {
+            object.insert("op".into(), "replace".into());
+            object.insert("node".into(), tree_to_json(node));
+        }
+        NodeOp::Text(TextOp::Set(content)) => {
+            object.insert("op".into(), "set_text".into());
+            object.insert("text".into(), content.as_str().into());
+        }
+        NodeOp::Text(TextOp::Splice { at, remove, insert }) => {
+            object.insert("op".into(), "splice".into());
+            object.insert("at".into(), (*at).into());
+            object.insert("remove".into(), (*remove).into());
+            object.insert("insert".into(), insert.as_str().into());
+        }
+    }
+    object.into()
+}
+
+fn attr_op_to_json(op: &AttrOp) -> Value {
+    let token =
+        |kind: &str, name: &str, token: &str| json!({"op": kind, "name": name, "token": token});
+    let (kind, name, value) = match op {
+        AttrOp::InsertClass(name) => ("insert_class", name, None),
+        AttrOp::RemoveClass(name) => ("remove_class", name, None),
+        AttrOp::Insert(name, value) => ("insert", name, Some(value)),
+        AttrOp::Update(name, value) => ("update", name, Some(value)),
+        AttrOp::Remove(name) => ("remove", name, None),
+        AttrOp::InsertToken(name, value) => return token("insert_token", name, value),
+        AttrOp::RemoveToken(name, value) => return token("remove_token", name, value),
+    };
+    let mut object = Map::new();
+    object.insert("op".into(), kind.into());
+    object.insert("name".into(), name.as_str().into());
+    if let Some(value) = value {
+        object.insert("value".into(), value.as_str().into());
+    }
+    object.into()
+}
+
+/// JSON Schema of trees, as written by `tree_to_json`.
+///
+pub fn tree_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Tree",
+        "$ref": "#/definitions/node",
+        "definitions": tree_definitions(),
+    })
+}
+
+/// JSON Schema of patches, as written by `op_to_json`.
+///
+pub fn patch_schema() -> Value {
+    let mut definitions = tree_definitions();
+    let operations = json!({
+        "op": {
+            "oneOf": [
+                {
+                    "type": "object",
+                    "required": ["op", "count"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"enum": ["skip", "remove"]},
+                        "count": {"type": "integer", "minimum": 0},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"const": "update"},
+                        "attrs": {"type": "array", "items": {"$ref": "#/definitions/attr_op"}},
+                        "children": {"type": "array", "items": {"$ref": "#/definitions/op"}},
+                        "inserts": {"type": "array", "items": {"$ref": "#/definitions/insert"}},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op", "position"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"const": "move"},
+                        "position": {"type": "integer", "minimum": 0},
+                        "attrs": {"type": "array", "items": {"$ref": "#/definitions/attr_op"}},
+                        "children": {"type": "array", "items": {"$ref": "#/definitions/op"}},
+                        "inserts": {"type": "array", "items": {"$ref": "#/definitions/insert"}},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op", "node"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"const": "replace"},
+                        "node": {"$ref": "#/definitions/node"},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op", "text"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"const": "set_text"},
+                        "text": {"type": "string"},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op", "at", "remove", "insert"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"const": "splice"},
+                        "at": {"type": "integer", "minimum": 0},
+                        "remove": {"type": "integer", "minimum": 0},
+                        "insert": {"type": "string"},
+                    },
+                },
+            ],
+        },
+        "insert": {
+            "type": "object",
+            "required": ["position", "node"],
+            "additionalProperties": false,
+            "properties": {
+                "position": {"type": "integer", "minimum": 0},
+                "node": {"$ref": "#/definitions/node"},
+            },
+        },
+        "attr_op": {
+            "oneOf": [
+                {
+                    "type": "object",
+                    "required": ["op", "name"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"enum": ["insert_class", "remove_class", "remove"]},
+                        "name": {"type": "string"},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op", "name", "value"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"enum": ["insert", "update"]},
+                        "name": {"type": "string"},
+                        "value": {"type": "string"},
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["op", "name", "token"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "op": {"enum": ["insert_token", "remove_token"]},
+                        "name": {"type": "string"},
+                        "token": {"type": "string"},
+                    },
+                },
+            ],
+        },
+    });
+    if let (Value::Object(definitions), Value::Object(operations)) = (&mut definitions, operations)
+    {
+        definitions.extend(operations);
+    }
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Patch",
+        "$ref": "#/definitions/op",
+        "definitions": definitions,
+    })
+}
+
+fn tree_definitions() -> Value {
+    json!({
+        "node": {
+            "anyOf": [{"type": "string"}, {"$ref": "#/definitions/element"}],
+        },
+        "element": {
+            "type": "object",
+            "required": ["tag"],
+            "additionalProperties": false,
+            "properties": {
+                "tag": {"type": "string"},
+                "key": {"$ref": "#/definitions/key"},
+                "void": {"type": "boolean"},
+                "classes": {"type": "array", "items": {"type": "string"}},
+                "attributes": {
+                    "type": "object",
+                    "additionalProperties": {"type": "string"},
+                },
+                "children": {"type": "array", "items": {"$ref": "#/definitions/node"}},
+            },
+        },
+        "key": {
+            "anyOf": [
+                {"type": "string"},
+                {"type": "integer"},
+                {
+                    "type": "object",
+                    "required": ["scope", "key"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "scope": {"type": "string"},
+                        "key": {"$ref": "#/definitions/key"},
+                    },
+                },
+            ],
+        },
+    })
+}
+
+fn node_from_value(value: &Value) -> Result<VNode, JsonError> {
+    fn invalid(message: &str) -> JsonError {
+        JsonError::InvalidTree(message.to_string())
+    }
+
+    fn string(value: &Value, what: &str) -> Result<String, JsonError> {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| invalid(&format!("{} must be a string", what)))
+    }
+
+    let object = match value {
+        Value::String(content) => return Ok(text(content.clone()).done()),
+        Value::Object(object) => object,
+        _ => return Err(invalid("node must be a string or an object")),
+    };
+
+    let tag = string(
+        object
+            .get("tag")
+            .ok_or_else(|| invalid("element without tag"))?,
+        "tag",
+    )?;
+    let void = match object.get("void") {
+        Some(void) => void
+            .as_bool()
+            .ok_or_else(|| invalid("void must be a boolean"))?,
+        None => false,
+    };
+    let mut element = if void {
+        VElement::new_void(tag)
+    } else {
+        VElement::new(tag)
+    };
+
+    if let Some(key) = object.get("key") {
+        element = element.key(key_from_json(key)?);
+    }
+    if let Some(classes) = object.get("classes") {
+        let classes = classes
+            .as_array()
+            .ok_or_else(|| invalid("classes must be an array"))?;
+        for class in classes {
+            element = element.class(string(class, "class")?);
+        }
+    }
+    if let Some(attributes) = object.get("attributes") {
+        let attributes = attributes
+            .as_object()
+            .ok_or_else(|| invalid("attributes must be an object"))?;
+        for (name, value) in attributes {
+            element = element.attr(name.clone(), string(value, "attribute value")?);
+        }
+    }
+    if let Some(children) = object.get("children") {
+        let children = children
+            .as_array()
+            .ok_or_else(|| invalid("children must be an array"))?;
+        for child in children {
+            element = element.child_node(node_from_value(child)?);
+        }
+    }
+    Ok(element.done())
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use binary::encode_node;
+    use tags::*;
+
+    #[test]
+    fn tree_round_trip() {
+        #[rustfmt::skip]
+        let tree = ul().class("users").attr("id", "list")
+            .child(li().key("1").text("Ash"))
+            .child(li().key("2").child(br()))
+            .done();
+        let json = tree_to_json(&tree).to_string();
+
+        assert_eq!(
+            json,
+            r#"{"attributes":{"id":"list"},"children":[{"children":["Ash"],"key":"1","tag":"li"},{"children":[{"tag":"br","void":true}],"key":"2","tag":"li"}],"classes":["users"],"tag":"ul"}"#
+        );
+        assert_eq!(tree_from_json(&json), Ok(tree));
+    }
+
+    #[test]
+    fn numeric_keys() {
+        let tree = ul()
+            .child(li().key(1u64))
+            .child(li().key(-1i64))
+            .child(li().key("1"))
+            .done();
+        let json = tree_to_json(&tree).to_string();
+
+        assert_eq!(
+            json,
+            r#"{"children":[{"key":1,"tag":"li"},{"key":-1,"tag":"li"},{"key":"1","tag":"li"}],"tag":"ul"}"#
+        );
+        assert_eq!(tree_from_json(&json), Ok(tree));
+    }
+
+    #[test]
+    fn scoped_keys() {
+        let tree = ul().child(li().key_in("users", 1u64)).done();
+        let json = tree_to_json(&tree).to_string();
+
+        assert_eq!(
+            json,
+            r#"{"children":[{"key":{"key":1,"scope":"users"},"tag":"li"}],"tag":"ul"}"#
+        );
+        assert_eq!(tree_from_json(&json), Ok(tree));
+    }
+
+    #[test]
+    fn diff_formats() {
+        let old = ul().child(li().key("a")).done();
+        let new = ul().child(li().key("b")).child(li().key("a")).done();
+
+        assert_eq!(
+            diff_json(
+                &tree_to_json(&old).to_string(),
+                &tree_to_json(&new).to_string()
+            ),
+            Ok(
+                r#"{"inserts":[{"node":{"key":"b","tag":"li"},"position":0}],"op":"update"}"#
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            diff_binary(&encode_node(&old), &encode_node(&new)),
+            Ok(encode_op(&diff(&old, &new)))
+        );
+    }
+
+    #[test]
+    fn invalid_trees() {
+        assert!(matches!(tree_from_json("{"), Err(JsonError::Syntax(_))));
+        assert_eq!(
+            tree_from_json(r#"{"tag": "p", "children": [1]}"#),
+            Err(JsonError::InvalidTree(
+                "node must be a string or an object".to_string()
+            ))
+        );
+        assert_eq!(
+            tree_from_json("{}"),
+            Err(JsonError::InvalidTree("element without tag".to_string()))
+        );
+    }
+
+    /// Check a value against the parts of JSON Schema the schemas use.
+    ///
+    fn conforms(value: &Value, schema: &Value, root: &Value) -> bool {
+        let schema = schema.as_object().unwrap();
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            let name = reference.trim_start_matches("#/definitions/");
+            return conforms(value, &root["definitions"][name], root);
+        }
+        if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+            return schemas.iter().any(|schema| conforms(value, schema, root));
+        }
+        if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+            return schemas
+                .iter()
+                .filter(|schema| conforms(value, schema, root))
+                .count()
+                == 1;
+        }
+        let fits_type = match schema.get("type").and_then(Value::as_str) {
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("boolean") => value.is_boolean(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            _ => true,
+        };
+        let fits_value = match (schema.get("const"), schema.get("enum")) {
+            (Some(constant), _) => constant == value,
+            (_, Some(Value::Array(values))) => values.contains(value),
+            _ => true,
+        };
+        let fits_minimum = match (schema.get("minimum"), value.as_f64()) {
+            (Some(minimum), Some(number)) => number >= minimum.as_f64().unwrap(),
+            _ => true,
+        };
+        if !(fits_type && fits_value && fits_minimum) {
+            return false;
+        }
+        if let (Some(items), Value::Array(values)) = (schema.get("items"), value) {
+            return values.iter().all(|value| conforms(value, items, root));
+        }
+        if let Value::Object(object) = value {
+            let empty = Map::new();
+            let properties = schema
+                .get("properties")
+                .and_then(Value::as_object)
+                .unwrap_or(&empty);
+            let required = schema.get("required").and_then(Value::as_array);
+            if !required
+                .into_iter()
+                .flatten()
+                .all(|name| object.contains_key(name.as_str().unwrap()))
+            {
+                return false;
+            }
+            return object.iter().all(|(name, value)| {
+                match (properties.get(name), schema.get("additionalProperties")) {
+                    (Some(property), _) => conforms(value, property, root),
+                    (None, Some(Value::Bool(allowed))) => *allowed,
+                    (None, Some(additional)) => conforms(value, additional, root),
+                    (None, None) => true,
+                }
+            });
+        }
+        true
+    }
+
+    #[test]
+    fn serialization_conforms_to_schemas() {
+        let (tree, patch) = (tree_schema(), patch_schema());
+
+        #[rustfmt::skip]
+        let old = ul().class("users").attr("id", "list")
+            .child(li().key("a").text("Ash"))
+            .child(li().key(2u64).child(br()))
+            .child(li().key_in("users", -1i64).text("Cid"))
+            .child(li().key("d").attr("title", "Dan"))
+            .done();
+        #[rustfmt::skip]
+        let new = ul().class("people").attr("title", "list")
+            .child(li().key("d").attr("title", "Dana"))
+            .child(li().key_in("users", -1i64).text("Cid"))
+            .child(li().key("e").text("Eve"))
+            .child(li().key("a").text("Ash"))
+            .done();
+        for node in &[&old, &new] {
+            assert!(conforms(&tree_to_json(node), &tree, &tree));
+        }
+        for op in &[diff(&old, &new), diff(&new, &old), diff(&old, &p().done())] {
+            assert!(conforms(&op_to_json(op), &patch, &patch), "{:?}", op);
+        }
+
+        assert!(!conforms(
+            &json!({"tag": "p", "children": [1]}),
+            &tree,
+            &tree
+        ));
+        assert!(!conforms(&json!({"op": "skip"}), &patch, &patch));
+        assert!(!conforms(
+            &json!({"op": "update", "attrs": [{"op": "insert", "name": "id"}]}),
+            &patch,
+            &patch
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! # Keys
+//!
+//! Keys identify children of a list across frames. Numeric ids are common
+//! keys, so `Key` stores them as numbers instead of formatting each of them
+//! into a string on every frame.
+//!
+//! Keys of different kinds are different keys: `Key::U64(1)` doesn't match
+//! `Key::Str("1")`.
+//!
+//! Several lists which share a parent can scope their keys, so an item with
+//! id 1 doesn't collide with another kind of item with the same id.
+//!
+//! ```
+//! use troy::key::scoped_key;
+//! use troy::tags::*;
+//!
+//! let list = ul()
+//!     .child(li().key_in("users", 1u64))
+//!     .child(li().key_in("groups", 1u64))
+//!     .done_checked();
+//!
+//! assert!(list.is_ok());
+//! assert_eq!(li().key_in("users", 1u64), li().key(scoped_key("users", 1u64)));
+//! ```
+//!
+//! ```
+//! use troy::key::Key;
+//! use troy::tags::*;
+//!
+//! let item = li().key(42u64);
+//!
+//! assert_eq!(item.get_key(), Some(&Key::U64(42)));
+//! assert_ne!(item.get_key(), li().key("42").get_key());
+//! ```
+//!
+use std::fmt;
+use types::CowString;
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Str(CowString),
+    U64(u64),
+    I64(i64),
+    /// Key within a named scope.
+    Scoped(CowString, Box<Key>),
+}
+
+/// Key within a named scope, without formatting the two into one string.
+///
+pub fn scoped_key<S, K>(scope: S, key: K) -> Key
+where
+    S: Into<CowString>,
+    K: Into<Key>,
+{
+    Key::Scoped(scope.into(), Box::new(key.into()))
+}
+
+/// Keys are written like the values they hold, strings quoted.
+///
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Str(key) => write!(f, "{:?}", key),
+            Key::U64(key) => write!(f, "{}", key),
+            Key::I64(key) => write!(f, "{}", key),
+            Key::Scoped(scope, key) => write!(f, "{:?}:{:?}", scope, key),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Str(key) => write!(f, "{}", key),
+            Key::U64(key) => write!(f, "{}", key),
+            Key::I64(key) => write!(f, "{}", key),
+            Key::Scoped(scope, key) => write!(f, "{}:{}", scope, key),
+        }
+    }
+}
+
+impl From<&'static str> for Key {
+    fn from(key: &'static str) -> Self {
+        Key::Str(key.into())
+    }
+}
+
+impl From<String> for Key {
+    fn from(key: String) -> Self {
+        Key::Str(key.into())
+    }
+}
+
+impl From<CowString> for Key {
+    fn from(key: CowString) -> Self {
+        Key::Str(key)
+    }
+}
+
+impl From<u64> for Key {
+    fn from(key: u64) -> Self {
+        Key::U64(key)
+    }
+}
+
+impl From<u32> for Key {
+    fn from(key: u32) -> Self {
+        Key::U64(key.into())
+    }
+}
+
+impl From<usize> for Key {
+    fn from(key: usize) -> Self {
+        Key::U64(key as u64)
+    }
+}
+
+impl From<i64> for Key {
+    fn from(key: i64) -> Self {
+        Key::I64(key)
+    }
+}
+
+impl From<i32> for Key {
+    fn from(key: i32) -> Self {
+        Key::I64(key.into())
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::{diff, NodeOp};
+    use tags::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            format!("{:?} {}", Key::from("a"), Key::from("a")),
+            "\"a\" a"
+        );
+        assert_eq!(format!("{:?} {}", Key::from(-1), Key::from(2u64)), "-1 2");
+        let key = scoped_key("users", scoped_key("a", 1));
+        assert_eq!(format!("{:?} {}", key, key), "\"users\":\"a\":1 users:a:1");
+    }
+
+    #[test]
+    fn numeric_keys_are_matched() {
+        let old = ul().child(li().key(1u64)).child(li().key(2u64)).done();
+        let new = ul().child(li().key(2u64)).child(li().key(1u64)).done();
+
+        assert_eq!(
+            diff(&old, &new),
+            NodeOp::Update(
+                None,
+                Some(vec![NodeOp::Skip(1), NodeOp::Move(0, None, None, None)]),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn scoped_keys_do_not_collide() {
+        let old = ul().child(li().key_in("users", 1)).done();
+        let new = ul()
+            .child(li().key_in("groups", 1))
+            .child(li().key_in("users", 1))
+            .done();
+
+        assert_ne!(scoped_key("users", 1), scoped_key("groups", 1));
+        match diff(&old, &new) {
+            NodeOp::Update(None, None, Some(inserts)) => assert_eq!(inserts.len(), 1),
+            op => panic!("expected an insert, got {:?}", op),
+        }
+    }
+}
@@ -1,11 +1,91 @@
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "macros")]
+extern crate troy_macros;
 
+pub mod a11y;
+pub mod async_diff;
+pub mod attrs;
+pub mod backend;
+#[cfg(feature = "bench-support")]
+pub mod bench_support;
+pub mod binary;
+pub mod boundary;
+pub mod builder;
+#[cfg(feature = "display")]
+pub mod compare;
+pub mod component;
+#[cfg(feature = "strict")]
+pub mod content_model;
+pub mod context;
+pub mod convert;
+#[cfg(feature = "display")]
+pub mod debug;
+pub mod diagnostics;
 pub mod diff;
+pub mod diff_cache;
+pub mod dirty;
 pub mod element;
+pub mod email;
+pub mod error;
+pub mod escape;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "display")]
+pub mod fuzz;
+#[cfg(all(feature = "parser", feature = "display"))]
+pub mod golden;
+pub mod head;
+pub mod i18n;
+pub mod intern;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod key;
+pub mod list;
+pub mod live;
+#[cfg(feature = "tags")]
+#[macro_use]
+pub mod macros;
+pub mod matchers;
+pub mod memo;
 pub mod node;
-mod op_queue;
+#[cfg(feature = "parser")]
+pub mod parser;
+pub mod patch;
+pub mod patch_builder;
+pub mod path;
+pub mod persistent;
+#[cfg(feature = "sync")]
+pub mod pipeline;
+pub mod profile;
+pub mod render;
+pub mod reuse;
+#[cfg(any(feature = "display", feature = "bench-support"))]
+mod rng;
+pub mod stable;
+pub mod state;
+pub mod stream;
+pub mod style;
+pub mod sync;
+#[cfg(feature = "tags")]
 pub mod tags;
+#[cfg(feature = "parser")]
+pub mod template;
+pub mod terminal;
 pub mod text;
+#[cfg(feature = "tracing")]
+pub mod trace;
 mod types;
+pub mod url;
+pub mod vdom;
+pub mod view;
+pub mod whitespace;
+#[cfg(feature = "worker")]
+pub mod worker;
+
+pub use error::Error;
+#[cfg(feature = "macros")]
+pub use troy_macros::{rsx, Render};
@@ -0,0 +1,131 @@
+//! # Lists
+//!
+//! Helpers for rendering collections into children lists.
+//!
+use element::VElement;
+use key::Key;
+use node::VNode;
+use std::collections::HashSet;
+
+/// Render each item with `view` and key the resulting element with
+/// `key_fn`.
+///
+/// Keys must be unique among the items, which is checked in debug builds.
+///
+/// ```
+/// use troy::list::keyed_list;
+/// use troy::tags::*;
+///
+/// let users = vec![(1, "Ash"), (2, "Bob")];
+/// let list = ul()
+///     .children(keyed_list(
+///         &users,
+///         |(id, _)| id.to_string(),
+///         |(_, name)| li().text(*name),
+///     ))
+///     .done();
+/// ```
+///
+pub fn keyed_list<I, K, V, S>(items: I, mut key_fn: K, mut view: V) -> Vec<VNode>
+where
+    I: IntoIterator,
+    K: FnMut(&I::Item) -> S,
+    V: FnMut(I::Item) -> VElement,
+    S: Into<Key>,
+{
+    let nodes: Vec<VNode> = items
+        .into_iter()
+        .map(|item| {
+            let key = key_fn(&item);
+            view(item).key(key).done()
+        })
+        .collect();
+
+    if cfg!(debug_assertions) {
+        let mut keys = HashSet::new();
+        for node in &nodes {
+            let key = node.key().unwrap();
+            assert!(keys.insert(key), "duplicate key {:?} in keyed_list", key);
+        }
+    }
+
+    nodes
+}
+
+/// Data which knows its own key, such as a record with an id.
+///
+pub trait Keyed {
+    fn key(&self) -> Key;
+}
+
+impl<T> Keyed for &T
+where
+    T: Keyed + ?Sized,
+{
+    fn key(&self) -> Key {
+        (**self).key()
+    }
+}
+
+/// Render each item with `view` and key the resulting element with the key
+/// of the item, see `keyed_list`.
+///
+/// ```
+/// use troy::key::Key;
+/// use troy::list::{keyed, Keyed};
+/// use troy::tags::*;
+///
+/// struct User {
+///     id: u64,
+///     name: &'static str,
+/// }
+///
+/// impl Keyed for User {
+///     fn key(&self) -> Key {
+///         Key::U64(self.id)
+///     }
+/// }
+///
+/// let users = vec![User { id: 1, name: "Ash" }, User { id: 2, name: "Bob" }];
+/// let list = ul().children(keyed(&users, |user| li().text(user.name)));
+///
+/// assert_eq!(list, ul().child(li().key(1u64).text("Ash")).child(li().key(2u64).text("Bob")));
+/// ```
+///
+pub fn keyed<I, V>(items: I, view: V) -> Vec<VNode>
+where
+    I: IntoIterator,
+    I::Item: Keyed,
+    V: FnMut(I::Item) -> VElement,
+{
+    keyed_list(items, Keyed::key, view)
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn keyed_items() {
+        let list = ul().children(keyed_list(
+            vec!["a", "b"],
+            |item| *item,
+            |item| li().text(item),
+        ));
+
+        #[rustfmt::skip]
+        let expected = ul()
+            .child(li().key("a").text("a"))
+            .child(li().key("b").text("b"));
+
+        assert_eq!(list, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate key \"a\"")]
+    #[cfg(debug_assertions)]
+    fn duplicate_keys() {
+        keyed_list(vec!["a", "b", "a"], |item| *item, |item| li().text(item));
+    }
+}
@@ -0,0 +1,336 @@
+//! # Live sessions
+//!
+//! Server runtime for views rendered on the server and patched in the
+//! browser, LiveView style. `Live` owns a `VDom` per connected session.
+//! Events from the client update the session's view, which is rendered and
+//! diffed again, and the encoded patch is handed to the transport callback,
+//! which sends it to the client over a websocket or any other channel.
+//!
+//! ```
+//! use troy::live::{Live, LiveFrame, LiveView};
+//! use troy::node::VNode;
+//! use troy::tags::*;
+//!
+//! struct Counter(u32);
+//!
+//! impl LiveView for Counter {
+//!     type Event = u32;
+//!
+//!     fn handle(&mut self, add: u32) {
+//!         self.0 += add;
+//!     }
+//!
+//!     fn render(&self) -> VNode {
+//!         p().text(self.0.to_string()).done()
+//!     }
+//! }
+//!
+//! let mut sent: Vec<(String, LiveFrame)> = Vec::new();
+//! let mut live = Live::new(|session: &str, frame| sent.push((session.to_string(), frame)));
+//!
+//! live.connect("a", Counter(0));
+//! live.event("a", 2).unwrap();
+//! drop(live);
+//!
+//! assert_eq!(sent.len(), 2);
+//! assert_eq!(sent[1].1.stamp.generation, 2);
+//! ```
+//!
+//! Frames carry the `patch::Stamp` of the patch and the patch in the
+//! `binary` encoding. Every event sends a frame, even without changes, so
+//! the client's generation follows the session's. A client which finds a
+//! patch doesn't fit, or receives a stale one, asks for `resync`, which
+//! sends the whole current tree as `Replace` against no tree (base 0).
+//!
+//! A runtime made with `Live::compressed` encodes the frames of each
+//! session with a `binary::SessionEncoder`, so names are sent once per
+//! connection. Every frame with base 0 starts a new dictionary, and the
+//! client decodes it with a new `binary::SessionDecoder`.
+//!
+use binary::{encode_op, SessionEncoder};
+use diff::NodeOp;
+use error::Error;
+use node::VNode;
+use patch::Stamp;
+use std::collections::HashMap;
+use vdom::VDom;
+
+/// Application state of one session and its view.
+///
+pub trait LiveView {
+    /// Event sent by the client, decoded by the application.
+    type Event;
+
+    fn handle(&mut self, event: Self::Event);
+    fn render(&self) -> VNode;
+}
+
+/// Patch for the client of a session.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveFrame {
+    pub stamp: Stamp,
+    /// Patch encoded with `binary::encode_op`, or a session message for a
+    /// compressed runtime.
+    pub patch: Vec<u8>,
+}
+
+struct Session<V> {
+    view: V,
+    vdom: VDom,
+    encoder: Option<SessionEncoder>,
+}
+
+/// Sessions of a server, each with its view and tree.
+///
+pub struct Live<V, T> {
+    sessions: HashMap<String, Session<V>>,
+    transport: T,
+    compressed: bool,
+}
+
+impl<V, T> Live<V, T>
+where
+    V: LiveView,
+    T: FnMut(&str, LiveFrame),
+{
+    /// Create a runtime which sends frames with the transport callback.
+    ///
+    pub fn new(transport: T) -> Self {
+        Live {
+            sessions: HashMap::new(),
+            transport,
+            compressed: false,
+        }
+    }
+
+    /// Create a runtime which sends frames as session messages, with the
+    /// names of each session sent once.
+    ///
+    pub fn compressed(transport: T) -> Self {
+        Live {
+            compressed: true,
+            ..Live::new(transport)
+        }
+    }
+
+    /// Start a session with the view and send its first tree. A session
+    /// with the same id is replaced.
+    ///
+    pub fn connect<S: Into<String>>(&mut self, id: S, view: V) {
+        let id = id.into();
+        let mut session = Session {
+            view,
+            vdom: VDom::new(),
+            encoder: if self.compressed {
+                Some(SessionEncoder::new())
+            } else {
+                None
+            },
+        };
+        let frame = session.render();
+        (self.transport)(&id, frame);
+        self.sessions.insert(id, session);
+    }
+
+    /// End the session.
+    ///
+    pub fn disconnect(&mut self, id: &str) -> bool {
+        self.sessions.remove(id).is_some()
+    }
+
+    /// Apply the event to the session's view, render it again and send the
+    /// patch.
+    ///
+    pub fn event(&mut self, id: &str, event: V::Event) -> Result<(), Error> {
+        let session = self.session(id)?;
+        session.view.handle(event);
+        let frame = session.render();
+        (self.transport)(id, frame);
+        Ok(())
+    }
+
+    /// Send the whole current tree of the session, for a client whose tree
+    /// diverged from it.
+    ///
+    pub fn resync(&mut self, id: &str) -> Result<(), Error> {
+        let session = self.session(id)?;
+        let generation = session.vdom.stamp().generation;
+        let tree = session.vdom.tree().expect("connected session has a tree");
+        let op = NodeOp::Replace(tree);
+        let patch = match &mut session.encoder {
+            Some(encoder) => {
+                *encoder = SessionEncoder::new();
+                encoder.encode_op(&op)
+            }
+            None => encode_op(&op),
+        };
+        let frame = LiveFrame {
+            stamp: Stamp {
+                base: 0,
+                generation,
+            },
+            patch,
+        };
+        (self.transport)(id, frame);
+        Ok(())
+    }
+
+    /// View of the session.
+    ///
+    pub fn view(&self, id: &str) -> Option<&V> {
+        self.sessions.get(id).map(|session| &session.view)
+    }
+
+    /// Current tree of the session, as its client should have it.
+    ///
+    pub fn tree(&self, id: &str) -> Option<&VNode> {
+        self.sessions.get(id)?.vdom.tree()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    fn session(&mut self, id: &str) -> Result<&mut Session<V>, Error> {
+        self.sessions
+            .get_mut(id)
+            .ok_or_else(|| Error::UnknownSession(id.to_string()))
+    }
+}
+
+impl<V: LiveView> Session<V> {
+    fn render(&mut self) -> LiveFrame {
+        let view = &self.view;
+        let op = self.vdom.update(|| view.render());
+        let patch = match &mut self.encoder {
+            Some(encoder) => encoder.encode_op(&op),
+            None => encode_op(&op),
+        };
+        LiveFrame {
+            stamp: self.vdom.stamp(),
+            patch,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use backend::Mounted;
+    use binary::{decode_op, SessionDecoder};
+    use render::render_to_string;
+    use std::cell::RefCell;
+    use tags::*;
+    use terminal::TextBackend;
+    use text::text;
+
+    struct Todos(Vec<&'static str>);
+
+    impl LiveView for Todos {
+        type Event = &'static str;
+
+        fn handle(&mut self, todo: &'static str) {
+            self.0.insert(0, todo);
+        }
+
+        fn render(&self) -> VNode {
+            let mut list = ul();
+            for todo in &self.0 {
+                list = list.child(li().key(*todo).text(*todo));
+            }
+            list.done()
+        }
+    }
+
+    #[test]
+    fn sessions_send_patches_and_resync() {
+        let frames = RefCell::new(Vec::new());
+        let mut live = Live::new(|id: &str, frame| {
+            frames.borrow_mut().push((id.to_string(), frame));
+        });
+
+        live.connect("a", Todos(vec![]));
+        live.connect("b", Todos(vec!["x"]));
+        live.event("a", "write").unwrap();
+        live.event("a", "test").unwrap();
+        assert_eq!(
+            live.event("c", "lost"),
+            Err(Error::UnknownSession("c".to_string()))
+        );
+        assert_eq!(live.view("a").unwrap().0, ["test", "write"]);
+
+        // Client of session a starts with a placeholder of no generation.
+        let mut backend = TextBackend::default();
+        let mut mounted = Mounted::mount(&mut backend, &text("").done());
+        let received: Vec<LiveFrame> = frames
+            .borrow_mut()
+            .drain(..)
+            .filter(|(id, _)| id == "a")
+            .map(|(_, frame)| frame)
+            .collect();
+        assert_eq!(received.len(), 3);
+        for frame in &received {
+            let op = decode_op(&frame.patch).unwrap();
+            mounted
+                .patch_stamped(&mut backend, frame.stamp, &op.as_op())
+                .unwrap();
+        }
+        assert_eq!(mounted.generation(), 3);
+
+        // After a divergence, the client mounts a placeholder again.
+        live.resync("a").unwrap();
+        let (_, frame) = frames.borrow_mut().remove(0);
+        assert_eq!(
+            frame.stamp,
+            Stamp {
+                base: 0,
+                generation: 3
+            }
+        );
+        let mut mounted = Mounted::mount(&mut backend, &text("").done());
+        let op = decode_op(&frame.patch).unwrap();
+        mounted
+            .patch_stamped(&mut backend, frame.stamp, &op.as_op())
+            .unwrap();
+        assert_eq!(
+            render_to_string(live.tree("a").unwrap()),
+            "<ul><li>test</li><li>write</li></ul>"
+        );
+
+        assert!(live.disconnect("b"));
+        assert_eq!(live.len(), 1);
+        assert!(live.resync("b").is_err());
+    }
+
+    #[test]
+    fn compressed_sessions_send_names_once() {
+        let frames = RefCell::new(Vec::new());
+        let mut live = Live::compressed(|_: &str, frame: LiveFrame| {
+            frames.borrow_mut().push(frame);
+        });
+        live.connect("a", Todos(vec!["a"]));
+        live.event("a", "b").unwrap();
+        live.resync("a").unwrap();
+
+        let mut backend = TextBackend::default();
+        let mut mounted = Mounted::mount(&mut backend, &text("").done());
+        let mut decoder = SessionDecoder::new();
+        for frame in frames.borrow().iter() {
+            if frame.stamp.base == 0 {
+                mounted = Mounted::mount(&mut backend, &text("").done());
+                decoder = SessionDecoder::new();
+            }
+            let op = decoder.decode_op(&frame.patch).unwrap();
+            mounted
+                .patch_stamped(&mut backend, frame.stamp, &op.as_op())
+                .unwrap();
+        }
+        assert_eq!(mounted.generation(), 2);
+        assert!(decode_op(&frames.borrow()[1].patch).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! # html! macro
+//!
+//! HTML-like syntax for building trees, expanded into builder calls.
+//!
+//! ```
+//! # #[macro_use] extern crate troy;
+//! # fn main() {
+//! let users = vec![(1, "Ash"), (2, "Bob")];
+//! let selected = Some(2);
+//! let busy = false;
+//!
+//! let view = html! {
+//!     <div class="users" data-count={users.len().to_string()}>
+//!         <h1>"Users"</h1>
+//!         <ul>
+//!             { users.iter().map(|(id, name)| html! {
+//!                 <li key={id.to_string()} aria-selected?={
+//!                     (selected == Some(*id)).then_some("true")
+//!                 }>{ *name }</li>
+//!             }).collect::<Vec<_>>() }
+//!         </ul>
+//!         <input type="text" disabled?={busy.then_some("")} />
+//!     </div>
+//! };
+//! # }
+//! ```
+//!
+//! - Tags are the functions from the `tags` module.
+//! - Attribute values are string literals or `{expr}` with any value
+//!   convertible into a string.
+//! - `key` sets the element key.
+//! - `name?={expr}` takes an `Option` and adds the attribute only for `Some`.
+//! - Children are nested elements, string literals, and `{expr}` with any
+//!   `Child`: nodes, elements, strings, and `Option`s or `Vec`s of them.
+//! - Closing tags are not checked against opening tags.
+//!
+//! The macro is recursive, large templates may need a higher
+//! `#![recursion_limit]`.
+//!
+//! With the `macros` feature, `troy::rsx!` accepts the same syntax and
+//! validates tags, void elements and closing tags at compile time.
+//!
+use component::VComponent;
+use element::{AttrValue, VElement};
+use node::VNode;
+use text::VText;
+
+/// Value which can be added to an element by the `html!` macro as a child.
+///
+pub trait Child {
+    fn append_to(self, parent: VElement) -> VElement;
+}
+
+impl Child for VNode {
+    fn append_to(self, parent: VElement) -> VElement {
+        parent.child_node(self)
+    }
+}
+
+impl Child for VElement {
+    fn append_to(self, parent: VElement) -> VElement {
+        parent.child(self)
+    }
+}
+
+impl Child for VText {
+    fn append_to(self, parent: VElement) -> VElement {
+        parent.child_node(self.done())
+    }
+}
+
+impl Child for VComponent {
+    fn append_to(self, parent: VElement) -> VElement {
+        parent.child_node(self.done())
+    }
+}
+
+impl Child for &str {
+    fn append_to(self, parent: VElement) -> VElement {
+        parent.text(self.to_string())
+    }
+}
+
+impl Child for String {
+    fn append_to(self, parent: VElement) -> VElement {
+        parent.text(self)
+    }
+}
+
+impl<T> Child for Option<T>
+where
+    T: Child,
+{
+    fn append_to(self, parent: VElement) -> VElement {
+        match self {
+            Some(child) => child.append_to(parent),
+            None => parent,
+        }
+    }
+}
+
+impl<T> Child for Vec<T>
+where
+    T: Child,
+{
+    fn append_to(self, parent: VElement) -> VElement {
+        self.into_iter()
+            .fold(parent, |parent, child| child.append_to(parent))
+    }
+}
+
+#[doc(hidden)]
+pub fn append<C>(parent: VElement, child: C) -> VElement
+where
+    C: Child,
+{
+    child.append_to(parent)
+}
+
+#[doc(hidden)]
+pub fn attr<S>(element: VElement, name: &'static str, value: S) -> VElement
+where
+    S: Into<AttrValue>,
+{
+    element.attr(name, value)
+}
+
+#[doc(hidden)]
+pub fn root(root: VElement) -> VNode {
+    let mut children = root.into_children();
+    assert!(
+        children.len() == 1,
+        "html! expects a single root node, got {}",
+        children.len()
+    );
+    children.pop().unwrap()
+}
+
+#[macro_export]
+macro_rules! html {
+    // # Children
+
+    // Closing tag, add current element to its parent.
+    (@children [($parent:expr) $($stack:tt)*] ($element:expr) < / $close:ident > $($rest:tt)*) => {
+        $crate::html!(@children [$($stack)*] ($parent.child($element)) $($rest)*)
+    };
+    // Opening tag, current element becomes the parent.
+    (@children [$($stack:tt)*] ($element:expr) < $tag:ident $($rest:tt)*) => {
+        $crate::html!(@attributes [($element) $($stack)*] ($crate::tags::$tag()) $($rest)*)
+    };
+    (@children [$($stack:tt)*] ($element:expr) { $child:expr } $($rest:tt)*) => {
+        $crate::html!(@children [$($stack)*] ($crate::macros::append($element, $child)) $($rest)*)
+    };
+    (@children [$($stack:tt)*] ($element:expr) $text:literal $($rest:tt)*) => {
+        $crate::html!(@children [$($stack)*] ($element.text($text)) $($rest)*)
+    };
+    (@children [] ($root:expr)) => {
+        $crate::macros::root($root)
+    };
+
+    // # Attributes
+
+    (@attributes [$($stack:tt)*] ($element:expr) > $($rest:tt)*) => {
+        $crate::html!(@children [$($stack)*] ($element) $($rest)*)
+    };
+    // Self-closing tag, add element to its parent.
+    (@attributes [($parent:expr) $($stack:tt)*] ($element:expr) / > $($rest:tt)*) => {
+        $crate::html!(@children [$($stack)*] ($parent.child($element)) $($rest)*)
+    };
+    (@attributes [$($stack:tt)*] ($element:expr) key = $key:literal $($rest:tt)*) => {
+        $crate::html!(@attributes [$($stack)*] ($element.key($key)) $($rest)*)
+    };
+    (@attributes [$($stack:tt)*] ($element:expr) key = { $key:expr } $($rest:tt)*) => {
+        $crate::html!(@attributes [$($stack)*] ($element.key($key)) $($rest)*)
+    };
+    (@attributes [$($stack:tt)*] ($element:expr)
+        $name:ident $(- $part:ident)* = $value:literal $($rest:tt)*
+    ) => {
+        $crate::html!(@attributes [$($stack)*] ($crate::macros::attr(
+            $element,
+            concat!(stringify!($name) $(, "-", stringify!($part))*),
+            $value
+        )) $($rest)*)
+    };
+    (@attributes [$($stack:tt)*] ($element:expr)
+        $name:ident $(- $part:ident)* = { $value:expr } $($rest:tt)*
+    ) => {
+        $crate::html!(@attributes [$($stack)*] ($crate::macros::attr(
+            $element,
+            concat!(stringify!($name) $(, "-", stringify!($part))*),
+            $value
+        )) $($rest)*)
+    };
+    (@attributes [$($stack:tt)*] ($element:expr)
+        $name:ident $(- $part:ident)* ?= { $value:expr } $($rest:tt)*
+    ) => {
+        $crate::html!(@attributes [$($stack)*] ({
+            let element = $element;
+            match $value {
+                Some(value) => $crate::macros::attr(
+                    element,
+                    concat!(stringify!($name) $(, "-", stringify!($part))*),
+                    value
+                ),
+                None => element,
+            }
+        }) $($rest)*)
+    };
+
+    // # Entry
+
+    ($($tokens:tt)*) => {
+        $crate::html!(@children [] ($crate::element::VElement::new("")) $($tokens)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use node::VNode;
+    use tags::*;
+    use text::text;
+
+    #[test]
+    fn elements_and_text() {
+        let name = "Ash";
+
+        #[rustfmt::skip]
+        let expected = div().class_list("user online").attr("data-user-id", "1")
+            .child(h1().text("User"))
+            .child(p().text("Ash").text("!"))
+            .child(br())
+            .done();
+
+        let actual = html! {
+            <div class="user online" data-user-id="1">
+                <h1>"User"</h1>
+                <p>{name}"!"</p>
+                <br/>
+            </div>
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expressions() {
+        let items = ["a", "b"];
+        let selected: Option<&str> = None;
+
+        #[rustfmt::skip]
+        let expected = ul().attr("data-count", "2")
+            .child(li().key("a").text("a"))
+            .child(li().key("b").text("b"))
+            .child_node(text("end").done())
+            .done();
+
+        let actual = html! {
+            <ul data-count={items.len().to_string()} aria-selected?={selected}>
+                { items.iter().map(|item| html! { <li key={*item}>{*item}</li> }).collect::<Vec<VNode>>() }
+                { None::<VNode> }
+                { text("end") }
+            </ul>
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn keyword_attributes() {
+        let actual = html! { <label for="name"><input type="text" /></label> };
+
+        assert_eq!(
+            actual,
+            label()
+                .attr("for", "name")
+                .child(input().attr("type", "text"))
+                .done()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "single root node")]
+    fn several_roots() {
+        html! { <p></p><p></p> };
+    }
+}
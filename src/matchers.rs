@@ -0,0 +1,346 @@
+//! # Matchers
+//!
+//! Declarative predicates for testing views.
+//!
+//! ```
+//! use troy::matchers::*;
+//! use troy::tags::*;
+//!
+//! let node = nav()
+//!     .child(a().class("active").attr("href", "/home").text("Hello, home"))
+//!     .done();
+//!
+//! assert!(node.matches(contains("a.active[href=\"/home\"]")));
+//! assert!(node.matches(has_text_containing("Hello")));
+//! assert!(!node.matches(has_class("active")));
+//! ```
+//!
+use element::VElement;
+use key::Key;
+use node::VNode;
+
+/// Predicate over a VNode.
+///
+/// Implemented for all `Fn(&VNode) -> bool` closures, so custom matchers
+/// don't need their own type.
+///
+pub trait Matcher {
+    fn matches(&self, node: &VNode) -> bool;
+}
+
+impl<F> Matcher for F
+where
+    F: Fn(&VNode) -> bool,
+{
+    fn matches(&self, node: &VNode) -> bool {
+        self(node)
+    }
+}
+
+impl VNode {
+    /// Check node against a matcher.
+    ///
+    pub fn matches<M: Matcher>(&self, matcher: M) -> bool {
+        matcher.matches(self)
+    }
+}
+
+fn element_matches<F>(node: &VNode, predicate: F) -> bool
+where
+    F: Fn(&VElement) -> bool,
+{
+    match node.resolve() {
+        VNode::Element(element) => predicate(element),
+        _ => false,
+    }
+}
+
+/// Element has specified tag.
+///
+pub fn has_tag(tag: &str) -> impl Matcher + '_ {
+    move |node: &VNode| element_matches(node, |element| element.get_tag() == tag)
+}
+
+/// Element has specified key.
+///
+pub fn has_key<K>(key: K) -> impl Matcher
+where
+    K: Into<Key>,
+{
+    let key = key.into();
+    move |node: &VNode| element_matches(node, |element| element.get_key() == Some(&key))
+}
+
+/// Element has specified class.
+///
+pub fn has_class(class: &str) -> impl Matcher + '_ {
+    move |node: &VNode| element_matches(node, |element| element.get_classes().contains(class))
+}
+
+/// Element has attribute with specified value.
+///
+pub fn has_attr<'a>(name: &'a str, value: &'a str) -> impl Matcher + 'a {
+    move |node: &VNode| {
+        element_matches(node, |element| {
+            element.get_attributes().get(name).map(|v| v.as_ref()) == Some(value)
+        })
+    }
+}
+
+/// Text content of the node and its descendants contains `text`.
+///
+pub fn has_text_containing(text: &str) -> impl Matcher + '_ {
+    move |node: &VNode| node.text_content().contains(text)
+}
+
+/// Node matches a CSS selector.
+///
+/// See `contains` for supported selector syntax. Panics if the selector
+/// can't be parsed.
+///
+pub fn matches_selector(selector: &str) -> impl Matcher {
+    let selector = Selector::parse(selector);
+    move |node: &VNode| selector.matches(node, &[])
+}
+
+/// Some descendant of the node matches a CSS selector.
+///
+/// Supported syntax is a subset of CSS: type (`p`), class (`.active`),
+/// id (`#main`) and attribute (`[href]`, `[href="/home"]`) selectors, any
+/// compound of them (`a.active[href]`), and the descendant combinator
+/// (`nav a.active`). Panics if the selector can't be parsed.
+///
+pub fn contains(selector: &str) -> impl Matcher {
+    let selector = Selector::parse(selector);
+    move |node: &VNode| {
+        fn walk<'a>(
+            selector: &Selector,
+            node: &'a VNode,
+            ancestors: &mut Vec<&'a VElement>,
+        ) -> bool {
+            if let VNode::Element(element) = node.resolve() {
+                ancestors.push(element);
+                for child in element.get_children() {
+                    if selector.matches(child, ancestors) || walk(selector, child, ancestors) {
+                        return true;
+                    }
+                }
+                ancestors.pop();
+            }
+            false
+        }
+
+        walk(&selector, node, &mut Vec::new())
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Compound {
+    tag: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl Compound {
+    fn matches(&self, element: &VElement) -> bool {
+        self.tag.as_ref().is_none_or(|tag| tag == element.get_tag())
+            && self
+                .classes
+                .iter()
+                .all(|class| element.get_classes().contains(class.as_str()))
+            && self.attributes.iter().all(|(name, value)| {
+                match (element.get_attributes().get(name.as_str()), value) {
+                    (Some(actual), Some(expected)) => actual == expected,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                }
+            })
+    }
+}
+
+/// Parsed selector: compounds separated by descendant combinators.
+///
+#[derive(Debug, PartialEq)]
+struct Selector {
+    compounds: Vec<Compound>,
+}
+
+impl Selector {
+    fn parse(selector: &str) -> Self {
+        let compounds: Vec<Compound> = split_compounds(selector)
+            .into_iter()
+            .map(|compound| {
+                Self::parse_compound(compound)
+                    .unwrap_or_else(|| panic!("invalid selector `{}`", selector))
+            })
+            .collect();
+
+        if compounds.is_empty() {
+            panic!("empty selector");
+        }
+
+        Selector { compounds }
+    }
+
+    fn parse_compound(input: &str) -> Option<Compound> {
+        let mut compound = Compound::default();
+        let mut rest = input;
+
+        let is_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+        let take_name = |rest: &mut &str| {
+            let len = rest.find(|c: char| !is_name(c)).unwrap_or(rest.len());
+            let name = &rest[..len];
+            *rest = &rest[len..];
+            name.to_string()
+        };
+
+        if rest.starts_with(is_name) {
+            compound.tag = Some(take_name(&mut rest));
+        } else if rest.starts_with('*') {
+            rest = &rest[1..];
+        }
+
+        while !rest.is_empty() {
+            if rest.starts_with('.') {
+                rest = &rest[1..];
+                compound.classes.push(take_name(&mut rest));
+            } else if rest.starts_with('#') {
+                rest = &rest[1..];
+                compound
+                    .attributes
+                    .push(("id".to_string(), Some(take_name(&mut rest))));
+            } else if rest.starts_with('[') {
+                let end = rest.find(']')?;
+                let inner = &rest[1..end];
+                rest = &rest[end + 1..];
+                match inner.find('=') {
+                    Some(eq) => {
+                        let value = inner[eq + 1..]
+                            .trim()
+                            .trim_matches(|c| c == '"' || c == '\'');
+                        compound
+                            .attributes
+                            .push((inner[..eq].trim().to_string(), Some(value.to_string())));
+                    }
+                    None => compound.attributes.push((inner.trim().to_string(), None)),
+                }
+            } else {
+                return None;
+            }
+        }
+
+        Some(compound)
+    }
+
+    /// Match node with specified ancestors, closest ancestor last.
+    ///
+    fn matches(&self, node: &VNode, ancestors: &[&VElement]) -> bool {
+        let element = match node.resolve() {
+            VNode::Element(element) => element,
+            _ => return false,
+        };
+
+        let (last, rest) = self.compounds.split_last().unwrap();
+        if !last.matches(element) {
+            return false;
+        }
+
+        // Match remaining compounds against ancestors, right to left.
+        let mut ancestors = ancestors.iter().rev();
+        rest.iter()
+            .rev()
+            .all(|compound| ancestors.any(|ancestor| compound.matches(ancestor)))
+    }
+}
+
+/// Split selector on whitespace outside of attribute brackets.
+///
+fn split_compounds(selector: &str) -> Vec<&str> {
+    let mut compounds = Vec::new();
+    let mut depth = 0;
+    let mut start = None;
+
+    for (index, c) in selector.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    compounds.push(&selector[s..index]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if start.is_none() {
+            start = Some(index);
+        }
+    }
+
+    if let Some(s) = start {
+        compounds.push(&selector[s..]);
+    }
+
+    compounds
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+    use text::text;
+
+    #[test]
+    fn attribute_matchers() {
+        let node = a()
+            .key("k")
+            .class_list("nav active")
+            .attr("href", "/home")
+            .done();
+
+        assert!(node.matches(has_tag("a")));
+        assert!(node.matches(has_key("k")));
+        assert!(node.matches(has_class("active")));
+        assert!(node.matches(has_attr("href", "/home")));
+        assert!(!node.matches(has_attr("href", "/about")));
+        assert!(!text("a").done().matches(has_tag("a")));
+    }
+
+    #[test]
+    fn text_matchers() {
+        let node = p().text("Hello, ").child(b().text("world")).done();
+
+        assert!(node.matches(has_text_containing("Hello, world")));
+        assert!(!node.matches(has_text_containing("Goodbye")));
+    }
+
+    #[test]
+    fn selector_matchers() {
+        #[rustfmt::skip]
+        let node = div().attr("id", "main")
+            .child(nav()
+                .child(a().class("active").attr("href", "/home"))
+                .child(a().attr("href", "/about")))
+            .child(p().class("intro"))
+            .done();
+
+        assert!(node.matches(matches_selector("div#main")));
+        assert!(node.matches(contains("a")));
+        assert!(node.matches(contains("nav a.active[href=\"/home\"]")));
+        assert!(node.matches(contains("#main [href='/about']")));
+        assert!(node.matches(contains("div p.intro")));
+        assert!(!node.matches(contains("nav p")));
+        assert!(!node.matches(contains("div#main")));
+        assert!(!node.matches(contains("a.active[href=\"/about\"]")));
+    }
+
+    #[test]
+    fn custom_matcher() {
+        let node = ul().child(li()).child(li()).done();
+
+        assert!(node.matches(|node: &VNode| match node {
+            VNode::Element(element) => element.get_children().len() == 2,
+            _ => false,
+        }));
+    }
+}
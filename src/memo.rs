@@ -0,0 +1,167 @@
+//! # Memoized views
+//!
+//! `MemoCache` keeps nodes rendered in the previous frame, keyed by the call
+//! site of `memoize` and the hash of the props. When a view is memoized with
+//! the same props again, its previous node is returned as `VNode::Shared`
+//! without calling the view function, and the differ produces `Skip(1)` for
+//! it by pointer equality without descending into it.
+//!
+//! The cache belongs to whoever keeps the previous tree, one cache per tree.
+//! Call `next_frame` before building each new tree: nodes which weren't
+//! memoized during a frame are dropped.
+//!
+//! ```
+//! use troy::diff::{diff, NodeOp};
+//! use troy::memo::MemoCache;
+//! use troy::node::VNode;
+//! use troy::tags::*;
+//!
+//! fn view(cache: &mut MemoCache, items: &[&str]) -> VNode {
+//!     let list = cache.memoize(items, || {
+//!         let mut list = ul();
+//!         for item in items {
+//!             list = list.child(li().text(item.to_string()));
+//!         }
+//!         list.done()
+//!     });
+//!     div().child_node(list).done()
+//! }
+//!
+//! let mut cache = MemoCache::new();
+//! let old = view(&mut cache, &["a", "b"]);
+//! cache.next_frame();
+//! let new = view(&mut cache, &["a", "b"]);
+//!
+//! assert_eq!(diff(&old, &new), NodeOp::Skip(1));
+//! ```
+//!
+//! Props are compared by a 64 bit hash only, so a hash collision returns a
+//! stale node.
+//!
+use node::VNode;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::panic::Location;
+use sync::Shared;
+
+type MemoKey = (&'static Location<'static>, u64);
+
+#[derive(Debug, Default)]
+pub struct MemoCache {
+    current: HashMap<MemoKey, Shared<VNode>>,
+    previous: HashMap<MemoKey, Shared<VNode>>,
+}
+
+impl MemoCache {
+    pub fn new() -> Self {
+        MemoCache::default()
+    }
+
+    /// Return the node memoized at this call site with the same props during
+    /// this or the previous frame, or call `view` and memoize its result.
+    ///
+    #[track_caller]
+    pub fn memoize<P, F>(&mut self, props: &P, view: F) -> VNode
+    where
+        P: Hash + ?Sized,
+        F: FnOnce() -> VNode,
+    {
+        let mut hasher = DefaultHasher::new();
+        props.hash(&mut hasher);
+        let key = (Location::caller(), hasher.finish());
+
+        let node = match self.current.get(&key) {
+            Some(node) => node.clone(),
+            None => {
+                let node = self
+                    .previous
+                    .remove(&key)
+                    .unwrap_or_else(|| Shared::new(view()));
+                self.current.insert(key, node.clone());
+                node
+            }
+        };
+        VNode::Shared(node)
+    }
+
+    /// Start a new frame. Nodes not memoized since the previous call are
+    /// dropped.
+    ///
+    pub fn next_frame(&mut self) {
+        self.previous = mem::take(&mut self.current);
+    }
+
+    /// Number of nodes memoized during the current frame.
+    ///
+    pub fn len(&self) -> usize {
+        self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use diff::NodeOp::*;
+    use std::cell::Cell;
+    use tags::*;
+
+    fn counter(cache: &mut MemoCache, count: u32, renders: &Cell<u32>) -> VNode {
+        cache.memoize(&count, || {
+            renders.set(renders.get() + 1);
+            p().text(count.to_string()).done()
+        })
+    }
+
+    #[test]
+    fn same_props_reuse_node() {
+        let renders = Cell::new(0);
+        let mut cache = MemoCache::new();
+
+        let old = counter(&mut cache, 1, &renders);
+        cache.next_frame();
+        let new = counter(&mut cache, 1, &renders);
+
+        assert_eq!(renders.get(), 1);
+        assert_eq!(diff(&old, &new), Skip(1));
+        match (&old, &new) {
+            (VNode::Shared(old), VNode::Shared(new)) => assert!(Shared::ptr_eq(old, new)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn changed_props_render_again() {
+        let renders = Cell::new(0);
+        let mut cache = MemoCache::new();
+
+        let old = counter(&mut cache, 1, &renders);
+        cache.next_frame();
+        let new = counter(&mut cache, 2, &renders);
+
+        assert_eq!(renders.get(), 2);
+        assert_eq!(new, p().text("2").done());
+        assert_ne!(diff(&old, &new), Skip(1));
+    }
+
+    #[test]
+    fn unused_nodes_are_dropped() {
+        let renders = Cell::new(0);
+        let mut cache = MemoCache::new();
+
+        counter(&mut cache, 1, &renders);
+        cache.next_frame();
+        assert!(cache.is_empty());
+        cache.next_frame();
+        counter(&mut cache, 1, &renders);
+
+        assert_eq!(renders.get(), 2);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -1,110 +1,442 @@
+use component::VComponent;
 use element::VElement;
+use error::Error;
+use escape::{escape_attr, escape_text};
+use key::Key;
+use std::borrow::Cow;
+#[cfg(feature = "display")]
 use std::fmt;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use sync::Shared;
 use text::VText;
 use types::CowString;
 
-#[derive(Debug, PartialEq)]
+/// Node of a tree.
+///
+/// Clones of elements and text are deep. A clone of a shared node points to
+/// the same node, so it is diffed as unchanged against the original without
+/// being compared. A clone of a component shares the component and the node
+/// it rendered to.
+///
+#[derive(Debug, Clone)]
 pub enum VNode {
     Element(VElement),
     Text(VText),
+    Component(VComponent),
+    /// Node shared between frames, see `memo`. `Rc` or `Arc`, see `sync`.
+    Shared(Shared<VNode>),
 }
 
 impl VNode {
-    pub fn key(&self) -> Option<&CowString> {
+    pub fn key(&self) -> Option<&Key> {
         match self {
             VNode::Element(element) => element.get_key(),
+            VNode::Component(component) => component.get_key(),
+            VNode::Shared(node) => node.key(),
             _ => None,
         }
     }
-}
 
-impl fmt::Display for VNode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fn fmt_indent(indent_level: usize, node: &VNode, f: &mut fmt::Formatter) -> fmt::Result {
-            let indent_string = "    ";
+    /// Element of an element node, for changing it in place. Components
+    /// and shared nodes are not owned by the tree and can't be changed.
+    ///
+    pub fn as_element_mut(&mut self) -> Option<&mut VElement> {
+        match self {
+            VNode::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Node the tree consists of at this position: for components the node
+    /// they render to (rendering them if needed), for shared nodes the node
+    /// they point to, otherwise the node itself.
+    ///
+    pub fn resolve(&self) -> &VNode {
+        let mut node = self;
+        loop {
+            node = match node {
+                VNode::Component(component) => component.rendered(),
+                VNode::Shared(shared) => shared,
+                _ => return node,
+            }
+        }
+    }
+
+    /// Approximate number of bytes the tree owns on the heap: owned
+    /// strings, attribute and class tables, and children vectors with the
+    /// nodes in them. Static strings cost nothing. Components and shared
+    /// nodes are counted as the nodes they stand for wherever they appear,
+    /// so nodes shared between trees are counted in each of them.
+    ///
+    pub fn approx_heap_size(&self) -> usize {
+        fn string(string: &CowString) -> usize {
+            match string {
+                Cow::Owned(string) => string.capacity(),
+                Cow::Borrowed(_) => 0,
+            }
+        }
+
+        fn key_size(key: &Key) -> usize {
+            match key {
+                Key::Str(key) => string(key),
+                Key::U64(_) | Key::I64(_) => 0,
+                Key::Scoped(scope, inner) => {
+                    string(scope) + mem::size_of::<Key>() + key_size(inner)
+                }
+            }
+        }
+
+        /// Hash tables store an entry and a control byte per slot.
+        ///
+        fn table<T>(capacity: usize) -> usize {
+            capacity * (mem::size_of::<T>() + 1)
+        }
+
+        match self.resolve() {
+            VNode::Element(element) => {
+                let attributes = element.get_attributes();
+                let classes = element.get_classes();
+                let children = element.get_children();
+
+                element.get_key().map_or(0, key_size)
+                    + table::<(CowString, CowString)>(attributes.capacity())
+                    + attributes
+                        .iter()
+                        .map(|(name, value)| string(name) + string(value))
+                        .sum::<usize>()
+                    + table::<CowString>(classes.capacity())
+                    + classes.iter().map(string).sum::<usize>()
+                    + children.capacity() * mem::size_of::<VNode>()
+                    + children.iter().map(VNode::approx_heap_size).sum::<usize>()
+            }
+            VNode::Text(text) => text.heap_size(),
+            _ => unreachable!("resolved node is an element or text"),
+        }
+    }
+
+    /// Concatenated content of all text nodes in the tree.
+    ///
+    pub fn text_content(&self) -> String {
+        fn collect(node: &VNode, out: &mut String) {
             match node {
                 VNode::Element(element) => {
-                    // Begin opening tag
-                    write!(
-                        f,
-                        "{}<{}",
-                        indent_string.repeat(indent_level),
-                        element.get_tag()
-                    )?;
-
-                    // Classes
-                    let mut classes: Vec<&CowString> = element.get_classes().iter().collect();
-                    classes.sort_by(|a, b| a.cmp(b));
-
-                    if classes.len() > 0 {
-                        write!(f, " class=\"")?;
-                        for (index, class) in classes.iter().enumerate() {
-                            if index > 0 {
-                                write!(f, " ")?;
-                            }
-                            write!(f, "{}", class)?;
+                    for child in element.get_children() {
+                        collect(child, out);
+                    }
+                }
+                VNode::Text(text) => out.push_str(text.get_content()),
+                VNode::Component(_) | VNode::Shared(_) => collect(node.resolve(), out),
+            }
+        }
+
+        let mut out = String::new();
+        collect(self, &mut out);
+        out
+    }
+
+    /// Check that no node is nested deeper than `max_depth`, counting this
+    /// node as 1, and that the tree has at most `max_nodes` elements and
+    /// texts. Zero doesn't limit. The tree is walked without recursion, so
+    /// trees from untrusted sources can be checked before functions which
+    /// recurse, such as diffing and rendering, overflow the stack.
+    ///
+    pub fn check_limits(&self, max_depth: usize, max_nodes: usize) -> Result<(), Error> {
+        if max_depth == 0 && max_nodes == 0 {
+            return Ok(());
+        }
+        let mut stack = vec![(self, 1)];
+        let mut nodes = 0;
+        while let Some((node, depth)) = stack.pop() {
+            nodes += 1;
+            if max_depth > 0 && depth > max_depth {
+                return Err(Error::TooDeep(max_depth));
+            }
+            if max_nodes > 0 && nodes > max_nodes {
+                return Err(Error::TooManyNodes(max_nodes));
+            }
+            if let VNode::Element(element) = node.resolve() {
+                stack.extend(
+                    element
+                        .get_children()
+                        .iter()
+                        .map(|child| (child, depth + 1)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Render canonical representation of the tree for snapshot tests.
+    ///
+    /// Unlike `Display`, the output is meant to be stable rather than pretty:
+    /// keys are included, classes and attributes are sorted, every attribute
+    /// has a quoted value, text and attribute values are escaped, and runs of
+    /// whitespace in text are collapsed into a single space.
+    ///
+    pub fn to_snapshot_string(&self) -> String {
+        fn write_node(out: &mut String, indent_level: usize, node: &VNode) {
+            let indent = "  ".repeat(indent_level);
+            match node {
+                VNode::Element(element) => {
+                    write!(out, "{}<{}", indent, element.get_tag()).unwrap();
+
+                    match element.get_key() {
+                        Some(Key::Str(key)) => {
+                            write!(out, " key=\"{}\"", escape_attr(key)).unwrap()
                         }
-                        write!(f, "\"")?;
+                        Some(key) => write!(out, " key={:?}", key).unwrap(),
+                        None => {}
+                    }
+
+                    let mut classes: Vec<&CowString> = element.get_classes().iter().collect();
+                    classes.sort();
+                    if !classes.is_empty() {
+                        let classes: Vec<&str> = classes.iter().map(|c| c.as_ref()).collect();
+                        write!(out, " class=\"{}\"", escape_attr(&classes.join(" "))).unwrap();
                     }
 
-                    // Attributes
                     let mut attr_pairs: Vec<(&CowString, &CowString)> =
                         element.get_attributes().iter().collect();
-                    attr_pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    attr_pairs.sort_by_key(|(name, _)| *name);
                     for (name, value) in attr_pairs {
-                        if value.len() > 0 {
-                            write!(f, " {}=\"{}\"", name, value)?;
-                        } else {
-                            write!(f, " {}", name)?;
-                        }
+                        write!(out, " {}=\"{}\"", name, escape_attr(value)).unwrap();
                     }
 
-                    // Void elements do not have cloning tag or children.
                     if element.is_void() {
-                        write!(f, ">\n")
+                        writeln!(out, "/>").unwrap();
+                    } else if element.get_children().is_empty() {
+                        writeln!(out, "></{}>", element.get_tag()).unwrap();
                     } else {
-                        // End opening tag
-                        write!(f, ">")?;
-
-                        // Children
-
-                        if element.get_children().len() > 0 {
-                            write!(f, "\n")?;
-                        }
-
+                        writeln!(out, ">").unwrap();
                         for child in element.get_children() {
-                            fmt_indent(indent_level + 1, child, f)?;
+                            write_node(out, indent_level + 1, child);
                         }
-
-                        // Closing tag
-                        write!(
-                            f,
-                            "{}</{}>\n",
-                            indent_string.repeat(indent_level),
-                            element.get_tag()
-                        )
+                        writeln!(out, "{}</{}>", indent, element.get_tag()).unwrap();
                     }
                 }
-                VNode::Text(text) => write!(
-                    f,
-                    "{}{}\n",
-                    indent_string.repeat(indent_level),
-                    text.get_content()
-                ),
+                VNode::Text(text) => {
+                    let words: Vec<&str> = text.get_content().split_whitespace().collect();
+                    writeln!(out, "{}\"{}\"", indent, escape_text(&words.join(" "))).unwrap();
+                }
+                VNode::Component(_) | VNode::Shared(_) => {
+                    write_node(out, indent_level, node.resolve());
+                }
+            }
+        }
+
+        let mut out = String::new();
+        write_node(&mut out, 0, self);
+        out
+    }
+}
+
+/// Nodes are compared by what they stand for: components by the node they
+/// render to and shared nodes by the node they point to.
+///
+impl PartialEq for VNode {
+    fn eq(&self, other: &VNode) -> bool {
+        match (self.resolve(), other.resolve()) {
+            (VNode::Element(a), VNode::Element(b)) => a == b,
+            (VNode::Text(a), VNode::Text(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for VNode {}
+
+/// Consistent with equality: nodes are hashed as the node they stand for.
+///
+impl Hash for VNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.resolve() {
+            VNode::Element(element) => {
+                0u8.hash(state);
+                element.hash(state);
+            }
+            VNode::Text(text) => {
+                1u8.hash(state);
+                text.hash(state);
+            }
+            _ => unreachable!("resolved node is an element or text"),
+        }
+    }
+}
+
+#[cfg(feature = "display")]
+impl fmt::Display for VNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_node(0, self, f)
+    }
+}
+
+#[cfg(feature = "display")]
+impl VNode {
+    /// Abridged `Display` for logs: children deeper than `max_depth` and
+    /// children after the first `max_children` of each element are counted
+    /// instead of printed.
+    ///
+    pub fn display_truncated(&self, max_depth: usize, max_children: usize) -> Truncated<'_> {
+        Truncated {
+            node: self,
+            limits: Limits {
+                max_depth,
+                max_children,
+            },
+        }
+    }
+}
+
+/// Abridged `Display` of a node, see `VNode::display_truncated`.
+///
+#[cfg(feature = "display")]
+pub struct Truncated<'a> {
+    node: &'a VNode,
+    limits: Limits,
+}
+
+#[cfg(feature = "display")]
+impl<'a> fmt::Display for Truncated<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_node_within(0, self.node, &self.limits, f)
+    }
+}
+
+#[cfg(feature = "display")]
+struct Limits {
+    max_depth: usize,
+    max_children: usize,
+}
+
+#[cfg(feature = "display")]
+const UNLIMITED: Limits = Limits {
+    max_depth: usize::MAX,
+    max_children: usize::MAX,
+};
+
+#[cfg(feature = "display")]
+const INDENT: &str = "    ";
+
+/// Pretty-print a node, shared by the `Display` implementations of nodes,
+/// elements and texts.
+///
+#[cfg(feature = "display")]
+pub(crate) fn fmt_node(indent_level: usize, node: &VNode, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt_node_within(indent_level, node, &UNLIMITED, f)
+}
+
+#[cfg(feature = "display")]
+pub(crate) fn fmt_element(
+    indent_level: usize,
+    element: &VElement,
+    f: &mut fmt::Formatter,
+) -> fmt::Result {
+    fmt_element_within(indent_level, element, &UNLIMITED, f)
+}
+
+#[cfg(feature = "display")]
+fn fmt_node_within(
+    indent_level: usize,
+    node: &VNode,
+    limits: &Limits,
+    f: &mut fmt::Formatter,
+) -> fmt::Result {
+    match node {
+        VNode::Element(element) => fmt_element_within(indent_level, element, limits, f),
+        VNode::Text(text) => fmt_text(indent_level, text, f),
+        VNode::Component(_) | VNode::Shared(_) => {
+            fmt_node_within(indent_level, node.resolve(), limits, f)
+        }
+    }
+}
+
+#[cfg(feature = "display")]
+fn fmt_element_within(
+    indent_level: usize,
+    element: &VElement,
+    limits: &Limits,
+    f: &mut fmt::Formatter,
+) -> fmt::Result {
+    // Begin opening tag
+    write!(f, "{}<{}", INDENT.repeat(indent_level), element.get_tag())?;
+
+    // Classes
+    let mut classes: Vec<&CowString> = element.get_classes().iter().collect();
+    classes.sort();
+
+    if !classes.is_empty() {
+        write!(f, " class=\"")?;
+        for (index, class) in classes.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
             }
+            write!(f, "{}", class)?;
+        }
+        write!(f, "\"")?;
+    }
+
+    // Attributes
+    let mut attr_pairs: Vec<(&CowString, &CowString)> = element.get_attributes().iter().collect();
+    attr_pairs.sort_by_key(|(a, _)| *a);
+    for (name, value) in attr_pairs {
+        if !value.is_empty() {
+            write!(f, " {}=\"{}\"", name, value)?;
+        } else {
+            write!(f, " {}", name)?;
+        }
+    }
+
+    // Void elements do not have cloning tag or children.
+    if element.is_void() {
+        writeln!(f, ">")
+    } else {
+        // End opening tag
+        write!(f, ">")?;
+
+        // Children
+
+        if !element.get_children().is_empty() {
+            writeln!(f)?;
+        }
+
+        let children = element.get_children();
+        let shown = if indent_level < limits.max_depth {
+            children.len().min(limits.max_children)
+        } else {
+            0
+        };
+        for child in &children[..shown] {
+            fmt_node_within(indent_level + 1, child, limits, f)?;
+        }
+        if shown < children.len() {
+            let hidden = children.len() - shown;
+            let more = if shown == 0 { "" } else { " more" };
+            let noun = if hidden == 1 { "child" } else { "children" };
+            let indent = INDENT.repeat(indent_level + 1);
+            writeln!(f, "{}\u{2026} {}{} {}", indent, hidden, more, noun)?;
         }
 
-        fmt_indent(0, self, f)
+        // Closing tag
+        writeln!(f, "{}</{}>", INDENT.repeat(indent_level), element.get_tag())
     }
 }
 
-#[cfg(test)]
+#[cfg(feature = "display")]
+pub(crate) fn fmt_text(indent_level: usize, text: &VText, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}{}", INDENT.repeat(indent_level), text.get_content())
+}
+
+#[cfg(all(test, feature = "tags"))]
 mod tests {
     use tags::*;
+    use text::text;
 
     #[test]
+    #[cfg(feature = "display")]
     fn node_to_string() {
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let node = div().class_list("aaa bbb").attr("id", "ccc").attr("hidden", "")
             .child(p().class("one").text("1"))
             .child(p().class("two").text("2"))
@@ -112,7 +444,7 @@ mod tests {
             .child(p().class("three").text("3"))
             .done();
 
-        let result = format!("\n{}", node.to_string());
+        let result = format!("\n{}", node);
 
         let expected = r#"
 <div class="aaa bbb" hidden id="ccc">
@@ -131,4 +463,95 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    #[cfg(feature = "display")]
+    fn element_and_text_to_string() {
+        let element = ul().class("list").child(li().text("1"));
+        assert_eq!(element.to_string(), element.clone().done().to_string());
+
+        let content = text("1");
+        assert_eq!(content.to_string(), "1\n");
+        assert_eq!(content.to_string(), content.clone().done().to_string());
+    }
+
+    #[test]
+    fn node_to_snapshot_string() {
+        #[rustfmt::skip]
+        let node = div().key("root").class_list("bbb aaa").attr("title", "\"quoted\"").attr("hidden", "")
+            .child(p().text("  a <b>\n   &  c  "))
+            .child(br())
+            .child(span())
+            .done();
+
+        let result = format!("\n{}", node.to_snapshot_string());
+
+        let expected = r#"
+<div key="root" class="aaa bbb" hidden="" title="&quot;quoted&quot;">
+  <p>
+    "a &lt;b&gt; &amp; c"
+  </p>
+  <br/>
+  <span></span>
+</div>
+"#;
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn clone_tree() {
+        use diff::{diff, NodeOp};
+        use node::VNode;
+        use sync::Shared;
+
+        let shared = VNode::Shared(Shared::new(p().text("shared").done()));
+        let node = div().class("a").child_node(shared).done();
+        let clone = node.clone();
+
+        assert_eq!(clone, node);
+        assert_eq!(diff(&node, &clone), NodeOp::Skip(1));
+    }
+
+    #[test]
+    #[cfg(feature = "display")]
+    fn truncated_display() {
+        #[rustfmt::skip]
+        let node = ul()
+            .child(li().child(p().text("a")))
+            .child(li().text("b"))
+            .child(li().text("c"))
+            .child(li().text("d"))
+            .done();
+
+        let result = format!("\n{}", node.display_truncated(1, 2));
+
+        let expected = "
+<ul>
+    <li>
+        \u{2026} 1 child
+    </li>
+    <li>
+        \u{2026} 1 child
+    </li>
+    \u{2026} 2 more children
+</ul>
+";
+
+        assert_eq!(result, expected);
+        assert_eq!(node.display_truncated(10, 10).to_string(), node.to_string());
+    }
+
+    #[test]
+    fn approx_heap_size() {
+        assert_eq!(text("static").done().approx_heap_size(), 0);
+        assert_eq!(text(String::from("owned")).done().approx_heap_size(), 5);
+
+        let empty = div().done();
+        let owned = div().attr("id", String::from("x")).done();
+        assert!(owned.approx_heap_size() > empty.approx_heap_size());
+
+        let parent = div().child(p().text(String::from("owned"))).done();
+        assert!(parent.approx_heap_size() >= 5 + ::std::mem::size_of::<super::VNode>());
+    }
 }
@@ -0,0 +1,516 @@
+//! # HTML parser
+//!
+//! Minimal HTML parser which turns markup into a VNode tree.
+//!
+//! It is meant for fixtures and migrating existing templates, not for
+//! parsing arbitrary documents from the web. Supported syntax:
+//!
+//! - Elements with quoted, unquoted and valueless attributes.
+//! - Void elements and self-closing tags (`<br/>`).
+//! - Raw text elements (`script`, `style`).
+//! - Comments and doctype declarations, which are skipped.
+//!
+//! The `class` attribute is split into the class set and the `key` attribute
+//! becomes the element key, mirroring the builder API. Text content is trimmed
+//! and whitespace-only text is dropped. Character references in text and
+//! attribute values are decoded with `escape::decode_entities`.
+//!
+//! Tag and attribute names are lowercased, so `<DIV>` and `div()` don't
+//! differ in diffs, except for the camelCase names of SVG such as `viewBox`
+//! and `linearGradient`, which are restored like HTML parsers do. Parsing
+//! with `ParseOptions::preserve_case` keeps names as written, for XML.
+//!
+use element::VElement;
+use escape::decode_entities;
+use node::VNode;
+use std::borrow::Cow;
+use std::fmt;
+use text::VText;
+
+/// Void elements as defined by the HTML spec.
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+///
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is not parsed as markup.
+///
+const RAW_TEXT_TAGS: &[&str] = &["script", "style"];
+
+/// Mixed-case tag and attribute names of SVG, which keep their case.
+///
+const CAMEL_CASE_NAMES: &[&str] = &[
+    "attributeName",
+    "baseFrequency",
+    "clipPath",
+    "clipPathUnits",
+    "diffuseConstant",
+    "feBlend",
+    "feColorMatrix",
+    "feComposite",
+    "feDropShadow",
+    "feFlood",
+    "feGaussianBlur",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+    "feTurbulence",
+    "foreignObject",
+    "gradientTransform",
+    "gradientUnits",
+    "kernelMatrix",
+    "lengthAdjust",
+    "linearGradient",
+    "markerHeight",
+    "markerUnits",
+    "markerWidth",
+    "maskContentUnits",
+    "maskUnits",
+    "numOctaves",
+    "pathLength",
+    "patternContentUnits",
+    "patternTransform",
+    "patternUnits",
+    "preserveAspectRatio",
+    "radialGradient",
+    "refX",
+    "refY",
+    "repeatCount",
+    "spreadMethod",
+    "stdDeviation",
+    "stitchTiles",
+    "surfaceScale",
+    "textLength",
+    "textPath",
+    "viewBox",
+];
+
+/// Name as the parser stores it: lowercased, or with the case of SVG for
+/// its camelCase names. Useful for normalizing names from other sources
+/// the same way.
+///
+pub fn normalize_name(name: &str) -> Cow<'_, str> {
+    let camel = CAMEL_CASE_NAMES
+        .iter()
+        .find(|camel| camel.eq_ignore_ascii_case(name));
+    match camel {
+        Some(camel) => Cow::Borrowed(camel),
+        None if name.bytes().any(|b| b.is_ascii_uppercase()) => {
+            Cow::Owned(name.to_ascii_lowercase())
+        }
+        None => Cow::Borrowed(name),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Keep tag and attribute names as written instead of normalizing them
+    /// with `normalize_name`.
+    pub preserve_case: bool,
+    /// Fail on nodes nested deeper than this, counting the root nodes as 1,
+    /// so untrusted markup can't build trees which overflow the stack of
+    /// functions walking them. Zero, the default, doesn't limit.
+    pub max_depth: usize,
+    /// Fail on markup with more nodes than this. Zero, the default, doesn't
+    /// limit.
+    pub max_nodes: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse markup containing exactly one root node.
+///
+pub fn parse(input: &str) -> Result<VNode, ParseError> {
+    parse_with(input, &ParseOptions::default())
+}
+
+/// Parse markup containing exactly one root node, with options.
+///
+pub fn parse_with(input: &str, options: &ParseOptions) -> Result<VNode, ParseError> {
+    let mut nodes = parse_fragment_with(input, options)?;
+    match nodes.len() {
+        1 => Ok(nodes.remove(0)),
+        count => Err(ParseError {
+            position: 0,
+            message: format!("expected a single root node, found {}", count),
+        }),
+    }
+}
+
+/// Parse markup into a list of sibling nodes.
+///
+pub fn parse_fragment(input: &str) -> Result<Vec<VNode>, ParseError> {
+    parse_fragment_with(input, &ParseOptions::default())
+}
+
+/// Parse markup into a list of sibling nodes, with options.
+///
+pub fn parse_fragment_with(input: &str, options: &ParseOptions) -> Result<Vec<VNode>, ParseError> {
+    let mut parser = Parser {
+        input,
+        position: 0,
+        preserve_case: options.preserve_case,
+        max_depth: options.max_depth,
+        max_nodes: options.max_nodes,
+        depth: 0,
+        nodes: 0,
+    };
+    let nodes = parser.parse_nodes(None)?;
+    Ok(nodes)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    preserve_case: bool,
+    max_depth: usize,
+    max_nodes: usize,
+    /// Number of open elements.
+    depth: usize,
+    nodes: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.position,
+            message,
+        })
+    }
+
+    /// Count a node starting at the current position against the limits.
+    ///
+    fn count_node(&mut self) -> Result<(), ParseError> {
+        self.nodes += 1;
+        if self.max_depth > 0 && self.depth >= self.max_depth {
+            return self.error(format!("nodes nested deeper than {}", self.max_depth));
+        }
+        if self.max_nodes > 0 && self.nodes > self.max_nodes {
+            return self.error(format!("more than {} nodes", self.max_nodes));
+        }
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume input until `pattern` and return everything before it.
+    ///
+    fn take_until(&mut self, pattern: &str) -> Result<&'a str, ParseError> {
+        match self.rest().find(pattern) {
+            Some(index) => {
+                let taken = &self.rest()[..index];
+                self.position += index + pattern.len();
+                Ok(taken)
+            }
+            None => self.error(format!("expected `{}`", pattern)),
+        }
+    }
+
+    /// Consume a tag or attribute name.
+    ///
+    fn take_name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    /// Consume a tag or attribute name and normalize its case.
+    ///
+    fn take_normalized_name(&mut self) -> Cow<'a, str> {
+        let name = self.take_name();
+        if self.preserve_case {
+            Cow::Borrowed(name)
+        } else {
+            normalize_name(name)
+        }
+    }
+
+    /// Parse sibling nodes until the closing tag of `parent` or end of input.
+    ///
+    fn parse_nodes(&mut self, parent: Option<&str>) -> Result<Vec<VNode>, ParseError> {
+        let mut nodes = Vec::new();
+
+        loop {
+            let rest = self.rest();
+
+            if rest.is_empty() {
+                return match parent {
+                    Some(tag) => self.error(format!("unclosed element `{}`", tag)),
+                    None => Ok(nodes),
+                };
+            }
+
+            if rest.starts_with("<!--") {
+                self.position += 4;
+                self.take_until("-->")?;
+            } else if rest.starts_with("<!") {
+                self.take_until(">")?;
+            } else if rest.starts_with("</") {
+                self.position += 2;
+                let tag = self.take_normalized_name();
+                self.skip_whitespace();
+                self.take_until(">")?;
+                return match parent {
+                    Some(parent) if parent == tag => Ok(nodes),
+                    _ => self.error(format!("unexpected closing tag `{}`", tag)),
+                };
+            } else if rest.starts_with('<') {
+                self.count_node()?;
+                nodes.push(self.parse_element()?);
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                self.position += len;
+                let content = rest[..len].trim();
+                if !content.is_empty() {
+                    self.count_node()?;
+                    nodes.push(VText::new_decoded(content).done());
+                }
+            }
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<VNode, ParseError> {
+        // Skip `<`
+        self.position += 1;
+
+        let written_tag = self.take_name();
+        let tag: &str = &if self.preserve_case {
+            Cow::Borrowed(written_tag)
+        } else {
+            normalize_name(written_tag)
+        };
+        if tag.is_empty() {
+            return self.error("expected tag name".to_string());
+        }
+
+        let void = VOID_TAGS.contains(&tag);
+        let mut element = if void {
+            VElement::new_void(tag.to_string())
+        } else {
+            VElement::new(tag.to_string())
+        };
+
+        // Attributes
+        let self_closing = loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+
+            if rest.starts_with("/>") {
+                self.position += 2;
+                break true;
+            } else if rest.starts_with('>') {
+                self.position += 1;
+                break false;
+            } else if rest.is_empty() {
+                return self.error(format!("unclosed tag `{}`", tag));
+            }
+
+            let name = self.take_normalized_name();
+            if name.is_empty() {
+                return self.error(format!("unexpected character in tag `{}`", tag));
+            }
+
+            self.skip_whitespace();
+            let value = if self.rest().starts_with('=') {
+                self.position += 1;
+                self.skip_whitespace();
+                self.parse_attribute_value()?
+            } else {
+                ""
+            };
+
+            let value = decode_entities(value).into_owned();
+            element = match name.as_ref() {
+                "class" => element.class_list(value),
+                "key" => element.key(value),
+                _ => element.attr(name.to_string(), value),
+            };
+        };
+
+        if void || self_closing {
+            return Ok(element.done());
+        }
+
+        // Children
+        if RAW_TEXT_TAGS.contains(&tag) {
+            let content = self.take_until(&format!("</{}>", written_tag))?;
+            if !content.trim().is_empty() {
+                element = element.text(content.trim().to_string());
+            }
+        } else {
+            self.depth += 1;
+            for child in self.parse_nodes(Some(tag))? {
+                element = element.child_node(child);
+            }
+            self.depth -= 1;
+        }
+
+        Ok(element.done())
+    }
+
+    fn parse_attribute_value(&mut self) -> Result<&'a str, ParseError> {
+        let rest = self.rest();
+
+        if rest.starts_with('"') {
+            self.position += 1;
+            self.take_until("\"")
+        } else if rest.starts_with('\'') {
+            self.position += 1;
+            self.take_until("'")
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || c == '>')
+                .unwrap_or(rest.len());
+            self.position += len;
+            Ok(&rest[..len])
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+    use text::text;
+
+    #[test]
+    fn parse_nested_elements() {
+        let markup = r#"
+            <!DOCTYPE html>
+            <div class="aaa bbb" id=ccc hidden>
+                <!-- comment -->
+                <p key="1">Hello</p>
+                <hr>
+                <input type='text'/>
+            </div>
+        "#;
+
+        #[rustfmt::skip]
+        let expected = div().class_list("aaa bbb").attr("id", "ccc").attr("hidden", "")
+            .child(p().key("1").text("Hello"))
+            .child(hr())
+            .child(input().attr("type", "text"))
+            .done();
+
+        assert_eq!(parse(markup), Ok(expected));
+    }
+
+    #[test]
+    fn parse_entities() {
+        let markup = r#"<a title="&quot;x&quot;">Tom &amp; Jerry&#x27;s</a>"#;
+
+        assert_eq!(
+            parse(markup),
+            Ok(a().attr("title", "\"x\"").text("Tom & Jerry's").done())
+        );
+    }
+
+    #[test]
+    fn parse_raw_text() {
+        let input = "<script>if (a < b) {}</script>";
+
+        assert_eq!(parse(input), Ok(script().text("if (a < b) {}").done()));
+    }
+
+    #[test]
+    fn parse_fragment_siblings() {
+        let input = "<p>one</p> two <br>";
+
+        assert_eq!(
+            parse_fragment(input),
+            Ok(vec![
+                p().text("one").done(),
+                text("two").done(),
+                br().done()
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("<div><p></div>").is_err());
+        assert!(parse("<div>").is_err());
+        assert!(parse("<p></p><p></p>").is_err());
+    }
+
+    #[test]
+    fn parse_normalizes_case() {
+        let markup = r#"<DIV ID="a"><svg viewbox="0 0 1 1"><linearGradient/></svg></div>"#;
+
+        #[rustfmt::skip]
+        let expected = div().attr("id", "a")
+            .child(VElement::new("svg").attr("viewBox", "0 0 1 1").child(VElement::new("linearGradient")))
+            .done();
+        assert_eq!(parse(markup), Ok(expected));
+
+        let options = ParseOptions {
+            preserve_case: true,
+            ..ParseOptions::default()
+        };
+        let markup = r#"<Item Name="a"></Item>"#;
+        assert_eq!(
+            parse_with(markup, &options),
+            Ok(VElement::new("Item").attr("Name", "a").done())
+        );
+        assert!(parse_with("<a></A>", &options).is_err());
+        assert_eq!(normalize_name("VIEWBOX"), "viewBox");
+        assert_eq!(normalize_name("Data-Id"), "data-id");
+    }
+
+    #[test]
+    fn parse_limits() {
+        let markup = "<div><p><b>a</b></p><p>b</p></div>";
+        let options = ParseOptions {
+            max_depth: 3,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            parse_with(markup, &options),
+            Err(ParseError {
+                position: 12,
+                message: "nodes nested deeper than 3".to_string(),
+            })
+        );
+
+        let options = ParseOptions {
+            max_depth: 4,
+            max_nodes: 5,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            parse_with(markup, &options).map_err(|error| error.message),
+            Err("more than 5 nodes".to_string())
+        );
+
+        let options = ParseOptions {
+            max_nodes: 6,
+            ..options
+        };
+        assert!(parse_with(markup, &options).is_ok());
+    }
+}
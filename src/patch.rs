@@ -0,0 +1,263 @@
+//! # Owned patches
+//!
+//! `NodeOp` borrows inserted and replacing nodes from the new tree, which
+//! keeps diffing cheap but ties the patch to the tree. `OwnedOp` is the same
+//! patch with its own copies of those nodes, so it can outlive the tree, be
+//! sent to another thread or decoded from bytes, see `binary`.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::patch::OwnedOp;
+//! use troy::tags::*;
+//!
+//! let old = div().child(p().text("a")).done();
+//! let patch = {
+//!     let new = div().child(p().text("b")).done();
+//!     OwnedOp::from(&diff(&old, &new))
+//! };
+//!
+//! // Patchers which take NodeOp can borrow it back.
+//! let op = patch.as_op();
+//! ```
+//!
+//! When patches travel through channels or async tasks, frames can be
+//! dropped or arrive out of order. A `Stamp` records which tree a patch was
+//! diffed against, and patchers such as `backend::Mounted::patch_stamped`
+//! reject patches against any other tree.
+//!
+//! Views which switch between a few known states, such as spinners,
+//! accordions and tabs, can diff each transition once with
+//! `Patch::precompile` and apply the stored patch every time the view makes
+//! that transition, instead of diffing again.
+//!
+use binary::{decode_patch, encode_patch, DecodeError};
+use diff::{diff, AttrDiff, ChildDiff, ChildInserts, NodeOp, TextOp};
+use error::Error;
+use node::VNode;
+
+/// Generations of the trees a patch was diffed between. Generations count
+/// trees from 1, and 0 stands for no tree.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stamp {
+    /// Generation of the old tree.
+    pub base: u64,
+    /// Generation of the new tree.
+    pub generation: u64,
+}
+
+impl Stamp {
+    /// Check that the patch applies to the tree of the `mounted` generation.
+    ///
+    pub fn check(&self, mounted: u64) -> Result<(), Error> {
+        if self.base == mounted {
+            Ok(())
+        } else {
+            Err(Error::StalePatch(self.base, mounted))
+        }
+    }
+}
+
+pub type OwnedChildDiff = Option<Vec<OwnedOp>>;
+pub type OwnedChildInserts = Option<Vec<(usize, VNode)>>;
+
+#[derive(Debug, PartialEq)]
+pub enum OwnedOp {
+    Skip(usize),
+    Remove(usize),
+    Move(usize, AttrDiff, OwnedChildDiff, OwnedChildInserts),
+    Update(AttrDiff, OwnedChildDiff, OwnedChildInserts),
+    Replace(VNode),
+    Text(TextOp),
+}
+
+impl OwnedOp {
+    /// Borrowed form of the patch.
+    ///
+    pub fn as_op(&self) -> NodeOp<'_> {
+        fn children(children: &OwnedChildDiff) -> ChildDiff<'_> {
+            children
+                .as_ref()
+                .map(|children| children.iter().map(OwnedOp::as_op).collect())
+        }
+
+        fn inserts(inserts: &OwnedChildInserts) -> ChildInserts<'_> {
+            inserts.as_ref().map(|inserts| {
+                inserts
+                    .iter()
+                    .map(|(position, node)| (*position, node))
+                    .collect()
+            })
+        }
+
+        match self {
+            OwnedOp::Skip(count) => NodeOp::Skip(*count),
+            OwnedOp::Remove(count) => NodeOp::Remove(*count),
+            OwnedOp::Move(position, attrs, c, i) => {
+                NodeOp::Move(*position, attrs.clone(), children(c), inserts(i))
+            }
+            OwnedOp::Update(attrs, c, i) => NodeOp::Update(attrs.clone(), children(c), inserts(i)),
+            OwnedOp::Replace(node) => NodeOp::Replace(node),
+            OwnedOp::Text(op) => NodeOp::Text(op.clone()),
+        }
+    }
+}
+
+/// Copy the patch. Components and shared nodes in inserted and replacing
+/// nodes are copied as the nodes they stand for.
+///
+impl<'a, 'new> From<&'a NodeOp<'new>> for OwnedOp {
+    fn from(op: &'a NodeOp<'new>) -> Self {
+        fn children(children: &ChildDiff) -> OwnedChildDiff {
+            children
+                .as_ref()
+                .map(|children| children.iter().map(OwnedOp::from).collect())
+        }
+
+        fn inserts(inserts: &ChildInserts) -> OwnedChildInserts {
+            inserts.as_ref().map(|inserts| {
+                inserts
+                    .iter()
+                    .map(|(position, node)| (*position, (*node).clone()))
+                    .collect()
+            })
+        }
+
+        match op {
+            NodeOp::Skip(count) => OwnedOp::Skip(*count),
+            NodeOp::Remove(count) => OwnedOp::Remove(*count),
+            NodeOp::Move(position, attrs, c, i) => {
+                OwnedOp::Move(*position, attrs.clone(), children(c), inserts(i))
+            }
+            NodeOp::Update(attrs, c, i) => OwnedOp::Update(attrs.clone(), children(c), inserts(i)),
+            NodeOp::Replace(node) => OwnedOp::Replace((*node).clone()),
+            NodeOp::Text(op) => OwnedOp::Text(op.clone()),
+        }
+    }
+}
+
+impl<'new> From<NodeOp<'new>> for OwnedOp {
+    fn from(op: NodeOp<'new>) -> Self {
+        OwnedOp::from(&op)
+    }
+}
+
+/// Named patch between two known states of a view, diffed ahead of time.
+///
+/// ```
+/// use troy::patch::Patch;
+/// use troy::tags::*;
+///
+/// let empty = div().child(p().class("spinner")).done();
+/// let loaded = div().child(ul().child(li().text("a"))).done();
+///
+/// let patch = Patch::precompile("empty→loaded", &empty, &loaded);
+/// let stored = patch.to_bytes();
+/// assert_eq!(Patch::from_bytes(&stored), Ok(patch));
+/// ```
+///
+#[derive(Debug, PartialEq)]
+pub struct Patch {
+    name: String,
+    op: OwnedOp,
+}
+
+impl Patch {
+    /// Diff the states once and keep the patch under the name of the
+    /// transition.
+    ///
+    pub fn precompile<S: Into<String>>(name: S, old: &VNode, new: &VNode) -> Patch {
+        Patch {
+            name: name.into(),
+            op: OwnedOp::from(&diff(old, new)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn op(&self) -> &OwnedOp {
+        &self.op
+    }
+
+    /// Borrowed form of the patch, for patchers.
+    ///
+    pub fn as_op(&self) -> NodeOp<'_> {
+        self.op.as_op()
+    }
+
+    /// Check that the patch fits the tree it's about to be applied to, which
+    /// should be the old state it was precompiled from.
+    ///
+    pub fn validate_against(&self, tree: &VNode) -> Result<(), Error> {
+        self.op.as_op().validate_against(tree)
+    }
+
+    /// Encode the name and the patch, see `binary`.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode_patch(&self.name, &self.op.as_op())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Patch, DecodeError> {
+        let (name, op) = decode_patch(bytes)?;
+        Ok(Patch { name, op })
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    fn owned_patch_outlives_tree() {
+        let old = ul().child(li().key("a").text("a")).done();
+        let (patch, expected) = {
+            let new = ul()
+                .child(li().key("b").class("new").text("b"))
+                .child(li().key("a").text("a"))
+                .done();
+            let op = diff(&old, &new);
+            (OwnedOp::from(&op), format!("{:?}", op))
+        };
+
+        assert_eq!(format!("{:?}", patch.as_op()), expected);
+    }
+
+    #[test]
+    fn precompiled_patches_apply_repeatedly() {
+        let closed = div().child(button().text("Open")).done();
+        let open = div()
+            .child(button().text("Close"))
+            .child(p().text("Details"))
+            .done();
+        let opening = Patch::precompile("closed→open", &closed, &open);
+        let closing = Patch::precompile("open→closed", &open, &closed);
+
+        assert_eq!(opening.name(), "closed→open");
+        assert_eq!(opening.as_op(), diff(&closed, &open));
+        assert_eq!(opening.validate_against(&closed), Ok(()));
+        assert!(closing.validate_against(&div().done()).is_err());
+
+        let stored = Patch::from_bytes(&closing.to_bytes()).unwrap();
+        assert_eq!(stored.as_op(), diff(&open, &closed));
+    }
+
+    #[test]
+    fn stale_stamps_are_rejected() {
+        let stamp = Stamp {
+            base: 2,
+            generation: 3,
+        };
+
+        assert_eq!(stamp.check(2), Ok(()));
+        assert_eq!(stamp.check(1), Err(Error::StalePatch(2, 1)));
+        assert_eq!(
+            Error::StalePatch(2, 1).to_string(),
+            "patch against generation 2 applied to generation 1"
+        );
+    }
+}
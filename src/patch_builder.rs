@@ -0,0 +1,482 @@
+//! # Patch builder
+//!
+//! `PatchBuilder` collects operations on a children list and keeps the
+//! list compact, so custom diff strategies emit the same well-formed
+//! output as `diff`:
+//!
+//! - sequences of `Skip` and of `Remove` are merged into one operation
+//!   with the summed count,
+//! - updates which change nothing are folded into `Skip(1)`,
+//! - operations with a zero count are dropped,
+//! - trailing skips are dropped, since children after the last operation
+//!   stay in place anyway.
+//!
+//! ```
+//! use troy::diff::NodeOp::*;
+//! use troy::patch_builder::PatchBuilder;
+//!
+//! let mut builder = PatchBuilder::new();
+//! builder.push(Skip(1));
+//! builder.push(Update(None, None, None));
+//! builder.push(Remove(1));
+//! builder.push(Remove(2));
+//! builder.push(Skip(4));
+//!
+//! assert_eq!(builder.done(), vec![Skip(2), Remove(3)]);
+//! ```
+//!
+//! Consecutive `Replace` operations are kept as they are: each one carries
+//! its own node, and `NodeOp` has no operation replacing a range.
+//!
+//! `done_with_inserts` also rewrites operations together with the inserts
+//! of the list:
+//!
+//! - a removed child whose slot in the new list is taken by an insert is
+//!   replaced by the inserted node instead,
+//! - a moved child which lands where it would stay anyway is updated in
+//!   place instead.
+//!
+//! ```
+//! use troy::diff::NodeOp::*;
+//! use troy::patch_builder::PatchBuilder;
+//! use troy::tags::*;
+//!
+//! let node = p().done();
+//! let mut builder = PatchBuilder::new();
+//! builder.push(Skip(1));
+//! builder.push(Remove(1));
+//!
+//! assert_eq!(
+//!     builder.done_with_inserts(vec![(1, &node)]),
+//!     (vec![Skip(1), Replace(&node)], vec![])
+//! );
+//! ```
+//!
+use diff::{ChildInsert, NodeOp};
+
+/// Compacting list of operations on children, see the module
+/// documentation.
+///
+#[derive(Debug, Default)]
+pub struct PatchBuilder<'new> {
+    ops: Vec<NodeOp<'new>>,
+}
+
+impl<'new> PatchBuilder<'new> {
+    pub fn new() -> Self {
+        PatchBuilder { ops: Vec::new() }
+    }
+
+    /// Append an operation on the next children, merging it with the
+    /// previous operation when possible.
+    ///
+    pub fn push(&mut self, op: NodeOp<'new>) {
+        use diff::NodeOp::*;
+
+        let op = match op {
+            Update(attrs, children, inserts) => {
+                let attrs = attrs.filter(|attrs| !attrs.is_empty());
+                let children = children.filter(|children| !children.is_empty());
+                let inserts = inserts.filter(|inserts| !inserts.is_empty());
+                match (attrs, children, inserts) {
+                    (None, None, None) => Skip(1),
+                    (attrs, children, inserts) => Update(attrs, children, inserts),
+                }
+            }
+            op => op,
+        };
+
+        match (self.ops.last_mut(), op) {
+            (_, Skip(0)) | (_, Remove(0)) => {}
+            (Some(Skip(last_count)), Skip(count)) => *last_count += count,
+            (Some(Remove(last_count)), Remove(count)) => *last_count += count,
+            (_, op) => self.ops.push(op),
+        }
+    }
+
+    /// Whether no operations other than trailing skips were pushed.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.ops.iter().all(|op| matches!(op, NodeOp::Skip(_)))
+    }
+
+    /// Finish the list, dropping trailing skips.
+    ///
+    pub fn done(mut self) -> Vec<NodeOp<'new>> {
+        if let Some(NodeOp::Skip(_)) = self.ops.last() {
+            self.ops.pop();
+        }
+        self.ops
+    }
+
+    /// Finish the list together with the inserts into the same children,
+    /// replacing removed children and cancelling moves where the result is
+    /// the same. Inserts must be sorted by position.
+    ///
+    pub fn done_with_inserts(
+        self,
+        inserts: Vec<ChildInsert<'new>>,
+    ) -> (Vec<NodeOp<'new>>, Vec<ChildInsert<'new>>) {
+        use diff::NodeOp::*;
+
+        // One operation for each old child.
+        let mut children: Vec<NodeOp<'new>> = Vec::new();
+        for op in self.done() {
+            match op {
+                Skip(count) => children.extend((0..count).map(|_| Skip(1))),
+                Remove(count) => children.extend((0..count).map(|_| Remove(1))),
+                op => children.push(op),
+            }
+        }
+
+        // Positions taken by moved and inserted children, in order. Other
+        // children fill the remaining positions in their old order.
+        let mut placed: Vec<(usize, Placed)> = inserts
+            .iter()
+            .enumerate()
+            .map(|(index, (position, _))| (*position, Placed::Insert(index)))
+            .collect();
+        for (index, op) in children.iter().enumerate() {
+            if let Move(position, ..) = op {
+                placed.push((*position, Placed::Move(index)));
+            }
+        }
+        placed.sort_by_key(|(position, _)| *position);
+
+        let mut consumed = vec![false; inserts.len()];
+        let mut placed = placed.into_iter().peekable();
+        // Position the next child staying in place would take, unless it is
+        // taken by a placed child.
+        let mut position = 0;
+
+        for (index, child) in children.iter_mut().enumerate() {
+            let next = placed.peek().filter(|(next, _)| *next == position);
+            match (&*child, next) {
+                (Remove(_), Some((_, Placed::Insert(insert)))) => {
+                    *child = Replace(inserts[*insert].1);
+                    consumed[*insert] = true;
+                }
+                (Move(..), Some((_, Placed::Move(moved)))) if *moved == index => {
+                    if let Move(_, attrs, grandchildren, grandinserts) =
+                        ::std::mem::replace(child, Skip(1))
+                    {
+                        *child = Update(attrs, grandchildren, grandinserts);
+                    }
+                }
+                (Remove(_), _) | (Move(..), _) => continue,
+                (_, _) => {
+                    while placed.peek().map(|(next, _)| *next) == Some(position) {
+                        placed.next();
+                        position += 1;
+                    }
+                    position += 1;
+                    continue;
+                }
+            }
+            // The child stays at the placed position now.
+            placed.next();
+            position += 1;
+        }
+
+        let mut builder = PatchBuilder::new();
+        builder.extend(children);
+        let inserts = inserts
+            .into_iter()
+            .zip(consumed)
+            .filter(|(_, consumed)| !consumed)
+            .map(|(insert, _)| insert)
+            .collect();
+        (builder.done(), inserts)
+    }
+}
+
+/// Source of a child placed at an explicit position.
+///
+#[derive(Debug, Clone, Copy)]
+enum Placed {
+    /// Index of the insert.
+    Insert(usize),
+    /// Index of the old child.
+    Move(usize),
+}
+
+impl<'new> Extend<NodeOp<'new>> for PatchBuilder<'new> {
+    fn extend<I>(&mut self, ops: I)
+    where
+        I: IntoIterator<Item = NodeOp<'new>>,
+    {
+        for op in ops {
+            self.push(op);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::AttrOp;
+    use diff::NodeOp::*;
+    use node::VNode;
+    use tags::{div, p};
+    use text::text;
+
+    #[test]
+    fn adding_skips() {
+        let mut builder = PatchBuilder::new();
+
+        builder.push(Skip(1));
+        builder.push(Skip(2));
+        builder.push(Remove(1));
+        builder.push(Skip(1));
+
+        let result = builder.done();
+
+        assert_eq!(result, vec![Skip(3), Remove(1)]);
+    }
+
+    #[test]
+    fn adding_removes() {
+        let mut builder = PatchBuilder::new();
+
+        builder.push(Remove(3));
+        builder.push(Remove(1));
+        builder.push(Remove(5));
+
+        let result = builder.done();
+
+        assert_eq!(result, vec![Remove(9)]);
+    }
+
+    #[test]
+    fn adding_mixed_ops() {
+        let node = div().done();
+        let mut builder = PatchBuilder::new();
+
+        builder.push(Skip(1));
+        builder.push(Skip(1));
+        builder.push(Skip(1));
+        builder.push(Remove(2));
+        builder.push(Replace(&node));
+        builder.push(Replace(&node));
+        builder.push(Skip(2));
+        builder.push(Skip(5));
+        builder.push(Remove(1));
+        builder.push(Replace(&node));
+        builder.push(Remove(4));
+        builder.push(Skip(4));
+
+        let result = builder.done();
+
+        assert_eq!(
+            result,
+            vec![
+                Skip(3),
+                Remove(2),
+                Replace(&node),
+                Replace(&node),
+                Skip(7),
+                Remove(1),
+                Replace(&node),
+                Remove(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn folding_empty_updates() {
+        let mut builder = PatchBuilder::new();
+        builder.push(Skip(1));
+        builder.push(Update(Some(vec![]), Some(vec![]), None));
+        builder.push(Remove(0));
+        builder.push(Skip(1));
+        assert!(builder.is_empty());
+        assert_eq!(builder.done(), vec![]);
+
+        let attrs = Some(vec![AttrOp::Remove("id".to_string())]);
+        let mut builder = PatchBuilder::new();
+        builder.push(Update(attrs.clone(), Some(vec![]), None));
+        assert!(!builder.is_empty());
+        assert_eq!(builder.done(), vec![Update(attrs, None, None)]);
+    }
+
+    //
+    // # Rewriting with inserts
+    //
+
+    fn rewrite<'new>(
+        ops: Vec<NodeOp<'new>>,
+        inserts: Vec<ChildInsert<'new>>,
+    ) -> (Vec<NodeOp<'new>>, Vec<ChildInsert<'new>>) {
+        let mut builder = PatchBuilder::new();
+        builder.extend(ops);
+        builder.done_with_inserts(inserts)
+    }
+
+    #[test]
+    fn remove_and_insert_become_replace() {
+        let a = p().done();
+        let b = div().done();
+
+        // Beginning, middle and end of the list.
+        assert_eq!(
+            rewrite(vec![Remove(1)], vec![(0, &a)]),
+            (vec![Replace(&a)], vec![])
+        );
+        assert_eq!(
+            rewrite(vec![Skip(1), Remove(2), Skip(1)], vec![(1, &a), (2, &b)]),
+            (vec![Skip(1), Replace(&a), Replace(&b)], vec![])
+        );
+        assert_eq!(
+            rewrite(vec![Skip(2), Remove(1)], vec![(2, &a), (3, &b)]),
+            (vec![Skip(2), Replace(&a)], vec![(3, &b)])
+        );
+    }
+
+    #[test]
+    fn remove_stays_when_insert_is_elsewhere() {
+        let a = p().done();
+
+        assert_eq!(
+            rewrite(vec![Remove(1)], vec![(1, &a)]),
+            (vec![Remove(1)], vec![(1, &a)])
+        );
+        // The slot of the removed child is taken by a moved child.
+        assert_eq!(
+            rewrite(
+                vec![Remove(1), Skip(1), Move(0, None, None, None)],
+                vec![(1, &a)]
+            ),
+            (
+                vec![Remove(1), Skip(1), Move(0, None, None, None)],
+                vec![(1, &a)]
+            )
+        );
+    }
+
+    #[test]
+    fn moves_in_place_are_cancelled() {
+        let attrs = Some(vec![AttrOp::InsertClass("a".to_string())]);
+
+        assert_eq!(
+            rewrite(vec![Skip(1), Move(1, None, None, None)], vec![]),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            rewrite(vec![Remove(1), Move(0, attrs.clone(), None, None)], vec![]),
+            (vec![Remove(1), Update(attrs, None, None)], vec![])
+        );
+        assert_eq!(
+            rewrite(vec![Move(2, None, None, None)], vec![]),
+            (vec![Move(2, None, None, None)], vec![])
+        );
+    }
+
+    /// Labels of children after applying the operations to old children
+    /// labeled by their index.
+    ///
+    fn apply(old_len: usize, ops: &[NodeOp], inserts: &[ChildInsert]) -> Vec<String> {
+        fn label(node: &VNode) -> String {
+            node.text_content()
+        }
+
+        let mut staying = Vec::new();
+        let mut placed = Vec::new();
+        let mut index = 0;
+        for op in ops {
+            match op {
+                Skip(count) => {
+                    staying.extend((index..index + count).map(|index| index.to_string()));
+                    index += count;
+                    continue;
+                }
+                Remove(count) => {
+                    index += count;
+                    continue;
+                }
+                Move(position, ..) => placed.push((*position, index.to_string())),
+                Replace(node) => staying.push(label(node)),
+                Update(..) | Text(_) => staying.push(index.to_string()),
+            }
+            index += 1;
+        }
+        staying.extend((index..old_len).map(|index| index.to_string()));
+        placed.extend(
+            inserts
+                .iter()
+                .map(|(position, node)| (*position, label(node))),
+        );
+
+        let mut slots: Vec<Option<String>> = vec![None; staying.len() + placed.len()];
+        for (position, child) in placed {
+            slots[position] = Some(child);
+        }
+        let mut staying = staying.into_iter();
+        slots
+            .into_iter()
+            .map(|slot| slot.or_else(|| staying.next()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rewrites_keep_result_for_all_small_patches() {
+        const OLD_LEN: usize = 3;
+        const MAX_LEN: usize = 6;
+        let nodes: Vec<VNode> = (0..MAX_LEN)
+            .map(|index| text(format!("new {}", index)).done())
+            .collect();
+
+        // Each old child is skipped, removed or moved to one of the
+        // positions, and inserts take any subset of the positions.
+        let choices = 2 + MAX_LEN;
+        for children in 0..choices.pow(OLD_LEN as u32) {
+            for insert_set in 0..1 << MAX_LEN {
+                let mut ops = Vec::new();
+                let mut taken = Vec::new();
+                let mut staying = 0;
+                let mut choice = children;
+                for _ in 0..OLD_LEN {
+                    ops.push(match choice % choices {
+                        0 => {
+                            staying += 1;
+                            Skip(1)
+                        }
+                        1 => Remove(1),
+                        position => {
+                            taken.push(position - 2);
+                            Move(position - 2, None, None, None)
+                        }
+                    });
+                    choice /= choices;
+                }
+                let inserts: Vec<ChildInsert> = (0..MAX_LEN)
+                    .filter(|position| insert_set & (1 << position) != 0)
+                    .map(|position| (position, &nodes[position]))
+                    .collect();
+                taken.extend(inserts.iter().map(|(position, _)| *position));
+
+                let new_len = staying + taken.len();
+                taken.sort();
+                taken.dedup();
+                if taken.len() + staying != new_len
+                    || taken.iter().any(|position| *position >= new_len)
+                {
+                    continue;
+                }
+
+                let expected = apply(OLD_LEN, &ops, &inserts);
+                let (rewritten, rest) = rewrite(ops.clone(), inserts.clone());
+                assert_eq!(
+                    apply(OLD_LEN, &rewritten, &rest),
+                    expected,
+                    "{:?} {:?}",
+                    ops,
+                    inserts
+                );
+
+                // Rewriting is complete after one pass.
+                let again = rewrite(rewritten.clone(), rest.clone());
+                assert_eq!(again, (rewritten, rest));
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use node::VNode;
+use std::fmt;
+
+/// Position of a node in a tree, as child indices from the root.
+///
+/// Displayed as `/0/2` for the third child of the first child of the root,
+/// and as `/` for the root itself.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodePath(Vec<usize>);
+
+impl NodePath {
+    /// Path to the root node.
+    ///
+    pub fn root() -> Self {
+        NodePath(Vec::new())
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Path to the child at `index` of the node at this path.
+    ///
+    pub fn child(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(index);
+        path
+    }
+
+    /// Path to the parent node, or None for the root.
+    ///
+    pub fn parent(&self) -> Option<Self> {
+        if self.0.is_empty() {
+            None
+        } else {
+            Some(NodePath(self.0[..self.0.len() - 1].to_vec()))
+        }
+    }
+
+    /// Whether this path is `other` or a path to one of its descendants.
+    ///
+    pub fn starts_with(&self, other: &NodePath) -> bool {
+        self.0.starts_with(&other.0)
+    }
+
+    pub fn push(&mut self, index: usize) {
+        self.0.push(index);
+    }
+
+    pub fn pop(&mut self) -> Option<usize> {
+        self.0.pop()
+    }
+
+    /// Find the node at this path in a tree.
+    ///
+    pub fn resolve<'a>(&self, root: &'a VNode) -> Option<&'a VNode> {
+        let mut node = root;
+        for index in &self.0 {
+            node = match node.resolve() {
+                VNode::Element(element) => element.get_children().get(*index)?,
+                _ => return None,
+            };
+        }
+        Some(node)
+    }
+}
+
+impl From<Vec<usize>> for NodePath {
+    fn from(indices: Vec<usize>) -> Self {
+        NodePath(indices)
+    }
+}
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for index in &self.0 {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn resolve_and_display() {
+        let node = div().child(p()).child(ul().child(li().text("x"))).done();
+        let path = NodePath::root().child(1).child(0);
+
+        assert_eq!(path.to_string(), "/1/0");
+        assert_eq!(NodePath::root().to_string(), "/");
+        assert_eq!(path.resolve(&node), Some(&li().text("x").done()));
+        assert_eq!(path.parent().unwrap().to_string(), "/1");
+        assert!(path.starts_with(&path.parent().unwrap()) && path.starts_with(&path));
+        assert!(!path.starts_with(&NodePath::from(vec![0])));
+        assert_eq!(NodePath::from(vec![5]).resolve(&node), None);
+    }
+}
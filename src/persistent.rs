@@ -0,0 +1,252 @@
+//! # Persistent trees
+//!
+//! `PTree` is a tree where every child is a shared node. Its edits, such as
+//! `with_attr` or `with_child_replaced`, return a new tree which copies only
+//! the elements on the path to the edit and shares every other branch with
+//! the old tree. The differ skips shared nodes by pointer equality, so
+//! diffing two versions only descends along the edited paths.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::path::NodePath;
+//! use troy::persistent::PTree;
+//! use troy::tags::*;
+//!
+//! let old = PTree::new(
+//!     ul().child(li().text("a"))
+//!         .child(li().text("b"))
+//!         .done(),
+//! );
+//! let new = old
+//!     .with_attr(&NodePath::from(vec![1]), "class", "done")
+//!     .unwrap();
+//!
+//! // The first item is shared by both trees and skipped.
+//! let op = diff(&old.node(), &new.node());
+//! ```
+//!
+//! Edits return None when the path doesn't lead to an element of the tree,
+//! or the child index is out of range.
+//!
+use element::{AttrValue, VElement};
+use node::VNode;
+use path::NodePath;
+use std::mem;
+use sync::Shared;
+use types::CowString;
+
+#[derive(Debug, Clone)]
+pub struct PTree {
+    root: Shared<VNode>,
+}
+
+impl PTree {
+    /// Convert a tree, sharing each of its nodes.
+    ///
+    pub fn new(node: VNode) -> Self {
+        PTree { root: share(node) }
+    }
+
+    /// Root of the tree as a shared node, for diffing and rendering.
+    ///
+    pub fn node(&self) -> VNode {
+        VNode::Shared(self.root.clone())
+    }
+
+    /// Find the node at `path`.
+    ///
+    pub fn get(&self, path: &NodePath) -> Option<&VNode> {
+        path.resolve(&self.root)
+    }
+
+    /// New tree with `edit` applied to the element at `path`. Children the
+    /// edit adds are shared in the new tree.
+    ///
+    pub fn with_element<F>(&self, path: &NodePath, edit: F) -> Option<PTree>
+    where
+        F: FnOnce(&mut VElement),
+    {
+        Some(PTree {
+            root: edit_at(&self.root, path.indices(), edit)?,
+        })
+    }
+
+    /// New tree with an attribute of the element at `path` set, see
+    /// `VElement::set_attr`.
+    ///
+    pub fn with_attr<N, V>(&self, path: &NodePath, name: N, value: V) -> Option<PTree>
+    where
+        N: Into<CowString>,
+        V: Into<AttrValue>,
+    {
+        self.with_element(path, |element| element.set_attr(name, value))
+    }
+
+    /// New tree with an attribute of the element at `path` removed.
+    ///
+    pub fn without_attr(&self, path: &NodePath, name: &str) -> Option<PTree> {
+        self.with_element(path, |element| {
+            element.remove_attr(name);
+        })
+    }
+
+    /// New tree with the child at `index` of the element at `path` replaced
+    /// by `node`.
+    ///
+    pub fn with_child_replaced(&self, path: &NodePath, index: usize, node: VNode) -> Option<PTree> {
+        if index >= self.children_len(path)? {
+            return None;
+        }
+        self.with_element(path, |element| element.get_children_mut()[index] = node)
+    }
+
+    /// New tree with `node` inserted as the child at `index` of the element
+    /// at `path`.
+    ///
+    pub fn with_child_inserted(&self, path: &NodePath, index: usize, node: VNode) -> Option<PTree> {
+        if index > self.children_len(path)? {
+            return None;
+        }
+        self.with_element(path, |element| {
+            element.get_children_mut().insert(index, node)
+        })
+    }
+
+    /// New tree without the child at `index` of the element at `path`.
+    ///
+    pub fn with_child_removed(&self, path: &NodePath, index: usize) -> Option<PTree> {
+        if index >= self.children_len(path)? {
+            return None;
+        }
+        self.with_element(path, |element| {
+            element.get_children_mut().remove(index);
+        })
+    }
+
+    fn children_len(&self, path: &NodePath) -> Option<usize> {
+        match self.get(path)?.resolve() {
+            VNode::Element(element) => Some(element.get_children().len()),
+            _ => None,
+        }
+    }
+}
+
+impl From<VNode> for PTree {
+    fn from(node: VNode) -> Self {
+        PTree::new(node)
+    }
+}
+
+/// Share the node and, for elements, each of its descendants.
+///
+fn share(node: VNode) -> Shared<VNode> {
+    match node {
+        VNode::Shared(shared) => shared,
+        VNode::Element(mut element) => {
+            share_children(&mut element);
+            Shared::new(VNode::Element(element))
+        }
+        node => Shared::new(node),
+    }
+}
+
+fn share_children(element: &mut VElement) {
+    let children = mem::take(element.get_children_mut());
+    *element.get_children_mut() = children
+        .into_iter()
+        .map(|child| VNode::Shared(share(child)))
+        .collect();
+}
+
+/// Copy the elements on the path to the edited one, sharing the rest.
+///
+fn edit_at<F>(node: &Shared<VNode>, indices: &[usize], edit: F) -> Option<Shared<VNode>>
+where
+    F: FnOnce(&mut VElement),
+{
+    let element = match &**node {
+        VNode::Element(element) => element,
+        _ => return None,
+    };
+    let element = match indices.split_first() {
+        None => {
+            let mut element = element.clone();
+            edit(&mut element);
+            share_children(&mut element);
+            element
+        }
+        Some((index, rest)) => {
+            let child = match element.get_children().get(*index)? {
+                VNode::Shared(child) => edit_at(child, rest, edit)?,
+                _ => return None,
+            };
+            let mut element = element.clone();
+            element.get_children_mut()[*index] = VNode::Shared(child);
+            element
+        }
+    };
+    Some(Shared::new(VNode::Element(element)))
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+    use text::text;
+
+    fn shared(node: &VNode) -> &Shared<VNode> {
+        match node {
+            VNode::Shared(shared) => shared,
+            _ => panic!("node isn't shared"),
+        }
+    }
+
+    #[test]
+    fn edits_share_untouched_branches() {
+        #[rustfmt::skip]
+        let old = PTree::new(
+            div()
+                .child(ul().child(li().text("a")).child(li().text("b")))
+                .child(p().text("footer"))
+                .done(),
+        );
+        let path = NodePath::from(vec![0, 1]);
+        let new = old.with_attr(&path, "class", "done").unwrap();
+
+        let footer = NodePath::from(vec![1]);
+        assert!(Shared::ptr_eq(
+            shared(old.get(&footer).unwrap()),
+            shared(new.get(&footer).unwrap())
+        ));
+
+        // The footer is skipped as a trailing Skip.
+        assert_eq!(
+            format!("{:?}", diff(&old.node(), &new.node())),
+            "Update(None, Some([Update(None, Some([Skip(1), \
+             Update(Some([InsertClass(\"done\")]), None, None)]), None)]), None)"
+        );
+    }
+
+    #[test]
+    fn child_edits() {
+        let old = PTree::new(ul().child(li().text("a")).done());
+        let root = NodePath::root();
+
+        let new = old.with_child_inserted(&root, 1, text("b").done()).unwrap();
+        let new = new.with_child_replaced(&root, 0, text("c").done()).unwrap();
+        assert_eq!(new.node().text_content(), "cb");
+        assert_eq!(
+            new.with_child_removed(&root, 0)
+                .unwrap()
+                .node()
+                .text_content(),
+            "b"
+        );
+
+        assert!(old.with_child_removed(&root, 1).is_none());
+        assert!(old
+            .with_attr(&NodePath::from(vec![0, 0]), "id", "x")
+            .is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! # Diff pipeline
+//!
+//! Ready-made pattern for multi-threaded applications: a worker thread owns
+//! the previous tree, receives new trees over a channel, diffs them and
+//! sends owned patches back. Requires the `sync` feature, which makes trees
+//! `Send`.
+//!
+//! Both channels are bounded. A producer which submits trees faster than
+//! they are diffed blocks, and a worker whose patches aren't received blocks
+//! too. When several trees are waiting, the worker diffs only the latest one
+//! and reports the others as skipped: a patch against a tree which is about
+//! to be replaced is wasted work.
+//!
+//! ```
+//! use troy::patch::OwnedOp;
+//! use troy::pipeline::Pipeline;
+//! use troy::tags::*;
+//!
+//! let pipeline = Pipeline::new(2);
+//! pipeline.submit(p().text("Loading").done()).unwrap();
+//!
+//! let frame = pipeline.recv().unwrap();
+//! assert!(matches!(frame.op, OwnedOp::Replace(_)));
+//!
+//! pipeline.submit(p().text("Done").done()).unwrap();
+//! assert!(matches!(pipeline.recv().unwrap().op, OwnedOp::Update(..)));
+//! ```
+//!
+use diff::diff;
+use node::VNode;
+use patch::{OwnedOp, Stamp};
+use std::sync::mpsc::{
+    self, Receiver, RecvError, SendError, SyncSender, TryRecvError, TrySendError,
+};
+use std::thread;
+
+/// Patch produced by the worker.
+///
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    /// Diff from the previous diffed tree, or `Replace` with the first tree.
+    pub op: OwnedOp,
+    /// Number of trees submitted since the previous frame which were
+    /// replaced by a newer tree before they were diffed.
+    pub skipped: usize,
+    /// Generations of the trees the patch was diffed between, counting all
+    /// submitted trees, skipped ones included.
+    pub stamp: Stamp,
+}
+
+/// Channels of a worker thread. The worker stops when the pipeline and all
+/// submitters are dropped.
+///
+pub struct Pipeline {
+    trees: SyncSender<VNode>,
+    frames: Receiver<Frame>,
+}
+
+impl Pipeline {
+    /// Start the worker. Up to `capacity` trees and `capacity` frames wait
+    /// in the channels before the sender blocks.
+    ///
+    pub fn new(capacity: usize) -> Self {
+        let (trees, tree_receiver) = mpsc::sync_channel(capacity);
+        let (frame_sender, frames) = mpsc::sync_channel(capacity);
+        thread::spawn(move || run(&tree_receiver, &frame_sender));
+        Pipeline { trees, frames }
+    }
+
+    /// Sender for submitting trees from other threads.
+    ///
+    pub fn submitter(&self) -> SyncSender<VNode> {
+        self.trees.clone()
+    }
+
+    /// Submit a new tree, blocking while the channel is full. The tree is
+    /// returned if the worker stopped.
+    ///
+    pub fn submit(&self, tree: VNode) -> Result<(), Box<SendError<VNode>>> {
+        self.trees.send(tree).map_err(Box::new)
+    }
+
+    /// Submit a new tree if there is room in the channel.
+    ///
+    pub fn try_submit(&self, tree: VNode) -> Result<(), Box<TrySendError<VNode>>> {
+        self.trees.try_send(tree).map_err(Box::new)
+    }
+
+    /// Wait for the next frame.
+    ///
+    pub fn recv(&self) -> Result<Frame, RecvError> {
+        self.frames.recv()
+    }
+
+    /// Next frame if one is ready.
+    ///
+    pub fn try_recv(&self) -> Result<Frame, TryRecvError> {
+        self.frames.try_recv()
+    }
+}
+
+/// Worker loop, which runs until either channel is closed.
+///
+fn run(trees: &Receiver<VNode>, frames: &SyncSender<Frame>) {
+    let mut current: Option<VNode> = None;
+    let mut generation = 0;
+
+    while let Ok(mut tree) = trees.recv() {
+        let mut skipped = 0;
+        while let Ok(newer) = trees.try_recv() {
+            tree = newer;
+            skipped += 1;
+        }
+        let stamp = Stamp {
+            base: generation,
+            generation: generation + skipped as u64 + 1,
+        };
+        generation = stamp.generation;
+
+        let op = match &current {
+            Some(old) => OwnedOp::from(diff(old, &tree)),
+            None => OwnedOp::Replace(tree.clone()),
+        };
+        current = Some(tree);
+
+        if frames.send(Frame { op, skipped, stamp }).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn only_latest_tree_is_diffed() {
+        let (trees, tree_receiver) = mpsc::sync_channel(4);
+        let (frame_sender, frames) = mpsc::sync_channel(4);
+        for count in 0..3 {
+            trees.send(p().text(count.to_string()).done()).unwrap();
+        }
+        drop(trees);
+
+        run(&tree_receiver, &frame_sender);
+
+        assert_eq!(
+            frames.try_recv(),
+            Ok(Frame {
+                op: OwnedOp::Replace(p().text("2").done()),
+                skipped: 2,
+                stamp: Stamp {
+                    base: 0,
+                    generation: 3
+                },
+            })
+        );
+        assert!(frames.try_recv().is_err());
+    }
+
+    #[test]
+    fn frames_account_for_all_trees() {
+        let pipeline = Pipeline::new(1);
+        let submitter = pipeline.submitter();
+        let producer = thread::spawn(move || {
+            for count in 0..20 {
+                submitter.send(p().text(count.to_string()).done()).unwrap();
+            }
+        });
+
+        let mut trees = 0;
+        while trees < 20 {
+            trees += pipeline.recv().unwrap().skipped + 1;
+        }
+        producer.join().unwrap();
+        assert_eq!(trees, 20);
+    }
+}
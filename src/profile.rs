@@ -0,0 +1,160 @@
+//! # Diff profiles
+//!
+//! With `DiffOptions::profile` set, `diff::diff_with_profile` records how
+//! long the diff of each node took and how many operations it produced, in
+//! a `DiffProfile` tree which mirrors the diffed nodes. Components are
+//! nodes of the profile with the nodes they render as their child, so the
+//! component whose diff is slow is found without an external profiler.
+//!
+//! ```
+//! use troy::diff::{diff_with_profile, DiffOptions};
+//! use troy::tags::*;
+//!
+//! let old = ul().child(li().text("a")).done();
+//! let new = ul().child(li().text("b")).done();
+//! let options = DiffOptions {
+//!     profile: true,
+//!     ..DiffOptions::default()
+//! };
+//!
+//! let (_, profile) = diff_with_profile(&old, &new, &options);
+//! let profile = profile.unwrap();
+//! assert_eq!(profile.label, "ul");
+//! assert_eq!(profile.children[0].children[0].label, "#text");
+//! assert_eq!(profile.ops, 1);
+//! ```
+//!
+//! Times and operation counts of a node include its children. Nodes which
+//! are skipped without being compared, such as static subtrees, have no
+//! children in the profile.
+//!
+use diff::{AttrDiff, ChildDiff, ChildInserts, NodeOp};
+use node::VNode;
+use std::time::{Duration, Instant};
+
+/// Diff of one node pair.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffProfile {
+    /// Tag of an element, type name of a component, `#text` or `#shared`.
+    pub label: String,
+    pub elapsed: Duration,
+    /// Operations of the patch of the node, counting attribute changes and
+    /// inserts.
+    pub ops: usize,
+    pub children: Vec<DiffProfile>,
+}
+
+impl DiffProfile {
+    /// Time spent on the node itself, without its children.
+    ///
+    pub fn self_elapsed(&self) -> Duration {
+        let children = self.children.iter().map(|child| child.elapsed).sum();
+        self.elapsed.saturating_sub(children)
+    }
+
+    /// Path from this node down the slowest child at each level.
+    ///
+    pub fn hottest_path(&self) -> Vec<&DiffProfile> {
+        let mut path = vec![self];
+        let mut node = self;
+        while let Some(child) = node.children.iter().max_by_key(|child| child.elapsed) {
+            path.push(child);
+            node = child;
+        }
+        path
+    }
+}
+
+/// Profiles of the diffs in progress, innermost last.
+///
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    stack: Vec<(DiffProfile, Instant)>,
+    done: Vec<DiffProfile>,
+}
+
+impl Recorder {
+    pub(crate) fn enter(&mut self, new: &VNode) {
+        let label = match new {
+            VNode::Element(element) => element.get_tag().to_string(),
+            VNode::Text(_) => "#text".to_string(),
+            VNode::Component(component) => component.get_type_name().to_string(),
+            VNode::Shared(_) => "#shared".to_string(),
+        };
+        let profile = DiffProfile {
+            label,
+            ..DiffProfile::default()
+        };
+        self.stack.push((profile, Instant::now()));
+    }
+
+    pub(crate) fn exit(&mut self, op: &NodeOp) {
+        let (mut profile, start) = self.stack.pop().expect("profile was entered");
+        profile.elapsed = start.elapsed();
+        profile.ops = count_ops(op);
+        match self.stack.last_mut() {
+            Some((parent, _)) => parent.children.push(profile),
+            None => self.done.push(profile),
+        }
+    }
+
+    /// Profile of the outermost diff.
+    ///
+    pub(crate) fn finish(mut self) -> Option<DiffProfile> {
+        self.done.pop()
+    }
+}
+
+fn count_ops(op: &NodeOp) -> usize {
+    match op {
+        NodeOp::Skip(_) => 0,
+        NodeOp::Remove(_) | NodeOp::Replace(_) | NodeOp::Text(_) => 1,
+        NodeOp::Update(attrs, children, inserts) => count_update(attrs, children, inserts),
+        NodeOp::Move(_, attrs, children, inserts) => 1 + count_update(attrs, children, inserts),
+    }
+}
+
+fn count_update(attrs: &AttrDiff, children: &ChildDiff, inserts: &ChildInserts) -> usize {
+    attrs.as_ref().map_or(0, Vec::len)
+        + children.iter().flatten().map(count_ops).sum::<usize>()
+        + inserts.as_ref().map_or(0, Vec::len)
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use diff::{diff_with_profile, DiffOptions};
+    use tags::*;
+
+    #[test]
+    fn profile_mirrors_diffed_nodes() {
+        #[rustfmt::skip]
+        let old = div()
+            .child(p().class("a").text("x"))
+            .child(ul().child(li()))
+            .done();
+        #[rustfmt::skip]
+        let new = div()
+            .child(p().class("b").text("y"))
+            .child(ol())
+            .done();
+        let options = DiffOptions {
+            profile: true,
+            ..DiffOptions::default()
+        };
+
+        let (_, profile) = diff_with_profile(&old, &new, &options);
+        let profile = profile.unwrap();
+        let labels: Vec<&str> = profile.children.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, ["p", "ol"]);
+        assert_eq!(profile.children[0].ops, 3);
+        assert_eq!(profile.children[0].children[0].label, "#text");
+        assert!(profile.children[1].children.is_empty());
+        assert_eq!(profile.ops, 4);
+        assert_eq!(profile.hottest_path()[0].label, "div");
+        assert!(profile.self_elapsed() <= profile.elapsed);
+
+        let (_, profile) = diff_with_profile(&old, &new, &DiffOptions::default());
+        assert_eq!(profile, None);
+    }
+}
@@ -0,0 +1,524 @@
+//! # HTML rendering
+//!
+//! Renders a VNode tree into compact HTML. Unlike `Display`, which is meant
+//! for reading trees, the output is escaped and can be sent to a browser.
+//!
+//! Classes and attributes are sorted so the output is deterministic. Content
+//! of raw text elements (`script`, `style`) is not escaped, except for `</`
+//! which is written as `<\/` so it can't close the element. Boolean
+//! attributes set to an empty value or their own name are written without
+//! a value. Quotes, escaped characters and whether empty values are written
+//! are set with `RenderOptions`, for consumers such as email clients, XML
+//! parsers or minifiers.
+//!
+//! With `RenderOptions::check_escaping`, text and attribute values which
+//! look escaped already, like `&amp;lt;` from legacy template output, are
+//! reported as `diagnostics::Kind::DoubleEscaped` with their path.
+//!
+//! Content rendered without escaping goes through `RenderOptions::raw_policy`
+//! when one is set, so sanitizing or auditing it happens in one place.
+//!
+//! Whitespace policy applies to text outside raw text elements and outside
+//! elements where whitespace is significant (`pre`, `textarea`, `code`).
+//!
+use attrs;
+use diagnostics::{self, Kind};
+use error::Error;
+use escape::{escape_attr_with, escape_raw, escape_text_with, looks_escaped, Escaping, Quote};
+use node::VNode;
+use path::NodePath;
+use std::fmt;
+use sync::{MaybeSync, Shared};
+use types::CowString;
+use url::{encode_url, is_url_attribute, UrlPolicy};
+use whitespace::{self, Whitespace};
+
+const RAW_TEXT_TAGS: &[&str] = &["script", "style"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderOptions {
+    /// URL attributes with schemes not allowed by the policy are not
+    /// rendered. No URLs are dropped when not set.
+    pub url_policy: Option<UrlPolicy>,
+    /// Whitespace policy for text nodes.
+    pub whitespace: Whitespace,
+    /// Add `data-troy-path` with the path of each element, as in patches
+    /// and `path::NodePath`, and `data-troy-key` with its key, to find
+    /// elements of the tree when inspecting the rendered page.
+    pub debug_ids: bool,
+    /// Report text and attribute values containing character references to
+    /// the current `diagnostics::Diagnostics` collection, since they render
+    /// escaped twice. Meant for debugging.
+    pub check_escaping: bool,
+    /// Content Security Policy nonce set as the `nonce` attribute of every
+    /// `script` and `style` element, replacing their own.
+    pub nonce: Option<CowString>,
+    /// Check of content rendered without escaping, the text of `script` and
+    /// `style` elements.
+    pub raw_policy: Option<RawPolicy>,
+    /// Quote around attribute values, double by default.
+    pub quote: Quote,
+    /// Characters escaped in text and attribute values.
+    pub escaping: Escaping,
+    /// Write empty attribute values as `name=""` instead of bare names, as
+    /// XML needs. Boolean attributes set to their own name keep the value
+    /// then, as in `checked="checked"`.
+    pub empty_values: bool,
+    /// Fail on trees with nodes nested deeper than this, see
+    /// `try_render_with`. Zero, the default, doesn't limit.
+    pub max_depth: usize,
+    /// Fail on trees with more nodes than this. Zero, the default, doesn't
+    /// limit.
+    pub max_nodes: usize,
+}
+
+trait RawCheck: Fn(&str, &str) -> Option<String> + MaybeSync {}
+
+impl<F: Fn(&str, &str) -> Option<String> + MaybeSync> RawCheck for F {}
+
+/// Callback which gets the tag and the content of each raw text element
+/// before it's rendered, and returns the content to render, or `None` to
+/// render the element empty. It can sanitize, allow-list or log content.
+///
+#[derive(Clone)]
+pub struct RawPolicy(Shared<dyn RawCheck>);
+
+impl RawPolicy {
+    pub fn new<F>(check: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<String> + MaybeSync + 'static,
+    {
+        RawPolicy(Shared::new(check))
+    }
+
+    pub fn check(&self, tag: &str, content: &str) -> Option<String> {
+        (self.0)(tag, content)
+    }
+}
+
+impl fmt::Debug for RawPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RawPolicy")
+    }
+}
+
+impl PartialEq for RawPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        Shared::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl RenderOptions {
+    /// Set the CSP nonce of `script` and `style` elements. The nonce changes
+    /// with each response, so it's given here instead of in views.
+    ///
+    pub fn with_nonce(mut self, nonce: &str) -> Self {
+        self.nonce = Some(nonce.to_string().into());
+        self
+    }
+}
+
+/// Render tree into HTML with default options.
+///
+pub fn render_to_string(node: &VNode) -> String {
+    render_with(node, &RenderOptions::default())
+}
+
+/// Render tree into HTML.
+///
+pub fn render_with(node: &VNode, options: &RenderOptions) -> String {
+    let mut out = String::new();
+    render_node(
+        &mut out,
+        node,
+        options,
+        Context::Normal,
+        &mut NodePath::root(),
+    );
+    out
+}
+
+/// Render tree into HTML after checking it against `max_depth` and
+/// `max_nodes`, for trees from untrusted sources. `render_with` doesn't
+/// check the limits.
+///
+pub fn try_render_with(node: &VNode, options: &RenderOptions) -> Result<String, Error> {
+    node.check_limits(options.max_depth, options.max_nodes)?;
+    Ok(render_with(node, options))
+}
+
+/// How text is rendered inside the current element.
+///
+#[derive(Clone, Copy, PartialEq)]
+enum Context<'a> {
+    Normal,
+    Preserved,
+    /// Inside a raw text element with the tag.
+    Raw(&'a str),
+}
+
+fn render_node(
+    out: &mut String,
+    node: &VNode,
+    options: &RenderOptions,
+    context: Context<'_>,
+    path: &mut NodePath,
+) {
+    match node {
+        VNode::Text(text) => match context {
+            Context::Raw(tag) => match &options.raw_policy {
+                Some(policy) => {
+                    if let Some(content) = policy.check(tag, text.get_content()) {
+                        out.push_str(&escape_raw(&content));
+                    }
+                }
+                None => out.push_str(&escape_raw(text.get_content())),
+            },
+            Context::Preserved => {
+                check_escaping(options, text.get_content(), "text", path);
+                out.push_str(&escape_text_with(text.get_content(), options.escaping));
+            }
+            Context::Normal => {
+                check_escaping(options, text.get_content(), "text", path);
+                let content = whitespace::apply(text.get_content(), options.whitespace);
+                out.push_str(&escape_text_with(&content, options.escaping));
+            }
+        },
+        VNode::Element(element) => {
+            let tag = element.get_tag();
+            out.push('<');
+            out.push_str(tag);
+
+            let mut classes: Vec<&CowString> = element.get_classes().iter().collect();
+            classes.sort();
+            if !classes.is_empty() {
+                let classes: Vec<&str> = classes.iter().map(|c| c.as_ref()).collect();
+                write_attr(out, options, "class", &classes.join(" "));
+            }
+
+            let mut attr_pairs: Vec<(&CowString, &CowString)> =
+                element.get_attributes().iter().collect();
+            let nonce_name: CowString = "nonce".into();
+            if RAW_TEXT_TAGS.contains(&tag) {
+                if let Some(nonce) = &options.nonce {
+                    attr_pairs.retain(|(name, _)| *name != "nonce");
+                    attr_pairs.push((&nonce_name, nonce));
+                }
+            }
+            attr_pairs.sort_by_key(|(name, _)| *name);
+            for (name, value) in attr_pairs {
+                let value = if is_url_attribute(name) {
+                    if let Some(policy) = &options.url_policy {
+                        if !policy.allows(value) {
+                            continue;
+                        }
+                    }
+                    encode_url(value)
+                } else {
+                    value.as_ref().into()
+                };
+
+                check_escaping(options, &value, name, path);
+                if options.empty_values
+                    || !value.is_empty() && !attrs::is_canonical_boolean(name, &value)
+                {
+                    write_attr(out, options, name, &value);
+                } else {
+                    out.push(' ');
+                    out.push_str(name);
+                }
+            }
+
+            if options.debug_ids {
+                write_attr(out, options, "data-troy-path", &path.to_string());
+                if let Some(key) = element.get_key() {
+                    write_attr(out, options, "data-troy-key", &key.to_string());
+                }
+            }
+            out.push('>');
+
+            if element.is_void() {
+                return;
+            }
+
+            let context = if RAW_TEXT_TAGS.contains(&tag) {
+                Context::Raw(tag)
+            } else if context == Context::Preserved || whitespace::is_preserved(tag) {
+                Context::Preserved
+            } else {
+                Context::Normal
+            };
+            for (index, child) in element.get_children().iter().enumerate() {
+                path.push(index);
+                render_node(out, child, options, context, path);
+                path.pop();
+            }
+
+            out.push_str("</");
+            out.push_str(tag);
+            out.push('>');
+        }
+        VNode::Component(_) | VNode::Shared(_) => {
+            render_node(out, node.resolve(), options, context, path)
+        }
+    }
+}
+
+/// Write an attribute with a quoted value.
+///
+fn write_attr(out: &mut String, options: &RenderOptions, name: &str, value: &str) {
+    let quote = options.quote.as_char();
+    out.push(' ');
+    out.push_str(name);
+    out.push('=');
+    out.push(quote);
+    out.push_str(&escape_attr_with(value, options.quote, options.escaping));
+    out.push(quote);
+}
+
+/// Report the value if it looks escaped and the check is on.
+///
+fn check_escaping(options: &RenderOptions, value: &str, what: &str, path: &NodePath) {
+    if options.check_escaping && looks_escaped(value) {
+        let message = format!("{} at {} looks escaped already: {:?}", what, path, value);
+        diagnostics::report(Kind::DoubleEscaped, message);
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diagnostics::Diagnostics;
+    use tags::*;
+
+    #[test]
+    fn render_escaped() {
+        #[rustfmt::skip]
+        let node = div().class_list("b a").attr("title", "\"x\" & y").attr("hidden", "")
+            .child(p().text("1 < 2"))
+            .child(br())
+            .child(script().text("if (a < b) {}"))
+            .done();
+
+        assert_eq!(
+            render_to_string(&node),
+            "<div class=\"a b\" hidden title=\"&quot;x&quot; &amp; y\">\
+             <p>1 &lt; 2</p><br><script>if (a < b) {}</script></div>"
+        );
+    }
+
+    #[test]
+    fn render_urls() {
+        #[rustfmt::skip]
+        let node = div()
+            .child(a().attr("href", "/a b?x=1&y=2"))
+            .child(a().attr("href", "javascript:alert(1)"))
+            .done();
+
+        assert_eq!(
+            render_to_string(&node),
+            "<div><a href=\"/a%20b?x=1&amp;y=2\"></a><a href=\"javascript:alert(1)\"></a></div>"
+        );
+
+        let options = RenderOptions {
+            url_policy: Some(UrlPolicy::default()),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<div><a href=\"/a%20b?x=1&amp;y=2\"></a><a></a></div>"
+        );
+    }
+
+    #[test]
+    fn render_whitespace() {
+        #[rustfmt::skip]
+        let node = div()
+            .child(p().text("  a\n  b  "))
+            .child(pre().child(code().text("  a\n  b  ")))
+            .done();
+
+        let options = RenderOptions {
+            whitespace: Whitespace::Trim,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<div><p>a b</p><pre><code>  a\n  b  </code></pre></div>"
+        );
+    }
+
+    #[test]
+    fn render_debug_ids() {
+        let node = ul().child(li().key("a&b")).child(li().key(2)).done();
+
+        let options = RenderOptions {
+            debug_ids: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<ul data-troy-path=\"/\">\
+             <li data-troy-path=\"/0\" data-troy-key=\"a&amp;b\"></li>\
+             <li data-troy-path=\"/1\" data-troy-key=\"2\"></li></ul>"
+        );
+    }
+
+    #[test]
+    fn render_boolean_attributes() {
+        #[rustfmt::skip]
+        let node = form()
+            .child(input().attr("checked", "checked").attr("disabled", "").attr("value", "value"))
+            .child(option().attr("selected", "SELECTED").attr("hidden", "until-found"))
+            .done();
+
+        assert_eq!(
+            render_to_string(&node),
+            "<form><input checked disabled value=\"value\">\
+             <option hidden=\"until-found\" selected></option></form>"
+        );
+
+        #[rustfmt::skip]
+        let node = input().attr("disabled", false).attr("draggable", false).done();
+        assert_eq!(render_to_string(&node), "<input draggable=\"false\">");
+        let node = button().attr("disabled", true).done();
+        assert_eq!(render_to_string(&node), "<button disabled></button>");
+    }
+
+    #[test]
+    fn render_check_escaping() {
+        #[rustfmt::skip]
+        let node = div().attr("title", "Tom &amp; Jerry")
+            .child(p().text("&lt;div&gt;"))
+            .child(p().text("Tom & Jerry; <b>"))
+            .done();
+
+        let options = RenderOptions {
+            check_escaping: true,
+            ..RenderOptions::default()
+        };
+        let (html, diagnostics) = Diagnostics::collect(|| render_with(&node, &options));
+        assert_eq!(html, render_to_string(&node));
+        assert_eq!(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "DoubleEscaped: title at / looks escaped already: \"Tom &amp; Jerry\"",
+                "DoubleEscaped: text at /0/0 looks escaped already: \"&lt;div&gt;\"",
+            ]
+        );
+
+        let (_, diagnostics) = Diagnostics::collect(|| render_to_string(&node));
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn render_nonce() {
+        #[rustfmt::skip]
+        let node = div()
+            .child(script().attr("src", "/app.js").attr("nonce", "old"))
+            .child(style().text("p {}"))
+            .child(p().attr("nonce", "kept"))
+            .done();
+
+        let options = RenderOptions::default().with_nonce("r4nd\"");
+        assert_eq!(
+            render_with(&node, &options),
+            "<div><script nonce=\"r4nd&quot;\" src=\"/app.js\"></script>\
+             <style nonce=\"r4nd&quot;\">p {}</style><p nonce=\"kept\"></p></div>"
+        );
+    }
+
+    #[test]
+    fn render_raw_policy() {
+        #[rustfmt::skip]
+        let node = div().child(script().text("alert(1)")).child(style().text("p {}")).done();
+
+        let options = RenderOptions {
+            raw_policy: Some(RawPolicy::new(|tag, content| match tag {
+                "style" => Some(content.to_string()),
+                _ => None,
+            })),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<div><script></script><style>p {}</style></div>"
+        );
+    }
+
+    #[test]
+    fn render_raw_close_tag() {
+        let node = script()
+            .text("</script><img src=x onerror=alert(1)>")
+            .done();
+
+        assert_eq!(
+            render_to_string(&node),
+            "<script><\\/script><img src=x onerror=alert(1)></script>"
+        );
+
+        let options = RenderOptions {
+            raw_policy: Some(RawPolicy::new(|_, content| Some(content.to_string()))),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<script><\\/script><img src=x onerror=alert(1)></script>"
+        );
+    }
+
+    #[test]
+    fn render_quoting_and_escaping() {
+        #[rustfmt::skip]
+        let node = p().attr("title", "it's \"5 > 3\"").attr("hidden", "").attr("checked", "checked")
+            .text("5 > 3 & caf\u{e9}")
+            .done();
+
+        let options = RenderOptions {
+            quote: Quote::Single,
+            escaping: Escaping::Minimal,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<p checked hidden title='it&#39;s \"5 > 3\"'>5 > 3 &amp; caf\u{e9}</p>"
+        );
+
+        let options = RenderOptions {
+            escaping: Escaping::Aggressive,
+            empty_values: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_with(&node, &options),
+            "<p checked=\"checked\" hidden=\"\" title=\"it&#39;s &quot;5 &gt; 3&quot;\">\
+             5 &gt; 3 &amp; caf&#xe9;</p>"
+        );
+    }
+
+    #[test]
+    fn render_limits() {
+        let node = ul().child(li().text("a")).child(li().text("b")).done();
+        let options = RenderOptions {
+            max_nodes: 4,
+            ..RenderOptions::default()
+        };
+
+        assert_eq!(
+            try_render_with(&node, &options),
+            Err(Error::TooManyNodes(4))
+        );
+        let options = RenderOptions {
+            max_depth: 3,
+            max_nodes: 5,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            try_render_with(&node, &options),
+            Ok(render_to_string(&node))
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! # Reusing the last frame
+//!
+//! Views often rebuild regions whose inputs didn't change since the last
+//! frame, only for the differ to find them equal. `ReuseBuilder` looks up a
+//! region of the last frame's tree by path or key, and `same_as_last`
+//! splices it into the new tree as a shared node instead of building it
+//! again.
+//!
+//! Regions built with `region` are shared nodes. When such a region is
+//! reused, the new tree holds the same pointer as the last one and the
+//! differ produces `Skip(1)` for it without descending into it.
+//!
+//! ```
+//! use troy::diff::{diff, NodeOp};
+//! use troy::node::VNode;
+//! use troy::reuse::ReuseBuilder;
+//! use troy::tags::*;
+//!
+//! fn view(last: Option<&VNode>, items_changed: bool) -> VNode {
+//!     let reuse = ReuseBuilder::new(last);
+//!     let list = reuse.region("items", !items_changed, || {
+//!         ul().key("items").child(li().text("a")).done()
+//!     });
+//!     div().child_node(list).done()
+//! }
+//!
+//! let old = view(None, true);
+//! let new = view(Some(&old), false);
+//!
+//! assert_eq!(diff(&old, &new), NodeOp::Skip(1));
+//! ```
+//!
+use key::Key;
+use node::VNode;
+use path::NodePath;
+use sync::Shared;
+
+/// Region of the last frame's tree: the node at a path, or the first node
+/// with a key in depth-first order.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Region {
+    Path(NodePath),
+    Key(Key),
+}
+
+impl From<NodePath> for Region {
+    fn from(path: NodePath) -> Self {
+        Region::Path(path)
+    }
+}
+
+impl From<Key> for Region {
+    fn from(key: Key) -> Self {
+        Region::Key(key)
+    }
+}
+
+impl From<&'static str> for Region {
+    fn from(key: &'static str) -> Self {
+        Region::Key(key.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReuseBuilder<'last> {
+    last: Option<&'last VNode>,
+}
+
+impl<'last> ReuseBuilder<'last> {
+    /// Builder reusing regions of `last`, or nothing for the first frame.
+    ///
+    pub fn new(last: Option<&'last VNode>) -> Self {
+        ReuseBuilder { last }
+    }
+
+    /// The region of the last frame as a shared node, or None if the last
+    /// frame doesn't have it. Regions which aren't shared nodes already are
+    /// copied into one, and are diffed as usual.
+    ///
+    pub fn same_as_last<R>(&self, region: R) -> Option<VNode>
+    where
+        R: Into<Region>,
+    {
+        let node = match region.into() {
+            Region::Path(path) => path.resolve(self.last?)?,
+            Region::Key(key) => find_key(self.last?, &key)?,
+        };
+        Some(match node {
+            VNode::Shared(shared) => VNode::Shared(shared.clone()),
+            node => VNode::Shared(Shared::new(node.clone())),
+        })
+    }
+
+    /// Reuse the region of the last frame when its inputs are `unchanged`,
+    /// or build it and share the result for the next frame.
+    ///
+    pub fn region<R, F>(&self, region: R, unchanged: bool, build: F) -> VNode
+    where
+        R: Into<Region>,
+        F: FnOnce() -> VNode,
+    {
+        if unchanged {
+            if let Some(node) = self.same_as_last(region) {
+                return node;
+            }
+        }
+        match build() {
+            VNode::Shared(shared) => VNode::Shared(shared),
+            node => VNode::Shared(Shared::new(node)),
+        }
+    }
+}
+
+/// First node with `key` in depth-first order, the shared node itself
+/// rather than the node it stands for.
+///
+fn find_key<'a>(node: &'a VNode, key: &Key) -> Option<&'a VNode> {
+    if node.key() == Some(key) {
+        return Some(node);
+    }
+    match node.resolve() {
+        VNode::Element(element) => element
+            .get_children()
+            .iter()
+            .find_map(|child| find_key(child, key)),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    fn reuses_by_path_and_key() {
+        #[rustfmt::skip]
+        let last = div()
+            .child(p().text("intro"))
+            .child(section().child(ul().key("list").child(li().text("a"))))
+            .done();
+        let reuse = ReuseBuilder::new(Some(&last));
+
+        let intro = reuse.same_as_last(NodePath::from(vec![0])).unwrap();
+        assert_eq!(intro.text_content(), "intro");
+        let list = reuse.same_as_last("list").unwrap();
+        assert_eq!(list.key(), Some(&Key::from("list")));
+
+        assert!(reuse.same_as_last("missing").is_none());
+        assert!(ReuseBuilder::new(None).same_as_last("list").is_none());
+    }
+
+    #[test]
+    fn changed_regions_are_rebuilt() {
+        let view = |last: Option<&VNode>, text: &'static str, unchanged: bool| {
+            let reuse = ReuseBuilder::new(last);
+            let body = reuse.region("body", unchanged, || p().key("body").text(text).done());
+            div().child_node(body).done()
+        };
+
+        let old = view(None, "a", false);
+        let new = view(Some(&old), "b", false);
+        assert_eq!(new.text_content(), "b");
+        assert_ne!(format!("{:?}", diff(&old, &new)), "Skip(1)");
+
+        let newer = view(Some(&new), "c", true);
+        assert_eq!(newer.text_content(), "b");
+    }
+}
@@ -0,0 +1,501 @@
+//! # Stable-identifier patches
+//!
+//! `NodeOp` addresses children by index, so a patch is only valid against
+//! the exact tree it was computed for. When several producers edit the same
+//! document concurrently, an insert by one shifts the indices the other's
+//! patch refers to.
+//!
+//! `IdOp` addresses nodes by `NodeId`, the keys on the path from the root,
+//! and places nodes after a sibling instead of at an index. Patches made
+//! against the same base tree stay meaningful after each other and can be
+//! merged with `merge`.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::stable::{merge, to_id_ops};
+//! use troy::tags::*;
+//!
+//! let base = ul().child(li().key("a").text("a")).child(li().key("b").text("b")).done();
+//! let first = ul().child(li().key("a").class("done").text("a")).child(li().key("b").text("b")).done();
+//! let second = ul().child(li().key("b").text("b")).done();
+//!
+//! let merged = merge(
+//!     to_id_ops(&base, &diff(&base, &first)),
+//!     to_id_ops(&base, &diff(&base, &second)),
+//! );
+//!
+//! // Second producer removed the item the first one changed.
+//! assert_eq!(merged.conflicts.len(), 1);
+//! ```
+//!
+//! Keyed nodes have stable ids. Unkeyed nodes are identified by their index
+//! among siblings, so they are only as stable as in index based patches.
+//! Unkeyed nodes inserted by a patch are identified by their position after
+//! the patch, apart from the indices of existing nodes.
+//!
+use diff::{AttrDiff, AttrOp, ChildDiff, ChildInserts, NodeOp, TextOp};
+use key::Key;
+use node::VNode;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Segment {
+    Key(Key),
+    Index(usize),
+    /// Unkeyed node inserted by the patch, by its position after the patch.
+    Inserted(usize),
+}
+
+/// Path of a node from the root, by key or by index for unkeyed nodes.
+///
+/// Displayed as `/list/#2`, with indices prefixed by `#` and positions of
+/// inserted nodes by `+`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(Vec<Segment>);
+
+impl NodeId {
+    pub fn root() -> Self {
+        NodeId(Vec::new())
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+
+    pub fn child(&self, segment: Segment) -> Self {
+        let mut id = self.clone();
+        id.0.push(segment);
+        id
+    }
+
+    /// Check if this node is the other node or one of its descendants.
+    ///
+    pub fn is_within(&self, other: &NodeId) -> bool {
+        self.0.starts_with(&other.0)
+    }
+
+    /// Parent of the node, or `None` for the root.
+    ///
+    pub fn parent(&self) -> Option<NodeId> {
+        let (_, parent) = self.0.split_last()?;
+        Some(NodeId(parent.to_vec()))
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for segment in &self.0 {
+            match segment {
+                Segment::Key(key) => write!(f, "/{}", key)?,
+                Segment::Index(index) => write!(f, "/#{}", index)?,
+                Segment::Inserted(position) => write!(f, "/+{}", position)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IdOp {
+    SetAttribute(NodeId, String, String),
+    RemoveAttribute(NodeId, String),
+    AddClass(NodeId, String),
+    RemoveClass(NodeId, String),
+    /// Add a token to the named token-list attribute.
+    AddToken(NodeId, String, String),
+    RemoveToken(NodeId, String, String),
+    /// Insert the node into the parent after the sibling, or first.
+    Insert {
+        parent: NodeId,
+        after: Option<Segment>,
+        node: VNode,
+    },
+    /// Move the node after the sibling, or first, among its siblings.
+    Move {
+        id: NodeId,
+        after: Option<Segment>,
+    },
+    Remove(NodeId),
+    Replace(NodeId, VNode),
+    /// Change the content of a text node.
+    Text(NodeId, TextOp),
+}
+
+impl IdOp {
+    /// Node the operation changes. For inserts, the parent.
+    ///
+    pub fn target(&self) -> &NodeId {
+        match self {
+            IdOp::SetAttribute(id, ..)
+            | IdOp::RemoveAttribute(id, _)
+            | IdOp::AddClass(id, _)
+            | IdOp::RemoveClass(id, _)
+            | IdOp::AddToken(id, ..)
+            | IdOp::RemoveToken(id, ..)
+            | IdOp::Move { id, .. }
+            | IdOp::Remove(id)
+            | IdOp::Replace(id, _)
+            | IdOp::Text(id, _) => id,
+            IdOp::Insert { parent, .. } => parent,
+        }
+    }
+
+    /// Sibling an insert or a move places its node after.
+    ///
+    pub fn anchor(&self) -> Option<NodeId> {
+        match self {
+            IdOp::Insert {
+                parent,
+                after: Some(after),
+                ..
+            } => Some(parent.child(after.clone())),
+            IdOp::Move {
+                id,
+                after: Some(after),
+            } => Some(id.parent()?.child(after.clone())),
+            _ => None,
+        }
+    }
+
+    /// Check if applying both operations in either order can give different
+    /// results, or if one makes the other meaningless.
+    ///
+    pub fn conflicts_with(&self, other: &IdOp) -> bool {
+        use self::IdOp::*;
+
+        match (self, other) {
+            // Removed or replaced subtree conflicts with any change in it,
+            // and with placing a node after it.
+            (Remove(id), op) | (op, Remove(id)) | (Replace(id, _), op) | (op, Replace(id, _)) => {
+                (op.target().is_within(id) || op.anchor().as_ref() == Some(id)) && self != other
+            }
+            (SetAttribute(a, name_a, value_a), SetAttribute(b, name_b, value_b)) => {
+                a == b && name_a == name_b && value_a != value_b
+            }
+            (SetAttribute(a, name_a, _), RemoveAttribute(b, name_b))
+            | (RemoveAttribute(b, name_b), SetAttribute(a, name_a, _)) => {
+                a == b && name_a == name_b
+            }
+            (AddClass(a, name_a), RemoveClass(b, name_b))
+            | (RemoveClass(b, name_b), AddClass(a, name_a)) => a == b && name_a == name_b,
+            (AddToken(a, name_a, token_a), RemoveToken(b, name_b, token_b))
+            | (RemoveToken(b, name_b, token_b), AddToken(a, name_a, token_a)) => {
+                a == b && name_a == name_b && token_a == token_b
+            }
+            // Setting the whole value conflicts with changing its tokens.
+            (SetAttribute(a, name_a, _), AddToken(b, name_b, _))
+            | (SetAttribute(a, name_a, _), RemoveToken(b, name_b, _))
+            | (RemoveAttribute(a, name_a), AddToken(b, name_b, _))
+            | (RemoveAttribute(a, name_a), RemoveToken(b, name_b, _))
+            | (AddToken(b, name_b, _), SetAttribute(a, name_a, _))
+            | (RemoveToken(b, name_b, _), SetAttribute(a, name_a, _))
+            | (AddToken(b, name_b, _), RemoveAttribute(a, name_a))
+            | (RemoveToken(b, name_b, _), RemoveAttribute(a, name_a)) => a == b && name_a == name_b,
+            // Splices of one text shift each other's offsets.
+            (Text(a, op_a), Text(b, op_b)) => a == b && op_a != op_b,
+            (
+                Move {
+                    id: a,
+                    after: after_a,
+                },
+                Move {
+                    id: b,
+                    after: after_b,
+                },
+            ) => a == b && after_a != after_b,
+            // Two inserts after the same sibling have no defined order.
+            (
+                Insert {
+                    parent: a,
+                    after: after_a,
+                    ..
+                },
+                Insert {
+                    parent: b,
+                    after: after_b,
+                    ..
+                },
+            ) => a == b && after_a == after_b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IdOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn after(segment: &Option<Segment>) -> String {
+            match segment {
+                Some(segment) => NodeId(vec![segment.clone()]).to_string(),
+                None => "start".to_string(),
+            }
+        }
+
+        match self {
+            IdOp::SetAttribute(id, name, value) => write!(f, "{} +{}={:?}", id, name, value),
+            IdOp::RemoveAttribute(id, name) => write!(f, "{} -{}", id, name),
+            IdOp::AddClass(id, name) => write!(f, "{} +class {:?}", id, name),
+            IdOp::RemoveClass(id, name) => write!(f, "{} -class {:?}", id, name),
+            IdOp::AddToken(id, name, token) => write!(f, "{} +{} {:?}", id, name, token),
+            IdOp::RemoveToken(id, name, token) => write!(f, "{} -{} {:?}", id, name, token),
+            IdOp::Insert {
+                parent,
+                after: segment,
+                ..
+            } => write!(f, "{} insert after {}", parent, after(segment)),
+            IdOp::Move { id, after: segment } => write!(f, "{} move after {}", id, after(segment)),
+            IdOp::Remove(id) => write!(f, "{} remove", id),
+            IdOp::Replace(id, _) => write!(f, "{} replace", id),
+            IdOp::Text(id, op) => write!(f, "{} {}", id, op),
+        }
+    }
+}
+
+fn segment(node: &VNode, index: usize) -> Segment {
+    match node.key() {
+        Some(key) => Segment::Key(key.clone()),
+        None => Segment::Index(index),
+    }
+}
+
+/// Express the diff of the old tree with stable ids.
+///
+pub fn to_id_ops(old: &VNode, op: &NodeOp) -> Vec<IdOp> {
+    let mut ops = Vec::new();
+    convert(&mut ops, NodeId::root(), old, op);
+    ops
+}
+
+fn convert(ops: &mut Vec<IdOp>, id: NodeId, old: &VNode, op: &NodeOp) {
+    match op {
+        NodeOp::Skip(_) => {}
+        NodeOp::Remove(_) => ops.push(IdOp::Remove(id)),
+        NodeOp::Replace(node) => ops.push(IdOp::Replace(id, (*node).clone())),
+        NodeOp::Text(op) => ops.push(IdOp::Text(id, op.clone())),
+        NodeOp::Update(attrs, children, inserts) | NodeOp::Move(_, attrs, children, inserts) => {
+            convert_update(ops, id, old, attrs, children, inserts)
+        }
+    }
+}
+
+fn convert_update(
+    ops: &mut Vec<IdOp>,
+    id: NodeId,
+    old: &VNode,
+    attrs: &AttrDiff,
+    children: &ChildDiff,
+    inserts: &ChildInserts,
+) {
+    for attr in attrs.iter().flatten() {
+        ops.push(match attr {
+            AttrOp::InsertClass(name) => IdOp::AddClass(id.clone(), name.clone()),
+            AttrOp::RemoveClass(name) => IdOp::RemoveClass(id.clone(), name.clone()),
+            AttrOp::Insert(name, value) | AttrOp::Update(name, value) => {
+                IdOp::SetAttribute(id.clone(), name.clone(), value.clone())
+            }
+            AttrOp::Remove(name) => IdOp::RemoveAttribute(id.clone(), name.clone()),
+            AttrOp::InsertToken(name, token) => {
+                IdOp::AddToken(id.clone(), name.clone(), token.clone())
+            }
+            AttrOp::RemoveToken(name, token) => {
+                IdOp::RemoveToken(id.clone(), name.clone(), token.clone())
+            }
+        });
+    }
+
+    let old_children: &[VNode] = match old.resolve() {
+        VNode::Element(element) => element.get_children(),
+        _ => &[],
+    };
+
+    // Segments of children which stay in place, in order, and of children
+    // placed at explicit positions by Move or Insert.
+    let mut staying = Vec::new();
+    let mut placed = Vec::new();
+    let mut index = 0;
+
+    for op in children.iter().flatten() {
+        let count = match op {
+            NodeOp::Skip(count) | NodeOp::Remove(count) => *count,
+            _ => 1,
+        };
+        for old_child in old_children.iter().skip(index).take(count) {
+            let child_segment = segment(old_child, index);
+            let child_id = id.child(child_segment.clone());
+            match op {
+                NodeOp::Remove(_) => ops.push(IdOp::Remove(child_id)),
+                NodeOp::Move(position, ..) => {
+                    convert(ops, child_id, old_child, op);
+                    placed.push((*position, child_segment, None));
+                }
+                op => {
+                    convert(ops, child_id, old_child, op);
+                    staying.push(child_segment);
+                }
+            }
+            index += 1;
+        }
+    }
+    for (offset, old_child) in old_children.iter().enumerate().skip(index) {
+        staying.push(segment(old_child, offset));
+    }
+    for (position, node) in inserts.iter().flatten() {
+        let child_segment = match node.key() {
+            Some(key) => Segment::Key(key.clone()),
+            None => Segment::Inserted(*position),
+        };
+        placed.push((*position, child_segment, Some(*node)));
+    }
+
+    // Placements are emitted in position order, so each refers to a
+    // sibling which is already in place.
+    placed.sort_by_key(|(position, ..)| *position);
+    let mut placed = placed.into_iter().peekable();
+    let mut staying = staying.into_iter();
+    let mut previous: Option<Segment> = None;
+    let mut position = 0;
+    loop {
+        let current = match placed.peek() {
+            Some((placed_position, ..)) if *placed_position == position => {
+                let (_, child_segment, node) = placed.next().unwrap();
+                ops.push(match node {
+                    Some(node) => IdOp::Insert {
+                        parent: id.clone(),
+                        after: previous.clone(),
+                        node: node.clone(),
+                    },
+                    None => IdOp::Move {
+                        id: id.child(child_segment.clone()),
+                        after: previous.clone(),
+                    },
+                });
+                child_segment
+            }
+            _ => match staying.next() {
+                Some(child_segment) => child_segment,
+                None => break,
+            },
+        };
+        previous = Some(current);
+        position += 1;
+    }
+}
+
+/// Result of merging two patches made against the same tree.
+///
+#[derive(Debug, PartialEq)]
+pub struct Merged {
+    /// Operations of the first patch, followed by operations of the second
+    /// patch which don't conflict with them.
+    pub ops: Vec<IdOp>,
+    /// Operations of the second patch which conflict with the first patch.
+    pub conflicts: Vec<IdOp>,
+}
+
+/// Merge two patches made against the same tree. The first patch wins
+/// conflicts, and operations present in both patches are kept once.
+///
+pub fn merge(first: Vec<IdOp>, second: Vec<IdOp>) -> Merged {
+    let mut ops = first;
+    let mut added = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for op in second {
+        if ops.contains(&op) {
+            continue;
+        }
+        if ops.iter().any(|existing| existing.conflicts_with(&op)) {
+            conflicts.push(op);
+        } else {
+            added.push(op);
+        }
+    }
+    ops.extend(added);
+    Merged { ops, conflicts }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    fn list(items: &[&'static str]) -> VNode {
+        let mut list = ul();
+        for item in items {
+            list = list.child(li().key(*item).text(*item));
+        }
+        div().child(list.key("list")).done()
+    }
+
+    fn ops(old: &VNode, new: &VNode) -> Vec<String> {
+        to_id_ops(old, &diff(old, new))
+            .iter()
+            .map(IdOp::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn positions_are_stable_ids() {
+        let old = list(&["a", "b", "c"]);
+
+        assert_eq!(
+            ops(&old, &list(&["c", "a", "x", "b"])),
+            ["/list/c move after start", "/list insert after /a",]
+        );
+        assert_eq!(ops(&old, &list(&["a", "c"])), ["/list/b remove"]);
+    }
+
+    #[test]
+    fn merge_concurrent_patches() {
+        let base = list(&["a", "b"]);
+        let first = to_id_ops(&base, &diff(&base, &list(&["x", "a", "b"])));
+        let second = to_id_ops(&base, &diff(&base, &list(&["a", "b", "y"])));
+
+        let merged = merge(first, second);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(
+            merged.ops.iter().map(IdOp::to_string).collect::<Vec<_>>(),
+            ["/list insert after start", "/list insert after /b"]
+        );
+
+        let first = to_id_ops(&base, &diff(&base, &list(&["x", "a", "b"])));
+        let second = to_id_ops(&base, &diff(&base, &list(&["y", "a", "b"])));
+        assert_eq!(merge(first, second).conflicts.len(), 1);
+
+        // Second producer inserts after the item the first one removed.
+        let first = to_id_ops(&base, &diff(&base, &list(&["b"])));
+        let second = to_id_ops(&base, &diff(&base, &list(&["a", "y", "b"])));
+        assert_eq!(merge(first, second).conflicts.len(), 1);
+        let base = list(&["a", "b", "c"]);
+        let first = to_id_ops(&base, &diff(&base, &list(&["a", "b"])));
+        let second = to_id_ops(&base, &diff(&base, &list(&["b", "c", "a"])));
+        assert_eq!(merge(first, second).conflicts.len(), 1);
+    }
+
+    #[test]
+    fn inserted_unkeyed_nodes_have_own_ids() {
+        let old = ul().child(li().text("a")).child(li().text("b")).done();
+        #[rustfmt::skip]
+        let new = ul()
+            .child(li().text("a"))
+            .child(li().text("x"))
+            .child(li().text("y"))
+            .child(li().text("b"))
+            .done();
+        let ops = to_id_ops(&old, &diff(&old, &new));
+
+        let ids: Vec<String> = ops.iter().map(IdOp::to_string).collect();
+        assert_eq!(
+            ids,
+            [
+                "/#1/#0 set \"x\"",
+                "/ insert after /#1",
+                "/ insert after /+2"
+            ]
+        );
+    }
+}
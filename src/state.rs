@@ -0,0 +1,320 @@
+//! # Stateful components
+//!
+//! Components with local state owned by a `VDom`. State is addressed by the
+//! component type and an id chosen by the application, so it survives
+//! between frames no matter where the component moves in the tree.
+//!
+//! State is created with `Stateful::init` the first time a component is
+//! built, changed with `VDom::send`, and dropped after a frame in which no
+//! component with its id was built or kept in a skipped subtree. A message
+//! which changes the state renders the component again, along with its
+//! ancestor components which would be skipped otherwise.
+//!
+//! ```
+//! use troy::node::VNode;
+//! use troy::state::{stateful, Stateful};
+//! use troy::tags::*;
+//! use troy::vdom::VDom;
+//!
+//! #[derive(PartialEq)]
+//! struct Counter {
+//!     step: u32,
+//! }
+//!
+//! impl Stateful for Counter {
+//!     type State = u32;
+//!     type Message = ();
+//!
+//!     fn init(&self) -> u32 {
+//!         0
+//!     }
+//!
+//!     fn view(&self, count: &u32) -> VNode {
+//!         span().text(count.to_string()).done()
+//!     }
+//!
+//!     fn update(&self, count: &mut u32, _: ()) -> bool {
+//!         *count += self.step;
+//!         true
+//!     }
+//! }
+//!
+//! let mut vdom = VDom::new();
+//! let view = || div().child_node(stateful("counter", Counter { step: 2 }).done()).done();
+//!
+//! vdom.update(view);
+//! vdom.send::<Counter>("counter", ());
+//! vdom.update(view);
+//!
+//! assert_eq!(vdom.tree(), Some(&div().child(span().text("2")).done()));
+//! ```
+//!
+use component::{Component, VComponent};
+use node::VNode;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use sync::{AnyValue, Lock, MaybeSync, Shared};
+
+pub trait Stateful: PartialEq + MaybeSync + 'static {
+    type State: MaybeSync + 'static;
+    type Message;
+
+    /// Initial state, used when the component is built for the first time.
+    ///
+    fn init(&self) -> Self::State;
+
+    fn view(&self, state: &Self::State) -> VNode;
+
+    /// Change state in response to a message. Returns true if the component
+    /// needs to be rendered again.
+    ///
+    fn update(&self, state: &mut Self::State, message: Self::Message) -> bool;
+}
+
+pub(crate) type StateId = (TypeId, String);
+
+struct Slot {
+    state: Option<Box<AnyValue>>,
+    /// Last built component, which handles messages.
+    component: Option<Box<AnyValue>>,
+    dirty: bool,
+    seen: bool,
+}
+
+/// States of all stateful components of a VDom.
+///
+#[derive(Default)]
+pub(crate) struct StateStore {
+    slots: HashMap<StateId, Slot>,
+}
+
+pub(crate) type SharedStore = Shared<Lock<StateStore>>;
+
+thread_local! {
+    static CURRENT: RefCell<Option<SharedStore>> = const { RefCell::new(None) };
+}
+
+impl StateStore {
+    fn current() -> Option<SharedStore> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Run function with the store as the one new stateful components are
+    /// registered in.
+    ///
+    pub(crate) fn enter<R, F>(store: &SharedStore, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Restores previous store even if `f` panics.
+        struct Restore(Option<SharedStore>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+
+        let previous = CURRENT.with(|current| current.replace(Some(store.clone())));
+        let _restore = Restore(previous);
+        f()
+    }
+
+    pub(crate) fn begin_frame(&mut self) {
+        for slot in self.slots.values_mut() {
+            slot.seen = false;
+        }
+    }
+
+    /// Drop states of components which weren't built during the frame.
+    ///
+    pub(crate) fn end_frame(&mut self) {
+        self.slots.retain(|_, slot| slot.seen);
+    }
+
+    pub(crate) fn send<C>(&mut self, id: &str, message: C::Message) -> bool
+    where
+        C: Stateful,
+    {
+        let slot = match self.slots.get_mut(&(TypeId::of::<C>(), id.to_string())) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let component = slot
+            .component
+            .as_ref()
+            .and_then(|c| c.downcast_ref::<Shared<C>>());
+        let state = slot
+            .state
+            .as_mut()
+            .and_then(|s| s.downcast_mut::<C::State>());
+        match (component, state) {
+            (Some(component), Some(state)) => {
+                let changed = component.update(state, message);
+                slot.dirty |= changed;
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    /// Keep states of the stateful components of a subtree which was
+    /// skipped during the frame, and so wasn't built again.
+    ///
+    pub(crate) fn keep(node: &VNode) {
+        let store = match StateStore::current() {
+            Some(store) => store,
+            None => return,
+        };
+        let mut store = store.borrow_mut();
+        if store.slots.values().all(|slot| slot.seen) {
+            return;
+        }
+        any_state(node, &mut |id| {
+            if let Some(slot) = store.slots.get_mut(id) {
+                slot.seen = true;
+            }
+            false
+        });
+    }
+
+    /// Check if a stateful component of the subtree has changed state, so
+    /// the subtree can't be skipped.
+    ///
+    pub(crate) fn is_dirty(node: &VNode) -> bool {
+        let store = match StateStore::current() {
+            Some(store) => store,
+            None => return false,
+        };
+        let store = store.borrow();
+        if !store.slots.values().any(|slot| slot.dirty) {
+            return false;
+        }
+        any_state(node, &mut |id| {
+            store.slots.get(id).is_some_and(|slot| slot.dirty)
+        })
+    }
+
+    pub(crate) fn get<C>(&self, id: &str) -> Option<&C::State>
+    where
+        C: Stateful,
+    {
+        self.slots
+            .get(&(TypeId::of::<C>(), id.to_string()))
+            .and_then(|slot| slot.state.as_ref())
+            .and_then(|state| state.downcast_ref::<C::State>())
+    }
+}
+
+/// Check if `f` returns true for the state id of a stateful component of
+/// the subtree, going through components which were rendered.
+///
+fn any_state<F>(node: &VNode, f: &mut F) -> bool
+where
+    F: FnMut(&StateId) -> bool,
+{
+    match node {
+        VNode::Element(element) => element
+            .get_children()
+            .iter()
+            .any(|child| any_state(child, f)),
+        VNode::Text(_) => false,
+        VNode::Shared(shared) => any_state(shared, f),
+        VNode::Component(component) => {
+            component.get_state_id().is_some_and(&mut *f)
+                || component
+                    .get_rendered()
+                    .is_some_and(|node| any_state(node, f))
+        }
+    }
+}
+
+/// Component wrapper which keeps state of a Stateful in the store.
+///
+struct StatefulComponent<C> {
+    id: StateId,
+    component: Shared<C>,
+    store: SharedStore,
+}
+
+impl<C> Component for StatefulComponent<C>
+where
+    C: Stateful,
+{
+    type Props = Shared<C>;
+
+    fn props(&self) -> &Shared<C> {
+        &self.component
+    }
+
+    fn render(&self) -> VNode {
+        // State is taken out of the store while rendering, so nested stateful
+        // components can register themselves.
+        let state = {
+            let mut store = self.store.borrow_mut();
+            let slot = store.slots.get_mut(&self.id);
+            slot.and_then(|slot| {
+                slot.dirty = false;
+                slot.state.take()
+            })
+        };
+        let state = state.unwrap_or_else(|| Box::new(self.component.init()));
+
+        let node = match state.downcast_ref::<C::State>() {
+            Some(state) => self.component.view(state),
+            None => unreachable!("state type is part of the state id"),
+        };
+
+        if let Some(slot) = self.store.borrow_mut().slots.get_mut(&self.id) {
+            slot.state = Some(state);
+        }
+        node
+    }
+
+    fn should_update(&self, old: &Shared<C>) -> bool {
+        let dirty = self
+            .store
+            .borrow()
+            .slots
+            .get(&self.id)
+            .is_some_and(|slot| slot.dirty);
+        dirty || *self.component != **old
+    }
+}
+
+/// Create a stateful component with the id. Outside of `VDom::update` the
+/// component has no stored state and renders with its initial state.
+///
+pub fn stateful<C, S>(id: S, component: C) -> VComponent
+where
+    C: Stateful,
+    S: Into<String>,
+{
+    let id = (TypeId::of::<C>(), id.into());
+    let store = StateStore::current().unwrap_or_default();
+    let component = Shared::new(component);
+
+    store
+        .borrow_mut()
+        .slots
+        .entry(id.clone())
+        .and_modify(|slot| {
+            slot.seen = true;
+            slot.component = Some(Box::new(component.clone()));
+        })
+        .or_insert_with(|| Slot {
+            state: Some(Box::new(component.init())),
+            component: Some(Box::new(component.clone())),
+            dirty: false,
+            seen: true,
+        });
+
+    VComponent::new(StatefulComponent {
+        id: id.clone(),
+        component,
+        store,
+    })
+    .with_state_id(id)
+}
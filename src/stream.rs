@@ -0,0 +1,311 @@
+//! # Streaming diff
+//!
+//! `DiffStream` is an iterator of flat operations which walks both trees
+//! lazily: each call to `next` diffs at most one element against its old
+//! counterpart. A network sender can serialize and flush operations while
+//! the rest of a huge tree is still being diffed.
+//!
+//! Flat operations address nodes by their `NodePath` in the old tree, and
+//! place nodes at positions in the new children list of their parent. The
+//! receiver resolves all paths against the old tree, so operations don't
+//! depend on each other and can be applied in any order, as long as
+//! children lists are rearranged after all their operations arrived.
+//!
+//! ```
+//! use troy::diff::AttrOp;
+//! use troy::path::NodePath;
+//! use troy::stream::{DiffStream, FlatOp};
+//! use troy::tags::*;
+//!
+//! let old = ul().child(li().key("a")).child(li().key("b")).done();
+//! let new = ul().child(li().key("b").class("active")).child(li().key("c")).done();
+//! let c = li().key("c").done();
+//!
+//! let ops: Vec<FlatOp> = DiffStream::new(&old, &new).collect();
+//!
+//! assert_eq!(
+//!     ops,
+//!     [
+//!         FlatOp::Remove(NodePath::from(vec![0])),
+//!         FlatOp::Insert(NodePath::root(), 1, &c),
+//!         FlatOp::Attribute(NodePath::from(vec![1]), AttrOp::InsertClass("active".to_string())),
+//!     ]
+//! );
+//! ```
+//!
+use diff::{
+    child_pairs, diff_node, node_pair, AttrDiff, AttrOp, ChildDiff, ChildInserts, Lifecycle,
+    NodeOp, TextOp,
+};
+use node::VNode;
+use path::NodePath;
+use std::collections::VecDeque;
+
+#[derive(Debug, PartialEq)]
+pub enum FlatOp<'new> {
+    /// Change an attribute or class of the node at the old path.
+    Attribute(NodePath, AttrOp),
+    /// Replace the node at the old path.
+    Replace(NodePath, &'new VNode),
+    /// Change the content of the text node at the old path.
+    Text(NodePath, TextOp),
+    /// Remove the node at the old path.
+    Remove(NodePath),
+    /// Move the node at the old path to the position in the new children of
+    /// its parent.
+    Move(NodePath, usize),
+    /// Insert the node at the position in the new children of the element
+    /// at the old path.
+    Insert(NodePath, usize, &'new VNode),
+}
+
+/// Kinds of flat operations, in the order `grouped` puts them.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OpKind {
+    Remove,
+    Move,
+    Insert,
+    Replace,
+    Text,
+    Attribute,
+}
+
+impl<'new> FlatOp<'new> {
+    pub fn kind(&self) -> OpKind {
+        match self {
+            FlatOp::Remove(_) => OpKind::Remove,
+            FlatOp::Move(..) => OpKind::Move,
+            FlatOp::Insert(..) => OpKind::Insert,
+            FlatOp::Replace(..) => OpKind::Replace,
+            FlatOp::Text(..) => OpKind::Text,
+            FlatOp::Attribute(..) => OpKind::Attribute,
+        }
+    }
+
+    /// Old path of the node the operation targets, for inserts the parent.
+    ///
+    pub fn path(&self) -> &NodePath {
+        match self {
+            FlatOp::Attribute(path, _)
+            | FlatOp::Replace(path, _)
+            | FlatOp::Text(path, _)
+            | FlatOp::Remove(path)
+            | FlatOp::Move(path, _)
+            | FlatOp::Insert(path, _, _) => path,
+        }
+    }
+}
+
+/// Reorder operations into batches of one kind: all removals, then moves,
+/// inserts, replacements, text changes and finally attribute changes. Operations keep
+/// their order within a batch.
+///
+/// Flat operations are addressed by old paths and don't depend on each
+/// other, so the batches have the same effect as the original order.
+/// Backends which apply mutations of one kind faster in bulk can apply
+/// each batch in one go.
+///
+pub fn grouped<'new, I>(ops: I) -> Vec<FlatOp<'new>>
+where
+    I: IntoIterator<Item = FlatOp<'new>>,
+{
+    let mut ops: Vec<FlatOp<'new>> = ops.into_iter().collect();
+    ops.sort_by_key(FlatOp::kind);
+    ops
+}
+
+/// Flatten an operation on the node at the old path.
+///
+pub fn flat_ops<'new>(path: &NodePath, op: &NodeOp<'new>) -> Vec<FlatOp<'new>> {
+    let mut ops = Vec::new();
+    flatten(path, op, &mut ops);
+    ops
+}
+
+fn flatten<'new, E>(path: &NodePath, op: &NodeOp<'new>, ops: &mut E)
+where
+    E: Extend<FlatOp<'new>>,
+{
+    match op {
+        NodeOp::Skip(_) => {}
+        NodeOp::Remove(_) => ops.extend(Some(FlatOp::Remove(path.clone()))),
+        NodeOp::Replace(node) => ops.extend(Some(FlatOp::Replace(path.clone(), node))),
+        NodeOp::Text(op) => ops.extend(Some(FlatOp::Text(path.clone(), op.clone()))),
+        NodeOp::Move(position, attrs, children, inserts) => {
+            ops.extend(Some(FlatOp::Move(path.clone(), *position)));
+            flatten_update(path, attrs, children, inserts, ops);
+        }
+        NodeOp::Update(attrs, children, inserts) => {
+            flatten_update(path, attrs, children, inserts, ops)
+        }
+    }
+}
+
+fn flatten_update<'new, E>(
+    path: &NodePath,
+    attrs: &AttrDiff,
+    children: &ChildDiff<'new>,
+    inserts: &ChildInserts<'new>,
+    ops: &mut E,
+) where
+    E: Extend<FlatOp<'new>>,
+{
+    let attrs = attrs.iter().flatten();
+    ops.extend(attrs.map(|attr| FlatOp::Attribute(path.clone(), attr.clone())));
+
+    let mut index = 0;
+    for op in children.iter().flatten() {
+        match op {
+            NodeOp::Skip(count) => index += count,
+            NodeOp::Remove(count) => {
+                for _ in 0..*count {
+                    ops.extend(Some(FlatOp::Remove(path.child(index))));
+                    index += 1;
+                }
+            }
+            op => {
+                flatten(&path.child(index), op, ops);
+                index += 1;
+            }
+        }
+    }
+
+    let inserts = inserts.iter().flatten();
+    ops.extend(inserts.map(|(position, node)| FlatOp::Insert(path.clone(), *position, node)));
+}
+
+/// Lazy diff of two trees, see the module documentation.
+///
+pub struct DiffStream<'old, 'new> {
+    /// Node pairs left to diff, the next one last.
+    pairs: Vec<(NodePath, &'old VNode, &'new VNode)>,
+    ready: VecDeque<FlatOp<'new>>,
+    lifecycle: Lifecycle<'old, 'new>,
+}
+
+impl<'old, 'new> DiffStream<'old, 'new> {
+    pub fn new(old: &'old VNode, new: &'new VNode) -> Self {
+        DiffStream {
+            pairs: vec![(NodePath::root(), old, new)],
+            ready: VecDeque::new(),
+            lifecycle: Lifecycle::default(),
+        }
+    }
+
+    /// Diff the next node pair. Children of elements are rearranged, but
+    /// their pairs are diffed later.
+    ///
+    fn diff_next(&mut self, path: NodePath, old: &'old VNode, new: &'new VNode) {
+        let pairs = match (old, new) {
+            (VNode::Element(old_element), VNode::Element(new_element))
+                if old_element.get_tag() == new_element.get_tag()
+                    && old_element.get_key() == new_element.get_key()
+                    && !old_element.is_void()
+                    && !new_element.is_void() =>
+            {
+                child_pairs(old_element, new_element)
+            }
+            _ => Vec::new(),
+        };
+
+        // Child pairs are marked as diffed, so only this element is diffed
+        // now. Moved children are still reported as moved.
+        for (_, old_child, new_child) in &pairs {
+            let pair = node_pair(old_child, new_child);
+            self.lifecycle.diffed.insert(pair, NodeOp::Skip(1));
+        }
+        let op = diff_node(old, new, &mut self.lifecycle);
+        self.lifecycle.diffed.clear();
+
+        flatten(&path, &op, &mut self.ready);
+        let children = pairs
+            .into_iter()
+            .rev()
+            .map(|(index, old_child, new_child)| (path.child(index), old_child, new_child));
+        self.pairs.extend(children);
+    }
+}
+
+impl<'old, 'new> Iterator for DiffStream<'old, 'new> {
+    type Item = FlatOp<'new>;
+
+    fn next(&mut self) -> Option<FlatOp<'new>> {
+        loop {
+            if let Some(op) = self.ready.pop_front() {
+                return Some(op);
+            }
+            let (path, old, new) = self.pairs.pop()?;
+            self.diff_next(path, old, new);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    fn rows(rows: &[(&'static str, &'static str)]) -> VNode {
+        let mut body = tbody();
+        for (key, value) in rows {
+            body = body.child(
+                tr().key(*key)
+                    .child(td().text(*key))
+                    .child(td().class(*value).text(*value)),
+            );
+        }
+        table().child(body).done()
+    }
+
+    fn sorted(ops: Vec<FlatOp>) -> Vec<String> {
+        let mut ops: Vec<String> = ops.iter().map(|op| format!("{:?}", op)).collect();
+        ops.sort();
+        ops
+    }
+
+    #[test]
+    fn same_ops_as_diff() {
+        let old = rows(&[("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")]);
+        let new = rows(&[("d", "4"), ("b", "5"), ("a", "1"), ("e", "6")]);
+
+        let streamed: Vec<FlatOp> = DiffStream::new(&old, &new).collect();
+        let flattened = flat_ops(&NodePath::root(), &diff(&old, &new));
+
+        assert_eq!(sorted(streamed), sorted(flattened));
+    }
+
+    #[test]
+    fn grouped_by_kind() {
+        let old = rows(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let new = rows(&[("c", "3"), ("b", "5"), ("d", "4")]);
+
+        let ops = grouped(DiffStream::new(&old, &new));
+        let kinds: Vec<OpKind> = ops.iter().map(FlatOp::kind).collect();
+
+        let mut sorted_kinds = kinds.clone();
+        sorted_kinds.sort();
+        assert_eq!(kinds, sorted_kinds);
+        assert_eq!(kinds.first(), Some(&OpKind::Remove));
+        assert_eq!(kinds.last(), Some(&OpKind::Attribute));
+        assert_eq!(ops[0].path(), &NodePath::from(vec![0, 0]));
+    }
+
+    #[test]
+    fn lazy_walk() {
+        let old = rows(&[("a", "1"), ("b", "2")]);
+        let new = rows(&[("a", "3"), ("b", "4")]);
+
+        let mut stream = DiffStream::new(&old, &new);
+        assert_eq!(
+            stream.next(),
+            Some(FlatOp::Attribute(
+                NodePath::from(vec![0, 0, 1]),
+                AttrOp::RemoveClass("1".to_string())
+            ))
+        );
+        // Second row wasn't diffed yet.
+        assert_eq!(stream.pairs.len(), 2);
+    }
+}
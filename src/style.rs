@@ -0,0 +1,410 @@
+//! # Inline styles
+//!
+//! `Style` builds the value of the `style` attribute from typed
+//! properties. Lengths carry their unit and keyword properties take enums,
+//! so `width: 200` without a unit or `display: flx` doesn't compile.
+//! Properties without a typed setter can be added with `property`.
+//!
+//! Setting a shorthand like `margin` drops longhands it covers, like
+//! `margin-top`, which were set before it, since the shorthand would reset
+//! them anyway. `longhands` and `covers` tell which properties a shorthand
+//! sets.
+//!
+//! ```
+//! use troy::style::{Display, Px, Style};
+//! use troy::tags::*;
+//!
+//! let style = Style::new().width(Px(200)).display(Display::Flex).color("#333");
+//! let panel = div().style(style);
+//!
+//! assert_eq!(
+//!     panel.get_attributes().get("style").unwrap(),
+//!     "width: 200px; display: flex; color: #333"
+//! );
+//! ```
+//!
+use element::AttrValue;
+use std::fmt;
+use types::CowString;
+
+/// Length in pixels.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Px(pub i32);
+
+/// Length relative to the font size of the element.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Em(pub f64);
+
+/// Length relative to the font size of the root element.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rem(pub f64);
+
+/// Length relative to the containing block.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percent(pub f64);
+
+/// The `auto` length.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Auto;
+
+/// Length with a unit, see `Px`, `Em`, `Rem`, `Percent` and `Auto`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Length(String);
+
+impl From<Px> for Length {
+    fn from(Px(value): Px) -> Self {
+        Length(format!("{}px", value))
+    }
+}
+
+impl From<Em> for Length {
+    fn from(Em(value): Em) -> Self {
+        Length(format!("{}em", value))
+    }
+}
+
+impl From<Rem> for Length {
+    fn from(Rem(value): Rem) -> Self {
+        Length(format!("{}rem", value))
+    }
+}
+
+impl From<Percent> for Length {
+    fn from(Percent(value): Percent) -> Self {
+        Length(format!("{}%", value))
+    }
+}
+
+impl From<Auto> for Length {
+    fn from(_: Auto) -> Self {
+        Length("auto".to_string())
+    }
+}
+
+/// Value of the `display` property.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Display {
+    None,
+    Block,
+    Inline,
+    InlineBlock,
+    Flex,
+    InlineFlex,
+    Grid,
+    Contents,
+}
+
+impl Display {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Display::None => "none",
+            Display::Block => "block",
+            Display::Inline => "inline",
+            Display::InlineBlock => "inline-block",
+            Display::Flex => "flex",
+            Display::InlineFlex => "inline-flex",
+            Display::Grid => "grid",
+            Display::Contents => "contents",
+        }
+    }
+}
+
+/// Value of the `position` property.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+impl Position {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Position::Static => "static",
+            Position::Relative => "relative",
+            Position::Absolute => "absolute",
+            Position::Fixed => "fixed",
+            Position::Sticky => "sticky",
+        }
+    }
+}
+
+/// Longhand properties set by a shorthand property, or nothing for
+/// properties which aren't shorthands. Members can be shorthands in turn,
+/// like `border-top` of `border`.
+///
+pub fn longhands(shorthand: &str) -> &'static [&'static str] {
+    match shorthand {
+        "margin" => &["margin-top", "margin-right", "margin-bottom", "margin-left"],
+        "padding" => &[
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ],
+        "inset" => &["top", "right", "bottom", "left"],
+        "border" => &[
+            "border-top",
+            "border-right",
+            "border-bottom",
+            "border-left",
+            "border-width",
+            "border-style",
+            "border-color",
+        ],
+        "border-top" => &["border-top-width", "border-top-style", "border-top-color"],
+        "border-right" => &[
+            "border-right-width",
+            "border-right-style",
+            "border-right-color",
+        ],
+        "border-bottom" => &[
+            "border-bottom-width",
+            "border-bottom-style",
+            "border-bottom-color",
+        ],
+        "border-left" => &[
+            "border-left-width",
+            "border-left-style",
+            "border-left-color",
+        ],
+        "border-width" => &[
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ],
+        "border-style" => &[
+            "border-top-style",
+            "border-right-style",
+            "border-bottom-style",
+            "border-left-style",
+        ],
+        "border-color" => &[
+            "border-top-color",
+            "border-right-color",
+            "border-bottom-color",
+            "border-left-color",
+        ],
+        "border-radius" => &[
+            "border-top-left-radius",
+            "border-top-right-radius",
+            "border-bottom-right-radius",
+            "border-bottom-left-radius",
+        ],
+        "outline" => &["outline-width", "outline-style", "outline-color"],
+        "background" => &[
+            "background-color",
+            "background-image",
+            "background-position",
+            "background-size",
+            "background-repeat",
+            "background-origin",
+            "background-clip",
+            "background-attachment",
+        ],
+        "font" => &[
+            "font-style",
+            "font-variant",
+            "font-weight",
+            "font-stretch",
+            "font-size",
+            "line-height",
+            "font-family",
+        ],
+        "flex" => &["flex-grow", "flex-shrink", "flex-basis"],
+        "flex-flow" => &["flex-direction", "flex-wrap"],
+        "gap" => &["row-gap", "column-gap"],
+        "overflow" => &["overflow-x", "overflow-y"],
+        "list-style" => &["list-style-type", "list-style-position", "list-style-image"],
+        "text-decoration" => &[
+            "text-decoration-line",
+            "text-decoration-style",
+            "text-decoration-color",
+            "text-decoration-thickness",
+        ],
+        "transition" => &[
+            "transition-property",
+            "transition-duration",
+            "transition-timing-function",
+            "transition-delay",
+        ],
+        _ => &[],
+    }
+}
+
+/// Check if setting the shorthand sets the property, directly or through
+/// a nested shorthand.
+///
+pub fn covers(shorthand: &str, name: &str) -> bool {
+    longhands(shorthand)
+        .iter()
+        .any(|longhand| *longhand == name || covers(longhand, name))
+}
+
+/// Declarations of the `style` attribute, in the order they were added.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    properties: Vec<(CowString, CowString)>,
+}
+
+impl Style {
+    pub fn new() -> Self {
+        Style::default()
+    }
+
+    /// Set a property by name. Setting a property again replaces its value
+    /// in place, and setting a shorthand drops the longhands it covers.
+    ///
+    pub fn property<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<CowString>,
+        V: Into<CowString>,
+    {
+        let name = name.into();
+        let value = value.into();
+        self.properties.retain(|(other, _)| !covers(&name, other));
+        match self.properties.iter_mut().find(|(other, _)| *other == name) {
+            Some(property) => property.1 = value,
+            None => self.properties.push((name, value)),
+        }
+        self
+    }
+
+    pub fn get_properties(&self) -> &[(CowString, CowString)] {
+        &self.properties
+    }
+
+    pub fn display(self, display: Display) -> Self {
+        self.property("display", display.as_str())
+    }
+
+    pub fn position(self, position: Position) -> Self {
+        self.property("position", position.as_str())
+    }
+
+    pub fn width<L: Into<Length>>(self, width: L) -> Self {
+        self.property("width", width.into().0)
+    }
+
+    pub fn height<L: Into<Length>>(self, height: L) -> Self {
+        self.property("height", height.into().0)
+    }
+
+    pub fn margin<L: Into<Length>>(self, margin: L) -> Self {
+        self.property("margin", margin.into().0)
+    }
+
+    pub fn padding<L: Into<Length>>(self, padding: L) -> Self {
+        self.property("padding", padding.into().0)
+    }
+
+    pub fn font_size<L: Into<Length>>(self, size: L) -> Self {
+        self.property("font-size", size.into().0)
+    }
+
+    pub fn color<S: Into<CowString>>(self, color: S) -> Self {
+        self.property("color", color)
+    }
+
+    pub fn background_color<S: Into<CowString>>(self, color: S) -> Self {
+        self.property("background-color", color)
+    }
+
+    /// Opacity between 0 and 1, clamped to that range.
+    ///
+    pub fn opacity(self, opacity: f64) -> Self {
+        self.property("opacity", opacity.clamp(0.0, 1.0).to_string())
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, (name, value)) in self.properties.iter().enumerate() {
+            if index > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Style> for AttrValue {
+    fn from(style: Style) -> Self {
+        style.to_string().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units() {
+        let style = Style::new()
+            .width(Percent(50.0))
+            .height(Auto)
+            .margin(Em(1.5))
+            .padding(Rem(0.25))
+            .font_size(Px(-1));
+
+        assert_eq!(
+            style.to_string(),
+            "width: 50%; height: auto; margin: 1.5em; padding: 0.25rem; font-size: -1px"
+        );
+    }
+
+    #[test]
+    fn replaces_property() {
+        let style = Style::new()
+            .display(Display::None)
+            .opacity(2.0)
+            .property("display", "grid");
+
+        assert_eq!(style.to_string(), "display: grid; opacity: 1");
+        assert_eq!(Style::new().to_string(), "");
+    }
+
+    #[test]
+    fn shorthands_drop_longhands() {
+        let style = Style::new()
+            .property("margin-top", "4px")
+            .property("border-top-color", "red")
+            .property("color", "red")
+            .margin(Px(0))
+            .property("border", "1px solid");
+
+        assert_eq!(
+            style.to_string(),
+            "color: red; margin: 0px; border: 1px solid"
+        );
+
+        // Longhands set after the shorthand override it, so they are kept.
+        let style = style.property("margin-left", "auto").margin(Px(1));
+        assert_eq!(
+            style.to_string(),
+            "color: red; margin: 1px; border: 1px solid"
+        );
+        let style = style.property("margin-left", "auto");
+        assert_eq!(
+            style.to_string(),
+            "color: red; margin: 1px; border: 1px solid; margin-left: auto"
+        );
+
+        assert!(covers("border", "border-left-width"));
+        assert!(!covers("border-top", "border-left-width"));
+        assert!(longhands("color").is_empty());
+    }
+}
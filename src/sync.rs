@@ -0,0 +1,136 @@
+//! # Thread-safe trees
+//!
+//! By default trees are single-threaded: shared nodes are `Rc`, and
+//! components, context values and component states don't need to be `Send`.
+//!
+//! With the `sync` feature shared nodes are `Arc`, and everything a tree
+//! holds must be `Send + Sync`, which makes trees and owned patches `Send +
+//! Sync`. They can be built on one thread and diffed on another, such as a
+//! worker of a thread pool. Strings are `Cow<'static, str>` in both
+//! configurations, which is thread-safe already.
+//!
+//! Code which works with both configurations uses `Shared` for shared nodes
+//! and `MaybeSync` as the bound for types stored in trees.
+//!
+//! ```
+//! use troy::node::VNode;
+//! use troy::sync::Shared;
+//! use troy::tags::*;
+//!
+//! let header = Shared::new(h1().text("Title").done());
+//! let page = div().child_node(VNode::Shared(header.clone())).done();
+//! ```
+//!
+use std::any::Any;
+#[cfg(not(feature = "sync"))]
+use std::cell::{OnceCell, Ref, RefCell};
+#[cfg(not(feature = "sync"))]
+use std::rc::Rc;
+#[cfg(feature = "sync")]
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+/// Pointer to a value shared between trees, `Rc` by default and `Arc` with
+/// the `sync` feature.
+///
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = Rc<T>;
+#[cfg(feature = "sync")]
+pub type Shared<T> = Arc<T>;
+
+/// Bound for types stored in trees: any type by default, `Send + Sync` types
+/// with the `sync` feature.
+///
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+#[cfg(feature = "sync")]
+pub trait MaybeSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: ?Sized + Send + Sync> MaybeSync for T {}
+
+/// Type-erased value stored in trees.
+///
+#[cfg(not(feature = "sync"))]
+pub(crate) type AnyValue = dyn Any;
+#[cfg(feature = "sync")]
+pub(crate) type AnyValue = dyn Any + Send + Sync;
+
+/// Cell initialized once, such as the rendered node of a component.
+///
+#[cfg(not(feature = "sync"))]
+pub(crate) type Once<T> = OnceCell<T>;
+#[cfg(feature = "sync")]
+pub(crate) type Once<T> = OnceLock<T>;
+
+/// Interior mutability for values shared by nodes of a tree, with the
+/// `RefCell` interface.
+///
+#[derive(Default)]
+pub(crate) struct Lock<T> {
+    #[cfg(not(feature = "sync"))]
+    inner: RefCell<T>,
+    #[cfg(feature = "sync")]
+    inner: Mutex<T>,
+}
+
+#[cfg(not(feature = "sync"))]
+pub(crate) type LockRef<'a, T> = Ref<'a, T>;
+#[cfg(not(feature = "sync"))]
+pub(crate) type LockMut<'a, T> = ::std::cell::RefMut<'a, T>;
+#[cfg(feature = "sync")]
+pub(crate) type LockRef<'a, T> = MutexGuard<'a, T>;
+#[cfg(feature = "sync")]
+pub(crate) type LockMut<'a, T> = MutexGuard<'a, T>;
+
+#[cfg(not(feature = "sync"))]
+impl<T> Lock<T> {
+    pub(crate) fn borrow(&self) -> LockRef<'_, T> {
+        self.inner.borrow()
+    }
+
+    pub(crate) fn borrow_mut(&self) -> LockMut<'_, T> {
+        self.inner.borrow_mut()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> Lock<T> {
+    pub(crate) fn borrow(&self) -> LockRef<'_, T> {
+        self.borrow_mut()
+    }
+
+    pub(crate) fn borrow_mut(&self) -> LockMut<'_, T> {
+        // State is consistent between calls, a panic while it was locked
+        // doesn't leave it half-updated.
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(all(test, feature = "sync", feature = "tags"))]
+mod tests {
+    use diff::diff;
+    use node::VNode;
+    use patch::OwnedOp;
+    use std::thread;
+    use tags::*;
+
+    fn assert_thread_safe<T: Send + Sync>() {}
+
+    #[test]
+    fn trees_cross_threads() {
+        assert_thread_safe::<VNode>();
+        assert_thread_safe::<OwnedOp>();
+
+        let old = ul().child(li().key("a").text("a")).done();
+        let new = ul().child(li().key("b").text("b")).done();
+
+        let op = thread::spawn(move || OwnedOp::from(diff(&old, &new)))
+            .join()
+            .unwrap();
+        assert!(matches!(op, OwnedOp::Update(..)));
+    }
+}
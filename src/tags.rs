@@ -8,6 +8,7 @@ use element::VElement;
 macro_rules! tags {
     ($($tag:ident),*) => {
         $(
+            #[cfg_attr(feature = "debug-locations", track_caller)]
             pub fn $tag() -> VElement {
                 VElement::new(stringify!($tag))
             }
@@ -19,6 +20,7 @@ macro_rules! tags {
 macro_rules! void_tags {
     ($($tag:ident),*) => {
         $(
+            #[cfg_attr(feature = "debug-locations", track_caller)]
             pub fn $tag() -> VElement {
                 VElement::new_void(stringify!($tag))
             }
@@ -37,20 +39,18 @@ void_tags![link, meta];
 tags![body];
 
 // Content sectioning
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 tags![
     address, article, aside, footer, header, h1, h2, h3, h4, h5, h6, nav,
     section
 ];
 
 // Text content
-tags![
-    blockquote, dd, div, dl, dt, figcaption, figure, li, main, ol, p, pre, ul
-];
+tags![blockquote, dd, div, dl, dt, figcaption, figure, li, main, ol, p, pre, ul];
 void_tags![hr];
 
 // Inline text semantics
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 tags![
     a, abbr, b, bdi, bdo, cite, code, data, dfn, em, i, kbd, mark, q, rp, rt,
     rtc, ruby, s, samp, small, span, strong, sub, sup, time, u, var
@@ -72,12 +72,10 @@ tags![canvas, noscript, script];
 tags![del, ins];
 
 // Table content
-tags![
-    caption, col, colgroup, table, tbody, td, tfoot, th, thead, tr
-];
+tags![caption, col, colgroup, table, tbody, td, tfoot, th, thead, tr];
 
 // Forms
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 tags![
     button, datalist, fieldlist, form, label, legend, meter, optgroup, option,
     output, progress, select, textarea
@@ -0,0 +1,144 @@
+//! # Template adapter
+//!
+//! Adoption path for applications which render HTML with a string template
+//! engine (Tera, Handlebars, Askama, ...). The rendered output is parsed
+//! into a tree and normalized, so consecutive renders can be diffed instead
+//! of replacing the page.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::template::{from_template, TemplateOptions};
+//!
+//! let options = TemplateOptions {
+//!     key_attribute: Some("data-id".to_string()),
+//!     ..TemplateOptions::default()
+//! };
+//!
+//! // Output of the template engine for two frames.
+//! let old = from_template("<li data-id=\"1\">Ash</li> <li data-id=\"2\">Bob</li>", &options);
+//! let new = from_template("<li data-id=\"2\">Bob</li>", &options);
+//!
+//! let op = diff(&old.unwrap(), &new.unwrap());
+//! ```
+//!
+//! Normalization:
+//!
+//! - Output is wrapped into a container element, so templates rendering
+//!   several or varying numbers of root nodes have a stable root.
+//! - Whitespace in text is collapsed, outside of elements where it is
+//!   significant, so indentation changes don't produce patches.
+//! - Values of the key attribute become element keys, so lists rendered by
+//!   loops are diffed by key.
+//!
+use element::VElement;
+use key::Key;
+use node::VNode;
+use parser::{parse_fragment, ParseError};
+use whitespace::Whitespace;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateOptions {
+    /// Tag of the element which wraps the template output.
+    pub container: String,
+    /// Whitespace policy for text.
+    pub whitespace: Whitespace,
+    /// Attribute whose value becomes the element key, such as `id` or
+    /// `data-key`. Elements with a `key` attribute keep it.
+    pub key_attribute: Option<String>,
+}
+
+impl Default for TemplateOptions {
+    fn default() -> Self {
+        TemplateOptions {
+            container: "div".to_string(),
+            whitespace: Whitespace::Collapse,
+            key_attribute: None,
+        }
+    }
+}
+
+/// Parse and normalize the rendered output of a template.
+///
+pub fn from_template(html: &str, options: &TemplateOptions) -> Result<VNode, ParseError> {
+    let nodes = parse_fragment(html)?;
+    let container = VElement::new(options.container.clone())
+        .children(nodes.into_iter().map(|node| promote_keys(node, options)))
+        .whitespace(options.whitespace);
+    Ok(container.done())
+}
+
+fn promote_keys(node: VNode, options: &TemplateOptions) -> VNode {
+    let attribute = match &options.key_attribute {
+        Some(attribute) => attribute.as_str(),
+        None => return node,
+    };
+    let element = match node {
+        VNode::Element(element) => element,
+        node => return node,
+    };
+
+    let mut promoted = if element.is_void() {
+        VElement::new_void(element.get_tag().to_string())
+    } else {
+        VElement::new(element.get_tag().to_string())
+    };
+    let key = element.get_key().cloned().or_else(|| {
+        let value = element.get_attributes().get(attribute)?;
+        Some(Key::from(value.clone()))
+    });
+    if let Some(key) = key {
+        promoted = promoted.key(key);
+    }
+    for class in element.get_classes() {
+        promoted = promoted.class(class.clone());
+    }
+    for (name, value) in element.get_attributes() {
+        promoted = promoted.attr(name.clone(), value.clone());
+    }
+    promoted
+        .children(
+            element
+                .into_children()
+                .into_iter()
+                .map(|child| promote_keys(child, options)),
+        )
+        .done()
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn normalized_output() {
+        let options = TemplateOptions {
+            key_attribute: Some("id".to_string()),
+            ..TemplateOptions::default()
+        };
+        let html = "
+            <h1>Users</h1>
+            <ul>
+                <li id=\"u1\">Ash\n    Ketchum</li>
+                <li id=\"u2\" key=\"bob\">Bob</li>
+            </ul>
+            <pre>a  b</pre>
+        ";
+
+        #[rustfmt::skip]
+        let expected = div()
+            .child(h1().text("Users"))
+            .child(ul()
+                .child(li().key("u1").attr("id", "u1").text("Ash Ketchum"))
+                .child(li().key("bob").attr("id", "u2").text("Bob")))
+            .child(pre().text("a  b"))
+            .done();
+
+        assert_eq!(from_template(html, &options), Ok(expected));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(from_template("<p>", &TemplateOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! # Terminal backend
+//!
+//! Minimal backend for terminal UIs. Trees are laid out as lines of text:
+//! inline elements (`span`, `b`, `em` and the like) continue the current
+//! line, other elements start and end lines. Attributes and classes are kept
+//! but don't affect the output.
+//!
+//! `TextGrid` applies diffs and returns cursor-addressed updates, the rows
+//! whose text changed, which the application writes with its terminal
+//! library.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::tags::*;
+//! use troy::terminal::{RowUpdate, TextGrid};
+//!
+//! let old = div().child(p().text("Count: ")).child(p().text("Done")).done();
+//! let new = div().child(p().text("Count: ").child(b().text("1"))).child(p().text("Done")).done();
+//!
+//! let (mut grid, _) = TextGrid::new(&old);
+//! let updates = grid.patch(&diff(&old, &new)).unwrap();
+//!
+//! assert_eq!(updates, [RowUpdate { row: 0, text: "Count: 1".to_string() }]);
+//! ```
+//!
+use backend::{DomBackend, Mounted};
+use diff::{NodeOp, TextOp};
+use error::Error;
+use node::VNode;
+
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "br", "cite", "code", "em", "i", "kbd", "label", "mark", "q", "s", "samp",
+    "small", "span", "strong", "sub", "sup", "time", "u", "var",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Element(String),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct TextNode {
+    kind: Kind,
+    attributes: Vec<(String, String)>,
+    classes: Vec<String>,
+    children: Vec<usize>,
+}
+
+/// Nodes of a terminal UI, addressed by index.
+///
+#[derive(Debug, Default)]
+pub struct TextBackend {
+    nodes: Vec<Option<TextNode>>,
+    free: Vec<usize>,
+}
+
+impl TextBackend {
+    fn insert(&mut self, kind: Kind) -> usize {
+        let node = TextNode {
+            kind,
+            attributes: Vec::new(),
+            classes: Vec::new(),
+            children: Vec::new(),
+        };
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = Some(node);
+                index
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn node(&mut self, index: usize) -> &mut TextNode {
+        self.nodes[index].as_mut().expect("node was removed")
+    }
+
+    /// Number of live nodes.
+    ///
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lay out the tree with the root into lines.
+    ///
+    pub fn lines(&self, root: usize) -> Vec<String> {
+        let mut lines = vec![String::new()];
+        self.layout(root, &mut lines);
+        if lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+    }
+
+    fn layout(&self, index: usize, lines: &mut Vec<String>) {
+        let node = match &self.nodes[index] {
+            Some(node) => node,
+            None => return,
+        };
+        match &node.kind {
+            Kind::Text(content) => lines.last_mut().unwrap().push_str(content),
+            Kind::Element(tag) if tag == "br" => lines.push(String::new()),
+            Kind::Element(tag) => {
+                let block = !INLINE_TAGS.contains(&tag.as_str());
+                if block && !lines.last().unwrap().is_empty() {
+                    lines.push(String::new());
+                }
+                for child in &node.children {
+                    self.layout(*child, lines);
+                }
+                if block && !lines.last().unwrap().is_empty() {
+                    lines.push(String::new());
+                }
+            }
+        }
+    }
+}
+
+impl DomBackend for TextBackend {
+    type Node = usize;
+
+    fn create_element(&mut self, tag: &str) -> usize {
+        self.insert(Kind::Element(tag.to_string()))
+    }
+
+    fn create_text(&mut self, content: &str) -> usize {
+        self.insert(Kind::Text(content.to_string()))
+    }
+
+    fn set_attribute(&mut self, node: &usize, name: &str, value: &str) {
+        let attributes = &mut self.node(*node).attributes;
+        attributes.retain(|(n, _)| n != name);
+        attributes.push((name.to_string(), value.to_string()));
+    }
+
+    fn remove_attribute(&mut self, node: &usize, name: &str) {
+        self.node(*node).attributes.retain(|(n, _)| n != name);
+    }
+
+    fn add_class(&mut self, node: &usize, name: &str) {
+        let classes = &mut self.node(*node).classes;
+        if !classes.iter().any(|c| c == name) {
+            classes.push(name.to_string());
+        }
+    }
+
+    fn remove_class(&mut self, node: &usize, name: &str) {
+        self.node(*node).classes.retain(|c| c != name);
+    }
+
+    fn add_token(&mut self, node: &usize, name: &str, token: &str) {
+        let attributes = &mut self.node(*node).attributes;
+        match attributes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, value)) => {
+                if !value.split_whitespace().any(|t| t == token) {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(token);
+                }
+            }
+            None => attributes.push((name.to_string(), token.to_string())),
+        }
+    }
+
+    fn remove_token(&mut self, node: &usize, name: &str, token: &str) {
+        for (n, value) in &mut self.node(*node).attributes {
+            if n == name {
+                let tokens: Vec<&str> = value.split_whitespace().filter(|t| *t != token).collect();
+                *value = tokens.join(" ");
+            }
+        }
+    }
+
+    fn update_text(&mut self, node: &usize, op: &TextOp) {
+        if let Kind::Text(content) = &mut self.node(*node).kind {
+            *content = op.apply(content);
+        }
+    }
+
+    fn set_children(&mut self, parent: &usize, children: &[usize]) {
+        self.node(*parent).children = children.to_vec();
+    }
+
+    fn remove_node(&mut self, node: &usize) {
+        if let Some(removed) = self.nodes[*node].take() {
+            self.free.push(*node);
+            for child in removed.children {
+                self.remove_node(&child);
+            }
+        }
+    }
+}
+
+/// Text written at the start of a row, replacing the row.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowUpdate {
+    pub row: usize,
+    pub text: String,
+}
+
+/// Terminal screen driven by diffs.
+///
+#[derive(Debug)]
+pub struct TextGrid {
+    backend: TextBackend,
+    root: Mounted<usize>,
+    lines: Vec<String>,
+}
+
+impl TextGrid {
+    /// Mount the tree, returning the grid and the updates which draw it.
+    ///
+    pub fn new(node: &VNode) -> (Self, Vec<RowUpdate>) {
+        let mut backend = TextBackend::default();
+        let root = Mounted::mount(&mut backend, node);
+        let mut grid = TextGrid {
+            backend,
+            root,
+            lines: Vec::new(),
+        };
+        let updates = grid.refresh();
+        (grid, updates)
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Apply the diff and return rows which changed. Rows past the new end of
+    /// the screen are updated to empty text.
+    ///
+    pub fn patch(&mut self, op: &NodeOp) -> Result<Vec<RowUpdate>, Error> {
+        self.root.patch(&mut self.backend, op)?;
+        Ok(self.refresh())
+    }
+
+    fn refresh(&mut self) -> Vec<RowUpdate> {
+        let lines = self.backend.lines(*self.root.node());
+        let rows = lines.len().max(self.lines.len());
+        let updates = (0..rows)
+            .filter(|row| lines.get(*row) != self.lines.get(*row))
+            .map(|row| RowUpdate {
+                row,
+                text: lines.get(row).cloned().unwrap_or_default(),
+            })
+            .collect();
+        self.lines = lines;
+        updates
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    fn list(items: &[&'static str]) -> VNode {
+        let mut list = ul();
+        for item in items {
+            list = list.child(li().key(*item).text(*item));
+        }
+        div().child(h1().text("Items")).child(list).done()
+    }
+
+    #[test]
+    fn grid_follows_diffs() {
+        let (mut grid, updates) = TextGrid::new(&list(&["a", "b", "c"]));
+        assert_eq!(updates.len(), 4);
+        assert_eq!(grid.lines(), ["Items", "a", "b", "c"]);
+
+        let frames = [list(&["c", "a", "b"]), list(&["c", "d"]), list(&[])];
+        let mut old = list(&["a", "b", "c"]);
+        for new in frames {
+            grid.patch(&diff(&old, &new)).unwrap();
+            assert_eq!(grid.lines(), TextGrid::new(&new).0.lines());
+            old = new;
+        }
+
+        // Removed nodes are freed: the root, the heading with its text, and
+        // the empty list.
+        assert_eq!(grid.backend.len(), 4);
+    }
+
+    #[test]
+    fn row_updates() {
+        let (mut grid, _) = TextGrid::new(&list(&["a", "b"]));
+        let updates = grid
+            .patch(&diff(&list(&["a", "b"]), &list(&["a"])))
+            .unwrap();
+
+        assert_eq!(
+            updates,
+            [RowUpdate {
+                row: 2,
+                text: String::new()
+            }]
+        );
+    }
+}
@@ -1,11 +1,43 @@
+use escape::decode_entities;
+#[cfg(feature = "display")]
+use node::fmt_text;
 use node::VNode;
 use std::borrow::Cow;
+#[cfg(feature = "display")]
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use sync::Shared;
 
 type CowString = Cow<'static, str>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Eq)]
 pub struct VText {
-    content: CowString,
+    content: Content,
+}
+
+/// Content of a text, shared with other texts when it comes from an
+/// `intern::Interner`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Content {
+    Cow(CowString),
+    Shared(Shared<str>),
+}
+
+/// Texts sharing their content, such as interned ones, are equal without
+/// comparing the content, see `intern`.
+///
+impl PartialEq for VText {
+    fn eq(&self, other: &VText) -> bool {
+        let (this, other) = (self.get_content(), other.get_content());
+        (this.as_ptr() == other.as_ptr() && this.len() == other.len()) || this == other
+    }
+}
+
+impl Hash for VText {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get_content().hash(state);
+    }
 }
 
 impl VText {
@@ -16,7 +48,24 @@ impl VText {
         S: Into<Cow<'static, str>>,
     {
         VText {
-            content: content.into(),
+            content: Content::Cow(content.into()),
+        }
+    }
+
+    /// Create a new VText sharing its content with other texts.
+    ///
+    pub fn new_shared(content: Shared<str>) -> Self {
+        VText {
+            content: Content::Shared(content),
+        }
+    }
+
+    /// Create a new VText from content with HTML character references,
+    /// such as `&amp;` or `&#x27;`, decoding them.
+    ///
+    pub fn new_decoded(content: &str) -> Self {
+        VText {
+            content: Content::Cow(decode_entities(content).into_owned().into()),
         }
     }
 
@@ -27,7 +76,29 @@ impl VText {
     }
 
     pub fn get_content(&self) -> &str {
-        &self.content
+        match &self.content {
+            Content::Cow(content) => content,
+            Content::Shared(content) => content,
+        }
+    }
+
+    /// Capacity of the content when it is owned, see
+    /// `VNode::approx_heap_size`.
+    ///
+    pub(crate) fn heap_size(&self) -> usize {
+        match &self.content {
+            Content::Cow(Cow::Owned(content)) => content.capacity(),
+            Content::Cow(Cow::Borrowed(_)) | Content::Shared(_) => 0,
+        }
+    }
+}
+
+/// Same output as the `Display` of the text wrapped into `VNode`.
+///
+#[cfg(feature = "display")]
+impl fmt::Display for VText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_text(0, self, f)
     }
 }
 
@@ -37,3 +108,7 @@ where
 {
     VText::new(content.into())
 }
+
+pub fn text_decoded(content: &str) -> VText {
+    VText::new_decoded(content)
+}
@@ -0,0 +1,242 @@
+//! # Tracing
+//!
+//! Timed spans of the phases of the differ, with the `tracing` feature.
+//! `Trace::collect` runs a function, such as diffing a frame, and records a
+//! span each time the differ diffs attributes, scans the common prefix and
+//! suffix of children, indexes keys, computes the longest increasing
+//! subsequence of moved children, or emits operations. Each span has the
+//! number of items the phase went through.
+//!
+//! ```
+//! use troy::diff::diff;
+//! use troy::tags::*;
+//! use troy::trace::{Phase, Trace};
+//!
+//! let old = ul().child(li().key("a")).child(li().key("b")).done();
+//! let new = ul().child(li().key("b")).child(li().key("a")).done();
+//!
+//! let (_, trace) = Trace::collect(|| diff(&old, &new));
+//! let lis = trace.get_spans().iter().find(|span| span.phase == Phase::Lis);
+//! assert_eq!(lis.map(|span| span.count), Some(2));
+//! ```
+//!
+//! `Trace::folded` writes the time of each phase as folded stacks, which
+//! flamegraph tools read, so it's easy to see which phase dominates for a
+//! workload. Outside of a collection spans are not timed.
+//!
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static CURRENT: RefCell<Option<Vec<Span>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Phase {
+    /// Diff of classes and attributes of an element, counting operations.
+    Attributes,
+    /// Scan for the common prefix and suffix of children, counting the
+    /// children in both.
+    Scan,
+    /// Index of new children by key, counting keys.
+    KeyIndex,
+    /// Longest increasing subsequence of old positions of moved children,
+    /// counting positions.
+    Lis,
+    /// Emission of the operations of children, counting operations.
+    Emit,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Attributes => "attributes",
+            Phase::Scan => "scan",
+            Phase::KeyIndex => "key_index",
+            Phase::Lis => "lis",
+            Phase::Emit => "emit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub phase: Phase,
+    pub count: usize,
+    pub elapsed: Duration,
+}
+
+/// Spans recorded while running a function, in the order they ended.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    spans: Vec<Span>,
+    elapsed: Duration,
+}
+
+impl Trace {
+    /// Run the function and record spans of diffs it runs on this thread.
+    /// Collections nest, each one records its own spans.
+    ///
+    pub fn collect<R, F>(f: F) -> (R, Trace)
+    where
+        F: FnOnce() -> R,
+    {
+        // Restores the outer collection even if `f` panics.
+        struct Restore(Option<Vec<Span>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let outer = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = outer);
+            }
+        }
+
+        let outer = CURRENT.with(|current| current.replace(Some(Vec::new())));
+        let restore = Restore(outer);
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        let spans = CURRENT.with(|current| current.replace(None));
+        drop(restore);
+        let trace = Trace {
+            spans: spans.unwrap_or_default(),
+            elapsed,
+        };
+        (result, trace)
+    }
+
+    pub fn get_spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Time the whole function took.
+    ///
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Sum of the counts and times of the spans of each phase, in the order
+    /// of `Phase`. Phases without spans are left out.
+    ///
+    pub fn totals(&self) -> Vec<Span> {
+        let mut totals: Vec<Span> = Vec::new();
+        for span in &self.spans {
+            match totals.iter_mut().find(|total| total.phase == span.phase) {
+                Some(total) => {
+                    total.count += span.count;
+                    total.elapsed += span.elapsed;
+                }
+                None => totals.push(span.clone()),
+            }
+        }
+        totals.sort_by_key(|total| total.phase);
+        totals
+    }
+
+    /// Folded stacks of the phases under `diff`, in nanoseconds. Time spent
+    /// outside of the phases is counted for `diff` itself.
+    ///
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        let mut phases = Duration::default();
+        for total in self.totals() {
+            phases += total.elapsed;
+            writeln!(
+                folded,
+                "diff;{} {}",
+                total.phase.name(),
+                total.elapsed.as_nanos()
+            )
+            .expect("write to string");
+        }
+        let rest = self.elapsed.saturating_sub(phases);
+        writeln!(folded, "diff {}", rest.as_nanos()).expect("write to string");
+        folded
+    }
+}
+
+/// Span of a phase started by `enter`, recorded when it ends.
+///
+pub(crate) struct Entered(Option<(Phase, Instant)>);
+
+/// Start a span of the phase, timed only if spans are collected.
+///
+pub(crate) fn enter(phase: Phase) -> Entered {
+    let collecting = CURRENT.with(|current| current.borrow().is_some());
+    Entered(if collecting {
+        Some((phase, Instant::now()))
+    } else {
+        None
+    })
+}
+
+impl Entered {
+    /// End the span, with the number of items the phase went through.
+    ///
+    pub(crate) fn exit(self, count: usize) {
+        if let Some((phase, start)) = self.0 {
+            let elapsed = start.elapsed();
+            CURRENT.with(|current| {
+                if let Some(spans) = current.borrow_mut().as_mut() {
+                    spans.push(Span {
+                        phase,
+                        count,
+                        elapsed,
+                    });
+                }
+            });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use diff::diff;
+    use tags::*;
+
+    #[test]
+    fn diff_phases() {
+        #[rustfmt::skip]
+        let old = ul().class("a")
+            .child(li().key("1"))
+            .child(li().key("2"))
+            .child(li().key("3"))
+            .done();
+        #[rustfmt::skip]
+        let new = ul().class("b")
+            .child(li().key("3"))
+            .child(li().key("2"))
+            .child(li().key("1"))
+            .done();
+
+        let (_, trace) = Trace::collect(|| diff(&old, &new));
+        let totals: Vec<(Phase, usize)> = trace
+            .totals()
+            .into_iter()
+            .map(|total| (total.phase, total.count))
+            .collect();
+        assert_eq!(
+            totals,
+            vec![
+                (Phase::Attributes, 2),
+                (Phase::Scan, 0),
+                (Phase::KeyIndex, 3),
+                (Phase::Lis, 3),
+                (Phase::Emit, 3),
+            ]
+        );
+        let folded = trace.folded();
+        assert!(folded.starts_with("diff;attributes "));
+        assert_eq!(folded.lines().count(), 6);
+
+        let (_, trace) = Trace::collect(|| diff(&old, &old));
+        assert!(trace
+            .get_spans()
+            .iter()
+            .all(|span| span.phase != Phase::Lis));
+        assert!(enter(Phase::Emit).0.is_none());
+    }
+}
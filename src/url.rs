@@ -0,0 +1,195 @@
+//! # URL attributes
+//!
+//! Percent-encoding and scheme checks for URL valued attributes such as
+//! `href` and `src`.
+//!
+use error::Error;
+use node::VNode;
+use std::borrow::Cow;
+
+/// Attributes which hold a single URL.
+///
+pub const URL_ATTRIBUTES: &[&str] = &["action", "cite", "formaction", "href", "poster", "src"];
+
+/// Check if an attribute holds a URL.
+///
+pub fn is_url_attribute(name: &str) -> bool {
+    URL_ATTRIBUTES.contains(&name)
+}
+
+/// Percent-encode characters which are not allowed in URLs.
+///
+/// Reserved characters (`/`, `?`, `#`, `&`, ...) and existing percent escapes
+/// are left intact, so encoding an already encoded URL doesn't change it.
+/// Spaces, quotes, angle brackets, control and non-ASCII characters are
+/// encoded as UTF-8 bytes.
+///
+pub fn encode_url(url: &str) -> Cow<'_, str> {
+    let needs_encoding = |byte: u8| {
+        byte <= b' '
+            || byte >= 0x7f
+            || matches!(
+                byte,
+                b'"' | b'<' | b'>' | b'\\' | b'^' | b'`' | b'{' | b'|' | b'}'
+            )
+    };
+
+    let bytes = url.as_bytes();
+    let invalid_percent = |index: usize| {
+        bytes[index] == b'%'
+            && !(index + 2 < bytes.len()
+                && bytes[index + 1].is_ascii_hexdigit()
+                && bytes[index + 2].is_ascii_hexdigit())
+    };
+
+    if !(0..bytes.len()).any(|i| needs_encoding(bytes[i]) || invalid_percent(i)) {
+        return Cow::Borrowed(url);
+    }
+
+    let mut encoded = String::with_capacity(url.len() + 16);
+    for (index, byte) in bytes.iter().enumerate() {
+        if needs_encoding(*byte) || invalid_percent(index) {
+            encoded.push_str(&format!("%{:02X}", byte));
+        } else {
+            encoded.push(*byte as char);
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// Scheme of a URL in lowercase, or None for relative URLs.
+///
+/// Leading whitespace and embedded tabs and newlines are ignored, same as
+/// browsers do, so `" java\tscript:"` has the `javascript` scheme.
+///
+pub fn url_scheme(url: &str) -> Option<String> {
+    let cleaned: String = url
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+
+    let end = cleaned.find([':', '/', '?', '#'])?;
+    if !cleaned[end..].starts_with(':') {
+        return None;
+    }
+
+    let scheme = &cleaned[..end];
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if valid {
+        Some(scheme.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+/// Allow list of URL schemes. Relative URLs are always allowed.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlPolicy {
+    pub allowed_schemes: Vec<String>,
+}
+
+impl Default for UrlPolicy {
+    /// Allows `http`, `https`, `mailto` and `tel`.
+    ///
+    fn default() -> Self {
+        UrlPolicy {
+            allowed_schemes: ["http", "https", "mailto", "tel"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl UrlPolicy {
+    pub fn allows(&self, url: &str) -> bool {
+        match url_scheme(url) {
+            Some(scheme) => self.allowed_schemes.contains(&scheme),
+            None => true,
+        }
+    }
+
+    /// Check URL against the policy.
+    ///
+    pub fn check(&self, url: &str) -> Result<(), Error> {
+        if self.allows(url) {
+            Ok(())
+        } else {
+            Err(Error::DisallowedUrl(url.to_string()))
+        }
+    }
+
+    /// Check all URL attributes in a tree against the policy.
+    ///
+    pub fn check_tree(&self, node: &VNode) -> Result<(), Error> {
+        if let VNode::Element(element) = node.resolve() {
+            for (name, value) in element.get_attributes() {
+                if is_url_attribute(name) {
+                    self.check(value)?;
+                }
+            }
+            for child in element.get_children() {
+                self.check_tree(child)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(encode_url("/search?q=a&b=c#top"), "/search?q=a&b=c#top");
+        assert_eq!(encode_url("/a b/\"c\""), "/a%20b/%22c%22");
+        assert_eq!(encode_url("/caf\u{e9}"), "/caf%C3%A9");
+        assert_eq!(encode_url("/100%25"), "/100%25");
+        assert_eq!(encode_url("/100%"), "/100%25");
+    }
+
+    #[test]
+    fn schemes() {
+        assert_eq!(url_scheme("https://example.com"), Some("https".to_string()));
+        assert_eq!(
+            url_scheme(" JavaScript:alert(1)"),
+            Some("javascript".to_string())
+        );
+        assert_eq!(
+            url_scheme("java\tscript:alert(1)"),
+            Some("javascript".to_string())
+        );
+        assert_eq!(url_scheme("/path:with/colon"), None);
+        assert_eq!(url_scheme("page?a=b:c"), None);
+        assert_eq!(url_scheme("#anchor"), None);
+    }
+
+    #[test]
+    fn policy() {
+        let policy = UrlPolicy::default();
+
+        assert!(policy.allows("/relative"));
+        assert!(policy.allows("mailto:me@example.com"));
+        assert!(!policy.allows("javascript:alert(1)"));
+        assert_eq!(
+            policy.check("data:text/html,x"),
+            Err(Error::DisallowedUrl("data:text/html,x".to_string()))
+        );
+
+        let node = div()
+            .child(a().attr("href", "https://example.com"))
+            .child(img().attr("src", "javascript:x"))
+            .done();
+        assert_eq!(
+            policy.check_tree(&node),
+            Err(Error::DisallowedUrl("javascript:x".to_string()))
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! # VDom
+//!
+//! Runtime which keeps the current tree between frames, together with the
+//! state of stateful components in it. Each update builds the new tree,
+//! diffs it against the current one and returns the diff for the patcher.
+//!
+//! Updates driven by events which know what changed can rebuild only one
+//! subtree with `patch_at`, which diffs that subtree alone.
+//!
+//! `snapshot` saves the current tree and its generation as bytes, and
+//! `restore` brings them back in another process, so a server rendering for
+//! remote clients can restart or move sessions and keep patching the trees
+//! the clients have. States of stateful components are not saved: they
+//! start again from their initial state on the next update.
+//!
+use binary::{decode_snapshot_with, encode_snapshot, DecodeOptions};
+use diff::{diff_with_lifecycle, Lifecycle, NodeOp};
+use error::Error;
+use node::VNode;
+use patch::Stamp;
+use patch_builder::PatchBuilder;
+use path::NodePath;
+use state::{SharedStore, StateStore, Stateful};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use sync::LockRef;
+
+#[derive(Default)]
+pub struct VDom {
+    tree: Option<VNode>,
+    states: SharedStore,
+    stamp: Stamp,
+}
+
+impl VDom {
+    pub fn new() -> Self {
+        VDom::default()
+    }
+
+    /// Current tree, None before the first update.
+    ///
+    pub fn tree(&self) -> Option<&VNode> {
+        self.tree.as_ref()
+    }
+
+    /// Generations of the trees the last update diffed between, see
+    /// `patch::Stamp`.
+    ///
+    pub fn stamp(&self) -> Stamp {
+        self.stamp
+    }
+
+    /// Build the new tree with `view` and make it current.
+    ///
+    /// Returns the diff from the previous tree, or `Replace` with the whole
+    /// tree on the first update. Components unmounted and mounted by the diff
+    /// are notified before it is returned.
+    ///
+    pub fn update<F>(&mut self, view: F) -> NodeOp<'_>
+    where
+        F: FnOnce() -> VNode,
+    {
+        self.stamp = Stamp {
+            base: self.stamp.generation,
+            generation: self.stamp.generation + 1,
+        };
+        let states = &self.states;
+        states.borrow_mut().begin_frame();
+
+        let new = StateStore::enter(states, view);
+        let old = self.tree.replace(new);
+        let new = self.tree.as_ref().unwrap();
+
+        let op = StateStore::enter(states, || {
+            let (op, lifecycle) = match &old {
+                Some(old) => diff_with_lifecycle(old, new),
+                None => (NodeOp::Replace(new), Lifecycle::insert(new)),
+            };
+            #[cfg(debug_assertions)]
+            {
+                if let Some(Err(error)) = old.as_ref().map(|old| op.validate_against(old)) {
+                    panic!("diff doesn't fit the old tree: {}", error);
+                }
+            }
+            lifecycle.notify();
+            op
+        });
+
+        states.borrow_mut().end_frame();
+        op
+    }
+
+    /// Build a new subtree with `view`, put it in place of the node at `path`
+    /// of the current tree, and diff only that subtree.
+    ///
+    /// Returns the diff of the whole tree, which skips everything but the
+    /// path. Shared nodes on the path are copied, and paths into components
+    /// are invalid. States of stateful components removed from the subtree
+    /// are kept until the next `update`.
+    ///
+    pub fn patch_at<F>(&mut self, path: &NodePath, view: F) -> Result<NodeOp<'_>, Error>
+    where
+        F: FnOnce() -> VNode,
+    {
+        let tree = self
+            .tree
+            .as_mut()
+            .ok_or_else(|| Error::InvalidPath(format!("no tree to update at {}", path)))?;
+        let slot = node_at_mut(tree, path.indices())
+            .ok_or_else(|| Error::InvalidPath(format!("no node at {}", path)))?;
+        self.stamp = Stamp {
+            base: self.stamp.generation,
+            generation: self.stamp.generation + 1,
+        };
+        let states = &self.states;
+
+        let old = mem::replace(slot, StateStore::enter(states, view));
+        let new = path
+            .resolve(self.tree.as_ref().unwrap())
+            .expect("node was just put at the path");
+
+        let op = StateStore::enter(states, || {
+            let (op, lifecycle) = diff_with_lifecycle(&old, new);
+            lifecycle.notify();
+            op
+        });
+
+        // Wrap the diff into updates of its ancestors.
+        Ok(path.indices().iter().rev().fold(op, |op, index| {
+            if let NodeOp::Skip(_) = op {
+                return op;
+            }
+            let mut children = PatchBuilder::new();
+            children.push(NodeOp::Skip(*index));
+            children.push(op);
+            NodeOp::Update(None, Some(children.done()), None)
+        }))
+    }
+
+    /// Current tree and its generation as bytes, see `restore`.
+    ///
+    pub fn snapshot(&self) -> Vec<u8> {
+        encode_snapshot(self.stamp.generation, self.tree.as_ref())
+    }
+
+    /// VDom with the tree and generation of a snapshot. The next update is
+    /// diffed against the restored tree and stamped as following it.
+    ///
+    pub fn restore(snapshot: &[u8]) -> Result<VDom, Error> {
+        VDom::restore_with(snapshot, &DecodeOptions::default())
+    }
+
+    /// VDom restored from a snapshot which is checked against the limits,
+    /// for snapshots from untrusted sources. Fails with `Error::TooDeep` or
+    /// `Error::TooManyNodes` if the tree exceeds them.
+    ///
+    pub fn restore_with(snapshot: &[u8], options: &DecodeOptions) -> Result<VDom, Error> {
+        let (generation, tree) = decode_snapshot_with(snapshot, options)?;
+        Ok(VDom {
+            tree,
+            states: SharedStore::default(),
+            stamp: Stamp {
+                base: generation,
+                generation,
+            },
+        })
+    }
+
+    /// Send a message to the stateful component of type `C` with the id.
+    ///
+    /// Returns true if the component needs to be rendered again, which
+    /// happens on the next update.
+    ///
+    pub fn send<C>(&mut self, id: &str, message: C::Message) -> bool
+    where
+        C: Stateful,
+    {
+        self.states.borrow_mut().send::<C>(id, message)
+    }
+
+    /// State of the stateful component of type `C` with the id.
+    ///
+    pub fn state<C>(&self, id: &str) -> Option<StateRef<'_, C>>
+    where
+        C: Stateful,
+    {
+        let store = self.states.borrow();
+        store.get::<C>(id)?;
+        Some(StateRef {
+            store,
+            id: id.to_string(),
+            component: PhantomData,
+        })
+    }
+}
+
+/// Node at the path, copying shared nodes on the way so it can be changed.
+///
+fn node_at_mut<'a>(node: &'a mut VNode, indices: &[usize]) -> Option<&'a mut VNode> {
+    let (index, rest) = match indices.split_first() {
+        Some(first) => first,
+        None => return Some(node),
+    };
+    while let VNode::Shared(shared) = node {
+        *node = VNode::clone(shared);
+    }
+    match node {
+        VNode::Element(element) => node_at_mut(element.get_children_mut().get_mut(*index)?, rest),
+        _ => None,
+    }
+}
+
+/// Borrowed state of a stateful component, see `VDom::state`.
+///
+pub struct StateRef<'a, C> {
+    store: LockRef<'a, StateStore>,
+    id: String,
+    component: PhantomData<C>,
+}
+
+impl<'a, C> Deref for StateRef<'a, C>
+where
+    C: Stateful,
+{
+    type Target = C::State;
+
+    fn deref(&self) -> &C::State {
+        self.store
+            .get::<C>(&self.id)
+            .expect("state exists while it is borrowed")
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use component::{component, Component};
+    use diff::AttrOp;
+    use diff::NodeOp::*;
+    use state::stateful;
+    use tags::*;
+
+    #[derive(PartialEq)]
+    struct Toggle {
+        label: &'static str,
+    }
+
+    impl Stateful for Toggle {
+        type State = bool;
+        type Message = bool;
+
+        fn init(&self) -> bool {
+            false
+        }
+
+        fn view(&self, on: &bool) -> VNode {
+            let mut button = button().text(self.label);
+            if *on {
+                button = button.class("on");
+            }
+            button.done()
+        }
+
+        fn update(&self, on: &mut bool, message: bool) -> bool {
+            let changed = *on != message;
+            *on = message;
+            changed
+        }
+    }
+
+    fn view(ids: &[&'static str]) -> VNode {
+        let mut list = div();
+        for id in ids {
+            list = list.child_node(stateful(*id, Toggle { label: id }).key(*id).done());
+        }
+        list.done()
+    }
+
+    #[test]
+    fn state_between_frames() {
+        let mut vdom = VDom::new();
+
+        assert_eq!(
+            vdom.update(|| view(&["a", "b"])),
+            Replace(&view(&["a", "b"]))
+        );
+        assert_eq!(vdom.update(|| view(&["a", "b"])), Skip(1));
+        assert_eq!(
+            vdom.stamp(),
+            Stamp {
+                base: 1,
+                generation: 2
+            }
+        );
+
+        assert!(vdom.send::<Toggle>("b", true));
+        assert!(!vdom.send::<Toggle>("b", true));
+        assert_eq!(vdom.state::<Toggle>("b").as_deref(), Some(&true));
+
+        // State follows the id when the component moves.
+        vdom.update(|| view(&["b", "a"]));
+        assert_eq!(
+            vdom.tree(),
+            Some(
+                &div()
+                    .child(button().class("on").text("b"))
+                    .child(button().text("a"))
+                    .done()
+            )
+        );
+    }
+
+    #[test]
+    fn state_is_dropped_with_component() {
+        let mut vdom = VDom::new();
+
+        vdom.update(|| view(&["a"]));
+        vdom.send::<Toggle>("a", true);
+        vdom.update(|| view(&[]));
+        assert!(vdom.state::<Toggle>("a").is_none());
+
+        vdom.update(|| view(&["a"]));
+        assert_eq!(vdom.state::<Toggle>("a").as_deref(), Some(&false));
+    }
+
+    /// Component which is never rendered again, with a stateful child.
+    ///
+    struct Frozen;
+
+    impl Component for Frozen {
+        type Props = ();
+
+        fn props(&self) -> &() {
+            &()
+        }
+
+        fn render(&self) -> VNode {
+            div()
+                .child_node(stateful("a", Toggle { label: "a" }).done())
+                .done()
+        }
+
+        fn should_update(&self, _: &()) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn state_under_skipped_component() {
+        let mut vdom = VDom::new();
+        let view = || component(Frozen).done();
+
+        vdom.update(view);
+        assert_eq!(vdom.update(view), Skip(1));
+        assert_eq!(vdom.update(view), Skip(1));
+        assert_eq!(vdom.state::<Toggle>("a").as_deref(), Some(&false));
+
+        assert!(vdom.send::<Toggle>("a", true));
+        let class = Update(Some(vec![AttrOp::InsertClass("on".into())]), None, None);
+        assert_eq!(vdom.update(view), Update(None, Some(vec![class]), None));
+        assert_eq!(vdom.update(view), Skip(1));
+        assert_eq!(
+            vdom.tree().map(VNode::resolve),
+            Some(&div().child(button().class("on").text("a")).done())
+        );
+    }
+
+    #[test]
+    fn patch_at_path() {
+        use diff::diff;
+
+        let list = |items: &[&'static str]| {
+            let mut list = ul();
+            for item in items {
+                list = list.child(li().text(*item));
+            }
+            div().child(h1().text("List")).child(list).done()
+        };
+
+        let mut vdom = VDom::new();
+        assert!(vdom.patch_at(&NodePath::root(), || p().done()).is_err());
+        vdom.update(|| list(&["a", "b"]));
+
+        let path = NodePath::from(vec![1, 1]);
+        let op = format!(
+            "{:?}",
+            vdom.patch_at(&path, || li().text("c").done()).unwrap()
+        );
+        assert_eq!(
+            op,
+            format!("{:?}", diff(&list(&["a", "b"]), &list(&["a", "c"])))
+        );
+        assert_eq!(vdom.tree(), Some(&list(&["a", "c"])));
+        assert_eq!(vdom.stamp().generation, 2);
+
+        assert_eq!(vdom.patch_at(&path, || li().text("c").done()), Ok(Skip(1)));
+        assert_eq!(
+            vdom.patch_at(&NodePath::from(vec![0, 0, 0]), || p().done()),
+            Err(Error::InvalidPath("no node at /0/0/0".to_string()))
+        );
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        use diff::diff;
+
+        let mut vdom = VDom::new();
+        assert_eq!(VDom::restore(&vdom.snapshot()).unwrap().tree(), None);
+
+        vdom.update(|| p().text("a").done());
+        vdom.update(|| p().text("b").done());
+        let mut restored = VDom::restore(&vdom.snapshot()).unwrap();
+        assert_eq!(restored.tree(), vdom.tree());
+
+        let op = restored.update(|| p().text("c").done());
+        assert_eq!(op, diff(&p().text("b").done(), &p().text("c").done()));
+        assert_eq!(
+            restored.stamp(),
+            Stamp {
+                base: 2,
+                generation: 3
+            }
+        );
+        assert!(VDom::restore(&[1, 2]).is_err());
+
+        let options = DecodeOptions {
+            max_depth: 1,
+            ..DecodeOptions::default()
+        };
+        assert!(matches!(
+            VDom::restore_with(&vdom.snapshot(), &options),
+            Err(Error::TooDeep(1))
+        ));
+    }
+}
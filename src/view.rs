@@ -0,0 +1,135 @@
+//! # Render trait
+//!
+//! Conversion of plain data into views, mostly for admin tables and debug
+//! views of domain types. With the `macros` feature it can be derived for
+//! structs with `#[derive(Render)]`.
+//!
+//! Scalars render to text, `Option` renders to its value or to empty text,
+//! and `Vec` renders to a `ul` list keyed by `render_key` of the items.
+//!
+use element::VElement;
+use node::VNode;
+use std::collections::HashSet;
+use text::text;
+
+pub trait Render {
+    fn render(&self) -> VNode;
+
+    /// Key of the rendered node when the value is an item of a list.
+    ///
+    fn render_key(&self) -> Option<String> {
+        None
+    }
+}
+
+macro_rules! render_to_string {
+    ($($type:ty),*) => {
+        $(
+            impl Render for $type {
+                fn render(&self) -> VNode {
+                    text(self.to_string()).done()
+                }
+            }
+        )*
+    };
+}
+
+render_to_string![
+    bool, char, f32, f64, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, String
+];
+
+impl Render for str {
+    fn render(&self) -> VNode {
+        text(self.to_string()).done()
+    }
+}
+
+impl<T> Render for &T
+where
+    T: Render + ?Sized,
+{
+    fn render(&self) -> VNode {
+        (**self).render()
+    }
+
+    fn render_key(&self) -> Option<String> {
+        (**self).render_key()
+    }
+}
+
+impl<T> Render for Option<T>
+where
+    T: Render,
+{
+    fn render(&self) -> VNode {
+        match self {
+            Some(value) => value.render(),
+            None => text("").done(),
+        }
+    }
+
+    fn render_key(&self) -> Option<String> {
+        self.as_ref().and_then(Render::render_key)
+    }
+}
+
+/// Items with keys become keyed `li` elements. Keys must be unique, which is
+/// checked in debug builds.
+///
+impl<T> Render for [T]
+where
+    T: Render,
+{
+    fn render(&self) -> VNode {
+        let mut keys = HashSet::new();
+        let mut list = VElement::new("ul");
+        for item in self {
+            let mut element = VElement::new("li").child_node(item.render());
+            if let Some(key) = item.render_key() {
+                debug_assert!(keys.insert(key.clone()), "duplicate key {:?}", key);
+                element = element.key(key);
+            }
+            list = list.child(element);
+        }
+        list.done()
+    }
+}
+
+impl<T> Render for Vec<T>
+where
+    T: Render,
+{
+    fn render(&self) -> VNode {
+        self.as_slice().render()
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    struct Tag(&'static str);
+
+    impl Render for Tag {
+        fn render(&self) -> VNode {
+            span().text(self.0).done()
+        }
+
+        fn render_key(&self) -> Option<String> {
+            Some(self.0.to_string())
+        }
+    }
+
+    #[test]
+    fn render_values() {
+        assert_eq!(42.render(), text("42").done());
+        assert_eq!(None::<u32>.render(), text("").done());
+        assert_eq!(
+            vec![Tag("a"), Tag("b")].render(),
+            ul().child(li().key("a").child(span().text("a")))
+                .child(li().key("b").child(span().text("b")))
+                .done()
+        );
+    }
+}
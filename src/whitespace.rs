@@ -0,0 +1,84 @@
+//! # Whitespace handling
+//!
+//! Policy for insignificant whitespace in text nodes. It can be applied to a
+//! tree at build time with `VElement::whitespace`, or at render time with
+//! `RenderOptions::whitespace`.
+//!
+//! Normalizing both frames at build time keeps the differ from producing
+//! patches for text which differs only in indentation or line breaks.
+//!
+//! Text inside `pre`, `textarea` and `code` is always preserved.
+//!
+use std::borrow::Cow;
+
+/// Elements where whitespace is significant.
+///
+pub const PRESERVE_TAGS: &[&str] = &["pre", "textarea", "code"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Whitespace {
+    /// Keep text as it is.
+    #[default]
+    Preserve,
+    /// Replace each run of whitespace with a single space.
+    Collapse,
+    /// Collapse whitespace and remove it from both ends of the text.
+    /// Text which becomes empty is dropped.
+    Trim,
+}
+
+/// Check if whitespace is significant inside an element.
+///
+pub fn is_preserved(tag: &str) -> bool {
+    PRESERVE_TAGS.contains(&tag)
+}
+
+/// Apply policy to text content.
+///
+pub fn apply(text: &str, policy: Whitespace) -> Cow<'_, str> {
+    let text = match policy {
+        Whitespace::Preserve => return Cow::Borrowed(text),
+        Whitespace::Collapse => text,
+        Whitespace::Trim => text.trim(),
+    };
+
+    let collapsed = !text.char_indices().any(|(i, c)| {
+        c.is_whitespace() && (c != ' ' || text[i + 1..].starts_with(char::is_whitespace))
+    });
+    if collapsed {
+        return Cow::Borrowed(text);
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_whitespace {
+                output.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            output.push(c);
+            in_whitespace = false;
+        }
+    }
+    Cow::Owned(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies() {
+        let text = "\n    Hello,\t\tworld!\n";
+
+        assert_eq!(apply(text, Whitespace::Preserve), text);
+        assert_eq!(apply(text, Whitespace::Collapse), " Hello, world! ");
+        assert_eq!(apply(text, Whitespace::Trim), "Hello, world!");
+        assert!(match apply("a b", Whitespace::Collapse) {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        });
+    }
+}
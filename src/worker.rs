@@ -0,0 +1,103 @@
+//! # Web worker offloading
+//!
+//! Diffing in a worker keeps large updates off the main thread. The main
+//! thread encodes each new tree and posts it to the worker, the worker keeps
+//! the previous tree, diffs, and posts back the encoded patch, which the main
+//! thread decodes and applies.
+//!
+//! ```
+//! use troy::tags::*;
+//! use troy::worker::{decode_patch, encode_tree, WorkerDiffer};
+//!
+//! // Worker side, one per rendered root.
+//! let mut differ = WorkerDiffer::new();
+//!
+//! // Main thread posts trees, worker posts patches back.
+//! for count in 0..3 {
+//!     let message = encode_tree(&p().text(count.to_string()).done());
+//!     let reply = differ.handle(&message).unwrap();
+//!     let patch = decode_patch(&reply).unwrap();
+//! }
+//! ```
+//!
+//! Messages are plain bytes, so they can be moved to the worker without
+//! copying (as a transferable `ArrayBuffer` from JavaScript, or over a
+//! channel in native apps). Posting them is left to the application, the
+//! crate has no JavaScript bindings.
+//!
+//! Components are rendered on the main thread when the tree is encoded, the
+//! worker only sees elements and text.
+//!
+use binary::{decode_node, decode_op, encode_node, encode_op, DecodeError};
+use diff::{diff, NodeOp};
+use node::VNode;
+use patch::OwnedOp;
+
+/// Worker side of the pipeline, which keeps the last tree it received.
+///
+#[derive(Debug, Default)]
+pub struct WorkerDiffer {
+    tree: Option<VNode>,
+}
+
+impl WorkerDiffer {
+    pub fn new() -> Self {
+        WorkerDiffer::default()
+    }
+
+    /// Diff the tree encoded in the message against the previous one and
+    /// return the encoded patch. The first message produces `Replace` with
+    /// the whole tree.
+    ///
+    /// The previous tree is kept if the message can't be decoded.
+    ///
+    pub fn handle(&mut self, message: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let new = decode_node(message)?;
+        let patch = match &self.tree {
+            Some(old) => encode_op(&diff(old, &new)),
+            None => encode_op(&NodeOp::Replace(&new)),
+        };
+        self.tree = Some(new);
+        Ok(patch)
+    }
+}
+
+/// Encode the tree for posting it to the worker.
+///
+pub fn encode_tree(node: &VNode) -> Vec<u8> {
+    encode_node(node)
+}
+
+/// Decode a patch posted back by the worker.
+///
+pub fn decode_patch(message: &[u8]) -> Result<OwnedOp, DecodeError> {
+    decode_op(message)
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use super::*;
+    use tags::*;
+
+    #[test]
+    fn worker_round_trip() {
+        let mut differ = WorkerDiffer::new();
+        let old = ul().child(li().key("a").text("a")).done();
+        let new = ul()
+            .child(li().key("b").text("b"))
+            .child(li().key("a").text("a"))
+            .done();
+
+        let first = decode_patch(&differ.handle(&encode_tree(&old)).unwrap()).unwrap();
+        assert_eq!(
+            first,
+            OwnedOp::Replace(ul().child(li().key("a").text("a")).done())
+        );
+
+        let second = decode_patch(&differ.handle(&encode_tree(&new)).unwrap()).unwrap();
+        assert_eq!(second.as_op(), diff(&old, &new));
+
+        assert!(differ.handle(&[0]).is_err());
+        assert_eq!(differ.tree.as_ref(), Some(&new));
+    }
+}
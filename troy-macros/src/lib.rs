@@ -0,0 +1,677 @@
+//! # troy-macros
+//!
+//! Procedural macros re-exported by `troy` with the `macros` feature.
+//!
+//! ## rsx!
+//!
+//! Same syntax as `troy::html!`, but the markup is parsed and validated at
+//! compile time.
+//!
+//! - Tags must be known tags from `troy::tags`.
+//! - Void elements can't have children or a closing tag.
+//! - Closing tags must match opening tags.
+//! - Attributes can't be repeated.
+//!
+//! The generated code calls the builders directly, with collections
+//! reserved for the known number of attributes and children.
+//!
+//! ```
+//! extern crate troy;
+//! use troy::rsx;
+//! use troy::tags::*;
+//!
+//! # fn main() {
+//! let name = "Ash";
+//! let view = rsx! {
+//!     <div class="user" data-id="1">
+//!         <p>"Hello, " {name}</p>
+//!         <br>
+//!     </div>
+//! };
+//!
+//! assert_eq!(
+//!     view,
+//!     div().class("user").attr("data-id", "1")
+//!         .child(p().text("Hello, ").text("Ash"))
+//!         .child(br())
+//!         .done()
+//! );
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! extern crate troy;
+//! use troy::rsx;
+//!
+//! # fn main() {
+//! // error: void element `br` can't have children or a closing tag
+//! let view = rsx! { <div><br>"text"</br></div> };
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! extern crate troy;
+//! use troy::rsx;
+//!
+//! # fn main() {
+//! // error: unknown tag `dvi`
+//! let view = rsx! { <dvi></dvi> };
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! extern crate troy;
+//! use troy::rsx;
+//!
+//! # fn main() {
+//! // error: expected `</ul>`, found `</ol>`
+//! let view = rsx! { <ul><li></li></ol> };
+//! # }
+//! ```
+//!
+//! ## #[derive(Render)]
+//!
+//! Implements `troy::view::Render` for structs with named fields. The struct
+//! renders to a `div` with the struct name as a class, and each field to a
+//! child `div` with the field name as a class, containing the rendered field
+//! value. Names are converted to kebab case.
+//!
+//! - `#[key]` on a field makes it the key of the struct when it's rendered
+//!   as an item of a `Vec`, which renders to a keyed list.
+//! - `#[data]` on a field renders it as a `data-*` attribute instead of a
+//!   child.
+//!
+//! ```
+//! extern crate troy;
+//! use troy::tags::*;
+//! use troy::view::Render;
+//! use troy::Render;
+//!
+//! #[derive(Render)]
+//! struct UserRow {
+//!     #[key]
+//!     #[data]
+//!     id: u32,
+//!     user_name: String,
+//! }
+//!
+//! #[derive(Render)]
+//! struct UserTable {
+//!     users: Vec<UserRow>,
+//! }
+//!
+//! # fn main() {
+//! let table = UserTable {
+//!     users: vec![UserRow { id: 1, user_name: "Ash".to_string() }],
+//! };
+//!
+//! assert_eq!(
+//!     table.render(),
+//!     div().class("user-table")
+//!         .child(div().class("users").child(ul()
+//!             .child(li().key("1").child(div().class("user-row").attr("data-id", "1")
+//!                 .child(div().class("user-name").text("Ash"))))))
+//!         .done()
+//! );
+//! # }
+//! ```
+//!
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Span, TokenStream, TokenTree};
+
+#[rustfmt::skip]
+const TAGS: &[&str] = &[
+    "html", "style", "title", "body", "address", "article", "aside", "footer",
+    "header", "h1", "h2", "h3", "h4", "h5", "h6", "nav", "section",
+    "blockquote", "dd", "div", "dl", "dt", "figcaption", "figure", "li", "main",
+    "ol", "p", "pre", "ul", "a", "abbr", "b", "bdi", "bdo", "cite", "code",
+    "data", "dfn", "em", "i", "kbd", "mark", "q", "rp", "rt", "rtc", "ruby", "s",
+    "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "area",
+    "audio", "map", "video", "iframe", "object", "picture", "canvas",
+    "noscript", "script", "del", "ins", "caption", "col", "colgroup", "table",
+    "tbody", "td", "tfoot", "th", "thead", "tr", "button", "datalist",
+    "fieldlist", "form", "label", "legend", "meter", "optgroup", "option",
+    "output", "progress", "select", "textarea", "details", "dialog", "menu",
+    "menuitem", "summary",
+];
+
+#[rustfmt::skip]
+const VOID_TAGS: &[&str] = &[
+    "link", "meta", "hr", "br", "wbr", "img", "track", "embed", "param",
+    "source", "input",
+];
+
+type Result<T> = std::result::Result<T, (Span, String)>;
+
+enum Node {
+    Element(Element),
+    Text(Literal),
+    Expr(Group),
+}
+
+enum Value {
+    Literal(Literal),
+    Expr(Group),
+}
+
+struct Attribute {
+    name: String,
+    optional: bool,
+    value: Value,
+}
+
+struct Element {
+    tag: Ident,
+    key: Option<Value>,
+    attributes: Vec<Attribute>,
+    children: Vec<Node>,
+}
+
+#[proc_macro]
+pub fn rsx(input: TokenStream) -> TokenStream {
+    let mut parser = Parser {
+        tokens: input.into_iter().collect(),
+        position: 0,
+    };
+
+    match parser.parse_root() {
+        Ok(element) => {
+            let mut body = element_body(&element);
+            body.extend(code("element.done()"));
+            block(body)
+        }
+        Err((span, message)) => compile_error(span, &message),
+    }
+}
+
+//
+// # Parser
+//
+
+struct Parser {
+    tokens: Vec<TokenTree>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenTree> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_punct(&self, offset: usize, c: char) -> bool {
+        match self.tokens.get(self.position + offset) {
+            Some(TokenTree::Punct(punct)) => punct.as_char() == c,
+            _ => false,
+        }
+    }
+
+    fn next(&mut self) -> Option<TokenTree> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn span(&self) -> Span {
+        self.peek()
+            .map_or_else(Span::call_site, |token| token.span())
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<()> {
+        if self.peek_punct(0, c) {
+            self.next();
+            Ok(())
+        } else {
+            Err((self.span(), format!("expected `{}`", c)))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<Ident> {
+        match self.next() {
+            Some(TokenTree::Ident(ident)) => Ok(ident),
+            Some(token) => Err((token.span(), "expected a name".to_string())),
+            None => Err((Span::call_site(), "unexpected end of markup".to_string())),
+        }
+    }
+
+    fn parse_root(&mut self) -> Result<Element> {
+        let mut nodes = self.parse_nodes(None)?;
+        match (nodes.pop(), nodes.is_empty()) {
+            (Some(Node::Element(element)), true) => Ok(element),
+            (None, _) => Err((Span::call_site(), "expected a root element".to_string())),
+            _ => Err((
+                Span::call_site(),
+                "expected a single root element".to_string(),
+            )),
+        }
+    }
+
+    /// Parse nodes until the closing tag of the parent, or until the end of
+    /// input for the root.
+    ///
+    fn parse_nodes(&mut self, parent: Option<&Ident>) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+
+        loop {
+            let token = match self.peek() {
+                Some(token) => token.clone(),
+                None => {
+                    return match parent {
+                        Some(parent) => Err((parent.span(), format!("unclosed `<{}>`", parent))),
+                        None => Ok(nodes),
+                    }
+                }
+            };
+
+            match token {
+                TokenTree::Punct(ref punct) if punct.as_char() == '<' => {
+                    if self.peek_punct(1, '/') {
+                        self.parse_closing_tag(parent)?;
+                        return Ok(nodes);
+                    }
+                    nodes.push(Node::Element(self.parse_element()?));
+                }
+                TokenTree::Literal(literal) => {
+                    self.next();
+                    nodes.push(Node::Text(literal));
+                }
+                TokenTree::Group(ref group) if group.delimiter() == Delimiter::Brace => {
+                    self.next();
+                    nodes.push(Node::Expr(group.clone()));
+                }
+                token => return Err((token.span(), "unexpected token in markup".to_string())),
+            }
+        }
+    }
+
+    fn parse_closing_tag(&mut self, parent: Option<&Ident>) -> Result<()> {
+        self.expect_punct('<')?;
+        self.expect_punct('/')?;
+        let tag = self.expect_ident()?;
+        self.expect_punct('>')?;
+
+        let name = tag.to_string();
+        if VOID_TAGS.contains(&name.as_str()) {
+            return Err((
+                tag.span(),
+                format!(
+                    "void element `{}` can't have children or a closing tag",
+                    name
+                ),
+            ));
+        }
+
+        match parent {
+            Some(parent) if parent.to_string() == name => Ok(()),
+            Some(parent) => Err((
+                tag.span(),
+                format!("expected `</{}>`, found `</{}>`", parent, name),
+            )),
+            None => Err((tag.span(), format!("unexpected closing tag `</{}>`", name))),
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<Element> {
+        self.expect_punct('<')?;
+        let tag = self.expect_ident()?;
+        let name = tag.to_string();
+        let void = VOID_TAGS.contains(&name.as_str());
+        if !void && !TAGS.contains(&name.as_str()) {
+            return Err((tag.span(), format!("unknown tag `{}`", name)));
+        }
+
+        let mut element = Element {
+            tag,
+            key: None,
+            attributes: Vec::new(),
+            children: Vec::new(),
+        };
+
+        loop {
+            if self.peek_punct(0, '/') && self.peek_punct(1, '>') {
+                self.next();
+                self.next();
+                return Ok(element);
+            }
+            if self.peek_punct(0, '>') {
+                self.next();
+                if !void {
+                    element.children = self.parse_nodes(Some(&element.tag))?;
+                }
+                return Ok(element);
+            }
+            self.parse_attribute(&mut element)?;
+        }
+    }
+
+    fn parse_attribute(&mut self, element: &mut Element) -> Result<()> {
+        let first = self.expect_ident()?;
+        let span = first.span();
+        let mut name = first.to_string();
+        while self.peek_punct(0, '-') {
+            self.next();
+            name.push('-');
+            name.push_str(&self.expect_ident()?.to_string());
+        }
+
+        let optional = self.peek_punct(0, '?');
+        if optional {
+            self.next();
+        }
+        self.expect_punct('=')?;
+
+        let value = match self.next() {
+            Some(TokenTree::Literal(literal)) => Value::Literal(literal),
+            Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Brace => {
+                Value::Expr(group.clone())
+            }
+            _ => return Err((span, format!("expected a value for `{}`", name))),
+        };
+
+        if name == "key" {
+            if optional {
+                return Err((span, "key can't be optional".to_string()));
+            }
+            if element.key.is_some() {
+                return Err((span, "duplicate key".to_string()));
+            }
+            element.key = Some(value);
+        } else {
+            if element.attributes.iter().any(|a| a.name == name) {
+                return Err((span, format!("duplicate attribute `{}`", name)));
+            }
+            element.attributes.push(Attribute {
+                name,
+                optional,
+                value,
+            });
+        }
+        Ok(())
+    }
+}
+
+//
+// # Code generation
+//
+
+fn code(source: &str) -> TokenStream {
+    source.parse().unwrap()
+}
+
+fn block(body: TokenStream) -> TokenStream {
+    TokenTree::Group(Group::new(Delimiter::Brace, body)).into()
+}
+
+fn parens(inner: TokenStream) -> TokenStream {
+    TokenTree::Group(Group::new(Delimiter::Parenthesis, inner)).into()
+}
+
+fn value(value: &Value) -> TokenStream {
+    match value {
+        Value::Literal(literal) => TokenTree::Literal(literal.clone()).into(),
+        Value::Expr(group) => group.stream(),
+    }
+}
+
+/// Statements which build the element into `element` variable.
+///
+fn element_body(element: &Element) -> TokenStream {
+    let mut body = code(&format!(
+        "let mut element = ::troy::tags::{}().reserve({}, {});",
+        element.tag,
+        element.attributes.len(),
+        element.children.len()
+    ));
+
+    if let Some(key) = &element.key {
+        body.extend(code("element = element.key"));
+        body.extend(parens(value(key)));
+        body.extend(code(";"));
+    }
+
+    for attribute in &element.attributes {
+        let name = Literal::string(&attribute.name);
+        if attribute.optional {
+            body.extend(code("if let Some(value) ="));
+            body.extend(parens(value(&attribute.value)));
+            let mut set = code("element = ::troy::macros::attr");
+            set.extend(parens(
+                vec![
+                    code("element,"),
+                    TokenTree::Literal(name).into(),
+                    code(", value"),
+                ]
+                .into_iter()
+                .collect(),
+            ));
+            set.extend(code(";"));
+            body.extend(block(set));
+        } else {
+            body.extend(code("element = ::troy::macros::attr"));
+            body.extend(parens(
+                vec![
+                    code("element,"),
+                    TokenTree::Literal(name).into(),
+                    code(","),
+                    value(&attribute.value),
+                ]
+                .into_iter()
+                .collect(),
+            ));
+            body.extend(code(";"));
+        }
+    }
+
+    for child in &element.children {
+        match child {
+            Node::Element(child) => {
+                let mut child_body = element_body(child);
+                child_body.extend(code("element"));
+                body.extend(code("element = element.child"));
+                body.extend(parens(block(child_body)));
+            }
+            Node::Text(literal) => {
+                body.extend(code("element = element.text"));
+                body.extend(parens(TokenTree::Literal(literal.clone()).into()));
+            }
+            Node::Expr(group) => {
+                body.extend(code("element = ::troy::macros::append"));
+                let mut args = code("element,");
+                args.extend(group.stream());
+                body.extend(parens(args));
+            }
+        }
+        body.extend(code(";"));
+    }
+
+    body
+}
+
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    let mut tokens = code("compile_error!");
+    tokens.extend(parens(TokenTree::Literal(Literal::string(message)).into()));
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+//
+// # Derive Render
+//
+
+struct Field {
+    name: String,
+    key: bool,
+    data: bool,
+}
+
+#[proc_macro_derive(Render, attributes(key, data))]
+pub fn derive_render(input: TokenStream) -> TokenStream {
+    match parse_struct(input) {
+        Ok((name, fields)) => render_impl(&name, &fields),
+        Err((span, message)) => compile_error(span, &message),
+    }
+}
+
+fn parse_struct(input: TokenStream) -> Result<(Ident, Vec<Field>)> {
+    let mut tokens = input.into_iter().peekable();
+
+    // Skip attributes and visibility before the struct name.
+    loop {
+        match tokens.next() {
+            Some(TokenTree::Ident(ref ident)) if ident.to_string() == "struct" => break,
+            Some(TokenTree::Ident(ref ident)) if ident.to_string() == "enum" => {
+                return Err((
+                    ident.span(),
+                    "Render can only be derived for structs".to_string(),
+                ))
+            }
+            Some(_) => {}
+            None => return Err((Span::call_site(), "expected a struct".to_string())),
+        }
+    }
+
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(name)) => name,
+        _ => return Err((Span::call_site(), "expected a struct name".to_string())),
+    };
+
+    match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Brace => {
+            Ok((name, parse_fields(group.stream())?))
+        }
+        Some(TokenTree::Punct(ref punct)) if punct.as_char() == '<' => Err((
+            punct.span(),
+            "Render can't be derived for generic structs".to_string(),
+        )),
+        _ => Err((
+            name.span(),
+            "Render can only be derived for structs with named fields".to_string(),
+        )),
+    }
+}
+
+fn parse_fields(stream: TokenStream) -> Result<Vec<Field>> {
+    let mut fields = Vec::new();
+    let mut tokens = stream.into_iter().peekable();
+
+    while tokens.peek().is_some() {
+        let mut field = Field {
+            name: String::new(),
+            key: false,
+            data: false,
+        };
+
+        // Attributes, visibility and name.
+        loop {
+            match tokens.next() {
+                Some(TokenTree::Punct(ref punct)) if punct.as_char() == '#' => {
+                    if let Some(TokenTree::Group(group)) = tokens.next() {
+                        match group.stream().to_string().as_str() {
+                            "key" => field.key = true,
+                            "data" => field.data = true,
+                            _ => {}
+                        }
+                    }
+                }
+                Some(TokenTree::Ident(ref ident)) if ident.to_string() == "pub" => {
+                    if let Some(TokenTree::Group(_)) = tokens.peek() {
+                        tokens.next();
+                    }
+                }
+                Some(TokenTree::Ident(ident)) => {
+                    field.name = ident.to_string();
+                    break;
+                }
+                Some(token) => return Err((token.span(), "expected a field".to_string())),
+                None => return Err((Span::call_site(), "expected a field".to_string())),
+            }
+        }
+
+        // Skip the type up to the next comma outside of angle brackets.
+        let mut depth = 0;
+        for token in tokens.by_ref() {
+            if let TokenTree::Punct(ref punct) = token {
+                match punct.as_char() {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    ',' if depth == 0 => break,
+                    _ => {}
+                }
+            }
+        }
+
+        fields.push(field);
+    }
+
+    if fields.iter().filter(|field| field.key).count() > 1 {
+        return Err((
+            Span::call_site(),
+            "only one field can be #[key]".to_string(),
+        ));
+    }
+
+    Ok(fields)
+}
+
+/// Convert `CamelCase` or `snake_case` name into `kebab-case`.
+///
+fn kebab_case(name: &str) -> String {
+    let name = name.trim_start_matches("r#");
+    let mut kebab = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 {
+                kebab.push('-');
+            }
+            kebab.extend(c.to_lowercase());
+        } else if c == '_' {
+            kebab.push('-');
+        } else {
+            kebab.push(c);
+        }
+    }
+    kebab
+}
+
+fn render_impl(name: &Ident, fields: &[Field]) -> TokenStream {
+    let mut element = format!(
+        "::troy::tags::div().class({:?})",
+        kebab_case(&name.to_string())
+    );
+    for field in fields.iter().filter(|field| field.data) {
+        element.push_str(&format!(
+            ".attr(::std::borrow::Cow::from({:?}), ::std::borrow::Cow::from(::std::string::ToString::to_string(&self.{})))",
+            format!("data-{}", kebab_case(&field.name)),
+            field.name
+        ));
+    }
+    for field in fields.iter().filter(|field| !field.data) {
+        element.push_str(&format!(
+            ".child(::troy::tags::div().class({:?}).child_node(::troy::view::Render::render(&self.{})))",
+            kebab_case(&field.name),
+            field.name
+        ));
+    }
+
+    let render_key = match fields.iter().find(|field| field.key) {
+        Some(field) => format!(
+            "fn render_key(&self) -> ::std::option::Option<::std::string::String> {{ \
+             ::std::option::Option::Some(::std::string::ToString::to_string(&self.{})) }}",
+            field.name
+        ),
+        None => String::new(),
+    };
+
+    let source = format!(
+        "impl ::troy::view::Render for {} {{ \
+         fn render(&self) -> ::troy::node::VNode {{ {}.done() }} \
+         {} }}",
+        name, element, render_key
+    );
+    code(&source)
+}